@@ -0,0 +1,656 @@
+//! API key and JWT bearer authentication.
+//!
+//! Clients present a credential as `Authorization: Bearer <credential>` or
+//! `X-API-Key: <key>`. A bearer credential shaped like a JWT is verified
+//! against the keys of a local JWKS file; anything else is looked up among the
+//! static API keys of the `[auth]` configuration section, which may list the
+//! keys themselves or only their SHA-256 digests. With neither API keys nor
+//! JWT configured, authentication is off and every caller is anonymous.
+//!
+//! Servers run each dispatch inside [`with_principal`] so that tool and
+//! resource handlers can ask who is calling with [`current_principal`].
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, errors::ErrorKind, jwk::JwkSet};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// JSON-RPC error code for missing, invalid or expired credentials
+pub const UNAUTHENTICATED: i64 = -32001;
+/// JSON-RPC error code for authenticated callers that are not let in
+pub const FORBIDDEN: i64 = -32003;
+/// Header carrying an API key outside of `Authorization`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// `[auth]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub api_keys: Vec<ApiKeyConfig>,
+    pub jwt: Option<JwtConfig>,
+    /// Roles of which a principal needs at least one; any principal is let in when empty
+    pub required_roles: Vec<String>,
+}
+
+/// One `[[auth.api_keys]]` entry; exactly one of `key` and `key_sha256` is set
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Principal the key authenticates as
+    pub principal: String,
+    pub key: Option<String>,
+    /// Hex SHA-256 digest of the key, so the file need not hold the key itself
+    pub key_sha256: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// `[auth.jwt]` section
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    /// JSON Web Key Set holding the verification keys
+    pub jwks_file: PathBuf,
+    /// Required `iss` claim
+    pub issuer: Option<String>,
+    /// Required `aud` claim
+    pub audience: Option<String>,
+    /// Accepted signing algorithms
+    #[serde(default = "default_algorithms")]
+    pub algorithms: Vec<Algorithm>,
+    /// Claim holding the principal's roles, as an array or a space-separated string
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+    /// Clock skew tolerated on `exp` and `nbf`, in seconds
+    #[serde(default = "default_leeway_secs")]
+    pub leeway_secs: u64,
+}
+
+fn default_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::RS256, Algorithm::ES256]
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}
+
+fn default_leeway_secs() -> u64 {
+    30
+}
+
+/// How a principal authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    ApiKey,
+    Jwt,
+}
+
+/// An authenticated caller
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Principal {
+    pub id: String,
+    pub roles: Vec<String>,
+    pub method: AuthMethod,
+    /// Unix time the credential stops being valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Principal {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|held| held == role)
+    }
+
+    /// Whether the credential has expired since it was checked
+    ///
+    /// Long-lived connections outlive their tokens, so they check this per message.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| unix_now() >= at)
+    }
+}
+
+/// Why a request was not let in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No credential was presented
+    Missing,
+    /// The credential is unknown, malformed or fails verification
+    Invalid(String),
+    /// The token verified but has expired
+    Expired,
+    /// The principal lacks every required role
+    Forbidden(String),
+}
+
+impl AuthError {
+    /// HTTP status: 401 to ask for credentials, 403 when they do not suffice
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::Forbidden(_) => 403,
+            _ => 401,
+        }
+    }
+
+    /// JSON-RPC error code
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::Forbidden(_) => FORBIDDEN,
+            _ => UNAUTHENTICATED,
+        }
+    }
+
+    /// `WWW-Authenticate` challenge (RFC 6750)
+    pub fn challenge(&self) -> &'static str {
+        match self {
+            Self::Missing => r#"Bearer realm="mcp""#,
+            Self::Invalid(_) | Self::Expired => r#"Bearer realm="mcp", error="invalid_token""#,
+            Self::Forbidden(_) => r#"Bearer realm="mcp", error="insufficient_scope""#,
+        }
+    }
+
+    /// JSON-RPC error response to the request with `id`
+    pub fn to_response(&self, id: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": self.code(), "message": self.to_string() }
+        })
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "Authentication required"),
+            Self::Invalid(reason) => write!(f, "Invalid credentials: {reason}"),
+            Self::Expired => write!(f, "Credentials have expired"),
+            Self::Forbidden(reason) => write!(f, "Forbidden: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+struct ApiKey {
+    principal: String,
+    roles: Vec<String>,
+}
+
+/// Checks the credentials of incoming requests
+pub struct Authenticator {
+    /// Keyed by SHA-256 digest, so presented keys are only ever compared as digests
+    api_keys: HashMap<[u8; 32], ApiKey>,
+    jwt: Option<JwtVerifier>,
+    required_roles: Vec<String>,
+}
+
+impl Authenticator {
+    /// Build from configuration, reading the JWKS file if JWT is configured
+    pub fn new(config: &AuthConfig) -> Result<Self, String> {
+        let mut api_keys = HashMap::new();
+        for entry in &config.api_keys {
+            if entry.principal.is_empty() {
+                return Err("API key entries need a principal".to_string());
+            }
+            let digest = match (&entry.key, &entry.key_sha256) {
+                (Some(key), None) if !key.is_empty() => digest(key),
+                (None, Some(hex)) => parse_digest(hex).ok_or_else(|| {
+                    format!(
+                        "key_sha256 of '{}' is not a hex SHA-256 digest",
+                        entry.principal
+                    )
+                })?,
+                _ => {
+                    return Err(format!(
+                        "API key of '{}' needs exactly one of key and key_sha256",
+                        entry.principal
+                    ));
+                }
+            };
+            let key = ApiKey {
+                principal: entry.principal.clone(),
+                roles: entry.roles.clone(),
+            };
+            if api_keys.insert(digest, key).is_some() {
+                return Err(format!(
+                    "API key of '{}' is configured twice",
+                    entry.principal
+                ));
+            }
+        }
+        let jwt = config.jwt.as_ref().map(JwtVerifier::load).transpose()?;
+        Ok(Self {
+            api_keys,
+            jwt,
+            required_roles: config.required_roles.clone(),
+        })
+    }
+
+    /// Whether requests need credentials at all
+    pub fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty() || self.jwt.is_some()
+    }
+
+    /// Authenticate a request from its `Authorization` and `X-API-Key` headers
+    ///
+    /// Returns `Ok(None)` when authentication is disabled.
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        api_key: Option<&str>,
+    ) -> Result<Option<Principal>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let principal = match (authorization, api_key) {
+            (Some(authorization), _) => {
+                let credential = bearer_credential(authorization)
+                    .ok_or_else(|| AuthError::Invalid("expected a Bearer credential".into()))?;
+                match &self.jwt {
+                    Some(jwt) if looks_like_jwt(credential) => jwt.verify(credential)?,
+                    _ => self.api_key(credential)?,
+                }
+            }
+            (None, Some(key)) => self.api_key(key.trim())?,
+            (None, None) => return Err(AuthError::Missing),
+        };
+        self.authorize(&principal)?;
+        Ok(Some(principal))
+    }
+
+    fn api_key(&self, key: &str) -> Result<Principal, AuthError> {
+        let key = self
+            .api_keys
+            .get(&digest(key))
+            .ok_or_else(|| AuthError::Invalid("unknown API key".into()))?;
+        Ok(Principal {
+            id: key.principal.clone(),
+            roles: key.roles.clone(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        })
+    }
+
+    fn authorize(&self, principal: &Principal) -> Result<(), AuthError> {
+        if self.required_roles.is_empty()
+            || self
+                .required_roles
+                .iter()
+                .any(|role| principal.has_role(role))
+        {
+            return Ok(());
+        }
+        Err(AuthError::Forbidden(format!(
+            "'{}' needs one of the roles {}",
+            principal.id,
+            self.required_roles.join(", ")
+        )))
+    }
+}
+
+/// Verifies JWTs against a JWKS file
+struct JwtVerifier {
+    keys: JwkSet,
+    config: JwtConfig,
+}
+
+impl JwtVerifier {
+    fn load(config: &JwtConfig) -> Result<Self, String> {
+        let keys = read_jwks(&config.jwks_file)?;
+        if keys.keys.is_empty() {
+            return Err(format!(
+                "JWKS file {} holds no keys",
+                config.jwks_file.display()
+            ));
+        }
+        if config.algorithms.is_empty() {
+            return Err("auth.jwt.algorithms must not be empty".to_string());
+        }
+        Ok(Self {
+            keys,
+            config: config.clone(),
+        })
+    }
+
+    fn verify(&self, token: &str) -> Result<Principal, AuthError> {
+        let invalid = |e: jsonwebtoken::errors::Error| AuthError::Invalid(e.to_string());
+        let header = jsonwebtoken::decode_header(token).map_err(invalid)?;
+        // Only accepted algorithms, so a token cannot pick a weaker one for the key
+        if !self.config.algorithms.contains(&header.alg) {
+            return Err(AuthError::Invalid(format!(
+                "signing algorithm {:?} is not accepted",
+                header.alg
+            )));
+        }
+        let jwk = match &header.kid {
+            Some(kid) => self
+                .keys
+                .find(kid)
+                .ok_or_else(|| AuthError::Invalid(format!("unknown key id '{kid}'")))?,
+            None if self.keys.keys.len() == 1 => &self.keys.keys[0],
+            None => return Err(AuthError::Invalid("token names no key id".into())),
+        };
+        let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = self.config.leeway_secs;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => AuthError::Expired,
+                _ => invalid(e),
+            })?
+            .claims;
+
+        let id = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| AuthError::Invalid("'sub' claim is not a string".into()))?;
+        let roles = match claims.get(&self.config.roles_claim) {
+            Some(Value::Array(roles)) => roles
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(roles)) => roles.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Principal {
+            id: id.to_string(),
+            roles,
+            method: AuthMethod::Jwt,
+            expires_at: claims.get("exp").and_then(Value::as_u64),
+        })
+    }
+}
+
+fn read_jwks(path: &Path) -> Result<JwkSet, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read JWKS file {}: {e}", path.display()))?;
+    serde_json::from_str(&source).map_err(|e| format!("Invalid JWKS file {}: {e}", path.display()))
+}
+
+/// The credential of a `Bearer` authorization header
+fn bearer_credential(authorization: &str) -> Option<&str> {
+    let (scheme, credential) = authorization.trim().split_once(' ')?;
+    let credential = credential.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !credential.is_empty()).then_some(credential)
+}
+
+/// JWTs in compact serialization have three dot-separated parts
+fn looks_like_jwt(credential: &str) -> bool {
+    credential.split('.').count() == 3
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+fn parse_digest(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+tokio::task_local! {
+    static PRINCIPAL: Option<Principal>;
+}
+
+/// Run `future` with `principal` as the caller seen by [`current_principal`]
+pub async fn with_principal<F: Future>(principal: Option<Principal>, future: F) -> F::Output {
+    PRINCIPAL.scope(principal, future).await
+}
+
+/// The caller of the request being handled; `None` when anonymous or outside a request
+pub fn current_principal() -> Option<Principal> {
+    PRINCIPAL.try_with(Clone::clone).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    /// `test-secret`, base64url-encoded for the JWKS
+    const SECRET: &[u8] = b"test-secret";
+    const SECRET_B64: &str = "dGVzdC1zZWNyZXQ";
+
+    fn api_key(principal: &str, key: &str, roles: &[&str]) -> ApiKeyConfig {
+        ApiKeyConfig {
+            principal: principal.to_string(),
+            key: Some(key.to_string()),
+            key_sha256: None,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    fn jwt_config(name: &str, audience: Option<&str>) -> JwtConfig {
+        let jwks_file = std::env::temp_dir().join(format!(
+            "access-control-{name}-{}.jwks.json",
+            std::process::id()
+        ));
+        let jwks = json!({
+            "keys": [{ "kty": "oct", "kid": "test", "alg": "HS256", "k": SECRET_B64 }]
+        });
+        std::fs::write(&jwks_file, jwks.to_string()).unwrap();
+        JwtConfig {
+            jwks_file,
+            issuer: Some("https://issuer.test".to_string()),
+            audience: audience.map(str::to_string),
+            algorithms: vec![Algorithm::HS256],
+            roles_claim: "scope".to_string(),
+            leeway_secs: 0,
+        }
+    }
+
+    fn token(claims: Value) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test".to_string());
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[test]
+    fn test_api_keys_in_either_header() {
+        let authenticator = Authenticator::new(&AuthConfig {
+            api_keys: vec![
+                api_key("ci", "ci-key", &["admin"]),
+                ApiKeyConfig {
+                    principal: "ops".to_string(),
+                    key: None,
+                    key_sha256: Some(
+                        // SHA-256 of "ops-key"
+                        "2c69bc9111c27110a9b9a7974ba3f8ac0c053c16b23a0738115ee829fbc4d57b"
+                            .to_string(),
+                    ),
+                    roles: Vec::new(),
+                },
+            ],
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        assert!(authenticator.is_enabled());
+
+        let principal = authenticator
+            .authenticate(Some("Bearer ci-key"), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.id, "ci");
+        assert!(principal.has_role("admin"));
+        assert_eq!(principal.method, AuthMethod::ApiKey);
+        assert!(!principal.is_expired());
+
+        let principal = authenticator
+            .authenticate(None, Some("ops-key"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.id, "ops");
+
+        assert_eq!(
+            authenticator.authenticate(None, None),
+            Err(AuthError::Missing)
+        );
+        assert!(matches!(
+            authenticator.authenticate(Some("Bearer wrong"), None),
+            Err(AuthError::Invalid(_))
+        ));
+        assert!(matches!(
+            authenticator.authenticate(Some("Basic Y2k6Y2kta2V5"), None),
+            Err(AuthError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_required_roles_and_disabled_auth() {
+        let authenticator = Authenticator::new(&AuthConfig {
+            api_keys: vec![
+                api_key("admin", "admin-key", &["admin"]),
+                api_key("guest", "guest-key", &[]),
+            ],
+            required_roles: vec!["admin".to_string(), "operator".to_string()],
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        assert!(authenticator.authenticate(None, Some("admin-key")).is_ok());
+        let error = authenticator
+            .authenticate(None, Some("guest-key"))
+            .unwrap_err();
+        assert_eq!(error.status(), 403);
+        assert_eq!(error.code(), FORBIDDEN);
+
+        let open = Authenticator::new(&AuthConfig::default()).unwrap();
+        assert!(!open.is_enabled());
+        assert_eq!(open.authenticate(None, None), Ok(None));
+    }
+
+    #[test]
+    fn test_invalid_configuration_is_rejected() {
+        let mut both = api_key("x", "key", &[]);
+        both.key_sha256 = Some("00".repeat(32));
+        let mut bad_digest = api_key("x", "", &[]);
+        bad_digest.key = None;
+        bad_digest.key_sha256 = Some("not hex".to_string());
+        for api_keys in [
+            vec![both],
+            vec![bad_digest],
+            vec![api_key("a", "same", &[]), api_key("b", "same", &[])],
+            vec![api_key("", "key", &[])],
+        ] {
+            let config = AuthConfig {
+                api_keys,
+                ..AuthConfig::default()
+            };
+            assert!(Authenticator::new(&config).is_err());
+        }
+
+        let mut jwt = jwt_config("missing", None);
+        jwt.jwks_file = PathBuf::from("/nonexistent/jwks.json");
+        let config = AuthConfig {
+            jwt: Some(jwt),
+            ..AuthConfig::default()
+        };
+        assert!(Authenticator::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_jwt_bearer_tokens() {
+        let authenticator = Authenticator::new(&AuthConfig {
+            jwt: Some(jwt_config("verify", Some("mcp"))),
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        let exp = unix_now() + 600;
+        let claims = |aud: &str, exp: u64| {
+            json!({
+                "sub": "alice",
+                "iss": "https://issuer.test",
+                "aud": aud,
+                "exp": exp,
+                "scope": "tools:read tools:call"
+            })
+        };
+
+        let bearer = format!("Bearer {}", token(claims("mcp", exp)));
+        let principal = authenticator
+            .authenticate(Some(&bearer), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.id, "alice");
+        assert_eq!(principal.roles, vec!["tools:read", "tools:call"]);
+        assert_eq!(principal.method, AuthMethod::Jwt);
+        assert_eq!(principal.expires_at, Some(exp));
+
+        let wrong_audience = format!("Bearer {}", token(claims("other", exp)));
+        assert!(matches!(
+            authenticator.authenticate(Some(&wrong_audience), None),
+            Err(AuthError::Invalid(_))
+        ));
+        let expired = format!("Bearer {}", token(claims("mcp", unix_now() - 60)));
+        assert_eq!(
+            authenticator.authenticate(Some(&expired), None),
+            Err(AuthError::Expired)
+        );
+        let tampered = format!("{}x", bearer);
+        assert!(authenticator.authenticate(Some(&tampered), None).is_err());
+    }
+
+    #[test]
+    fn test_only_configured_algorithms_are_accepted() {
+        let mut config = jwt_config("algorithms", None);
+        config.algorithms = default_algorithms();
+        let authenticator = Authenticator::new(&AuthConfig {
+            jwt: Some(config),
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        let claims =
+            json!({ "sub": "alice", "iss": "https://issuer.test", "exp": unix_now() + 600 });
+        let error = authenticator
+            .authenticate(Some(&format!("Bearer {}", token(claims))), None)
+            .unwrap_err();
+        assert_eq!(error.status(), 401);
+        assert_eq!(error.code(), UNAUTHENTICATED);
+        assert!(error.challenge().contains("invalid_token"));
+        assert_eq!(
+            error.to_response(json!(7))["error"]["code"],
+            UNAUTHENTICATED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_principal_is_scoped_to_the_request() {
+        let principal = Principal {
+            id: "alice".to_string(),
+            roles: Vec::new(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        };
+        let seen = with_principal(Some(principal.clone()), async { current_principal() }).await;
+        assert_eq!(seen, Some(principal));
+        assert_eq!(current_principal(), None);
+    }
+}
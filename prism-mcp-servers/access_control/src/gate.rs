@@ -0,0 +1,199 @@
+//! The checks every network transport runs around a request.
+//!
+//! A request is checked against the [`PolicyEngine`] first, so a caller
+//! denied a tool does not use up its rate limits, and only then counted
+//! against the [`RateLimiter`] for as long as it runs. It is dispatched to the
+//! [`McpServer`] with the caller's principal in scope for the handlers, and
+//! the response is filtered by the same policy.
+//!
+//! Client notifications name no tool or resource and get no answer, so they
+//! are handed to the server without either check.
+
+use prism_mcp_rs::{
+    protocol::messages::{JsonRpcNotification, JsonRpcRequest},
+    server::McpServer,
+};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::auth::{self, AuthError, Principal};
+use crate::jsonrpc::{INTERNAL_ERROR, error_code, error_response};
+use crate::limits::{LimitError, RATE_LIMITED, RateLimiter};
+use crate::policy::{Action, PolicyEngine};
+
+/// Why a request was not dispatched
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// The policy does not allow it
+    Denied(AuthError),
+    /// A rate or in-flight limit was reached
+    Limited(LimitError),
+}
+
+impl Refusal {
+    /// JSON-RPC error code of the refusal
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::Denied(error) => error.code(),
+            Self::Limited(_) => RATE_LIMITED,
+        }
+    }
+
+    /// JSON-RPC error response to the request `id`
+    pub fn to_response(&self, id: Value) -> Value {
+        match self {
+            Self::Denied(error) => error.to_response(id),
+            Self::Limited(error) => error.to_response(id),
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Denied(error) => error.fmt(f),
+            Self::Limited(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Refusal {}
+
+/// Policy and limits applied to every request a transport dispatches
+#[derive(Clone)]
+pub struct Gate {
+    policy: Arc<PolicyEngine>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Gate {
+    pub fn new(policy: Arc<PolicyEngine>, limiter: Arc<RateLimiter>) -> Self {
+        Self { policy, limiter }
+    }
+
+    /// Dispatch `request` from `client` unless the policy or a limit refuses it
+    ///
+    /// Failures of the server itself are returned as JSON-RPC error responses.
+    pub async fn dispatch(
+        &self,
+        server: &McpServer,
+        request: JsonRpcRequest,
+        principal: Option<&Principal>,
+        client: &str,
+    ) -> Result<Value, Refusal> {
+        let id = request.id.clone();
+        let method = request.method.clone();
+        let params = request.params.clone().unwrap_or(Value::Null);
+        let policy = self.policy.current();
+        if let Err(denied) = policy.check_request(principal, &method, &params) {
+            tracing::debug!("Denied {method}: {denied}");
+            return Err(Refusal::Denied(denied));
+        }
+        // Held until the response is ready, counting the request as in flight
+        let _permit = match self.limiter.acquire(client, tool_name(&method, &params)) {
+            Ok(permit) => permit,
+            Err(limited) => {
+                tracing::debug!("Refused {method} from {client}: {limited}");
+                return Err(Refusal::Limited(limited));
+            }
+        };
+
+        let dispatch = auth::with_principal(principal.cloned(), server.handle_request(request));
+        let mut response = match dispatch.await {
+            Ok(response) => serde_json::to_value(&response)
+                .unwrap_or_else(|e| error_response(id, INTERNAL_ERROR, &e.to_string())),
+            Err(e) => error_response(id, error_code(&e), &e.to_string()),
+        };
+        policy.filter_response(principal, &method, &mut response);
+        Ok(response)
+    }
+
+    /// Whether `principal` may be told about changes to the resource `uri`
+    ///
+    /// Checked when each update is sent, so a subscription stops delivering
+    /// as soon as the policy no longer lets its subscriber read the resource.
+    pub fn allows_resource(&self, principal: Option<&Principal>, uri: &str) -> bool {
+        self.policy
+            .current()
+            .check(principal, &Action::ReadResource { uri })
+            .is_ok()
+    }
+
+    /// Hand a client notification to the server; failures are only logged
+    pub async fn deliver(
+        &self,
+        server: &McpServer,
+        notification: JsonRpcNotification,
+        principal: Option<&Principal>,
+    ) {
+        let method = notification.method.clone();
+        let delivery =
+            auth::with_principal(principal.cloned(), server.handle_notification(notification));
+        if let Err(e) = delivery.await {
+            tracing::debug!("Failed to handle {method} notification: {e}");
+        }
+    }
+}
+
+/// Name of the tool a `tools/call` request invokes
+pub fn tool_name<'a>(method: &str, params: &'a Value) -> Option<&'a str> {
+    (method == "tools/call")
+        .then(|| params.get("name").and_then(Value::as_str))
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::{Limit, LimitsConfig};
+    use crate::policy::Policy;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_denied_requests_do_not_use_up_limits() {
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            tools = ["delete"]
+            "#,
+        )
+        .unwrap();
+        let limiter = RateLimiter::new(LimitsConfig {
+            client: Limit {
+                requests_per_second: Some(0.001),
+                burst: Some(1),
+                ..Limit::default()
+            },
+            ..LimitsConfig::default()
+        });
+        let gate = Gate::new(Arc::new(PolicyEngine::from(policy)), limiter);
+        let server = McpServer::new("test".to_string(), "1.0.0".to_string());
+        let call = |id: i64, tool: &str| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: json!(id),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": tool })),
+        };
+
+        for id in 1..=3 {
+            let refusal = gate
+                .dispatch(&server, call(id, "delete"), None, "10.0.0.1")
+                .await
+                .unwrap_err();
+            assert!(matches!(refusal, Refusal::Denied(_)), "{refusal}");
+            assert_eq!(refusal.to_response(json!(id))["id"], id);
+        }
+        assert!(
+            gate.dispatch(&server, call(4, "read"), None, "10.0.0.1")
+                .await
+                .is_ok()
+        );
+        let refusal = gate
+            .dispatch(&server, call(5, "read"), None, "10.0.0.1")
+            .await
+            .unwrap_err();
+        assert_eq!(refusal.code(), RATE_LIMITED);
+    }
+}
@@ -0,0 +1,41 @@
+//! JSON-RPC error codes and error responses.
+
+use prism_mcp_rs::core::error::McpError;
+use serde_json::{Value, json};
+
+use crate::auth::FORBIDDEN;
+
+/// JSON-RPC error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// MCP error code for unknown resources
+pub const RESOURCE_NOT_FOUND: i64 = -32002;
+
+/// JSON-RPC error code reported for a dispatch failure
+pub fn error_code(error: &McpError) -> i64 {
+    match error {
+        McpError::Validation(_)
+        | McpError::InvalidUri(_)
+        | McpError::ToolNotFound(_)
+        | McpError::PromptNotFound(_) => INVALID_PARAMS,
+        McpError::ResourceNotFound(_) => RESOURCE_NOT_FOUND,
+        // Handlers refuse what the policy denies the caller in scope
+        McpError::Authentication(_) => FORBIDDEN,
+        McpError::Protocol(message) if message.to_lowercase().contains("method not found") => {
+            METHOD_NOT_FOUND
+        }
+        McpError::Protocol(_) => INVALID_REQUEST,
+        _ => INTERNAL_ERROR,
+    }
+}
+
+pub fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message }
+    })
+}
@@ -0,0 +1,19 @@
+//! Access control shared by the HTTP and WebSocket MCP servers.
+//!
+//! - [`auth`]: API key and JWT bearer authentication, and the authenticated
+//!   [`Principal`](auth::Principal) of the request being handled
+//! - [`policy`]: allow/deny rules deciding which tools and resources a
+//!   principal may use
+//! - [`gate`]: the policy check, limits and dispatch every request goes
+//!   through, in that order
+//! - [`jsonrpc`]: JSON-RPC error codes and error responses
+//! - [`limits`]: token-bucket rate limits and in-flight caps per client, per
+//!   tool and for the whole server
+//! - [`whoami`]: a tool reporting the principal a request runs as
+
+pub mod auth;
+pub mod gate;
+pub mod jsonrpc;
+pub mod limits;
+pub mod policy;
+pub mod whoami;
@@ -0,0 +1,418 @@
+//! Rate and concurrency limits.
+//!
+//! Every request is counted against up to three scopes: the whole server
+//! (`global`), the calling client (`client`, or its entry under `clients`),
+//! and, for tool calls, the client's use of that tool (`tools`). Each scope
+//! may set a token bucket (`requests_per_second` refilled up to `burst`) and a
+//! cap on requests handled at once (`max_in_flight`). A request is admitted
+//! only when every scope has room, so a client that exhausts its own budget,
+//! or keeps one expensive tool busy, does not use up anyone else's.
+//!
+//! Clients are identified by their principal id, or by their address when
+//! authentication is off.
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// JSON-RPC error code for requests refused by a limit
+pub const RATE_LIMITED: i64 = -32029;
+
+/// Scope states are dropped once idle and this many are tracked
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Limits for one scope; each is off unless set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limit {
+    /// Sustained request rate
+    pub requests_per_second: Option<f64>,
+    /// Requests allowed in a burst; defaults to one second's worth
+    pub burst: Option<u32>,
+    /// Requests handled at the same time
+    pub max_in_flight: Option<usize>,
+}
+
+impl Limit {
+    fn is_set(&self) -> bool {
+        self.requests_per_second.is_some() || self.max_in_flight.is_some()
+    }
+
+    fn capacity(&self) -> f64 {
+        match (self.burst, self.requests_per_second) {
+            (Some(burst), _) => f64::from(burst),
+            (None, Some(rate)) => rate.ceil().max(1.0),
+            (None, None) => 0.0,
+        }
+    }
+
+    fn validate(&self, scope: &str) -> Result<(), String> {
+        if let Some(rate) = self.requests_per_second {
+            if !(rate.is_finite() && rate > 0.0) {
+                return Err(format!(
+                    "{scope}: requests_per_second must be positive, got {rate}"
+                ));
+            }
+        } else if self.burst.is_some() {
+            return Err(format!("{scope}: burst needs requests_per_second"));
+        }
+        if self.burst == Some(0) {
+            return Err(format!("{scope}: burst must be at least 1"));
+        }
+        if self.max_in_flight == Some(0) {
+            return Err(format!("{scope}: max_in_flight must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+/// `[limits]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Shared by all requests
+    pub global: Limit,
+    /// Applied to each client separately
+    pub client: Limit,
+    /// Replaces `client` for the clients named
+    pub clients: BTreeMap<String, Limit>,
+    /// Applied to each client's calls of the named tool
+    pub tools: BTreeMap<String, Limit>,
+}
+
+impl LimitsConfig {
+    /// Reject limits that would refuse every request or make no sense
+    pub fn validate(&self) -> Result<(), String> {
+        self.global.validate("limits.global")?;
+        self.client.validate("limits.client")?;
+        for (client, limit) in &self.clients {
+            limit.validate(&format!("limits.clients.{client}"))?;
+        }
+        for (tool, limit) in &self.tools {
+            limit.validate(&format!("limits.tools.{tool}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    /// The scope's bucket is empty until `retry_after` has passed
+    Rate {
+        scope: String,
+        retry_after: Duration,
+    },
+    /// The scope already has `max_in_flight` requests running
+    InFlight { scope: String },
+}
+
+impl LimitError {
+    /// Whole seconds to wait before retrying, for `Retry-After`
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            Self::Rate { retry_after, .. } => retry_after.as_secs_f64().ceil().max(1.0) as u64,
+            Self::InFlight { .. } => 1,
+        }
+    }
+
+    /// JSON-RPC error response to request `id`
+    pub fn to_response(&self, id: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": RATE_LIMITED,
+                "message": self.to_string(),
+                "data": { "retryAfter": self.retry_after_secs() }
+            }
+        })
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rate { scope, .. } => write!(
+                f,
+                "Rate limit exceeded for {scope}; retry in {}s",
+                self.retry_after_secs()
+            ),
+            Self::InFlight { scope } => write!(f, "Too many requests in flight for {scope}"),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Scope {
+    Global,
+    Client(String),
+    Tool { client: String, tool: String },
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Global => write!(f, "the server"),
+            Self::Client(client) => write!(f, "client '{client}'"),
+            Self::Tool { client, tool } => write!(f, "tool '{tool}' (client '{client}')"),
+        }
+    }
+}
+
+/// Bucket and in-flight count of one scope
+struct Slot {
+    limit: Limit,
+    tokens: f64,
+    updated: Instant,
+    in_flight: usize,
+}
+
+impl Slot {
+    fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.capacity(),
+            updated: now,
+            in_flight: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(rate) = self.limit.requests_per_second {
+            let elapsed = now.duration_since(self.updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(self.limit.capacity());
+        }
+        self.updated = now;
+    }
+
+    fn check(&self, scope: &Scope) -> Result<(), LimitError> {
+        if self
+            .limit
+            .max_in_flight
+            .is_some_and(|max| self.in_flight >= max)
+        {
+            return Err(LimitError::InFlight {
+                scope: scope.to_string(),
+            });
+        }
+        match self.limit.requests_per_second {
+            Some(rate) if self.tokens < 1.0 => Err(LimitError::Rate {
+                scope: scope.to_string(),
+                retry_after: Duration::from_secs_f64((1.0 - self.tokens) / rate),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.in_flight == 0 && self.tokens >= self.limit.capacity()
+    }
+}
+
+/// Admits or refuses requests according to a [`LimitsConfig`]
+pub struct RateLimiter {
+    config: LimitsConfig,
+    slots: Mutex<HashMap<Scope, Slot>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: LimitsConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            slots: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.config.global.is_set()
+            || self.config.client.is_set()
+            || self.config.clients.values().any(Limit::is_set)
+            || self.config.tools.values().any(Limit::is_set)
+    }
+
+    /// Admit a request from `client`, calling `tool` if it is a tool call
+    ///
+    /// The request counts as in flight until the returned permit is dropped.
+    pub fn acquire(
+        self: &Arc<Self>,
+        client: &str,
+        tool: Option<&str>,
+    ) -> Result<Permit, LimitError> {
+        let mut scopes = vec![
+            (Scope::Global, self.config.global),
+            (
+                Scope::Client(client.to_string()),
+                *self
+                    .config
+                    .clients
+                    .get(client)
+                    .unwrap_or(&self.config.client),
+            ),
+        ];
+        if let Some(tool) = tool {
+            if let Some(limit) = self.config.tools.get(tool) {
+                let scope = Scope::Tool {
+                    client: client.to_string(),
+                    tool: tool.to_string(),
+                };
+                scopes.push((scope, *limit));
+            }
+        }
+        scopes.retain(|(_, limit)| limit.is_set());
+
+        let now = Instant::now();
+        let mut slots = self.slots.lock().unwrap();
+        if slots.len() >= PRUNE_THRESHOLD {
+            slots.retain(|_, slot| {
+                slot.refill(now);
+                !slot.is_idle()
+            });
+        }
+        for (scope, limit) in &scopes {
+            let slot = slots
+                .entry(scope.clone())
+                .or_insert_with(|| Slot::new(*limit, now));
+            slot.refill(now);
+            slot.check(scope)?;
+        }
+        for (scope, _) in &scopes {
+            let slot = slots.get_mut(scope).expect("slot was just checked");
+            if slot.limit.requests_per_second.is_some() {
+                slot.tokens -= 1.0;
+            }
+            slot.in_flight += 1;
+        }
+        Ok(Permit {
+            limiter: self.clone(),
+            scopes: scopes.into_iter().map(|(scope, _)| scope).collect(),
+        })
+    }
+}
+
+/// An admitted request; dropping it ends the request
+pub struct Permit {
+    limiter: Arc<RateLimiter>,
+    scopes: Vec<Scope>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut slots = self.limiter.slots.lock().unwrap();
+        for scope in &self.scopes {
+            if let Some(slot) = slots.get_mut(scope) {
+                slot.in_flight = slot.in_flight.saturating_sub(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(source: &str) -> Arc<RateLimiter> {
+        let config: LimitsConfig = toml::from_str(source).unwrap();
+        config.validate().unwrap();
+        RateLimiter::new(config)
+    }
+
+    #[test]
+    fn test_bucket_allows_a_burst_then_refuses() {
+        let limiter = limiter(
+            r#"
+            [client]
+            requests_per_second = 0.5
+            burst = 2
+            "#,
+        );
+        assert!(limiter.is_enabled());
+        let _first = limiter.acquire("alice", None).unwrap();
+        let _second = limiter.acquire("alice", None).unwrap();
+        let error = limiter.acquire("alice", None).err().unwrap();
+        match &error {
+            LimitError::Rate { retry_after, .. } => {
+                assert!(*retry_after > Duration::from_secs(1));
+                assert!(*retry_after <= Duration::from_secs(2));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(error.retry_after_secs(), 2);
+        let response = error.to_response(json!(9));
+        assert_eq!(response["error"]["code"], RATE_LIMITED);
+        assert_eq!(response["error"]["data"]["retryAfter"], 2);
+
+        // Other clients have buckets of their own
+        assert!(limiter.acquire("bob", None).is_ok());
+    }
+
+    #[test]
+    fn test_in_flight_requests_are_released_on_drop() {
+        let limiter = limiter(
+            r#"
+            [global]
+            max_in_flight = 2
+
+            [tools.generate_dataset]
+            max_in_flight = 1
+            "#,
+        );
+        let dataset = limiter.acquire("agent", Some("generate_dataset")).unwrap();
+        assert!(matches!(
+            limiter.acquire("agent", Some("generate_dataset")),
+            Err(LimitError::InFlight { .. })
+        ));
+        // A refused request takes nothing, so the global slot is still free
+        let other = limiter.acquire("alice", Some("generate_dataset")).unwrap();
+        let error = limiter.acquire("bob", None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Too many requests in flight for the server"
+        );
+
+        drop(dataset);
+        drop(other);
+        assert!(limiter.acquire("bob", None).is_ok());
+        assert!(limiter.acquire("agent", Some("generate_dataset")).is_ok());
+    }
+
+    #[test]
+    fn test_clients_override_the_default() {
+        let limiter = limiter(
+            r#"
+            [client]
+            requests_per_second = 1
+
+            [clients.ci]
+            requests_per_second = 100
+            "#,
+        );
+        assert!(limiter.acquire("alice", None).is_ok());
+        assert!(limiter.acquire("alice", None).is_err());
+        for _ in 0..50 {
+            assert!(limiter.acquire("ci", None).is_ok());
+        }
+        assert!(!RateLimiter::new(LimitsConfig::default()).is_enabled());
+    }
+
+    #[test]
+    fn test_invalid_limits_are_rejected() {
+        let invalid = |source: &str| {
+            toml::from_str::<LimitsConfig>(source)
+                .map_err(|e| e.to_string())
+                .and_then(|config| config.validate())
+                .is_err()
+        };
+        assert!(invalid("[global]\nrequests_per_second = 0"));
+        assert!(invalid("[client]\nburst = 5"));
+        assert!(invalid("[tools.x]\nmax_in_flight = 0"));
+        assert!(invalid("[client]\nrequests_per_minute = 5"));
+        assert!(!invalid("[tools.x]\nrequests_per_second = 0.1\nburst = 1"));
+    }
+}
@@ -0,0 +1,674 @@
+//! Per-tool and per-resource authorization policies.
+//!
+//! A policy is an ordered list of allow/deny rules, read from a TOML or JSON
+//! file (chosen by extension). The first rule matching a request decides it;
+//! when none does, the policy's `default` applies. A rule matches when every
+//! condition it sets holds:
+//!
+//! - `principals`: globs over the principal id
+//! - `roles`: the principal holds at least one of them
+//! - `tools`: globs over the tool name
+//! - `resources`: globs over the resource URI
+//! - `arguments`: predicates over tool call arguments, by name or dotted path
+//!
+//! A rule with `tools` but no `resources` only applies to tool calls, and the
+//! reverse for resource reads. Anonymous callers match no `principals` or
+//! `roles` condition. Tools a caller may never call are left out of
+//! `tools/list`; a tool allowed only for some arguments stays listed.
+//!
+//! [`PolicyEngine`] keeps the policy swappable, so the file can be reloaded
+//! while the server runs.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::auth::{AuthError, Principal};
+
+/// What a matching rule does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// An ordered rule list
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Effect when no rule matches
+    pub default: Effect,
+    pub rules: Vec<Rule>,
+}
+
+/// One `[[rules]]` entry; unset conditions match everything
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub effect: Effect,
+    #[serde(default)]
+    pub principals: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub arguments: BTreeMap<String, Predicate>,
+}
+
+/// Conditions on one tool argument; all that are set must hold
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Predicate {
+    pub exists: Option<bool>,
+    pub equals: Option<Value>,
+    pub one_of: Option<Vec<Value>>,
+    /// Glob the argument must be a string matching
+    pub matches: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// What a request wants to do
+#[derive(Debug, Clone, Copy)]
+pub enum Action<'a> {
+    CallTool { name: &'a str, arguments: &'a Value },
+    ReadResource { uri: &'a str },
+}
+
+impl Policy {
+    /// Parse a TOML policy
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Parse a JSON policy
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        serde_json::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Read a policy file; `.json` files are JSON, anything else TOML
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read policy file {}: {e}", path.display()))?;
+        let policy = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&source),
+            _ => Self::from_toml(&source),
+        };
+        policy.map_err(|e| format!("Invalid policy file {}: {e}", path.display()))
+    }
+
+    /// Whether `principal` may perform `action`
+    pub fn check(&self, principal: Option<&Principal>, action: &Action) -> Result<(), AuthError> {
+        let effect = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(principal, action))
+            .map_or(self.default, |rule| rule.effect);
+        if effect == Effect::Allow {
+            return Ok(());
+        }
+        let who = principal.map_or("anonymous callers", |principal| principal.id.as_str());
+        Err(AuthError::Forbidden(match action {
+            Action::CallTool { name, .. } => format!("{who} may not call tool '{name}'"),
+            Action::ReadResource { uri } => format!("{who} may not access resource '{uri}'"),
+        }))
+    }
+
+    /// Whether to list tool `name` for `principal`
+    ///
+    /// Arguments are unknown when listing, so a rule with argument predicates
+    /// keeps the tool listed if it allows and is passed over if it denies.
+    pub fn lists_tool(&self, principal: Option<&Principal>, name: &str) -> bool {
+        let action = Action::CallTool {
+            name,
+            arguments: &Value::Null,
+        };
+        for rule in &self.rules {
+            if !rule.matches_subject(principal) || !rule.matches_target(&action) {
+                continue;
+            }
+            if rule.arguments.is_empty() || rule.effect == Effect::Allow {
+                return rule.effect == Effect::Allow;
+            }
+        }
+        self.default == Effect::Allow
+    }
+
+    /// Reject a JSON-RPC request for a tool or resource `principal` may not use
+    pub fn check_request(
+        &self,
+        principal: Option<&Principal>,
+        method: &str,
+        params: &Value,
+    ) -> Result<(), AuthError> {
+        let action = match method {
+            "tools/call" => match params.get("name").and_then(Value::as_str) {
+                Some(name) => Action::CallTool {
+                    name,
+                    arguments: params.get("arguments").unwrap_or(&Value::Null),
+                },
+                None => return Ok(()),
+            },
+            "resources/read" | "resources/subscribe" => {
+                match params.get("uri").and_then(Value::as_str) {
+                    Some(uri) => Action::ReadResource { uri },
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        };
+        self.check(principal, &action)
+    }
+
+    /// Remove what `principal` may not use from a `tools/list` or `resources/list` response
+    pub fn filter_response(
+        &self,
+        principal: Option<&Principal>,
+        method: &str,
+        response: &mut Value,
+    ) {
+        let Some(result) = response.get_mut("result") else {
+            return;
+        };
+        match method {
+            "tools/list" => retain(result, "tools", |tool| {
+                tool.get("name")
+                    .and_then(Value::as_str)
+                    .is_none_or(|name| self.lists_tool(principal, name))
+            }),
+            "resources/list" => retain(result, "resources", |resource| {
+                resource
+                    .get("uri")
+                    .and_then(Value::as_str)
+                    .is_none_or(|uri| self.check(principal, &Action::ReadResource { uri }).is_ok())
+            }),
+            _ => {}
+        }
+    }
+}
+
+fn retain(result: &mut Value, field: &str, keep: impl Fn(&Value) -> bool) {
+    if let Some(Value::Array(items)) = result.get_mut(field) {
+        items.retain(keep);
+    }
+}
+
+impl Rule {
+    fn matches(&self, principal: Option<&Principal>, action: &Action) -> bool {
+        self.matches_subject(principal)
+            && self.matches_target(action)
+            && match action {
+                Action::CallTool { arguments, .. } => self
+                    .arguments
+                    .iter()
+                    .all(|(path, predicate)| predicate.holds(argument(arguments, path))),
+                Action::ReadResource { .. } => true,
+            }
+    }
+
+    fn matches_subject(&self, principal: Option<&Principal>) -> bool {
+        if self.principals.is_empty() && self.roles.is_empty() {
+            return true;
+        }
+        let Some(principal) = principal else {
+            return false;
+        };
+        (self.principals.is_empty()
+            || self
+                .principals
+                .iter()
+                .any(|pattern| glob_match(pattern, &principal.id)))
+            && (self.roles.is_empty() || self.roles.iter().any(|role| principal.has_role(role)))
+    }
+
+    fn matches_target(&self, action: &Action) -> bool {
+        let (patterns, other, target) = match action {
+            Action::CallTool { name, .. } => (&self.tools, &self.resources, *name),
+            Action::ReadResource { uri } => (&self.resources, &self.tools, *uri),
+        };
+        if patterns.is_empty() {
+            // A rule naming only the other kind of target does not apply
+            return other.is_empty();
+        }
+        patterns.iter().any(|pattern| glob_match(pattern, target))
+    }
+}
+
+impl Predicate {
+    fn holds(&self, value: Option<&Value>) -> bool {
+        if let Some(exists) = self.exists {
+            if value.is_some() != exists {
+                return false;
+            }
+        }
+        if let Some(expected) = &self.equals {
+            if value != Some(expected) {
+                return false;
+            }
+        }
+        if let Some(options) = &self.one_of {
+            if !value.is_some_and(|value| options.contains(value)) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.matches {
+            if !value
+                .and_then(Value::as_str)
+                .is_some_and(|text| glob_match(pattern, text))
+            {
+                return false;
+            }
+        }
+        let number = value.and_then(Value::as_f64);
+        if let Some(min) = self.min {
+            if !number.is_some_and(|number| number >= min) {
+                return false;
+            }
+        }
+        if let Some(max) = self.max {
+            if !number.is_some_and(|number| number <= max) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The argument at a dotted path such as `filter.owner` or `ids.0`
+fn argument<'a>(arguments: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(arguments, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// Match `text` against a glob where `*` is any run of characters and `?` any one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// `[policy]` configuration section
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// TOML or JSON policy file; everything is allowed without one
+    pub file: Option<PathBuf>,
+    /// Reload the file when it changes
+    pub hot_reload: bool,
+    /// How often to look for changes when reloading
+    pub reload_interval_secs: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            hot_reload: false,
+            reload_interval_secs: 5,
+        }
+    }
+}
+
+/// The policy in force, optionally reloaded from its file
+pub struct PolicyEngine {
+    file: Option<PathBuf>,
+    policy: RwLock<Arc<Policy>>,
+    /// Modification time of the file when last loaded
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl PolicyEngine {
+    /// Load the configured policy file, or allow everything without one
+    pub fn new(config: &PolicyConfig) -> Result<Self, String> {
+        let (policy, modified) = match &config.file {
+            Some(file) => (Policy::from_file(file)?, modified(file)),
+            None => (Policy::default(), None),
+        };
+        Ok(Self {
+            file: config.file.clone(),
+            policy: RwLock::new(Arc::new(policy)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// The policy to check the current request against
+    pub fn current(&self) -> Arc<Policy> {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Load the file again if it changed since it was last loaded
+    ///
+    /// An invalid file is reported and the previous policy stays in force.
+    pub fn reload_if_changed(&self) -> Result<bool, String> {
+        let Some(file) = &self.file else {
+            return Ok(false);
+        };
+        let modified = modified(file);
+        if *self.modified.lock().unwrap() == modified {
+            return Ok(false);
+        }
+        // Remember the attempt either way so a broken file is reported once
+        *self.modified.lock().unwrap() = modified;
+        let policy = Policy::from_file(file)?;
+        *self.policy.write().unwrap() = Arc::new(policy);
+        Ok(true)
+    }
+
+    /// Poll the policy file for changes every `interval`
+    pub fn spawn_reloader(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let engine = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                match engine.reload_if_changed() {
+                    Ok(true) => tracing::info!("Reloaded authorization policy"),
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!("Keeping the previous authorization policy: {e}"),
+                }
+            }
+        })
+    }
+}
+
+impl From<Policy> for PolicyEngine {
+    /// A fixed policy with no file behind it
+    fn from(policy: Policy) -> Self {
+        Self {
+            file: None,
+            policy: RwLock::new(Arc::new(policy)),
+            modified: Mutex::new(None),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthMethod;
+    use serde_json::json;
+
+    fn principal(id: &str, roles: &[&str]) -> Principal {
+        Principal {
+            id: id.to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        }
+    }
+
+    fn call<'a>(name: &'a str, arguments: &'a Value) -> Action<'a> {
+        Action::CallTool { name, arguments }
+    }
+
+    const ADMIN_ONLY_DELETE: &str = r#"
+        [[rules]]
+        effect = "allow"
+        roles = ["admin"]
+        tools = ["delete", "bulk_*"]
+
+        [[rules]]
+        effect = "deny"
+        tools = ["delete", "bulk_*"]
+    "#;
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let policy = Policy::from_toml(ADMIN_ONLY_DELETE).unwrap();
+        let admin = principal("root", &["admin"]);
+        let user = principal("alice", &["user"]);
+        let none = Value::Null;
+
+        assert!(policy.check(Some(&admin), &call("delete", &none)).is_ok());
+        assert!(
+            policy
+                .check(Some(&admin), &call("bulk_delete", &none))
+                .is_ok()
+        );
+        let error = policy
+            .check(Some(&user), &call("delete", &none))
+            .unwrap_err();
+        assert_eq!(error.status(), 403);
+        assert!(error.to_string().contains("'delete'"));
+        assert!(policy.check(None, &call("delete", &none)).is_err());
+        // Tool rules leave other tools and resources to the default
+        assert!(policy.check(Some(&user), &call("retrieve", &none)).is_ok());
+        assert!(
+            policy
+                .check(Some(&user), &Action::ReadResource { uri: "db:///users" })
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_principals_resources_and_default_deny() {
+        let policy = Policy::from_json(
+            r#"{
+                "default": "deny",
+                "rules": [
+                    { "effect": "allow", "principals": ["svc-*"], "resources": ["db:///reports/*"] },
+                    { "effect": "allow", "roles": ["reader"], "tools": ["retrieve", "search"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let service = principal("svc-reports", &[]);
+        let reader = principal("bob", &["reader"]);
+        let report = Action::ReadResource {
+            uri: "db:///reports/2024/q1",
+        };
+
+        assert!(policy.check(Some(&service), &report).is_ok());
+        assert!(policy.check(Some(&reader), &report).is_err());
+        assert!(
+            policy
+                .check(Some(&reader), &call("search", &Value::Null))
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check(Some(&service), &call("search", &Value::Null))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_argument_predicates() {
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            tools = ["query"]
+            arguments = { collection = { one_of = ["secrets", "audit"] } }
+
+            [[rules]]
+            effect = "deny"
+            tools = ["query"]
+            arguments = { limit = { min = 1001 } }
+
+            [[rules]]
+            effect = "allow"
+            tools = ["store"]
+            arguments = { "data.owner" = { matches = "team-*" }, id = { exists = true } }
+
+            [[rules]]
+            effect = "deny"
+            tools = ["store"]
+            "#,
+        )
+        .unwrap();
+        let user = principal("alice", &[]);
+        let allowed = |name: &str, arguments: Value| {
+            policy.check(Some(&user), &call(name, &arguments)).is_ok()
+        };
+
+        assert!(allowed(
+            "query",
+            json!({"collection": "users", "limit": 10})
+        ));
+        assert!(!allowed("query", json!({"collection": "secrets"})));
+        assert!(!allowed(
+            "query",
+            json!({"collection": "users", "limit": 5000})
+        ));
+        assert!(allowed(
+            "store",
+            json!({"id": "1", "data": {"owner": "team-a"}})
+        ));
+        assert!(!allowed("store", json!({"data": {"owner": "team-a"}})));
+        assert!(!allowed(
+            "store",
+            json!({"id": "1", "data": {"owner": "bob"}})
+        ));
+    }
+
+    #[test]
+    fn test_denied_tools_are_hidden_from_lists() {
+        let mut policy = Policy::from_toml(ADMIN_ONLY_DELETE).unwrap();
+        policy.rules.insert(
+            0,
+            Rule {
+                effect: Effect::Allow,
+                principals: vec!["alice".to_string()],
+                roles: Vec::new(),
+                tools: vec!["delete".to_string()],
+                resources: Vec::new(),
+                arguments: BTreeMap::from([(
+                    "collection".to_string(),
+                    Predicate {
+                        equals: Some(json!("scratch")),
+                        ..Predicate::default()
+                    },
+                )]),
+            },
+        );
+        let tools_list = || {
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": { "tools": [{ "name": "store" }, { "name": "delete" }, { "name": "bulk_import" }] }
+            })
+        };
+        let names = |response: &Value| -> Vec<String> {
+            response["result"]["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|tool| tool["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let mut response = tools_list();
+        policy.filter_response(Some(&principal("bob", &[])), "tools/list", &mut response);
+        assert_eq!(names(&response), vec!["store"]);
+
+        // Alice may delete in one collection, so the tool stays listed for her
+        let mut response = tools_list();
+        policy.filter_response(Some(&principal("alice", &[])), "tools/list", &mut response);
+        assert_eq!(names(&response), vec!["store", "delete"]);
+
+        let params = json!({ "name": "delete", "arguments": { "collection": "users" } });
+        assert!(
+            policy
+                .check_request(Some(&principal("alice", &[])), "tools/call", &params)
+                .is_err()
+        );
+        assert!(policy.check_request(None, "tools/list", &json!({})).is_ok());
+    }
+
+    #[test]
+    fn test_globs() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match(
+            "db:///users/*",
+            "db:///users/1/attachments/a.png"
+        ));
+        assert!(glob_match("bulk_?", "bulk_1"));
+        assert!(glob_match("*_delete*", "soft_delete_all"));
+        assert!(!glob_match("bulk_?", "bulk_10"));
+        assert!(!glob_match("delete", "delete_all"));
+        assert!(!glob_match("a*b", "acd"));
+    }
+
+    #[test]
+    fn test_policy_file_is_reloaded_when_changed() {
+        let file =
+            std::env::temp_dir().join(format!("access-control-policy-{}.toml", std::process::id()));
+        std::fs::write(&file, "default = \"allow\"").unwrap();
+        let engine = PolicyEngine::new(&PolicyConfig {
+            file: Some(file.clone()),
+            hot_reload: true,
+            ..PolicyConfig::default()
+        })
+        .unwrap();
+        let action = call("store", &Value::Null);
+        assert!(engine.current().check(None, &action).is_ok());
+        assert_eq!(engine.reload_if_changed(), Ok(false));
+
+        // Make sure the modification time moves even on coarse clocks
+        std::fs::write(&file, "default = \"deny\"").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(2);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(engine.reload_if_changed(), Ok(true));
+        assert!(engine.current().check(None, &action).is_err());
+
+        // A broken file keeps the last good policy
+        std::fs::write(&file, "default = ").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later + Duration::from_secs(2))
+            .unwrap();
+        assert!(engine.reload_if_changed().is_err());
+        assert!(engine.current().check(None, &action).is_err());
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+//! The `whoami` tool, reporting the principal a request was authenticated as.
+
+use async_trait::async_trait;
+use prism_mcp_rs::{
+    core::{error::McpResult, tool::ToolHandler},
+    protocol::types::{Content, ToolResult},
+};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::auth;
+
+/// Reports who the request was authenticated as
+pub struct WhoAmIHandler;
+
+#[async_trait]
+impl ToolHandler for WhoAmIHandler {
+    async fn call(&self, _arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let principal = auth::current_principal();
+        let text = match &principal {
+            Some(principal) => format!(
+                "Authenticated as '{}' with roles [{}]",
+                principal.id,
+                principal.roles.join(", ")
+            ),
+            None => "Anonymous: authentication is disabled".to_string(),
+        };
+        Ok(ToolResult {
+            content: vec![Content::text(text)],
+            is_error: None,
+            structured_content: Some(json!({ "principal": principal })),
+            meta: None,
+        })
+    }
+}
@@ -0,0 +1,37 @@
+//! The `[policy]` applied to what a request reaches without naming it.
+//!
+//! The transports check the tool a request calls and the resource it reads.
+//! A `transaction` also runs the operations of other tools, prompts embed
+//! records and collections, and completions suggest record ids and collection
+//! names, so their handlers check those here for the caller in scope. Refusals
+//! are `McpError::Authentication` errors, answered with the same `-32003`
+//! code as the transports' own.
+
+use serde_json::Value;
+
+use access_control::auth;
+use access_control::policy::{Action, PolicyEngine};
+use prism_mcp_rs::core::error::{McpError, McpResult};
+
+/// Refuse a tool call the caller may not make, such as an operation of a transaction
+pub fn check_tool(policy: &PolicyEngine, name: &str, arguments: &Value) -> McpResult<()> {
+    check(policy, &Action::CallTool { name, arguments })
+}
+
+/// Refuse a resource the caller may not read, such as one a prompt embeds
+pub fn check_resource(policy: &PolicyEngine, uri: &str) -> McpResult<()> {
+    check(policy, &Action::ReadResource { uri })
+}
+
+/// Whether the caller may read the resource at `uri`
+pub fn allows_resource(policy: &PolicyEngine, uri: &str) -> bool {
+    check_resource(policy, uri).is_ok()
+}
+
+fn check(policy: &PolicyEngine, action: &Action) -> McpResult<()> {
+    let principal = auth::current_principal();
+    policy
+        .current()
+        .check(principal.as_ref(), action)
+        .map_err(|denied| McpError::Authentication(denied.to_string()))
+}
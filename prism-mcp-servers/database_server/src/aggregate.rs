@@ -0,0 +1,369 @@
+//! Group-by aggregation over record data.
+//!
+//! Records are grouped by the values at one or more JSON Pointers and each
+//! group is summarized by the requested metrics. Numeric metrics ignore
+//! values that are missing or not numbers; `min` and `max` also accept
+//! strings, which sort after numbers.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::filter::RecordFilter;
+use crate::store::{self, Database, DatabaseRecord};
+
+/// Maximum number of groups returned
+pub const MAX_GROUPS: usize = 1000;
+/// Maximum number of distinct values reported per metric
+pub const MAX_DISTINCT: usize = 100;
+
+/// Aggregate function applied to a field of each group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Distinct,
+}
+
+/// A requested metric
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metric {
+    pub op: Op,
+    /// JSON Pointer into `data`; `count` without a field counts records
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Name of the metric in the output (default: e.g. `avg(/age)`)
+    #[serde(default, rename = "as")]
+    pub name: Option<String>,
+}
+
+impl Metric {
+    fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        match &self.field {
+            Some(field) => format!("{}({field})", self.op_name()),
+            None => self.op_name().to_string(),
+        }
+    }
+
+    fn op_name(&self) -> &'static str {
+        match self.op {
+            Op::Count => "count",
+            Op::Sum => "sum",
+            Op::Avg => "avg",
+            Op::Min => "min",
+            Op::Max => "max",
+            Op::Distinct => "distinct",
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match &self.field {
+            Some(field) if !field.is_empty() && !field.starts_with('/') => Err(format!(
+                "Invalid field '{field}': expected a JSON Pointer such as '/{field}'"
+            )),
+            None if self.op != Op::Count => {
+                Err(format!("Metric '{}' requires a 'field'", self.op_name()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Order JSON scalars for `min`/`max`: numbers first, then strings
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .unwrap_or_default()
+            .total_cmp(&b.as_f64().unwrap_or_default()),
+        (Value::Number(_), _) => Ordering::Less,
+        (_, Value::Number(_)) => Ordering::Greater,
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+/// Compute one metric over the records of a group
+fn evaluate(metric: &Metric, records: &[&DatabaseRecord]) -> Value {
+    let values = || {
+        records.iter().filter_map(|record| {
+            let field = metric.field.as_deref()?;
+            record.data.pointer(field).filter(|value| !value.is_null())
+        })
+    };
+    let numbers = || values().filter_map(Value::as_f64);
+
+    match metric.op {
+        Op::Count if metric.field.is_none() => json!(records.len()),
+        Op::Count => json!(values().count()),
+        Op::Sum => json!(numbers().sum::<f64>()),
+        Op::Avg => {
+            let (sum, count) =
+                numbers().fold((0.0, 0usize), |(sum, count), n| (sum + n, count + 1));
+            if count == 0 {
+                Value::Null
+            } else {
+                json!(sum / count as f64)
+            }
+        }
+        Op::Min | Op::Max => {
+            let comparable = values().filter(|value| value.is_number() || value.is_string());
+            let found = if metric.op == Op::Min {
+                comparable.min_by(|a, b| compare(a, b))
+            } else {
+                comparable.max_by(|a, b| compare(a, b))
+            };
+            found.cloned().unwrap_or(Value::Null)
+        }
+        Op::Distinct => {
+            let distinct: BTreeMap<String, &Value> =
+                values().map(|value| (value.to_string(), value)).collect();
+            json!(
+                distinct
+                    .into_values()
+                    .take(MAX_DISTINCT)
+                    .collect::<Vec<_>>()
+            )
+        }
+    }
+}
+
+/// Group `records` by the values at `group_by` and evaluate `metrics` per group
+pub fn aggregate(
+    records: &[&DatabaseRecord],
+    group_by: &[String],
+    metrics: &[Metric],
+) -> Vec<Value> {
+    let mut groups: BTreeMap<String, (Vec<Value>, Vec<&DatabaseRecord>)> = BTreeMap::new();
+    for record in records {
+        let key: Vec<Value> = group_by
+            .iter()
+            .map(|pointer| record.data.pointer(pointer).cloned().unwrap_or(Value::Null))
+            .collect();
+        groups
+            .entry(Value::Array(key.clone()).to_string())
+            .or_insert_with(|| (key, Vec::new()))
+            .1
+            .push(record);
+    }
+
+    groups
+        .into_values()
+        .map(|(key, members)| {
+            let key: Map<String, Value> = group_by.iter().cloned().zip(key).collect();
+            let values: Map<String, Value> = metrics
+                .iter()
+                .map(|metric| (metric.name(), evaluate(metric, &members)))
+                .collect();
+            json!({
+                "key": key,
+                "count": members.len(),
+                "metrics": values
+            })
+        })
+        .collect()
+}
+
+/// JSON Schema of the tool's `structured_content`
+pub fn output_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "matched": {
+                "type": "integer",
+                "description": "Number of records that matched the filter"
+            },
+            "total_groups": {
+                "type": "integer",
+                "description": "Number of groups before truncation to 'limit'"
+            },
+            "groups": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "key": {
+                            "type": "object",
+                            "description": "Value of each group_by pointer for this group (null when missing)",
+                            "additionalProperties": true
+                        },
+                        "count": {
+                            "type": "integer",
+                            "description": "Number of records in the group"
+                        },
+                        "metrics": {
+                            "type": "object",
+                            "description": "Metric results keyed by metric name",
+                            "additionalProperties": true
+                        }
+                    },
+                    "required": ["key", "count", "metrics"]
+                }
+            }
+        },
+        "required": ["matched", "total_groups", "groups"]
+    })
+}
+
+/// Database tool handler for grouped aggregates
+pub struct AggregateHandler {
+    pub db: Database,
+}
+
+#[async_trait]
+impl ToolHandler for AggregateHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let filter = RecordFilter::from_arguments(&arguments).map_err(McpError::Validation)?;
+
+        let group_by: Vec<String> = match arguments.get("group_by") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::String(pointer)) => vec![pointer.clone()],
+            Some(value) => serde_json::from_value(value.clone()).map_err(|_| {
+                McpError::Validation(
+                    "'group_by' must be a JSON Pointer or an array of JSON Pointers".to_string(),
+                )
+            })?,
+        };
+        if let Some(pointer) = group_by
+            .iter()
+            .find(|pointer| !pointer.is_empty() && !pointer.starts_with('/'))
+        {
+            return Err(McpError::Validation(format!(
+                "Invalid group_by '{pointer}': expected a JSON Pointer such as '/{pointer}'"
+            )));
+        }
+
+        let metrics: Vec<Metric> = match arguments.get("metrics") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| McpError::Validation(format!("Invalid 'metrics' parameter: {e}")))?,
+        };
+        for metric in &metrics {
+            metric.validate().map_err(McpError::Validation)?;
+        }
+
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(100)
+            .clamp(1, MAX_GROUPS as u64) as usize;
+
+        let db = self.db.read().await;
+        let records: Vec<_> = store::live_records(&db)
+            .filter(|record| filter.matches(record))
+            .collect();
+        let mut groups = aggregate(&records, &group_by, &metrics);
+        let total_groups = groups.len();
+        groups.truncate(limit);
+
+        let response = json!({
+            "matched": records.len(),
+            "total_groups": total_groups,
+            "groups": groups
+        });
+
+        Ok(ToolResult {
+            content: vec![Content::text(serde_json::to_string_pretty(&response)?)],
+            is_error: None,
+            structured_content: Some(response),
+            meta: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users() -> Vec<DatabaseRecord> {
+        [
+            (
+                "a",
+                json!({"age": 30, "address": {"city": "Paris"}, "name": "Alice"}),
+            ),
+            (
+                "b",
+                json!({"age": 20, "address": {"city": "Paris"}, "name": "Bob"}),
+            ),
+            (
+                "c",
+                json!({"age": 41, "address": {"city": "Lyon"}, "name": "Carol"}),
+            ),
+            ("d", json!({"age": "unknown", "name": "Dan"})),
+        ]
+        .into_iter()
+        .map(|(id, data)| DatabaseRecord::new(id, "users", data))
+        .collect()
+    }
+
+    fn metrics(value: Value) -> Vec<Metric> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_average_age_by_city() {
+        let users = users();
+        let records: Vec<_> = users.iter().collect();
+        let groups = aggregate(
+            &records,
+            &["/address/city".to_string()],
+            &metrics(json!([
+                {"op": "avg", "field": "/age"},
+                {"op": "max", "field": "/name", "as": "last_name"}
+            ])),
+        );
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0]["key"], json!({"/address/city": "Lyon"}));
+        assert_eq!(groups[0]["metrics"]["avg(/age)"], json!(41.0));
+        assert_eq!(groups[1]["key"], json!({"/address/city": "Paris"}));
+        assert_eq!(groups[1]["count"], json!(2));
+        assert_eq!(groups[1]["metrics"]["avg(/age)"], json!(25.0));
+        assert_eq!(groups[1]["metrics"]["last_name"], json!("Bob"));
+        // Dan has no city and a non-numeric age
+        assert_eq!(groups[2]["key"], json!({"/address/city": null}));
+        assert_eq!(groups[2]["metrics"]["avg(/age)"], Value::Null);
+    }
+
+    #[test]
+    fn test_ungrouped_metrics() {
+        let users = users();
+        let records: Vec<_> = users.iter().collect();
+        let groups = aggregate(
+            &records,
+            &[],
+            &metrics(json!([
+                {"op": "count"},
+                {"op": "count", "field": "/address"},
+                {"op": "sum", "field": "/age"},
+                {"op": "min", "field": "/age"},
+                {"op": "distinct", "field": "/address/city"}
+            ])),
+        );
+
+        assert_eq!(groups.len(), 1);
+        let values = &groups[0]["metrics"];
+        assert_eq!(values["count"], json!(4));
+        assert_eq!(values["count(/address)"], json!(3));
+        assert_eq!(values["sum(/age)"], json!(91.0));
+        assert_eq!(values["min(/age)"], json!(20));
+        assert_eq!(values["distinct(/address/city)"], json!(["Lyon", "Paris"]));
+    }
+}
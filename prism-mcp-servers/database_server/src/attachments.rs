@@ -0,0 +1,304 @@
+//! Binary attachments kept next to a record's JSON data.
+//!
+//! Attachments are named per record and served as blob resources at
+//! [`ATTACHMENT_TEMPLATE`](crate::uri::ATTACHMENT_TEMPLATE). Their content is
+//! identified by its SHA-256 hash, which clients can use to check integrity
+//! and which lets the storage backend keep identical content only once.
+//! Attaching or detaching bumps the record's version like any other write.
+
+use async_trait::async_trait;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::store::{self, Database};
+use crate::subscriptions::ChangeNotifier;
+use crate::uri;
+
+/// Default limit on the size of a single attachment, in bytes
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+/// Longest attachment name accepted
+const MAX_NAME_LENGTH: usize = 255;
+/// MIME type of attachments whose type is neither given nor recognized
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// A named blob attached to a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub mime_type: String,
+    /// Lowercase hex SHA-256 of `content`
+    pub sha256: String,
+    pub content: Arc<[u8]>,
+    pub attached_at: DateTime<Utc>,
+}
+
+impl Attachment {
+    /// Hash `content` and stamp it with the current time
+    pub fn new(mime_type: impl Into<String>, content: impl Into<Arc<[u8]>>) -> Self {
+        let content = content.into();
+        Self {
+            mime_type: mime_type.into(),
+            sha256: sha256(&content),
+            content,
+            attached_at: Utc::now(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Metadata listed on the record; the content is read through `uri`
+    pub fn to_json(&self, name: &str, uri: String) -> Value {
+        json!({
+            "name": name,
+            "uri": uri,
+            "mime_type": self.mime_type,
+            "size": self.size(),
+            "sha256": self.sha256,
+            "attached_at": self.attached_at.to_rfc3339()
+        })
+    }
+}
+
+/// Lowercase hex SHA-256 of `content`
+pub fn sha256(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Guess a MIME type from the extension of an attachment name
+pub fn guess_mime_type(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        Some("json") => "application/json",
+        Some("zip") => "application/zip",
+        Some("txt" | "log") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("csv") => "text/csv",
+        Some("html" | "htm") => "text/html",
+        _ => OCTET_STREAM,
+    }
+}
+
+/// Check an attachment name; names become a URI segment and must be unique per record
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "'name' must be between 1 and {MAX_NAME_LENGTH} bytes"
+        ));
+    }
+    if name.contains('/') || name.chars().any(char::is_control) {
+        return Err("'name' must not contain '/' or control characters".to_string());
+    }
+    Ok(())
+}
+
+fn required_str<'a>(arguments: &'a HashMap<String, Value>, name: &str) -> McpResult<&'a str> {
+    arguments
+        .get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| McpError::Validation(format!("Missing '{name}' parameter")))
+}
+
+/// Database tool handler for attaching a blob to a record
+pub struct AttachHandler {
+    pub db: Database,
+    pub notifier: ChangeNotifier,
+    /// Largest attachment accepted, in bytes
+    pub max_bytes: usize,
+}
+
+#[async_trait]
+impl ToolHandler for AttachHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let id = required_str(&arguments, "id")?;
+        let name = required_str(&arguments, "name")?;
+        validate_name(name).map_err(McpError::Validation)?;
+        let encoded = required_str(&arguments, "content")?;
+        let mime_type = match arguments.get("mime_type").and_then(|v| v.as_str()) {
+            Some(mime_type)
+                if mime_type
+                    .split_once('/')
+                    .is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty()) =>
+            {
+                mime_type
+            }
+            Some(mime_type) => {
+                return Err(McpError::Validation(format!(
+                    "'{mime_type}' is not a MIME type"
+                )));
+            }
+            None => guess_mime_type(name),
+        };
+        let expected_version = crate::expected_version(&arguments)?;
+
+        // Base64 encodes 3 bytes in 4 characters; reject oversized input before decoding it
+        let too_large = |size: usize| {
+            McpError::Validation(format!(
+                "Attachment of {size} bytes exceeds the limit of {} bytes",
+                self.max_bytes
+            ))
+        };
+        if encoded.len() / 4 * 3 > self.max_bytes + 2 {
+            return Err(too_large(encoded.len() / 4 * 3));
+        }
+        let content = BASE64
+            .decode(encoded)
+            .map_err(|e| McpError::Validation(format!("'content' is not valid base64: {e}")))?;
+        if content.len() > self.max_bytes {
+            return Err(too_large(content.len()));
+        }
+        let attachment = Attachment::new(mime_type, content);
+
+        let (outcome, changes) = {
+            let mut db = self.db.write().await;
+            let unchanged = store::get_live(&db, id).filter(|record| {
+                expected_version.is_none_or(|version| version == record.version)
+                    && record.attachments.get(name).is_some_and(|existing| {
+                        existing.sha256 == attachment.sha256
+                            && existing.mime_type == attachment.mime_type
+                    })
+            });
+            if let Some(record) = unchanged {
+                return Ok(ToolResult {
+                    content: vec![Content::text(format!(
+                        "Attachment '{name}' of record '{id}' is unchanged"
+                    ))],
+                    is_error: None,
+                    structured_content: Some(attachment_response(record, name, false)),
+                    meta: None,
+                });
+            }
+            match store::attach(&mut db, id, name, attachment, expected_version).and_then(
+                |outcome| {
+                    let changes = db.commit(vec![outcome.change()])?;
+                    Ok((outcome, changes))
+                },
+            ) {
+                Ok(committed) => committed,
+                Err(err) => return Ok(crate::store_error_result(err)),
+            }
+        };
+        self.notifier.publish(&changes).await;
+
+        let attachment = &outcome.record.attachments[name];
+        Ok(ToolResult {
+            content: vec![Content::text(format!(
+                "Attached '{name}' ({} bytes, {}) to record '{id}' (version {})",
+                attachment.size(),
+                attachment.mime_type,
+                outcome.record.version
+            ))],
+            is_error: None,
+            structured_content: Some(attachment_response(&outcome.record, name, true)),
+            meta: None,
+        })
+    }
+}
+
+fn attachment_response(record: &store::DatabaseRecord, name: &str, changed: bool) -> Value {
+    let mut response = record.attachments[name].to_json(
+        name,
+        uri::attachment_uri(&record.collection, &record.id, name),
+    );
+    response["id"] = json!(record.id);
+    response["version"] = json!(record.version);
+    response["changed"] = json!(changed);
+    response
+}
+
+/// Database tool handler for removing an attachment from a record
+pub struct DetachHandler {
+    pub db: Database,
+    pub notifier: ChangeNotifier,
+}
+
+#[async_trait]
+impl ToolHandler for DetachHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let id = required_str(&arguments, "id")?;
+        let name = required_str(&arguments, "name")?;
+        let expected_version = crate::expected_version(&arguments)?;
+
+        let result = {
+            let mut db = self.db.write().await;
+            store::detach(&mut db, id, name, expected_version).and_then(|outcome| {
+                let changes = db.commit(vec![outcome.change()])?;
+                Ok((outcome, changes))
+            })
+        };
+        match result {
+            Ok((outcome, changes)) => {
+                self.notifier.publish(&changes).await;
+                Ok(ToolResult {
+                    content: vec![Content::text(format!(
+                        "Detached '{name}' from record '{id}' (version {})",
+                        outcome.record.version
+                    ))],
+                    is_error: None,
+                    structured_content: Some(json!({
+                        "id": id,
+                        "name": name,
+                        "version": outcome.record.version
+                    })),
+                    meta: None,
+                })
+            }
+            Err(err) => Ok(crate::store_error_result(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_is_hashed() {
+        let attachment = Attachment::new("text/plain", b"hello".to_vec());
+        assert_eq!(attachment.size(), 5);
+        assert_eq!(
+            attachment.sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            attachment.to_json(
+                "greeting.txt",
+                "db://u/records/a/attachments/greeting.txt".into()
+            )["sha256"],
+            attachment.sha256
+        );
+    }
+
+    #[test]
+    fn test_names_and_mime_types() {
+        assert_eq!(guess_mime_type("Screenshot.PNG"), "image/png");
+        assert_eq!(guess_mime_type("report.pdf"), "application/pdf");
+        assert_eq!(guess_mime_type("blob"), OCTET_STREAM);
+
+        validate_name("report.pdf").unwrap();
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name("line\nbreak").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Storage backends that persist the database's records.
+//!
+//! Tools always read and write the in-memory [`Records`]; the configured
+//! backend loads them at startup and durably applies every committed change
+//! before the write lock is released. The tool surface is therefore the same
+//! whichever backend is selected. Backends also keep the collection schemas
+//! set with the `set_schema` tool.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::sqlite::SqliteBackend;
+use crate::store::{DatabaseRecord, Records};
+
+/// File name of the SQLite database when no path is configured
+pub const DEFAULT_SQLITE_FILE: &str = "database.sqlite3";
+
+/// Failure reported by a storage backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendError(pub String);
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+impl From<rusqlite::Error> for BackendError {
+    fn from(err: rusqlite::Error) -> Self {
+        BackendError(err.to_string())
+    }
+}
+
+/// Durable home of the database's records
+pub trait StorageBackend: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Load every stored record
+    fn load(&self) -> Result<Records, BackendError>;
+
+    /// Store `upserts` and remove `deletes`, all or nothing
+    fn apply(&self, upserts: &[&DatabaseRecord], deletes: &[&str]) -> Result<(), BackendError>;
+
+    /// Load the schemas set with `set_schema` by collection; `None` marks a
+    /// collection whose schema was removed
+    fn load_schemas(&self) -> Result<BTreeMap<String, Option<Value>>, BackendError>;
+
+    /// Store the schema set for `collection`, or `None` if it was removed
+    fn save_schema(&self, collection: &str, schema: Option<&Value>) -> Result<(), BackendError>;
+}
+
+/// Keeps records only in memory; they are lost when the server stops
+#[derive(Debug, Default)]
+pub struct MemoryBackend;
+
+impl StorageBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn load(&self) -> Result<Records, BackendError> {
+        Ok(Records::new())
+    }
+
+    fn apply(&self, _upserts: &[&DatabaseRecord], _deletes: &[&str]) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn load_schemas(&self) -> Result<BTreeMap<String, Option<Value>>, BackendError> {
+        Ok(BTreeMap::new())
+    }
+
+    fn save_schema(&self, _collection: &str, _schema: Option<&Value>) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+/// Available storage backends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Memory,
+    Sqlite,
+}
+
+/// Which backend to open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendConfig {
+    Memory,
+    /// An embedded SQLite database file
+    Sqlite(PathBuf),
+}
+
+impl BackendConfig {
+    /// Resolve `kind`; SQLite stores its file at `sqlite_file`, or at
+    /// [`DEFAULT_SQLITE_FILE`] inside `data_dir` (or the working directory)
+    pub fn new(kind: BackendKind, sqlite_file: Option<PathBuf>, data_dir: Option<&Path>) -> Self {
+        match kind {
+            BackendKind::Memory => BackendConfig::Memory,
+            BackendKind::Sqlite => {
+                BackendConfig::Sqlite(sqlite_file.unwrap_or_else(|| {
+                    data_dir.unwrap_or(Path::new(".")).join(DEFAULT_SQLITE_FILE)
+                }))
+            }
+        }
+    }
+
+    /// Open the configured backend
+    pub fn open(&self) -> Result<Box<dyn StorageBackend>, BackendError> {
+        match self {
+            BackendConfig::Memory => Ok(Box::new(MemoryBackend)),
+            BackendConfig::Sqlite(path) => Ok(Box::new(SqliteBackend::open(path)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_file_defaults_to_data_dir() {
+        assert_eq!(
+            BackendConfig::new(BackendKind::Memory, None, None),
+            BackendConfig::Memory
+        );
+        assert_eq!(
+            BackendConfig::new(BackendKind::Sqlite, None, Some(Path::new("/data"))),
+            BackendConfig::Sqlite(PathBuf::from("/data").join(DEFAULT_SQLITE_FILE))
+        );
+        assert_eq!(
+            BackendConfig::new(
+                BackendKind::Sqlite,
+                Some("db.sqlite".into()),
+                Some(Path::new("/data"))
+            ),
+            BackendConfig::Sqlite(PathBuf::from("db.sqlite"))
+        );
+    }
+}
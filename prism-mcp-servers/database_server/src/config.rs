@@ -0,0 +1,534 @@
+//! Command-line options and the TOML configuration file.
+//!
+//! Settings are layered: built-in defaults, then the configuration file
+//! given with `--config`, then command-line flags (several of which can
+//! also be set through the `DATABASE_*` environment variables).
+
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use access_control::auth::AuthConfig;
+use access_control::limits::LimitsConfig;
+use access_control::policy::PolicyConfig;
+use network_transport::origin::SecurityConfig;
+
+use crate::attachments;
+use crate::backend::{BackendConfig, BackendKind};
+use crate::quota::{EvictionPolicy, Quotas};
+use crate::transaction;
+
+/// Every tool the server can expose
+pub const TOOLS: &[&str] = &[
+    "store",
+    "insert",
+    "update",
+    "patch",
+    "transaction",
+    "set_schema",
+    "export",
+    "import",
+    "retrieve",
+    "history",
+    "revert",
+    "search",
+    "aggregate",
+    "list",
+    "delete",
+    "attach",
+    "detach",
+];
+
+/// Tools that modify the database; hidden in read-only mode
+pub const WRITE_TOOLS: &[&str] = &[
+    "store",
+    "insert",
+    "update",
+    "patch",
+    "transaction",
+    "set_schema",
+    "import",
+    "revert",
+    "delete",
+    "attach",
+    "detach",
+];
+
+/// Default limit on the size of an import, in bytes
+pub const DEFAULT_MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
+
+/// Command-line options
+#[derive(Debug, Default, Parser)]
+#[command(name = "database-server", version, about = "MCP database server")]
+pub struct Cli {
+    /// TOML configuration file
+    #[arg(short, long, env = "DATABASE_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Server name reported to clients
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Transport to serve on
+    #[arg(long, value_enum)]
+    pub transport: Option<TransportKind>,
+
+    /// Address to listen on for the http and websocket transports
+    #[arg(long)]
+    pub bind: Option<String>,
+
+    /// Where records are stored
+    #[arg(long, value_enum, env = "DATABASE_BACKEND")]
+    pub backend: Option<BackendKind>,
+
+    /// Directory for import/export files and the SQLite database
+    #[arg(long, env = "DATABASE_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// SQLite database file (default: database.sqlite3 in the data directory)
+    #[arg(long, env = "DATABASE_SQLITE_FILE")]
+    pub sqlite_file: Option<PathBuf>,
+
+    /// JSON file mapping collection names to JSON Schemas
+    #[arg(long, env = "DATABASE_SCHEMA_FILE")]
+    pub schema_file: Option<PathBuf>,
+
+    /// NDJSON, JSON or CSV file imported into an empty database at startup
+    #[arg(long, env = "DATABASE_SEED_FILE")]
+    pub seed_file: Option<PathBuf>,
+
+    /// Do not insert the sample users into an empty database
+    #[arg(long)]
+    pub no_sample_data: bool,
+
+    /// Hide every tool that modifies the database
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// Maximum size of an import, in bytes
+    #[arg(long)]
+    pub max_import_bytes: Option<usize>,
+
+    /// Maximum number of operations in a transaction
+    #[arg(long)]
+    pub max_transaction_operations: Option<usize>,
+
+    /// Maximum size of a single attachment, in bytes
+    #[arg(long)]
+    pub max_attachment_bytes: Option<usize>,
+
+    /// Maximum number of stored records
+    #[arg(long)]
+    pub max_records: Option<usize>,
+
+    /// Maximum size of a single record's data, in bytes
+    #[arg(long)]
+    pub max_record_bytes: Option<usize>,
+
+    /// Maximum total size of all records, in bytes
+    #[arg(long)]
+    pub max_total_bytes: Option<usize>,
+
+    /// What to do when a write would exceed the record or total size quota
+    #[arg(long, value_enum)]
+    pub quota_policy: Option<EvictionPolicy>,
+
+    /// Expose only these tools (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub tools: Option<Vec<String>>,
+
+    /// Never expose these tools (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub disable_tools: Vec<String>,
+
+    /// TOML or JSON authorization policy file
+    #[arg(long, env = "DATABASE_POLICY_FILE")]
+    pub policy_file: Option<PathBuf>,
+}
+
+/// How clients connect to the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Stdio,
+    Http,
+    Websocket,
+}
+
+/// `[transport]` section
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransportConfig {
+    #[serde(rename = "type")]
+    pub kind: TransportKind,
+    /// Listen address; defaults to 127.0.0.1:3000 (http) or 127.0.0.1:8081 (websocket)
+    pub bind: Option<String>,
+}
+
+impl TransportConfig {
+    /// The address to listen on
+    pub fn bind_address(&self) -> &str {
+        match (&self.bind, self.kind) {
+            (Some(bind), _) => bind,
+            (None, TransportKind::Websocket) => "127.0.0.1:8081",
+            (None, _) => "127.0.0.1:3000",
+        }
+    }
+}
+
+/// `[storage]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: BackendKind,
+    pub data_dir: Option<PathBuf>,
+    pub sqlite_file: Option<PathBuf>,
+    pub schema_file: Option<PathBuf>,
+    pub seed_file: Option<PathBuf>,
+    /// Insert the sample users when the database is empty and no seed file is set
+    pub sample_data: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendKind::default(),
+            data_dir: None,
+            sqlite_file: None,
+            schema_file: None,
+            seed_file: None,
+            sample_data: true,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// The storage backend to open
+    pub fn backend(&self) -> BackendConfig {
+        BackendConfig::new(
+            self.backend,
+            self.sqlite_file.clone(),
+            self.data_dir.as_deref(),
+        )
+    }
+}
+
+/// `[limits]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_import_bytes: usize,
+    pub max_transaction_operations: usize,
+    pub max_attachment_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_import_bytes: DEFAULT_MAX_IMPORT_BYTES,
+            max_transaction_operations: transaction::MAX_OPERATIONS,
+            max_attachment_bytes: attachments::DEFAULT_MAX_ATTACHMENT_BYTES,
+        }
+    }
+}
+
+/// `[tools]` section
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolSelection {
+    /// Expose only these tools; all tools when unset
+    pub enabled: Option<Vec<String>>,
+    /// Never expose these tools
+    pub disabled: Vec<String>,
+}
+
+/// Complete server configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Server name reported to clients
+    pub name: String,
+    /// Hide every tool that modifies the database and stop expiring records
+    pub read_only: bool,
+    pub transport: TransportConfig,
+    pub storage: StorageConfig,
+    pub limits: Limits,
+    pub quotas: Quotas,
+    pub tools: ToolSelection,
+    /// API keys and JWT verification on the http and websocket transports; off unless configured
+    pub auth: AuthConfig,
+    /// Which principals may use which tools and resources
+    pub policy: PolicyConfig,
+    /// Request rate and in-flight limits; off unless configured
+    pub rate_limits: LimitsConfig,
+    /// Accepted `Host` and `Origin` headers on the http transport
+    pub security: SecurityConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "database-server".to_string(),
+            read_only: false,
+            transport: TransportConfig::default(),
+            storage: StorageConfig::default(),
+            limits: Limits::default(),
+            quotas: Quotas::default(),
+            tools: ToolSelection::default(),
+            auth: AuthConfig::default(),
+            policy: PolicyConfig::default(),
+            rate_limits: LimitsConfig::default(),
+            security: SecurityConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Parse a TOML configuration
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Read the TOML configuration file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        Self::from_toml(&source).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Build the configuration from the command line and the file it names
+    pub fn load(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Override settings with those given on the command line
+    fn apply(&mut self, cli: Cli) {
+        let storage = &mut self.storage;
+        if let Some(name) = cli.name {
+            self.name = name;
+        }
+        if let Some(kind) = cli.transport {
+            self.transport.kind = kind;
+        }
+        if cli.bind.is_some() {
+            self.transport.bind = cli.bind;
+        }
+        if let Some(backend) = cli.backend {
+            storage.backend = backend;
+        }
+        storage.data_dir = cli.data_dir.or(storage.data_dir.take());
+        storage.sqlite_file = cli.sqlite_file.or(storage.sqlite_file.take());
+        storage.schema_file = cli.schema_file.or(storage.schema_file.take());
+        storage.seed_file = cli.seed_file.or(storage.seed_file.take());
+        storage.sample_data &= !cli.no_sample_data;
+        self.read_only |= cli.read_only;
+        if let Some(max) = cli.max_import_bytes {
+            self.limits.max_import_bytes = max;
+        }
+        if let Some(max) = cli.max_transaction_operations {
+            self.limits.max_transaction_operations = max;
+        }
+        if let Some(max) = cli.max_attachment_bytes {
+            self.limits.max_attachment_bytes = max;
+        }
+        let quotas = &mut self.quotas;
+        quotas.max_records = cli.max_records.or(quotas.max_records);
+        quotas.max_record_bytes = cli.max_record_bytes.or(quotas.max_record_bytes);
+        quotas.max_total_bytes = cli.max_total_bytes.or(quotas.max_total_bytes);
+        if let Some(policy) = cli.quota_policy {
+            quotas.policy = policy;
+        }
+        if cli.tools.is_some() {
+            self.tools.enabled = cli.tools;
+        }
+        self.tools.disabled.extend(cli.disable_tools);
+        if cli.policy_file.is_some() {
+            self.policy.file = cli.policy_file;
+        }
+    }
+
+    /// Reject settings that cannot work together
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(bind) = &self.transport.bind {
+            if self.transport.kind == TransportKind::Stdio {
+                return Err("'bind' requires the http or websocket transport".to_string());
+            }
+            bind.parse::<SocketAddr>()
+                .map_err(|e| format!("Invalid bind address '{bind}': {e}"))?;
+        }
+        // Only the network transports authenticate callers and check the policy
+        let access_controlled = self.auth != AuthConfig::default()
+            || self.policy != PolicyConfig::default()
+            || self.rate_limits != LimitsConfig::default()
+            || self.security != SecurityConfig::default();
+        if access_controlled && self.transport.kind == TransportKind::Stdio {
+            return Err(
+                "'auth', 'policy', 'rate_limits' and 'security' require the http or websocket transport"
+                    .to_string(),
+            );
+        }
+        self.rate_limits.validate()?;
+
+        let named = self
+            .tools
+            .enabled
+            .iter()
+            .flatten()
+            .chain(&self.tools.disabled);
+        for tool in named {
+            if !TOOLS.contains(&tool.as_str()) {
+                return Err(format!(
+                    "Unknown tool '{tool}' (available: {})",
+                    TOOLS.join(", ")
+                ));
+            }
+        }
+
+        if self.limits.max_transaction_operations == 0 {
+            return Err("'max_transaction_operations' must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the tool called `name` is exposed
+    pub fn exposes(&self, name: &str) -> bool {
+        !(self.read_only && WRITE_TOOLS.contains(&name))
+            && self
+                .tools
+                .enabled
+                .as_ref()
+                .is_none_or(|enabled| enabled.iter().any(|tool| tool == name))
+            && !self.tools.disabled.iter().any(|tool| tool == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_with_cli_overrides() {
+        let mut config = Config::from_toml(
+            r#"
+            name = "team-db"
+
+            [transport]
+            type = "http"
+            bind = "0.0.0.0:4000"
+
+            [storage]
+            backend = "sqlite"
+            data_dir = "/var/lib/db"
+            sample_data = false
+
+            [quotas]
+            max_records = 10000
+            policy = "lru"
+
+            [tools]
+            disabled = ["delete"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.transport.kind, TransportKind::Http);
+        assert_eq!(config.storage.backend, BackendKind::Sqlite);
+        assert_eq!(config.limits.max_import_bytes, DEFAULT_MAX_IMPORT_BYTES);
+
+        let cli = Cli::try_parse_from([
+            "database-server",
+            "--transport",
+            "websocket",
+            "--bind",
+            "127.0.0.1:9000",
+            "--read-only",
+            "--disable-tools",
+            "search,aggregate",
+            "--max-total-bytes",
+            "1048576",
+        ])
+        .unwrap();
+        config.apply(cli);
+        config.validate().unwrap();
+
+        assert_eq!(config.name, "team-db");
+        assert_eq!(config.transport.bind_address(), "127.0.0.1:9000");
+        assert_eq!(
+            config.storage.data_dir.as_deref(),
+            Some(Path::new("/var/lib/db"))
+        );
+        assert!(!config.storage.sample_data);
+        assert_eq!(
+            config.quotas,
+            Quotas {
+                max_records: Some(10000),
+                max_record_bytes: None,
+                max_total_bytes: Some(1048576),
+                policy: EvictionPolicy::Lru,
+            }
+        );
+        assert!(config.exposes("retrieve"));
+        assert!(!config.exposes("store"));
+        assert!(!config.exposes("delete"));
+        assert!(!config.exposes("search"));
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(Config::from_toml("unknown = true").is_err());
+        assert!(Config::from_toml("[transport]\ntype = \"carrier-pigeon\"").is_err());
+
+        let config = Config::from_toml("[transport]\nbind = \"127.0.0.1:3000\"").unwrap();
+        assert!(config.validate().is_err());
+
+        let config = Config::from_toml("[tools]\nenabled = [\"drop_table\"]").unwrap();
+        assert!(config.validate().is_err());
+
+        let config = Config::from_toml("[tools]\nenabled = [\"retrieve\", \"list\"]").unwrap();
+        config.validate().unwrap();
+        assert!(config.exposes("list"));
+        assert!(!config.exposes("search"));
+
+        let config = Config::from_toml("[policy]\nfile = \"policy.toml\"").unwrap();
+        assert!(config.validate().is_err());
+        let config =
+            Config::from_toml("[transport]\ntype = \"http\"\n[rate_limits.client]\nburst = 10")
+                .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_access_control_sections() {
+        let mut config = Config::from_toml(
+            r#"
+            [transport]
+            type = "websocket"
+
+            [[auth.api_keys]]
+            principal = "ops"
+            key = "secret"
+            roles = ["admin"]
+
+            [rate_limits.client]
+            requests_per_second = 5
+            burst = 10
+            "#,
+        )
+        .unwrap();
+        // The import and transaction limits keep their own section
+        assert_eq!(config.limits.max_import_bytes, DEFAULT_MAX_IMPORT_BYTES);
+        assert_eq!(config.auth.api_keys[0].roles, vec!["admin"]);
+
+        let cli = Cli::try_parse_from(["database-server", "--policy-file", "policy.json"]).unwrap();
+        config.apply(cli);
+        config.validate().unwrap();
+        assert_eq!(config.policy.file, Some(PathBuf::from("policy.json")));
+    }
+}
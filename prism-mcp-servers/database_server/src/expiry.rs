@@ -0,0 +1,217 @@
+//! Time-to-live support for records.
+//!
+//! Expired records are hidden from every read as soon as their expiry passes;
+//! the sweeper removes them in the background and notifies subscribers as if
+//! they had been deleted. It sleeps until the next record is due to expire
+//! (or until a write schedules an earlier expiry) and only then locks the
+//! records for writing.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+use crate::store::{self, Change, Database};
+use crate::subscriptions::ChangeNotifier;
+
+/// Expiry requested by a write, as either a relative or an absolute time
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExpiryArgs {
+    /// Seconds from now until the record expires
+    pub ttl_seconds: Option<u64>,
+    /// Absolute expiry time (RFC 3339)
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ExpiryArgs {
+    /// Read `ttl_seconds` and `expires_at` from tool arguments
+    pub fn from_arguments(arguments: &HashMap<String, Value>) -> Result<Self, String> {
+        let ttl_seconds = match arguments.get("ttl_seconds") {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(
+                value
+                    .as_u64()
+                    .ok_or("'ttl_seconds' must be a positive integer")?,
+            ),
+        };
+        let expires_at = match arguments.get("expires_at") {
+            None | Some(Value::Null) => None,
+            Some(value) => {
+                let value = value
+                    .as_str()
+                    .ok_or("'expires_at' must be an RFC 3339 date-time string")?;
+                let expires_at = DateTime::parse_from_rfc3339(value)
+                    .map_err(|e| format!("Invalid 'expires_at': {e}"))?;
+                Some(expires_at.with_timezone(&Utc))
+            }
+        };
+        Ok(Self {
+            ttl_seconds,
+            expires_at,
+        })
+    }
+
+    /// The absolute expiry time, if one was requested
+    pub fn resolve(&self, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        match (self.ttl_seconds, self.expires_at) {
+            (Some(_), Some(_)) => {
+                Err("Specify either 'ttl_seconds' or 'expires_at', not both".into())
+            }
+            (Some(0), None) => Err("'ttl_seconds' must be a positive integer".into()),
+            (Some(ttl), None) => i64::try_from(ttl)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .and_then(|ttl| now.checked_add_signed(ttl))
+                .map(Some)
+                .ok_or_else(|| "'ttl_seconds' is too large".to_string()),
+            (None, Some(expires_at)) if expires_at <= now => {
+                Err("'expires_at' must be in the future".into())
+            }
+            (None, expires_at) => Ok(expires_at),
+        }
+    }
+}
+
+/// Remove records as they expire and publish their deletion
+pub fn spawn_sweeper(db: Database, notifier: ChangeNotifier) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let next_expiry = db.read().await.next_expiry();
+            let wait = next_expiry
+                .map(|expires_at| (expires_at - Utc::now()).to_std().unwrap_or_default());
+            tokio::select! {
+                _ = tokio::time::sleep(wait.unwrap_or(std::time::Duration::MAX)) => {}
+                _ = db.expiry_scheduled() => continue,
+            }
+            let due = db.read().await.next_expiry();
+            if due.is_none_or(|expires_at| expires_at > Utc::now()) {
+                continue;
+            }
+
+            let changes: Vec<Change> = {
+                let mut db = db.write().await;
+                let changes: Vec<Change> = store::sweep_expired(&mut db, Utc::now())
+                    .iter()
+                    .filter_map(|record| Change::between(Some(record), None))
+                    .collect();
+                match db.commit(changes) {
+                    Ok(changes) => changes,
+                    Err(err) => {
+                        tracing::warn!("Failed to persist expired record removal: {err}");
+                        continue;
+                    }
+                }
+            };
+            if changes.is_empty() {
+                continue;
+            }
+
+            tracing::debug!("Removed {} expired record(s)", changes.len());
+            notifier.publish(&changes).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{DatabaseRecord, Records};
+
+    #[test]
+    fn test_resolve_expiry() {
+        let now = Utc::now();
+        let ttl = ExpiryArgs {
+            ttl_seconds: Some(60),
+            expires_at: None,
+        };
+        assert_eq!(
+            ttl.resolve(now).unwrap(),
+            Some(now + chrono::Duration::seconds(60))
+        );
+        assert_eq!(ExpiryArgs::default().resolve(now).unwrap(), None);
+
+        let past = ExpiryArgs {
+            ttl_seconds: None,
+            expires_at: Some(now - chrono::Duration::seconds(1)),
+        };
+        assert!(past.resolve(now).is_err());
+
+        let both = ExpiryArgs {
+            ttl_seconds: Some(60),
+            expires_at: Some(now + chrono::Duration::seconds(60)),
+        };
+        assert!(both.resolve(now).is_err());
+    }
+
+    #[test]
+    fn test_expired_records_are_hidden_and_swept() {
+        let now = Utc::now();
+        let mut records = Records::new();
+        for (id, expires_at) in [
+            ("expired", Some(now - chrono::Duration::seconds(1))),
+            ("live", Some(now + chrono::Duration::seconds(60))),
+            ("forever", None),
+        ] {
+            records.insert(
+                id.to_string(),
+                DatabaseRecord {
+                    expires_at,
+                    ..DatabaseRecord::new(id, "scratch", Value::Null)
+                },
+            );
+        }
+
+        assert!(store::get_live(&records, "expired").is_none());
+        assert!(store::get_live(&records, "live").is_some());
+        assert_eq!(store::live_records(&records).count(), 2);
+
+        let swept = store::sweep_expired(&mut records, now);
+        assert_eq!(swept.len(), 1);
+        assert_eq!(swept[0].id, "expired");
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records.next_expiry(),
+            Some(now + chrono::Duration::seconds(60))
+        );
+
+        records.remove("live");
+        assert_eq!(records.next_expiry(), None);
+    }
+
+    #[tokio::test]
+    async fn test_sweeper_wakes_for_new_expiries() {
+        use crate::backend::MemoryBackend;
+        use crate::quota::Quotas;
+        use crate::store::RecordStore;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let db = Arc::new(RecordStore::open(Box::new(MemoryBackend), Quotas::default()).unwrap());
+        let (notifier, _) = ChangeNotifier::new();
+        let sweeper = spawn_sweeper(db.clone(), notifier);
+        // Let the sweeper go to sleep with nothing to expire
+        tokio::task::yield_now().await;
+
+        let record = DatabaseRecord {
+            expires_at: Some(Utc::now() + chrono::Duration::milliseconds(50)),
+            ..DatabaseRecord::new("a", "scratch", Value::Null)
+        };
+        {
+            let mut records = db.write().await;
+            records.insert("a".to_string(), record.clone());
+            let created = Change::between(None, Some(&record)).unwrap();
+            records.commit(vec![created]).unwrap();
+        }
+
+        for _ in 0..100 {
+            if db.read().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(db.read().await.is_empty());
+        assert_eq!(db.read().await.next_expiry(), None);
+        sweeper.abort();
+    }
+}
@@ -0,0 +1,88 @@
+//! Record filters shared by tools that operate on a subset of records.
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::store::DatabaseRecord;
+
+/// Selects records by collection and by exact values inside their data
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordFilter {
+    pub collection: Option<String>,
+    /// JSON Pointers into `data` and the value each must equal
+    pub fields: Vec<(String, Value)>,
+}
+
+impl RecordFilter {
+    /// Read the `collection` and `filter` arguments.
+    ///
+    /// `filter` is an object mapping JSON Pointers (e.g. `/address/city`) to
+    /// the value the record's data must hold at that location.
+    pub fn from_arguments(arguments: &HashMap<String, Value>) -> Result<Self, String> {
+        let collection = match arguments.get("collection") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(collection)) => Some(collection.clone()),
+            Some(_) => return Err("'collection' must be a string".to_string()),
+        };
+
+        let fields = match arguments.get("filter") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Object(fields)) => fields
+                .iter()
+                .map(|(pointer, value)| {
+                    if pointer.is_empty() || pointer.starts_with('/') {
+                        Ok((pointer.clone(), value.clone()))
+                    } else {
+                        Err(format!(
+                            "Invalid filter key '{pointer}': expected a JSON Pointer such as '/{pointer}'"
+                        ))
+                    }
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => {
+                return Err("'filter' must be an object of JSON Pointer to value".to_string());
+            }
+        };
+
+        Ok(Self { collection, fields })
+    }
+
+    /// Whether `record` is selected by the filter
+    pub fn matches(&self, record: &DatabaseRecord) -> bool {
+        self.collection
+            .as_deref()
+            .is_none_or(|collection| record.collection == collection)
+            && self
+                .fields
+                .iter()
+                .all(|(pointer, value)| record.data.pointer(pointer) == Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_matches_collection_and_fields() {
+        let arguments: HashMap<String, Value> = serde_json::from_value(json!({
+            "collection": "users",
+            "filter": {"/address/city": "Paris"}
+        }))
+        .unwrap();
+        let filter = RecordFilter::from_arguments(&arguments).unwrap();
+
+        let paris = json!({"address": {"city": "Paris"}});
+        assert!(filter.matches(&DatabaseRecord::new("a", "users", paris.clone())));
+        assert!(!filter.matches(&DatabaseRecord::new("b", "admins", paris)));
+        assert!(!filter.matches(&DatabaseRecord::new("c", "users", json!({}))));
+    }
+
+    #[test]
+    fn test_filter_keys_must_be_pointers() {
+        let arguments: HashMap<String, Value> =
+            serde_json::from_value(json!({"filter": {"name": "Alice"}})).unwrap();
+        assert!(RecordFilter::from_arguments(&arguments).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+//! Bounded revision history and point-in-time reads.
+//!
+//! Each record carries the revisions it replaced, up to [`MAX_REVISIONS`].
+//! History lives and dies with the record: deleting a record (or letting it
+//! expire) discards its history.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::schema::Schemas;
+use crate::store::{self, Database, DatabaseRecord, WriteMode, WriteOptions};
+use crate::subscriptions::ChangeNotifier;
+
+/// Number of past revisions retained per record
+pub const MAX_REVISIONS: usize = 20;
+
+/// A past state of a record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revision {
+    pub version: u64,
+    pub collection: String,
+    pub data: Value,
+    /// When this revision was written
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Revision {
+    /// Snapshot the current state of `record`
+    pub fn of(record: &DatabaseRecord) -> Self {
+        Self {
+            version: record.version,
+            collection: record.collection.clone(),
+            data: record.data.clone(),
+            updated_at: record.updated_at,
+        }
+    }
+
+    /// JSON representation of the record as it was at this revision
+    pub fn to_json(&self, record: &DatabaseRecord) -> Value {
+        json!({
+            "id": record.id,
+            "collection": self.collection,
+            "data": self.data,
+            "version": self.version,
+            "created_at": record.created_at.to_rfc3339(),
+            "updated_at": self.updated_at.to_rfc3339(),
+            "current_version": record.version
+        })
+    }
+}
+
+/// The history to keep when `previous` is replaced by a newer version
+pub fn extend(previous: &DatabaseRecord) -> Vec<Revision> {
+    let mut history = previous.history.clone();
+    history.push(Revision::of(previous));
+    if history.len() > MAX_REVISIONS {
+        history.drain(..history.len() - MAX_REVISIONS);
+    }
+    history
+}
+
+/// Every retained revision of `record`, oldest first, ending with the current one
+pub fn revisions(record: &DatabaseRecord) -> impl Iterator<Item = Revision> + '_ {
+    record
+        .history
+        .iter()
+        .cloned()
+        .chain(std::iter::once(Revision::of(record)))
+}
+
+/// Which revision a point-in-time read asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selector {
+    Version(u64),
+    AsOf(DateTime<Utc>),
+}
+
+impl Selector {
+    /// Read the optional `version` and `as_of` arguments
+    pub fn from_arguments(arguments: &HashMap<String, Value>) -> Result<Option<Self>, String> {
+        let version = match arguments.get("version") {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(
+                value
+                    .as_u64()
+                    .ok_or("'version' must be a positive integer")?,
+            ),
+        };
+        let as_of = match arguments.get("as_of") {
+            None | Some(Value::Null) => None,
+            Some(value) => {
+                let value = value
+                    .as_str()
+                    .ok_or("'as_of' must be an RFC 3339 date-time string")?;
+                let as_of = DateTime::parse_from_rfc3339(value)
+                    .map_err(|e| format!("Invalid 'as_of': {e}"))?;
+                Some(as_of.with_timezone(&Utc))
+            }
+        };
+
+        match (version, as_of) {
+            (Some(_), Some(_)) => Err("Specify either 'version' or 'as_of', not both".to_string()),
+            (Some(version), None) => Ok(Some(Selector::Version(version))),
+            (None, Some(as_of)) => Ok(Some(Selector::AsOf(as_of))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Find the selected revision of `record`
+    pub fn find(&self, record: &DatabaseRecord) -> Result<Revision, String> {
+        let found = match self {
+            Selector::Version(version) => revisions(record).find(|r| r.version == *version),
+            Selector::AsOf(as_of) => revisions(record)
+                .take_while(|revision| revision.updated_at <= *as_of)
+                .last(),
+        };
+        found.ok_or_else(|| {
+            let oldest = record.history.first().map_or(record.version, |r| r.version);
+            match self {
+                Selector::Version(version) if *version > record.version => format!(
+                    "Record '{}' has no version {version} (current version is {})",
+                    record.id, record.version
+                ),
+                Selector::Version(version) => format!(
+                    "Version {version} of record '{}' is no longer retained (oldest retained version is {oldest})",
+                    record.id
+                ),
+                Selector::AsOf(as_of) => format!(
+                    "No retained version of record '{}' existed at {} (oldest retained version is {oldest})",
+                    record.id,
+                    as_of.to_rfc3339()
+                ),
+            }
+        })
+    }
+}
+
+fn not_found(message: String) -> ToolResult {
+    ToolResult {
+        content: vec![Content::text(message)],
+        is_error: Some(true),
+        structured_content: None,
+        meta: None,
+    }
+}
+
+/// Database tool handler listing a record's retained revisions
+pub struct HistoryHandler {
+    pub db: Database,
+}
+
+#[async_trait]
+impl ToolHandler for HistoryHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let id = arguments
+            .get("id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| McpError::Validation("Missing 'id' parameter".to_string()))?;
+
+        let db = self.db.read().await;
+        let Some(record) = store::get_live(&db, id) else {
+            return Ok(not_found(format!("No record found with ID: {id}")));
+        };
+
+        let revisions: Vec<Revision> = revisions(record).collect();
+        let mut entries: Vec<Value> = revisions
+            .iter()
+            .enumerate()
+            .map(|(index, revision)| {
+                let diff = match index {
+                    0 => Value::Null,
+                    _ => serde_json::to_value(json_patch::diff(
+                        &revisions[index - 1].data,
+                        &revision.data,
+                    ))
+                    .unwrap_or(Value::Null),
+                };
+                json!({
+                    "version": revision.version,
+                    "collection": revision.collection,
+                    "updated_at": revision.updated_at.to_rfc3339(),
+                    "current": revision.version == record.version,
+                    "diff": diff
+                })
+            })
+            .collect();
+        entries.reverse();
+
+        let response = json!({
+            "id": id,
+            "current_version": record.version,
+            "oldest_retained_version": revisions[0].version,
+            "versions": entries
+        });
+
+        Ok(ToolResult {
+            content: vec![Content::text(serde_json::to_string_pretty(&response)?)],
+            is_error: None,
+            structured_content: Some(response),
+            meta: None,
+        })
+    }
+}
+
+/// Database tool handler restoring the data of an earlier revision
+pub struct RevertHandler {
+    pub db: Database,
+    pub schemas: Schemas,
+    pub notifier: ChangeNotifier,
+}
+
+#[async_trait]
+impl ToolHandler for RevertHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let id = arguments
+            .get("id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| McpError::Validation("Missing 'id' parameter".to_string()))?;
+
+        let selector = Selector::from_arguments(&arguments)
+            .map_err(McpError::Validation)?
+            .ok_or_else(|| {
+                McpError::Validation("Specify the 'version' or 'as_of' to revert to".to_string())
+            })?;
+
+        let expected_version = crate::expected_version(&arguments)?;
+
+        let (outcome, changes, reverted_to) = {
+            let schemas = self.schemas.read().await;
+            let mut db = self.db.write().await;
+            let Some(record) = store::get_live(&db, id) else {
+                return Ok(not_found(format!("No record found with ID: {id}")));
+            };
+            let revision = match selector.find(record) {
+                Ok(revision) => revision,
+                Err(message) => return Ok(not_found(message)),
+            };
+
+            let options = WriteOptions {
+                collection: Some(&revision.collection),
+                mode: WriteMode::Update,
+                expected_version: Some(expected_version.unwrap_or(record.version)),
+                expires_at: None,
+            };
+            match store::write_record(&mut db, &schemas, id, revision.data.clone(), options)
+                .and_then(|outcome| {
+                    let changes = db.commit(vec![outcome.change()])?;
+                    Ok((outcome, changes))
+                }) {
+                Ok((outcome, changes)) => (outcome, changes, revision.version),
+                Err(err) => return Ok(crate::store_error_result(err)),
+            }
+        };
+        self.notifier.publish(&changes).await;
+
+        let version = outcome.record.version;
+        Ok(ToolResult {
+            content: vec![Content::text(format!(
+                "Reverted record '{id}' to the data of version {reverted_to} (now version {version})"
+            ))],
+            is_error: None,
+            structured_content: Some(json!({
+                "id": id,
+                "version": version,
+                "reverted_to": reverted_to
+            })),
+            meta: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SchemaRegistry;
+    use crate::store::Records;
+
+    fn write(records: &mut Records, data: Value) -> DatabaseRecord {
+        store::write_record(
+            records,
+            &SchemaRegistry::default(),
+            "a",
+            data,
+            WriteOptions::default(),
+        )
+        .unwrap()
+        .record
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut records = Records::new();
+        for value in 0..(MAX_REVISIONS as u64 + 5) {
+            write(&mut records, json!(value));
+        }
+
+        let record = &records["a"];
+        assert_eq!(record.history.len(), MAX_REVISIONS);
+        assert_eq!(record.history[0].version, 5);
+        assert!(Selector::Version(4).find(record).is_err());
+        assert_eq!(Selector::Version(6).find(record).unwrap().data, json!(5));
+        assert_eq!(
+            Selector::Version(record.version).find(record).unwrap().data,
+            record.data
+        );
+    }
+
+    #[test]
+    fn test_as_of_selects_revision_current_at_that_time() {
+        let mut records = Records::new();
+        let first = write(&mut records, json!("first"));
+        let second = write(&mut records, json!("second"));
+        let record = &records["a"];
+
+        let at = |time| Selector::AsOf(time).find(record).map(|r| r.data);
+        assert_eq!(at(first.updated_at).unwrap(), json!("first"));
+        assert_eq!(at(second.updated_at).unwrap(), json!("second"));
+        assert!(at(first.created_at - chrono::Duration::seconds(1)).is_err());
+    }
+}
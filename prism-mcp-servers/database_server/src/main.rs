@@ -4,38 +4,94 @@
 // ! including storage, retrieval, and query capabilities using an in-memory store.
 
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use clap::Parser;
 use serde_json::{Value, json};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
+use access_control::policy::PolicyEngine;
 use prism_mcp_rs::{
     core::{
         error::{McpError, McpResult},
-        resource::ResourceHandler,
-        tool::ToolHandler,
+        tool::{ToolBuilder, ToolHandler},
+    },
+    protocol::types::{
+        CompletionsCapability, Content, PromptsCapability, Resource as ResourceInfo,
+        ResourcesCapability, ServerCapabilities, ToolResult, ToolsCapability,
     },
-    protocol::types::{Content, Resource as ResourceInfo, ResourceContents, ToolResult},
     server::McpServer,
-    transport::stdio::StdioServerTransport,
 };
 
-/// In-memory database store
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct DatabaseRecord {
-    id: String,
-    data: Value,
-    created_at: chrono::DateTime<chrono::Utc>,
-    updated_at: chrono::DateTime<chrono::Utc>,
+mod access;
+mod aggregate;
+mod attachments;
+mod backend;
+mod config;
+mod expiry;
+mod filter;
+mod history;
+mod pagination;
+mod patch;
+mod prompts;
+mod quota;
+mod resources;
+mod schema;
+mod search;
+mod sqlite;
+mod store;
+mod subscriptions;
+mod transaction;
+mod transfer;
+mod transport;
+mod uri;
+
+use aggregate::AggregateHandler;
+use attachments::{AttachHandler, DetachHandler};
+use config::{Cli, Config};
+use expiry::ExpiryArgs;
+use history::{HistoryHandler, RevertHandler, Selector};
+use pagination::Cursor;
+use patch::PatchFormat;
+use prompts::{DatabasePromptHandler, Prompt};
+use resources::{DatabaseCompletionHandler, DatabaseResourceHandler};
+use schema::{SchemaRegistry, Schemas, SetSchemaHandler};
+use search::SearchHandler;
+use store::{Database, DatabaseRecord, RecordStore, StoreError, WriteMode, WriteOptions};
+use subscriptions::ChangeNotifier;
+use transaction::TransactionHandler;
+use transfer::{DataDir, ExportHandler, Exports, Format, ImportHandler, ImportMode};
+
+/// Build the error result returned when a write is refused
+fn store_error_result(err: StoreError) -> ToolResult {
+    ToolResult {
+        content: vec![Content::text(err.to_string())],
+        is_error: Some(true),
+        structured_content: Some(err.to_json()),
+        meta: None,
+    }
 }
 
-/// Shared database state
-type Database = Arc<RwLock<HashMap<String, DatabaseRecord>>>;
+/// Read the optional `expected_version` argument
+fn expected_version(arguments: &HashMap<String, Value>) -> McpResult<Option<u64>> {
+    match arguments.get("expected_version") {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| {
+            McpError::Validation("'expected_version' must be a non-negative integer".to_string())
+        }),
+    }
+}
 
 /// Database tool handler for storing records
+///
+/// The same handler backs the `store`, `insert` and `update` tools; `mode`
+/// decides how an existing or missing record is treated.
 struct StoreHandler {
     db: Database,
+    schemas: Schemas,
+    notifier: ChangeNotifier,
+    mode: WriteMode,
 }
 
 #[async_trait]
@@ -50,33 +106,109 @@ impl ToolHandler for StoreHandler {
             .get("data")
             .ok_or_else(|| McpError::Validation("Missing 'data' parameter".to_string()))?;
 
-        let now = chrono::Utc::now();
-        let record = DatabaseRecord {
-            id: id.to_string(),
-            data: data.clone(),
-            created_at: now,
-            updated_at: now,
-        };
+        let collection = arguments.get("collection").and_then(|v| v.as_str());
 
-        let mut db = self.db.write().await;
-        let was_update = db.contains_key(id);
-        db.insert(id.to_string(), record);
+        let expected_version = expected_version(&arguments)?;
 
-        let message = if was_update {
-            format!("Updated record with ID: {id}")
+        let expires_at = ExpiryArgs::from_arguments(&arguments)
+            .and_then(|expiry| expiry.resolve(chrono::Utc::now()))
+            .map_err(McpError::Validation)?;
+
+        let options = WriteOptions {
+            collection,
+            mode: self.mode,
+            expected_version,
+            expires_at,
+        };
+        let (outcome, changes) = {
+            let schemas = self.schemas.read().await;
+            let mut db = self.db.write().await;
+            match store::write_record(&mut db, &schemas, id, data.clone(), options).and_then(
+                |outcome| {
+                    let changes = db.commit(vec![outcome.change()])?;
+                    Ok((outcome, changes))
+                },
+            ) {
+                Ok(committed) => committed,
+                Err(err) => return Ok(store_error_result(err)),
+            }
+        };
+        self.notifier.publish(&changes).await;
+
+        let version = outcome.record.version;
+        let message = if outcome.created() {
+            format!("Created record with ID: {id} (version {version})")
         } else {
-            format!("Created record with ID: {id}")
+            format!("Updated record with ID: {id} (version {version})")
         };
 
         Ok(ToolResult {
             content: vec![Content::text(message)],
             is_error: None,
-            structured_content: None,
+            structured_content: Some(json!({
+                "id": id,
+                "collection": outcome.record.collection,
+                "uri": uri::record_uri(&outcome.record.collection, id),
+                "version": version,
+                "created": outcome.created(),
+                "expires_at": outcome.record.expires_at.map(|t| t.to_rfc3339())
+            })),
             meta: None,
         })
     }
 }
 
+/// Database tool handler for partially updating records with JSON Patch
+/// (RFC 6902) or JSON Merge Patch (RFC 7386)
+struct PatchHandler {
+    db: Database,
+    schemas: Schemas,
+    notifier: ChangeNotifier,
+}
+
+#[async_trait]
+impl ToolHandler for PatchHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let id = arguments
+            .get("id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| McpError::Validation("Missing 'id' parameter".to_string()))?;
+
+        let patch = arguments
+            .get("patch")
+            .ok_or_else(|| McpError::Validation("Missing 'patch' parameter".to_string()))?;
+
+        let format = PatchFormat::resolve(arguments.get("format").and_then(|v| v.as_str()), patch)
+            .map_err(McpError::Validation)?;
+
+        let expected_version = expected_version(&arguments)?;
+
+        let result = {
+            let schemas = self.schemas.read().await;
+            let mut db = self.db.write().await;
+            store::patch_record(&mut db, &schemas, id, patch, format, expected_version).and_then(
+                |outcome| {
+                    let changes = db.commit(vec![outcome.change()])?;
+                    Ok((outcome, changes))
+                },
+            )
+        };
+        match result {
+            Ok((outcome, changes)) => {
+                self.notifier.publish(&changes).await;
+                let response = outcome.record.to_json();
+                Ok(ToolResult {
+                    content: vec![Content::text(serde_json::to_string_pretty(&response)?)],
+                    is_error: None,
+                    structured_content: Some(response),
+                    meta: None,
+                })
+            }
+            Err(err) => Ok(store_error_result(err)),
+        }
+    }
+}
+
 /// Database tool handler for retrieving records
 struct RetrieveHandler {
     db: Database,
@@ -90,16 +222,25 @@ impl ToolHandler for RetrieveHandler {
             .and_then(|v| v.as_str())
             .ok_or_else(|| McpError::Validation("Missing 'id' parameter".to_string()))?;
 
+        let selector = Selector::from_arguments(&arguments).map_err(McpError::Validation)?;
+
         let db = self.db.read().await;
 
-        match db.get(id) {
+        match store::get_live(&db, id) {
             Some(record) => {
-                let response = json!({
-                    "id": record.id,
-                    "data": record.data,
-                    "created_at": record.created_at.to_rfc3339(),
-                    "updated_at": record.updated_at.to_rfc3339()
-                });
+                self.db.touch(id);
+                let response = match selector.map(|selector| selector.find(record)) {
+                    None => record.to_json(),
+                    Some(Ok(revision)) => revision.to_json(record),
+                    Some(Err(message)) => {
+                        return Ok(ToolResult {
+                            content: vec![Content::text(message)],
+                            is_error: Some(true),
+                            structured_content: None,
+                            meta: None,
+                        });
+                    }
+                };
 
                 Ok(ToolResult {
                     content: vec![Content::text(serde_json::to_string_pretty(&response)?)],
@@ -118,7 +259,7 @@ impl ToolHandler for RetrieveHandler {
     }
 }
 
-/// Database tool handler for listing all records
+/// Database tool handler for listing records a page at a time
 struct ListHandler {
     db: Database,
 }
@@ -130,26 +271,29 @@ impl ToolHandler for ListHandler {
             .get("limit")
             .and_then(|v| v.as_u64())
             .unwrap_or(10)
-            .min(100) as usize; // Cap at 100 records
+            .clamp(1, 100) as usize; // Cap at 100 records per page
+
+        let collection = arguments.get("collection").and_then(|v| v.as_str());
+
+        let cursor = match arguments.get("cursor").and_then(|v| v.as_str()) {
+            Some(cursor) => Some(Cursor::decode(cursor, collection).map_err(McpError::Validation)?),
+            None => None,
+        };
 
         let db = self.db.read().await;
-        let records: Vec<_> = db
-            .values()
-            .take(limit)
-            .map(|record| {
-                json!({
-                    "id": record.id,
-                    "data": record.data,
-                    "created_at": record.created_at.to_rfc3339(),
-                    "updated_at": record.updated_at.to_rfc3339()
-                })
-            })
-            .collect();
+        let now = chrono::Utc::now();
+        let in_collection = |record: &DatabaseRecord| {
+            !record.is_expired(now) && collection.is_none_or(|c| record.collection == c)
+        };
+        let total = db.values().filter(|record| in_collection(record)).count();
+        let page = pagination::page(&db, cursor.as_ref(), collection, limit, in_collection);
+        let records: Vec<_> = page.items.iter().map(|record| record.to_json()).collect();
 
         let response = json!({
-            "total": db.len(),
+            "total": total,
             "returned": records.len(),
-            "records": records
+            "records": records,
+            "next_cursor": page.next_cursor
         });
 
         Ok(ToolResult {
@@ -164,6 +308,7 @@ impl ToolHandler for ListHandler {
 /// Database tool handler for deleting records
 struct DeleteHandler {
     db: Database,
+    notifier: ChangeNotifier,
 }
 
 #[async_trait]
@@ -174,53 +319,135 @@ impl ToolHandler for DeleteHandler {
             .and_then(|v| v.as_str())
             .ok_or_else(|| McpError::Validation("Missing 'id' parameter".to_string()))?;
 
-        let mut db = self.db.write().await;
+        let expected_version = expected_version(&arguments)?;
 
-        match db.remove(id) {
-            Some(_) => Ok(ToolResult {
-                content: vec![Content::text(format!("Deleted record with ID: {id}"))],
-                is_error: None,
-                structured_content: None,
-                meta: None,
-            }),
-            None => Ok(ToolResult {
-                content: vec![Content::text(format!("No record found with ID: {id}"))],
-                is_error: Some(true),
-                structured_content: None,
-                meta: None,
-            }),
+        let result = {
+            let mut db = self.db.write().await;
+            store::delete_record(&mut db, id, expected_version).and_then(|record| {
+                let changes = db.commit(
+                    store::Change::between(Some(&record), None)
+                        .into_iter()
+                        .collect(),
+                )?;
+                Ok((record, changes))
+            })
+        };
+
+        match result {
+            Ok((record, changes)) => {
+                self.notifier.publish(&changes).await;
+                Ok(ToolResult {
+                    content: vec![Content::text(format!(
+                        "Deleted record with ID: {id} (version {})",
+                        record.version
+                    ))],
+                    is_error: None,
+                    structured_content: None,
+                    meta: None,
+                })
+            }
+            Err(err) => Ok(store_error_result(err)),
         }
     }
 }
 
-/// Resource handler for accessing database contents
-struct DatabaseResourceHandler {
-    db: Database,
-}
+#[tokio::main]
+async fn main() -> McpResult<()> {
+    // Initialize logging
+    #[cfg(feature = "tracing-subscriber")]
+    tracing_subscriber::fmt::init();
 
-#[async_trait]
-impl ResourceHandler for DatabaseResourceHandler {
-    async fn read(
-        &self,
-        uri: &str,
-        _params: &HashMap<String, String>,
-    ) -> McpResult<Vec<ResourceContents>> {
-        match uri {
-            "db:/// all" => {
-                let db = self.db.read().await;
-                let records: Vec<_> = db.values().collect();
-
-                let content = serde_json::to_string_pretty(&records)?;
-
-                Ok(vec![ResourceContents::Text {
-                    uri: uri.to_string(),
-                    mime_type: Some("application/json".to_string()),
-                    text: content,
-                    meta: None,
-                }])
-            }
-            "db:/// schema" => {
-                let schema = json!({
+    let config = Config::load(Cli::parse()).map_err(McpError::Validation)?;
+
+    let mut server = McpServer::new(config.name.clone(), "1.0.0".to_string());
+
+    server.set_capabilities(ServerCapabilities {
+        tools: Some(ToolsCapability {
+            list_changed: Some(false),
+        }),
+        resources: Some(ResourcesCapability {
+            subscribe: Some(true),
+            list_changed: Some(true),
+        }),
+        prompts: Some(PromptsCapability {
+            list_changed: Some(false),
+        }),
+        completions: Some(CompletionsCapability::default()),
+        sampling: None,
+        logging: None,
+        experimental: None,
+    });
+
+    let (notifier, mut notifications) = ChangeNotifier::new();
+    let policy = Arc::new(PolicyEngine::new(&config.policy).map_err(McpError::Validation)?);
+
+    // Load collection schemas from the schema file, if set
+    let schemas = match &config.storage.schema_file {
+        Some(path) => SchemaRegistry::from_file(path).map_err(McpError::Validation)?,
+        None => SchemaRegistry::default(),
+    };
+    let schemas: Schemas = Arc::new(RwLock::new(schemas));
+
+    // File import/export is confined to the data directory, if set
+    let data_dir = DataDir(config.storage.data_dir.clone());
+    let exports: Exports = Arc::default();
+
+    // Open the configured storage backend
+    let backend = config
+        .storage
+        .backend()
+        .open()
+        .map_err(|e| McpError::Internal(format!("Failed to open storage backend: {e}")))?;
+    let db: Database = Arc::new(
+        RecordStore::open(backend, config.quotas)
+            .map_err(|e| McpError::Internal(format!("Failed to load records: {e}")))?,
+    );
+    tracing::info!(
+        "Using {} storage with {} record(s)",
+        db.backend_name(),
+        db.read().await.len()
+    );
+
+    // Schemas set with `set_schema` are kept by the backend and replace the file's
+    let stored = db
+        .load_schemas()
+        .map_err(|e| McpError::Internal(format!("Failed to load schemas: {e}")))?;
+    schemas
+        .write()
+        .await
+        .restore(stored)
+        .map_err(McpError::Validation)?;
+
+    // Add tools
+    tracing::info!("Adding database tools...");
+
+    let write_tools = [
+        (
+            "store",
+            "Store a record in the database, creating or replacing it",
+            WriteMode::Upsert,
+        ),
+        (
+            "insert",
+            "Insert a new record; fails if a record with the same ID exists",
+            WriteMode::Insert,
+        ),
+        (
+            "update",
+            "Update an existing record; fails if the record does not exist",
+            WriteMode::Update,
+        ),
+    ];
+
+    for (name, description, mode) in write_tools
+        .into_iter()
+        .filter(|(name, ..)| config.exposes(name))
+    {
+        server
+            .add_tool(
+                name.to_string(),
+                Some(description.to_string()),
+                json!({
                     "type": "object",
                     "properties": {
                         "id": {
@@ -228,190 +455,619 @@ impl ResourceHandler for DatabaseResourceHandler {
                             "description": "Unique identifier for the record"
                         },
                         "data": {
-                            "description": "The stored data (can be any JSON value)"
+                            "description": "The data to store (can be any JSON value)"
                         },
-                        "created_at": {
+                        "collection": {
                             "type": "string",
-                            "format": "date-time",
-                            "description": "When the record was created"
+                            "description": "Collection to store the record in (default: the record's current collection, or 'default' for new records)"
                         },
-                        "updated_at": {
+                        "expected_version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Fail with a version conflict unless the stored record is at this version"
+                        },
+                        "ttl_seconds": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Expire the record this many seconds from now (default: keep the current expiry, if any)"
+                        },
+                        "expires_at": {
                             "type": "string",
                             "format": "date-time",
-                            "description": "When the record was last updated"
+                            "description": "Expire the record at this time; alternative to 'ttl_seconds'"
                         }
-                    }
-                });
-
-                Ok(vec![ResourceContents::Text {
-                    uri: uri.to_string(),
-                    mime_type: Some("application/json".to_string()),
-                    text: serde_json::to_string_pretty(&schema)?,
-                    meta: None,
-                }])
-            }
-            _ if uri.starts_with("db:/// record/") => {
-                let id = uri.strip_prefix("db:/// record/").unwrap();
-                let db = self.db.read().await;
-
-                match db.get(id) {
-                    Some(record) => {
-                        let content = serde_json::to_string_pretty(record)?;
-                        Ok(vec![ResourceContents::Text {
-                            uri: uri.to_string(),
-                            mime_type: Some("application/json".to_string()),
-                            text: content,
-                            meta: None,
-                        }])
-                    }
-                    None => Err(McpError::ResourceNotFound(uri.to_string())),
-                }
-            }
-            _ => Err(McpError::ResourceNotFound(uri.to_string())),
-        }
+                    },
+                    "required": ["id", "data"]
+                }),
+                StoreHandler {
+                    db: db.clone(),
+                    schemas: schemas.clone(),
+                    notifier: notifier.clone(),
+                    mode,
+                },
+            )
+            .await?;
     }
 
-    async fn list(&self) -> McpResult<Vec<ResourceInfo>> {
-        let db = self.db.read().await;
-        let mut resources = vec![
-            ResourceInfo {
-                uri: "db:/// all".to_string(),
-                name: "All Records".to_string(),
-                description: Some("All records in the database".to_string()),
-                mime_type: Some("application/json".to_string()),
-                annotations: None,
-                size: None,
-                title: Some("All Records".to_string()),
-                meta: None,
-            },
-            ResourceInfo {
-                uri: "db:/// schema".to_string(),
-                name: "Database Schema".to_string(),
-                description: Some("JSON schema for database records".to_string()),
-                mime_type: Some("application/json".to_string()),
-                annotations: None,
-                size: None,
-                title: Some("Database Schema".to_string()),
-                meta: None,
-            },
-        ];
-
-        // Add individual record resources
-        for id in db.keys() {
-            resources.push(ResourceInfo {
-                uri: format!("db:/// record/{id}"),
-                name: format!("Record: {id}"),
-                description: Some(format!("Individual database record with ID: {id}")),
-                mime_type: Some("application/json".to_string()),
-                annotations: None,
-                size: None,
-                title: Some(format!("Record: {id}")),
-                meta: None,
-            });
-        }
-
-        Ok(resources)
+    if config.exposes("patch") {
+        server
+            .add_tool(
+                "patch".to_string(),
+                Some(
+                    "Partially update a record with a JSON Patch (RFC 6902) or merge patch (RFC 7386)"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record to patch"
+                        },
+                        "patch": {
+                            "description": "An array of JSON Patch operations (add, remove, replace, move, copy, test) or a merge patch object"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["json-patch", "merge-patch"],
+                            "description": "Patch format (default: json-patch for arrays, merge-patch otherwise)"
+                        },
+                        "expected_version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Fail with a version conflict unless the stored record is at this version"
+                        }
+                    },
+                    "required": ["id", "patch"]
+                }),
+                PatchHandler {
+                    db: db.clone(),
+                    schemas: schemas.clone(),
+                    notifier: notifier.clone(),
+                },
+            )
+            .await?;
     }
 
-    async fn subscribe(&self, _uri: &str) -> McpResult<()> {
-        // In a real implementation, this would set up change notifications
-        Ok(())
+    if config.exposes("transaction") {
+        server
+            .add_tool(
+                "transaction".to_string(),
+                Some(
+                    "Apply an ordered list of store/patch/delete/assert operations atomically: all succeed or none are applied"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "operations": {
+                            "type": "array",
+                            "minItems": 1,
+                            "maxItems": config.limits.max_transaction_operations,
+                            "description": "Operations to apply in order",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "op": {
+                                        "type": "string",
+                                        "enum": ["store", "patch", "delete", "assert"]
+                                    },
+                                    "id": {
+                                        "type": "string",
+                                        "description": "Record the operation applies to"
+                                    },
+                                    "data": {
+                                        "description": "New record data (store)"
+                                    },
+                                    "collection": {
+                                        "type": "string",
+                                        "description": "Collection for the record (store)"
+                                    },
+                                    "mode": {
+                                        "type": "string",
+                                        "enum": ["upsert", "insert", "update"],
+                                        "default": "upsert",
+                                        "description": "Write semantics (store)"
+                                    },
+                                    "patch": {
+                                        "description": "JSON Patch array or merge patch object (patch)"
+                                    },
+                                    "format": {
+                                        "type": "string",
+                                        "enum": ["json-patch", "merge-patch"],
+                                        "description": "Patch format (patch)"
+                                    },
+                                    "exists": {
+                                        "type": "boolean",
+                                        "default": true,
+                                        "description": "Whether the record must exist (assert)"
+                                    },
+                                    "expected_version": {
+                                        "type": "integer",
+                                        "minimum": 1,
+                                        "description": "Required current version of the record"
+                                    },
+                                    "ttl_seconds": {
+                                        "type": "integer",
+                                        "minimum": 1,
+                                        "description": "Seconds until the record expires (store)"
+                                    },
+                                    "expires_at": {
+                                        "type": "string",
+                                        "format": "date-time",
+                                        "description": "When the record expires (store)"
+                                    }
+                                },
+                                "required": ["op", "id"]
+                            }
+                        }
+                    },
+                    "required": ["operations"]
+                }),
+                TransactionHandler {
+                    db: db.clone(),
+                    schemas: schemas.clone(),
+                    notifier: notifier.clone(),
+                    max_operations: config.limits.max_transaction_operations,
+                    policy: policy.clone(),
+                },
+            )
+            .await?;
     }
 
-    async fn unsubscribe(&self, _uri: &str) -> McpResult<()> {
-        // In a real implementation, this would remove change notifications
-        Ok(())
+    if config.exposes("set_schema") {
+        server
+            .add_tool(
+                "set_schema".to_string(),
+                Some(
+                    "Attach a JSON Schema to a collection; every later write to the collection is validated against it. The change is kept by the storage backend, so it lasts across restarts only with sqlite storage"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "collection": {
+                            "type": "string",
+                            "description": "Collection the schema applies to"
+                        },
+                        "schema": {
+                            "type": ["object", "boolean", "null"],
+                            "description": "JSON Schema for the 'data' of records in the collection; null removes the schema"
+                        }
+                    },
+                    "required": ["collection", "schema"]
+                }),
+                SetSchemaHandler {
+                    db: db.clone(),
+                    schemas: schemas.clone(),
+                    notifier: notifier.clone(),
+                },
+            )
+            .await?;
     }
-}
 
-#[tokio::main]
-async fn main() -> McpResult<()> {
-    // Initialize logging
-    #[cfg(feature = "tracing-subscriber")]
-    tracing_subscriber::fmt::init();
+    if config.exposes("export") {
+        server
+            .add_tool(
+                "export".to_string(),
+                Some(
+                    "Export records as NDJSON, a JSON array or CSV, either as a resource or to a file in the data directory"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "collection": {
+                            "type": "string",
+                            "description": "Only export records in this collection"
+                        },
+                        "filter": {
+                            "type": "object",
+                            "description": "Only export records whose data has these values, keyed by JSON Pointer (e.g. {\"/address/city\": \"Paris\"})"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["ndjson", "json", "csv"],
+                            "description": "Output format (default: inferred from 'path', otherwise ndjson); CSV flattens data into dotted columns"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Write to this file relative to the data directory instead of creating an export resource"
+                        }
+                    }
+                }),
+                ExportHandler {
+                    db: db.clone(),
+                    exports: exports.clone(),
+                    data_dir: data_dir.clone(),
+                    notifier: notifier.clone(),
+                },
+            )
+            .await?;
+    }
 
-    let mut server = McpServer::new("database-server".to_string(), "1.0.0".to_string());
+    if config.exposes("import") {
+        server
+            .add_tool(
+                "import".to_string(),
+                Some(
+                    "Import records from NDJSON, a JSON array or CSV; all rows are applied atomically"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "content": {
+                            "type": "string",
+                            "description": "Records to import; each needs an 'id' and usually 'data'"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Read records from this file relative to the data directory instead of 'content'"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["ndjson", "json", "csv"],
+                            "description": "Input format (default: inferred from 'path', otherwise ndjson)"
+                        },
+                        "collection": {
+                            "type": "string",
+                            "description": "Collection for records that do not name one"
+                        },
+                        "mode": {
+                            "type": "string",
+                            "enum": ["upsert", "skip", "fail"],
+                            "default": "upsert",
+                            "description": "What to do with records that already exist: replace them, leave them, or abort the import"
+                        }
+                    }
+                }),
+                ImportHandler {
+                    db: db.clone(),
+                    schemas: schemas.clone(),
+                    data_dir: data_dir.clone(),
+                    notifier: notifier.clone(),
+                    max_bytes: config.limits.max_import_bytes,
+                },
+            )
+            .await?;
+    }
 
-    // Create shared database
-    let db: Database = Arc::new(RwLock::new(HashMap::new()));
+    if config.exposes("retrieve") {
+        server
+            .add_tool(
+                "retrieve".to_string(),
+                Some("Retrieve a record from the database, optionally as it was at an earlier version or time".to_string()),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record to retrieve"
+                        },
+                        "version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Return this retained version instead of the current one"
+                        },
+                        "as_of": {
+                            "type": "string",
+                            "format": "date-time",
+                            "description": "Return the version that was current at this time"
+                        }
+                    },
+                    "required": ["id"]
+                }),
+                RetrieveHandler { db: db.clone() },
+            )
+            .await?;
+    }
 
-    // Add tools
-    tracing::info!("Adding database tools...");
+    if config.exposes("search") {
+        server
+            .add_tool(
+                "search".to_string(),
+                Some(
+                    "Full-text search over the text inside records, ranked by relevance with highlighted snippets"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Words to search for (case-insensitive); use \"quotes\" for phrases and a trailing * for prefixes, e.g. invoice* \"due date\""
+                        },
+                        "match": {
+                            "type": "string",
+                            "enum": ["all", "any"],
+                            "default": "all",
+                            "description": "Whether records must match every word/phrase or at least one"
+                        },
+                        "collection": {
+                            "type": "string",
+                            "description": "Only search records in this collection"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of results to return (default: 10, max: 100)",
+                            "minimum": 1,
+                            "maximum": 100,
+                            "default": 10
+                        }
+                    },
+                    "required": ["query"]
+                }),
+                SearchHandler {
+                    db: db.clone(),
+                    index: Default::default(),
+                },
+            )
+            .await?;
+    }
 
-    server
-        .add_tool(
-            "store".to_string(),
-            Some("Store a record in the database".to_string()),
-            json!({
+    if config.exposes("aggregate") {
+        let aggregate_tool = ToolBuilder::new("aggregate")
+            .description(
+                "Group records by fields of their data and compute count, sum, avg, min, max or distinct values",
+            )
+            .schema(json!({
                 "type": "object",
                 "properties": {
-                    "id": {
+                    "group_by": {
+                        "oneOf": [
+                            {"type": "string"},
+                            {"type": "array", "items": {"type": "string"}}
+                        ],
+                        "description": "JSON Pointer(s) into the record data to group by (e.g. '/address/city'); omit for a single group"
+                    },
+                    "metrics": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "op": {
+                                    "type": "string",
+                                    "enum": ["count", "sum", "avg", "min", "max", "distinct"]
+                                },
+                                "field": {
+                                    "type": "string",
+                                    "description": "JSON Pointer of the value to aggregate (optional for count)"
+                                },
+                                "as": {
+                                    "type": "string",
+                                    "description": "Name of the metric in the results (default: e.g. 'avg(/age)')"
+                                }
+                            },
+                            "required": ["op"]
+                        },
+                        "description": "Metrics to compute for each group"
+                    },
+                    "collection": {
                         "type": "string",
-                        "description": "Unique identifier for the record"
+                        "description": "Only aggregate records in this collection"
+                    },
+                    "filter": {
+                        "type": "object",
+                        "description": "Only aggregate records whose data holds these values, keyed by JSON Pointer",
+                        "additionalProperties": true
                     },
-                    "data": {
-                        "description": "The data to store (can be any JSON value)"
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of groups to return (default: 100, max: 1000)",
+                        "minimum": 1,
+                        "maximum": aggregate::MAX_GROUPS,
+                        "default": 100
                     }
+                }
+            }))
+            .output_schema(aggregate::output_schema())
+            .build(AggregateHandler { db: db.clone() })?;
+        server.add_tool_built(aggregate_tool).await?;
+    }
+
+    if config.exposes("history") {
+        server
+            .add_tool(
+                "history".to_string(),
+                Some(format!(
+                    "List the retained versions of a record (up to {} past versions) with timestamps and JSON Patch diffs",
+                    history::MAX_REVISIONS
+                )),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record"
+                        }
+                    },
+                    "required": ["id"]
+                }),
+                HistoryHandler { db: db.clone() },
+            )
+            .await?;
+    }
+
+    if config.exposes("revert") {
+        server
+            .add_tool(
+                "revert".to_string(),
+                Some(
+                    "Restore the data of an earlier version of a record, saved as a new version"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record to revert"
+                        },
+                        "version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Version to restore"
+                        },
+                        "as_of": {
+                            "type": "string",
+                            "format": "date-time",
+                            "description": "Restore the version that was current at this time"
+                        },
+                        "expected_version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Fail with a version conflict unless the stored record is at this version"
+                        }
+                    },
+                    "required": ["id"]
+                }),
+                RevertHandler {
+                    db: db.clone(),
+                    schemas: schemas.clone(),
+                    notifier: notifier.clone(),
                 },
-                "required": ["id", "data"]
-            }),
-            StoreHandler { db: db.clone() },
-        )
-        .await?;
+            )
+            .await?;
+    }
 
-    server
-        .add_tool(
-            "retrieve".to_string(),
-            Some("Retrieve a record from the database".to_string()),
-            json!({
-                "type": "object",
-                "properties": {
-                    "id": {
-                        "type": "string",
-                        "description": "Unique identifier of the record to retrieve"
+    if config.exposes("list") {
+        server
+            .add_tool(
+                "list".to_string(),
+                Some("List records in the database, one page at a time".to_string()),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of records to return (default: 10, max: 100)",
+                            "minimum": 1,
+                            "maximum": 100,
+                            "default": 10
+                        },
+                        "collection": {
+                            "type": "string",
+                            "description": "Only list records in this collection"
+                        },
+                        "cursor": {
+                            "type": "string",
+                            "description": "Opaque 'next_cursor' from a previous call to fetch the following page"
+                        }
                     }
+                }),
+                ListHandler { db: db.clone() },
+            )
+            .await?;
+    }
+
+    if config.exposes("delete") {
+        server
+            .add_tool(
+                "delete".to_string(),
+                Some("Delete a record from the database".to_string()),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record to delete"
+                        },
+                        "expected_version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Fail with a version conflict unless the stored record is at this version"
+                        }
+                    },
+                    "required": ["id"]
+                }),
+                DeleteHandler {
+                    db: db.clone(),
+                    notifier: notifier.clone(),
                 },
-                "required": ["id"]
-            }),
-            RetrieveHandler { db: db.clone() },
-        )
-        .await?;
+            )
+            .await?;
+    }
 
-    server.add_tool(
-        "list".to_string(),
-        Some("List all records in the database".to_string()),
-        json!({
-            "type": "object",
-            "properties": {
-                "limit": {
-                    "type": "integer",
-                    "description": "Maximum number of records to return (default: 10, max: 100)",
-                    "minimum": 1,
-                    "maximum": 100,
-                    "default": 10
-                }
-            }
-        }),
-        ListHandler { db: db.clone() },
-    ).await?;
+    if config.exposes("attach") {
+        server
+            .add_tool(
+                "attach".to_string(),
+                Some(
+                    "Attach a file to a record; it is served as a blob resource under the record's URI"
+                        .to_string(),
+                ),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record"
+                        },
+                        "name": {
+                            "type": "string",
+                            "description": "Attachment name, unique per record; an attachment with the same name is replaced"
+                        },
+                        "content": {
+                            "type": "string",
+                            "contentEncoding": "base64",
+                            "description": format!(
+                                "Base64-encoded content, at most {} bytes once decoded",
+                                config.limits.max_attachment_bytes
+                            )
+                        },
+                        "mime_type": {
+                            "type": "string",
+                            "description": "MIME type of the content; guessed from the name's extension when omitted"
+                        },
+                        "expected_version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Fail with a version conflict unless the stored record is at this version"
+                        }
+                    },
+                    "required": ["id", "name", "content"]
+                }),
+                AttachHandler {
+                    db: db.clone(),
+                    notifier: notifier.clone(),
+                    max_bytes: config.limits.max_attachment_bytes,
+                },
+            )
+            .await?;
+    }
 
-    server
-        .add_tool(
-            "delete".to_string(),
-            Some("Delete a record from the database".to_string()),
-            json!({
-                "type": "object",
-                "properties": {
-                    "id": {
-                        "type": "string",
-                        "description": "Unique identifier of the record to delete"
-                    }
+    if config.exposes("detach") {
+        server
+            .add_tool(
+                "detach".to_string(),
+                Some("Remove an attachment from a record".to_string()),
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Unique identifier of the record"
+                        },
+                        "name": {
+                            "type": "string",
+                            "description": "Name of the attachment to remove"
+                        },
+                        "expected_version": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Fail with a version conflict unless the stored record is at this version"
+                        }
+                    },
+                    "required": ["id", "name"]
+                }),
+                DetachHandler {
+                    db: db.clone(),
+                    notifier: notifier.clone(),
                 },
-                "required": ["id"]
-            }),
-            DeleteHandler { db: db.clone() },
-        )
-        .await?;
+            )
+            .await?;
+    }
 
     // Add database resource
     tracing::info!("Adding database resource...");
@@ -419,70 +1075,181 @@ async fn main() -> McpResult<()> {
     server
         .add_resource_detailed(
             ResourceInfo {
-                uri: "db:/// ".to_string(),
+                uri: "db://".to_string(),
                 name: "Database".to_string(),
-                description: Some("In-memory database with JSON records".to_string()),
+                description: Some(
+                    "Database records, collections, schemas, statistics and exports".to_string(),
+                ),
                 mime_type: Some("application/json".to_string()),
                 annotations: None,
                 size: None,
                 title: Some("Database".to_string()),
                 meta: None,
             },
-            DatabaseResourceHandler { db: db.clone() },
+            DatabaseResourceHandler {
+                db: db.clone(),
+                schemas: schemas.clone(),
+                exports: exports.clone(),
+                notifier: notifier.clone(),
+            },
         )
         .await?;
 
-    // Insert some sample data
-    tracing::info!("Inserting sample data...");
-    {
+    for template in resources::templates() {
+        server.add_resource_template(template).await?;
+    }
+    let exposed: Vec<&'static str> = config::TOOLS
+        .iter()
+        .copied()
+        .filter(|tool| config.exposes(tool))
+        .collect();
+    for prompt in Prompt::ALL {
+        let handler = DatabasePromptHandler {
+            db: db.clone(),
+            schemas: schemas.clone(),
+            prompt,
+            tools: exposed.clone(),
+            policy: policy.clone(),
+        };
+        server.add_prompt(prompt.info(), handler).await?;
+    }
+    server
+        .set_completion_handler(DatabaseCompletionHandler {
+            db: db.clone(),
+            policy: policy.clone(),
+        })
+        .await?;
+
+    // Seed an empty database from the seed file if set, otherwise insert some sample data
+    let is_empty = db.read().await.is_empty();
+    if !is_empty {
+        tracing::info!("Skipping seed data: the database already holds records");
+    } else if let Some(path) = &config.storage.seed_file {
+        let path = path.display().to_string();
+        tracing::info!("Importing seed data from {path}...");
+        let format = Format::resolve(None, Some(&path)).map_err(McpError::Validation)?;
+        let rows = transfer::decode(&std::fs::read_to_string(&path)?, format)
+            .map_err(|e| McpError::Validation(format!("Invalid seed file {path}: {e}")))?;
+        let schemas = schemas.read().await;
+        let mut db_guard = db.write().await;
+        let (_, changes) =
+            transfer::import_rows(&mut db_guard, &schemas, &rows, None, ImportMode::Upsert)
+                .map_err(|failure| {
+                    McpError::Validation(format!(
+                        "Invalid seed file {path}: row {}: {}",
+                        failure.row, failure.error
+                    ))
+                })?;
+        db_guard
+            .commit(changes)
+            .map_err(|e| McpError::Internal(e.to_string()))?;
+    } else if config.storage.sample_data {
+        tracing::info!("Inserting sample data...");
         let mut db_guard = db.write().await;
-        let now = chrono::Utc::now();
 
-        db_guard.insert(
-            "user1".to_string(),
-            DatabaseRecord {
-                id: "user1".to_string(),
-                data: json!({
+        let samples = [
+            DatabaseRecord::new(
+                "user1",
+                "users",
+                json!({
                     "name": "Alice",
                     "email": "alice@example.com",
                     "age": 30
                 }),
-                created_at: now,
-                updated_at: now,
-            },
-        );
-
-        db_guard.insert(
-            "user2".to_string(),
-            DatabaseRecord {
-                id: "user2".to_string(),
-                data: json!({
+            ),
+            DatabaseRecord::new(
+                "user2",
+                "users",
+                json!({
                     "name": "Bob",
                     "email": "bob@example.com",
                     "age": 25
                 }),
-                created_at: now,
-                updated_at: now,
-            },
-        );
+            ),
+        ];
+        let changes: Vec<_> = samples
+            .iter()
+            .filter_map(|record| store::Change::between(None, Some(record)))
+            .collect();
+        for record in samples {
+            db_guard.insert(record.id.clone(), record);
+        }
+        db_guard
+            .commit(changes)
+            .map_err(|e| McpError::Internal(e.to_string()))?;
     }
 
+    // Remove expired records in the background, unless the database is read-only
+    let sweeper = (!config.read_only).then(|| expiry::spawn_sweeper(db.clone(), notifier.clone()));
+
     // Start the server
-    tracing::info!("Starting database server...");
-    let transport = StdioServerTransport::new();
-    server.start(transport).await?;
+    let reloader = config
+        .policy
+        .hot_reload
+        .then(|| policy.spawn_reloader(Duration::from_secs(config.policy.reload_interval_secs)));
+    let (clients, mut listening) = transport::start(server, &config, policy, &notifier).await?;
 
+    let tool_help = [
+        ("store", "Store a new record"),
+        ("insert", "Create a record that must not exist yet"),
+        ("update", "Update a record that must already exist"),
+        ("patch", "Apply a JSON Patch or merge patch to a record"),
+        ("transaction", "Apply several operations atomically"),
+        (
+            "set_schema",
+            "Validate a collection's records against a JSON Schema",
+        ),
+        ("export", "Bulk export as NDJSON, JSON or CSV"),
+        ("import", "Bulk import from NDJSON, JSON or CSV"),
+        (
+            "retrieve",
+            "Get a record by ID, optionally at a past version",
+        ),
+        ("history", "Inspect the changes made to a record"),
+        ("revert", "Undo changes to a record"),
+        (
+            "search",
+            "Full-text search with ranked, highlighted results",
+        ),
+        (
+            "aggregate",
+            "Group records and compute count, sum, avg, min, max, distinct",
+        ),
+        ("list", "List records page by page"),
+        ("delete", "Remove a record"),
+        ("attach", "Attach a file to a record as a blob resource"),
+        ("detach", "Remove an attachment from a record"),
+    ];
     tracing::info!("Database server is running! Try these tools:");
-    tracing::info!("  - store: Store a new record");
-    tracing::info!("  - retrieve: Get a record by ID");
-    tracing::info!("  - list: List all records");
-    tracing::info!("  - delete: Remove a record");
+    for (name, help) in tool_help {
+        if config.exposes(name) {
+            tracing::info!("  - {name}: {help}");
+        }
+    }
+    let prompt_names: Vec<&str> = Prompt::ALL.iter().map(|prompt| prompt.name()).collect();
+    tracing::info!("Prompts: {}", prompt_names.join(", "));
 
-    // Keep running until interrupted
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for ctrl+c");
-    server.stop().await?;
+    // Forward change notifications until interrupted
+    loop {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.expect("Failed to listen for ctrl+c");
+                break;
+            }
+            result = &mut listening => {
+                result?;
+                break;
+            }
+            Some(notification) = notifications.recv() => clients.notify(notification).await,
+        }
+    }
+    if let Some(sweeper) = sweeper {
+        sweeper.abort();
+    }
+    if let Some(reloader) = reloader {
+        reloader.abort();
+    }
+    clients.stop().await?;
 
     Ok(())
 }
@@ -0,0 +1,127 @@
+//! Opaque cursors for paging through ordered listings.
+//!
+//! Pages are keyset-based: a cursor records the last key returned, and the
+//! next page starts strictly after it. Writes made between requests therefore
+//! never shift already-seen entries into the next page or skip unseen ones;
+//! entries created behind the cursor are simply not revisited.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Position in a listing, serialized into an opaque cursor string
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Last key returned on the previous page
+    pub after: String,
+    /// Listing the cursor belongs to (e.g. a collection filter), so a cursor
+    /// cannot be replayed against a different query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl Cursor {
+    /// Encode the cursor as an opaque URL-safe string
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor serialization cannot fail");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// Decode a cursor produced by [`Cursor::encode`] for the given scope
+    pub fn decode(cursor: &str, scope: Option<&str>) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| "Invalid cursor".to_string())?;
+        let cursor: Cursor =
+            serde_json::from_slice(&bytes).map_err(|_| "Invalid cursor".to_string())?;
+        if cursor.scope.as_deref() != scope {
+            return Err("Cursor does not belong to this listing".to_string());
+        }
+        Ok(cursor)
+    }
+}
+
+/// One page of a listing
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor for the following page, if there may be more entries
+    pub next_cursor: Option<String>,
+}
+
+/// Take up to `limit` entries of `map` that come after `cursor` and satisfy `filter`
+pub fn page<'a, V>(
+    map: &'a BTreeMap<String, V>,
+    cursor: Option<&Cursor>,
+    scope: Option<&str>,
+    limit: usize,
+    filter: impl Fn(&V) -> bool,
+) -> Page<&'a V> {
+    let start = match cursor {
+        Some(cursor) => Bound::Excluded(cursor.after.as_str()),
+        None => Bound::Unbounded,
+    };
+    let mut matching = map
+        .range::<str, _>((start, Bound::Unbounded))
+        .filter(|(_, value)| filter(value));
+
+    let mut items = Vec::with_capacity(limit);
+    let mut last_key = None;
+    for (key, value) in matching.by_ref().take(limit) {
+        items.push(value);
+        last_key = Some(key);
+    }
+
+    let next_cursor = match (last_key, matching.next()) {
+        (Some(key), Some(_)) => Some(
+            Cursor {
+                after: key.clone(),
+                scope: scope.map(str::to_string),
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    Page { items, next_cursor }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(keys: &[&str]) -> BTreeMap<String, String> {
+        keys.iter()
+            .map(|k| (k.to_string(), k.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_pages_are_stable_across_writes() {
+        let mut entries = map(&["a", "b", "c", "d", "e"]);
+
+        let first = page(&entries, None, None, 2, |_| true);
+        assert_eq!(first.items, ["a", "b"]);
+        let cursor = Cursor::decode(first.next_cursor.as_deref().unwrap(), None).unwrap();
+
+        // Concurrent writes: one entry before the cursor, one removed after it
+        entries.insert("aa".to_string(), "aa".to_string());
+        entries.remove("c");
+
+        let second = page(&entries, Some(&cursor), None, 2, |_| true);
+        assert_eq!(second.items, ["d", "e"]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_cursor_is_bound_to_its_scope() {
+        let entries = map(&["a", "b", "c"]);
+        let first = page(&entries, None, Some("users"), 1, |_| true);
+        let encoded = first.next_cursor.unwrap();
+
+        assert!(Cursor::decode(&encoded, Some("users")).is_ok());
+        assert!(Cursor::decode(&encoded, None).is_err());
+        assert!(Cursor::decode("not a cursor", None).is_err());
+    }
+}
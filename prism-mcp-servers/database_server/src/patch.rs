@@ -0,0 +1,86 @@
+//! JSON Patch (RFC 6902) and JSON Merge Patch (RFC 7386) support.
+//!
+//! Patches are always applied to a copy of the document, so a failed
+//! operation (including a failed `test`) never leaves a partial update behind.
+
+use json_patch::Patch;
+use serde_json::Value;
+
+/// Supported patch document formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    /// RFC 6902 array of operations
+    JsonPatch,
+    /// RFC 7386 merge patch object
+    MergePatch,
+}
+
+impl PatchFormat {
+    /// Resolve the format from the optional `format` argument, falling back to
+    /// the shape of the patch: arrays are JSON Patch, anything else is a merge patch
+    pub fn resolve(format: Option<&str>, patch: &Value) -> Result<Self, String> {
+        match format {
+            Some("json-patch") => Ok(PatchFormat::JsonPatch),
+            Some("merge-patch") => Ok(PatchFormat::MergePatch),
+            Some(other) => Err(format!(
+                "Unknown patch format '{other}' (expected 'json-patch' or 'merge-patch')"
+            )),
+            None if patch.is_array() => Ok(PatchFormat::JsonPatch),
+            None => Ok(PatchFormat::MergePatch),
+        }
+    }
+}
+
+/// Apply `patch` to a copy of `document` and return the patched copy
+pub fn apply(document: &Value, patch: &Value, format: PatchFormat) -> Result<Value, String> {
+    let mut patched = document.clone();
+
+    match format {
+        PatchFormat::JsonPatch => {
+            let operations: Patch = serde_json::from_value(patch.clone())
+                .map_err(|e| format!("Invalid JSON Patch document: {e}"))?;
+            json_patch::patch(&mut patched, &operations).map_err(|e| e.to_string())?;
+        }
+        PatchFormat::MergePatch => json_patch::merge(&mut patched, patch),
+    }
+
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_patch_with_failed_test_leaves_document_unchanged() {
+        let doc = json!({"name": "Alice", "age": 30});
+        let patch = json!([
+            {"op": "replace", "path": "/age", "value": 31},
+            {"op": "test", "path": "/name", "value": "Bob"}
+        ]);
+
+        assert!(apply(&doc, &patch, PatchFormat::JsonPatch).is_err());
+        assert_eq!(doc, json!({"name": "Alice", "age": 30}));
+
+        let patch = json!([
+            {"op": "test", "path": "/name", "value": "Alice"},
+            {"op": "replace", "path": "/age", "value": 31}
+        ]);
+        let patched = apply(&doc, &patch, PatchFormat::JsonPatch).unwrap();
+        assert_eq!(patched, json!({"name": "Alice", "age": 31}));
+    }
+
+    #[test]
+    fn test_merge_patch_removes_null_members() {
+        let doc = json!({"name": "Alice", "email": "alice@example.com"});
+        let patch = json!({"email": null, "city": "Paris"});
+
+        let format = PatchFormat::resolve(None, &patch).unwrap();
+        assert_eq!(format, PatchFormat::MergePatch);
+        assert_eq!(
+            apply(&doc, &patch, format).unwrap(),
+            json!({"name": "Alice", "city": "Paris"})
+        );
+    }
+}
@@ -0,0 +1,571 @@
+//! MCP prompts about the database contents.
+//!
+//! Each prompt embeds the records or schemas it concerns as embedded
+//! resources, under the same `db://` URIs and with the same JSON as
+//! `resources/read`, so the model sees exactly what a client would read.
+//! The policy on reading those URIs applies too: a prompt that would embed a
+//! resource the caller may not read is refused.
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use access_control::policy::PolicyEngine;
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        prompt::PromptHandler,
+    },
+    protocol::types::{Content, PromptArgument, PromptInfo, PromptMessage, PromptResult, Role},
+};
+
+use crate::access;
+use crate::resources;
+use crate::schema::{SchemaRegistry, Schemas};
+use crate::store::{self, Database, DatabaseRecord};
+use crate::uri::{self, ALL_URI, SCHEMA_URI};
+
+/// Most records embedded when summarizing a collection
+pub const MAX_EMBEDDED_RECORDS: usize = 50;
+/// Records embedded per collection to show the shape of the data
+const SAMPLE_RECORDS: usize = 5;
+/// Most schema violations listed when reviewing a schema
+const MAX_LISTED_VIOLATIONS: usize = 10;
+
+/// Tools `draft_query` may suggest, with what they are for
+const QUERY_TOOLS: &[(&str, &str)] = &[
+    (
+        "list",
+        "records in a collection, with a 'filter' object of JSON Pointer to exact value",
+    ),
+    (
+        "search",
+        "full-text search over record data, with phrases, prefixes and filters",
+    ),
+    (
+        "aggregate",
+        "counts, sums, averages, minimums, maximums and distinct values, optionally grouped",
+    ),
+    ("retrieve", "a single record by id"),
+];
+
+/// The prompts the server offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prompt {
+    SummarizeCollection,
+    ExplainRecord,
+    DraftQuery,
+    ReviewSchema,
+}
+
+impl Prompt {
+    pub const ALL: [Prompt; 4] = [
+        Prompt::SummarizeCollection,
+        Prompt::ExplainRecord,
+        Prompt::DraftQuery,
+        Prompt::ReviewSchema,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Prompt::SummarizeCollection => "summarize_collection",
+            Prompt::ExplainRecord => "explain_record",
+            Prompt::DraftQuery => "draft_query",
+            Prompt::ReviewSchema => "review_schema",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|prompt| prompt.name() == name)
+    }
+
+    /// Metadata published through `prompts/list`
+    pub fn info(self) -> PromptInfo {
+        let argument = |name: &str, description: &str, required: bool| PromptArgument {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            required: Some(required),
+            title: None,
+        };
+        let (title, description, arguments) = match self {
+            Prompt::SummarizeCollection => (
+                "Summarize collection",
+                "Summarize the records in a collection",
+                vec![
+                    argument("collection", "Collection to summarize", true),
+                    argument("focus", "Aspect of the data to concentrate on", false),
+                ],
+            ),
+            Prompt::ExplainRecord => (
+                "Explain record",
+                "Explain what a record represents and what its fields mean",
+                vec![
+                    argument("id", "ID of the record", true),
+                    argument("collection", "Collection the record belongs to", false),
+                ],
+            ),
+            Prompt::DraftQuery => (
+                "Draft query",
+                "Draft the tool calls that answer a question about the data",
+                vec![
+                    argument("question", "Question to answer", true),
+                    argument("collection", "Collection the question is about", false),
+                ],
+            ),
+            Prompt::ReviewSchema => (
+                "Review schema",
+                "Review collection schemas against the records they hold",
+                vec![argument(
+                    "collection",
+                    "Collection whose schema to review; all collections when omitted",
+                    false,
+                )],
+            ),
+        };
+        PromptInfo {
+            name: self.name().to_string(),
+            title: Some(title.to_string()),
+            description: Some(description.to_string()),
+            arguments: Some(arguments),
+            meta: None,
+        }
+    }
+}
+
+/// A non-empty string argument
+fn argument<'a>(arguments: &'a HashMap<String, Value>, name: &str) -> Option<&'a str> {
+    arguments
+        .get(name)
+        .and_then(|v| v.as_str())
+        .filter(|value| !value.is_empty())
+}
+
+fn required<'a>(arguments: &'a HashMap<String, Value>, name: &str) -> McpResult<&'a str> {
+    argument(arguments, name)
+        .ok_or_else(|| McpError::Validation(format!("Missing '{name}' argument")))
+}
+
+fn text(text: impl Into<String>) -> PromptMessage {
+    PromptMessage {
+        role: Role::User,
+        content: Content::text(text),
+    }
+}
+
+/// `value` embedded as the JSON contents of the resource at `uri`
+fn embedded(uri: &str, value: &Value) -> McpResult<PromptMessage> {
+    let resource = resources::json_contents(uri, value)?
+        .pop()
+        .expect("JSON contents hold one resource");
+    Ok(PromptMessage {
+        role: Role::User,
+        content: Content::Resource {
+            resource,
+            annotations: None,
+            meta: None,
+        },
+    })
+}
+
+fn to_json<'a>(records: impl IntoIterator<Item = &'a DatabaseRecord>) -> Value {
+    records.into_iter().map(DatabaseRecord::to_json).collect()
+}
+
+/// Serves one [`Prompt`]
+pub struct DatabasePromptHandler {
+    pub db: Database,
+    pub schemas: Schemas,
+    pub prompt: Prompt,
+    /// Exposed tools, so `draft_query` only suggests those
+    pub tools: Vec<&'static str>,
+    /// Checked for every resource a prompt embeds
+    pub policy: Arc<PolicyEngine>,
+}
+
+#[async_trait]
+impl PromptHandler for DatabasePromptHandler {
+    async fn get(&self, arguments: HashMap<String, Value>) -> McpResult<PromptResult> {
+        let messages = match self.prompt {
+            Prompt::SummarizeCollection => self.summarize_collection(&arguments).await?,
+            Prompt::ExplainRecord => self.explain_record(&arguments).await?,
+            Prompt::DraftQuery => self.draft_query(&arguments).await?,
+            Prompt::ReviewSchema => self.review_schema(&arguments).await?,
+        };
+        Ok(PromptResult {
+            description: self.prompt.info().description,
+            messages,
+            meta: None,
+        })
+    }
+}
+
+impl DatabasePromptHandler {
+    /// [`embedded`], unless the caller may not read the resource at `uri`
+    fn embed(&self, uri: &str, value: &Value) -> McpResult<PromptMessage> {
+        access::check_resource(&self.policy, uri)?;
+        embedded(uri, value)
+    }
+
+    async fn summarize_collection(
+        &self,
+        arguments: &HashMap<String, Value>,
+    ) -> McpResult<Vec<PromptMessage>> {
+        let collection = required(arguments, "collection")?;
+        let db = self.db.read().await;
+        let records: Vec<&DatabaseRecord> = store::live_records(&db)
+            .filter(|record| record.collection == collection)
+            .collect();
+        if records.is_empty() {
+            return Err(McpError::Validation(format!(
+                "Collection '{collection}' has no records"
+            )));
+        }
+
+        let mut instructions = format!(
+            "Summarize the '{collection}' collection of {} record(s): what the records represent, \
+             the fields they share and their typical values, and anything unusual such as \
+             missing fields, outliers or likely duplicates.",
+            records.len()
+        );
+        if records.len() > MAX_EMBEDDED_RECORDS {
+            instructions.push_str(&format!(
+                " Only the first {MAX_EMBEDDED_RECORDS} records are included."
+            ));
+        }
+        if let Some(focus) = argument(arguments, "focus") {
+            instructions.push_str(&format!("\n\nFocus on: {focus}"));
+        }
+
+        let embedded_records = to_json(records.into_iter().take(MAX_EMBEDDED_RECORDS));
+        Ok(vec![
+            text(instructions),
+            self.embed(&uri::collection_uri(collection), &embedded_records)?,
+        ])
+    }
+
+    async fn explain_record(
+        &self,
+        arguments: &HashMap<String, Value>,
+    ) -> McpResult<Vec<PromptMessage>> {
+        let id = required(arguments, "id")?;
+        let collection = argument(arguments, "collection");
+        let schemas = self.schemas.read().await;
+        let db = self.db.read().await;
+        let record = store::get_live(&db, id)
+            .filter(|record| collection.is_none_or(|collection| record.collection == collection))
+            .ok_or_else(|| McpError::Validation(format!("No record found with ID: {id}")))?;
+        self.db.touch(id);
+
+        let mut messages = vec![
+            text(format!(
+                "Explain record '{id}' from the '{}' collection: what it represents, what each \
+                 field means and how the values relate to each other. It is at version {}, \
+                 created {} and last updated {}.",
+                record.collection,
+                record.version,
+                record.created_at.to_rfc3339(),
+                record.updated_at.to_rfc3339()
+            )),
+            self.embed(&uri::record_uri(&record.collection, id), &record.to_json())?,
+        ];
+        if let Some(schema) = schemas.to_json().get(&record.collection) {
+            messages.push(text(format!(
+                "The '{}' collection has this JSON Schema:\n{}",
+                record.collection,
+                serde_json::to_string_pretty(schema)?
+            )));
+        }
+        Ok(messages)
+    }
+
+    async fn draft_query(
+        &self,
+        arguments: &HashMap<String, Value>,
+    ) -> McpResult<Vec<PromptMessage>> {
+        let question = required(arguments, "question")?;
+        let collection = argument(arguments, "collection");
+        let tools: Vec<String> = QUERY_TOOLS
+            .iter()
+            .filter(|(tool, _)| self.tools.contains(tool))
+            .map(|(tool, purpose)| format!("- `{tool}`: {purpose}"))
+            .collect();
+        if tools.is_empty() {
+            return Err(McpError::Validation(
+                "No query tools are exposed by this server".to_string(),
+            ));
+        }
+
+        let schemas = self.schemas.read().await;
+        let db = self.db.read().await;
+        let (sample_uri, sample) = match collection {
+            Some(collection) => (
+                uri::collection_uri(collection),
+                to_json(
+                    store::live_records(&db)
+                        .filter(|record| record.collection == collection)
+                        .take(SAMPLE_RECORDS),
+                ),
+            ),
+            None => (ALL_URI.to_string(), samples(&db)),
+        };
+
+        let scope = collection
+            .map(|collection| format!(" about the '{collection}' collection"))
+            .unwrap_or_default();
+        Ok(vec![
+            text(format!(
+                "Draft the database tool calls that answer this question{scope}:\n\n{question}\n\n\
+                 Available tools:\n{}\n\nReply with each tool name and its JSON arguments, and \
+                 explain briefly how the results answer the question. The schema and sample \
+                 records below show how the data is shaped.",
+                tools.join("\n")
+            )),
+            self.embed(SCHEMA_URI, &resources::schema_document(&schemas))?,
+            self.embed(&sample_uri, &sample)?,
+        ])
+    }
+
+    async fn review_schema(
+        &self,
+        arguments: &HashMap<String, Value>,
+    ) -> McpResult<Vec<PromptMessage>> {
+        let schemas = self.schemas.read().await;
+        let db = self.db.read().await;
+        let Some(collection) = argument(arguments, "collection") else {
+            return Ok(vec![
+                text(
+                    "Review the JSON Schemas attached to the database's collections, shown \
+                     below with a sample of each collection's records. Point out fields the \
+                     records hold that a schema does not describe, constraints that are missing \
+                     or too strict, and inconsistent naming or types across collections. For \
+                     collections without a schema, propose one. Give each suggested schema in \
+                     a form that can be passed to the `set_schema` tool.",
+                ),
+                self.embed(SCHEMA_URI, &resources::schema_document(&schemas))?,
+                self.embed(ALL_URI, &samples(&db))?,
+            ]);
+        };
+
+        let records: Vec<&DatabaseRecord> = store::live_records(&db)
+            .filter(|record| record.collection == collection)
+            .collect();
+        let instructions = match schemas.to_json().get(collection) {
+            Some(schema) => {
+                let violations = SchemaRegistry::check_records(schema, records.iter().copied())
+                    .map_err(McpError::Internal)?;
+                let mut instructions = format!(
+                    "Review the JSON Schema of the '{collection}' collection, shown below with a \
+                     sample of its records. Point out fields the records hold that the schema \
+                     does not describe, constraints that are missing or too strict, and naming \
+                     or typing inconsistencies, then suggest an improved schema that can be \
+                     passed to the `set_schema` tool.\n\nThe schema:\n{}",
+                    serde_json::to_string_pretty(schema)?
+                );
+                if !violations.is_empty() {
+                    instructions.push_str(&format!(
+                        "\n\n{} of the collection's {} record(s) do not match it:\n{}",
+                        violations.len(),
+                        records.len(),
+                        serde_json::to_string_pretty(
+                            &violations[..violations.len().min(MAX_LISTED_VIOLATIONS)]
+                        )?
+                    ));
+                }
+                instructions
+            }
+            None if records.is_empty() => {
+                return Err(McpError::Validation(format!(
+                    "Collection '{collection}' has no schema and no records"
+                )));
+            }
+            None => format!(
+                "The '{collection}' collection has no JSON Schema yet. Based on the sample of its \
+                 records below, propose one that captures their fields, types and required \
+                 members, in a form that can be passed to the `set_schema` tool."
+            ),
+        };
+
+        Ok(vec![
+            text(instructions),
+            self.embed(
+                &uri::collection_uri(collection),
+                &to_json(records.into_iter().take(SAMPLE_RECORDS)),
+            )?,
+        ])
+    }
+}
+
+/// A few records from every collection
+fn samples(records: &store::Records) -> Value {
+    let mut per_collection: HashMap<&str, usize> = HashMap::new();
+    to_json(store::live_records(records).filter(|record| {
+        let count = per_collection.entry(&record.collection).or_default();
+        *count += 1;
+        *count <= SAMPLE_RECORDS
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+    use crate::quota::Quotas;
+    use crate::store::RecordStore;
+    use access_control::auth::{self, AuthMethod, Principal};
+    use access_control::policy::Policy;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    async fn handler(prompt: Prompt) -> DatabasePromptHandler {
+        let db = Arc::new(RecordStore::open(Box::new(MemoryBackend), Quotas::default()).unwrap());
+        {
+            let mut records = db.write().await;
+            for i in 0..60 {
+                let id = format!("user{i}");
+                records.insert(
+                    id.clone(),
+                    DatabaseRecord::new(id, "users", json!({"name": format!("User {i}")})),
+                );
+            }
+        }
+        let mut schemas = SchemaRegistry::default();
+        schemas
+            .set("users", json!({"type": "object", "required": ["email"]}))
+            .unwrap();
+        DatabasePromptHandler {
+            db,
+            schemas: Arc::new(RwLock::new(schemas)),
+            prompt,
+            tools: vec!["list", "aggregate"],
+            policy: Arc::new(PolicyEngine::from(Policy::default())),
+        }
+    }
+
+    fn arguments(pairs: &[(&str, &str)]) -> HashMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect()
+    }
+
+    fn embedded_uris(result: &PromptResult) -> Vec<&str> {
+        result
+            .messages
+            .iter()
+            .filter_map(|message| match &message.content {
+                Content::Resource {
+                    resource: prism_mcp_rs::protocol::types::ResourceContents::Text { uri, .. },
+                    ..
+                } => Some(uri.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_prompts_embed_resources() {
+        let summarize = handler(Prompt::SummarizeCollection).await;
+        let result = summarize
+            .get(arguments(&[("collection", "users")]))
+            .await
+            .unwrap();
+        assert_eq!(embedded_uris(&result), ["db://users"]);
+        let Content::Text { text, .. } = &result.messages[0].content else {
+            panic!("expected instructions first");
+        };
+        assert!(text.contains("60 record(s)"));
+        assert!(text.contains(&format!("first {MAX_EMBEDDED_RECORDS} records")));
+
+        let explain = handler(Prompt::ExplainRecord).await;
+        let result = explain.get(arguments(&[("id", "user1")])).await.unwrap();
+        assert_eq!(embedded_uris(&result), [uri::record_uri("users", "user1")]);
+        assert!(explain.get(arguments(&[("id", "nobody")])).await.is_err());
+        assert!(
+            explain
+                .get(arguments(&[("id", "user1"), ("collection", "admins")]))
+                .await
+                .is_err()
+        );
+
+        let draft = handler(Prompt::DraftQuery).await;
+        let result = draft
+            .get(arguments(&[("question", "How many users are there?")]))
+            .await
+            .unwrap();
+        assert_eq!(embedded_uris(&result), [SCHEMA_URI, ALL_URI]);
+        let Content::Text { text, .. } = &result.messages[0].content else {
+            panic!("expected instructions first");
+        };
+        assert!(text.contains("`aggregate`"));
+        assert!(!text.contains("`search`"));
+        assert!(draft.get(HashMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_review_schema_lists_violations() {
+        let review = handler(Prompt::ReviewSchema).await;
+        let result = review
+            .get(arguments(&[("collection", "users")]))
+            .await
+            .unwrap();
+        let Content::Text { text, .. } = &result.messages[0].content else {
+            panic!("expected instructions first");
+        };
+        assert!(text.contains("60 of the collection's 60 record(s) do not match it"));
+        assert_eq!(embedded_uris(&result), ["db://users"]);
+
+        let result = review.get(HashMap::new()).await.unwrap();
+        assert_eq!(embedded_uris(&result), [SCHEMA_URI, ALL_URI]);
+        assert!(
+            review
+                .get(arguments(&[("collection", "missing")]))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prompts_only_embed_readable_resources() {
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            principals = ["ci"]
+            resources = ["db://users/records/user1", "db://users"]
+            "#,
+        )
+        .unwrap();
+        let ci = Principal {
+            id: "ci".to_string(),
+            roles: Vec::new(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        };
+        let policy = Arc::new(PolicyEngine::from(policy));
+        let get = |prompt: Prompt, pairs: &'static [(&'static str, &'static str)]| {
+            let policy = policy.clone();
+            let ci = ci.clone();
+            async move {
+                let handler = DatabasePromptHandler {
+                    policy,
+                    ..handler(prompt).await
+                };
+                auth::with_principal(Some(ci), handler.get(arguments(pairs))).await
+            }
+        };
+
+        let denied = get(Prompt::ExplainRecord, &[("id", "user1")]).await;
+        assert!(matches!(denied, Err(McpError::Authentication(_))));
+        let denied = get(Prompt::SummarizeCollection, &[("collection", "users")]).await;
+        assert!(matches!(denied, Err(McpError::Authentication(_))));
+        assert!(get(Prompt::ExplainRecord, &[("id", "user2")]).await.is_ok());
+        // Anyone else may still read them
+        let explain = DatabasePromptHandler {
+            policy,
+            ..handler(Prompt::ExplainRecord).await
+        };
+        assert!(explain.get(arguments(&[("id", "user1")])).await.is_ok());
+    }
+}
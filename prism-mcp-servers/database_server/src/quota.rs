@@ -0,0 +1,406 @@
+//! Limits on how much the database may hold.
+//!
+//! Quotas cap the number of records, the size of a single record's data and
+//! the total size of the database. They are checked whenever changes are
+//! committed: a write that would exceed them is either rejected or makes room
+//! by evicting other records, as chosen by the [`EvictionPolicy`]. A record
+//! whose data alone is over `max_record_bytes` is always rejected.
+//!
+//! Sizes are those of the serialized JSON. A record's share of the total also
+//! counts its id, the revisions kept in its history and its attachments.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::store::{Change, DatabaseRecord, Records, StoreError};
+
+/// What happens when a write would exceed `max_records` or `max_total_bytes`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// Refuse the write
+    #[default]
+    Reject,
+    /// Evict the records read or written least recently
+    Lru,
+    /// Evict the records created first
+    Oldest,
+}
+
+/// `[quotas]` section; limits left unset are unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+    pub max_records: Option<usize>,
+    /// Largest serialized `data` of a single record, in bytes
+    pub max_record_bytes: Option<usize>,
+    /// Largest total size of all records, in bytes
+    pub max_total_bytes: Option<usize>,
+    pub policy: EvictionPolicy,
+}
+
+/// A quota that can be exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    Records,
+    RecordBytes,
+    TotalBytes,
+}
+
+impl Quota {
+    /// Name of the setting that configures this quota
+    pub fn name(self) -> &'static str {
+        match self {
+            Quota::Records => "max_records",
+            Quota::RecordBytes => "max_record_bytes",
+            Quota::TotalBytes => "max_total_bytes",
+        }
+    }
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn json_len(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len())
+}
+
+/// Size of a record's data, as limited by `max_record_bytes`
+pub fn data_bytes(record: &DatabaseRecord) -> usize {
+    json_len(&record.data)
+}
+
+/// Size a record counts towards `max_total_bytes`
+pub fn footprint(record: &DatabaseRecord) -> usize {
+    let history: usize = record
+        .history
+        .iter()
+        .map(|revision| json_len(&revision.data))
+        .sum();
+    let attachments: usize = record
+        .attachments
+        .iter()
+        .map(|(name, attachment)| name.len() + attachment.size())
+        .sum();
+    record.id.len() + data_bytes(record) + history + attachments
+}
+
+/// How much the database holds, kept up to date as changes are committed
+#[derive(Debug, Default)]
+pub struct Usage {
+    /// Footprint of every stored record, including expired ones not yet swept
+    sizes: HashMap<String, usize>,
+    bytes: usize,
+    /// Records evicted to make room since startup
+    pub evicted: u64,
+    /// Writes rejected for exceeding a quota since startup
+    pub rejected: u64,
+}
+
+impl Usage {
+    /// Measure every record in `records`
+    pub fn measure(records: &Records) -> Self {
+        let mut usage = Self::default();
+        usage.update(records, records.keys().map(String::as_str));
+        usage
+    }
+
+    /// Number of stored records
+    pub fn records(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Total footprint of the stored records
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Re-measure the records in `ids`, dropping those no longer in `records`
+    pub fn update<'a>(&mut self, records: &Records, ids: impl IntoIterator<Item = &'a str>) {
+        for id in ids {
+            if let Some(size) = self.sizes.remove(id) {
+                self.bytes -= size;
+            }
+            if let Some(record) = records.get(id) {
+                let size = footprint(record);
+                self.bytes += size;
+                self.sizes.insert(id.to_string(), size);
+            }
+        }
+    }
+
+    /// Record count and total bytes once `changes` have been applied to `records`
+    fn projected(&self, records: &Records, changes: &[Change]) -> (usize, usize) {
+        let mut count = self.sizes.len();
+        let mut bytes = self.bytes;
+        let ids: HashSet<&str> = changes.iter().map(|change| change.id.as_str()).collect();
+        for id in ids {
+            if let Some(size) = self.sizes.get(id) {
+                count -= 1;
+                bytes -= size;
+            }
+            if let Some(record) = records.get(id) {
+                count += 1;
+                bytes += footprint(record);
+            }
+        }
+        (count, bytes)
+    }
+}
+
+impl Quotas {
+    /// The first limit `count` records of `bytes` in total exceed, with the amount requested
+    fn exceeded(&self, count: usize, bytes: usize) -> Option<(Quota, usize, usize)> {
+        if let Some(max) = self.max_records.filter(|&max| count > max) {
+            return Some((Quota::Records, max, count));
+        }
+        self.max_total_bytes
+            .filter(|&max| bytes > max)
+            .map(|max| (Quota::TotalBytes, max, bytes))
+    }
+
+    /// Check the records touched by `changes`, which have already been
+    /// applied to `records`, against the quotas.
+    ///
+    /// If the policy allows, records not touched by `changes` are evicted from
+    /// `records` to make room (expired ones first) and returned. On error
+    /// nothing has been evicted.
+    pub fn enforce(
+        &self,
+        usage: &Usage,
+        records: &mut Records,
+        changes: &[Change],
+        last_used: &HashMap<String, DateTime<Utc>>,
+    ) -> Result<Vec<DatabaseRecord>, StoreError> {
+        let exceeded = |(quota, limit, requested)| StoreError::QuotaExceeded {
+            quota,
+            limit,
+            requested,
+        };
+
+        if let Some(max) = self.max_record_bytes {
+            for change in changes {
+                let size = records.get(&change.id).map_or(0, data_bytes);
+                if size > max {
+                    return Err(exceeded((Quota::RecordBytes, max, size)));
+                }
+            }
+        }
+
+        // Writes that do not grow the database are always allowed, so records
+        // can still be removed when a limit was lowered below current usage
+        let (mut count, mut bytes) = usage.projected(records, changes);
+        if count <= usage.records() && bytes <= usage.bytes() {
+            return Ok(Vec::new());
+        }
+        let Some(over) = self.exceeded(count, bytes) else {
+            return Ok(Vec::new());
+        };
+        if self.policy == EvictionPolicy::Reject {
+            return Err(exceeded(over));
+        }
+
+        let now = Utc::now();
+        let changed: HashSet<&str> = changes.iter().map(|change| change.id.as_str()).collect();
+        let mut candidates: Vec<&DatabaseRecord> = records
+            .values()
+            .filter(|record| !changed.contains(record.id.as_str()))
+            .collect();
+        candidates.sort_by_key(|record| {
+            let age = match self.policy {
+                EvictionPolicy::Oldest => record.created_at,
+                _ => last_used
+                    .get(&record.id)
+                    .copied()
+                    .unwrap_or(record.updated_at),
+            };
+            (!record.is_expired(now), age)
+        });
+
+        let mut victims = Vec::new();
+        for record in candidates {
+            if self.exceeded(count, bytes).is_none() {
+                break;
+            }
+            count -= 1;
+            bytes -= usage.sizes.get(&record.id).copied().unwrap_or(0);
+            victims.push(record.id.clone());
+        }
+        if let Some(over) = self.exceeded(count, bytes) {
+            return Err(exceeded(over));
+        }
+        Ok(victims.iter().filter_map(|id| records.remove(id)).collect())
+    }
+
+    /// Usage against the limits, as served by the stats resource
+    pub fn report(&self, usage: &Usage) -> Value {
+        let utilization = |used: usize, max: Option<usize>| {
+            max.map(|max| {
+                if max == 0 {
+                    1.0
+                } else {
+                    used as f64 / max as f64
+                }
+            })
+        };
+        json!({
+            "records": usage.records(),
+            "total_bytes": usage.bytes(),
+            "limits": {
+                "max_records": self.max_records,
+                "max_record_bytes": self.max_record_bytes,
+                "max_total_bytes": self.max_total_bytes,
+                "policy": self.policy
+            },
+            "utilization": {
+                "records": utilization(usage.records(), self.max_records),
+                "total_bytes": utilization(usage.bytes(), self.max_total_bytes)
+            },
+            "evicted": usage.evicted,
+            "rejected": usage.rejected
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(records: &[DatabaseRecord]) -> Records {
+        records
+            .iter()
+            .map(|record| (record.id.clone(), record.clone()))
+            .collect()
+    }
+
+    /// Insert `record` into `records`, returning the change
+    fn insert(records: &mut Records, record: DatabaseRecord) -> Vec<Change> {
+        let change = Change::between(None, Some(&record)).unwrap();
+        records.insert(record.id.clone(), record);
+        vec![change]
+    }
+
+    #[test]
+    fn test_usage_tracks_footprints() {
+        let mut records = stored(&[DatabaseRecord::new("a", "users", json!({"n": 1}))]);
+        let mut usage = Usage::measure(&records);
+        assert_eq!(usage.records(), 1);
+        assert_eq!(usage.bytes(), "a".len() + r#"{"n":1}"#.len());
+
+        records.remove("a");
+        records.insert(
+            "bb".to_string(),
+            DatabaseRecord::new("bb", "users", json!([])),
+        );
+        usage.update(&records, ["a", "bb"]);
+        assert_eq!(usage.records(), 1);
+        assert_eq!(usage.bytes(), 4);
+    }
+
+    #[test]
+    fn test_reject_policy() {
+        let quotas = Quotas {
+            max_records: Some(1),
+            max_record_bytes: Some(10),
+            ..Quotas::default()
+        };
+        let mut records = stored(&[DatabaseRecord::new("a", "users", json!(1))]);
+        let usage = Usage::measure(&records);
+
+        let changes = insert(
+            &mut records,
+            DatabaseRecord::new("b", "users", json!("far more than ten bytes")),
+        );
+        let err = quotas
+            .enforce(&usage, &mut records, &changes, &HashMap::new())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            StoreError::QuotaExceeded {
+                quota: Quota::RecordBytes,
+                limit: 10,
+                ..
+            }
+        ));
+
+        let changes = insert(&mut records, DatabaseRecord::new("b", "users", json!(2)));
+        let err = quotas
+            .enforce(&usage, &mut records, &changes, &HashMap::new())
+            .unwrap_err();
+        assert_eq!(err.code(), "quota_exceeded");
+        assert!(matches!(
+            err,
+            StoreError::QuotaExceeded {
+                quota: Quota::Records,
+                limit: 1,
+                requested: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_eviction_policies() {
+        let mut old = DatabaseRecord::new("old", "users", json!(1));
+        old.created_at -= chrono::Duration::hours(1);
+        old.updated_at = Utc::now();
+        let mut idle = DatabaseRecord::new("idle", "users", json!(2));
+        idle.updated_at -= chrono::Duration::hours(2);
+        let initial = stored(&[old, idle]);
+        let usage = Usage::measure(&initial);
+        let new = DatabaseRecord::new("new", "users", json!(3));
+
+        let evicted = |policy, last_used: &HashMap<String, DateTime<Utc>>| {
+            let quotas = Quotas {
+                max_records: Some(2),
+                policy,
+                ..Quotas::default()
+            };
+            let mut records = initial.clone();
+            let changes = insert(&mut records, new.clone());
+            let evicted = quotas
+                .enforce(&usage, &mut records, &changes, last_used)
+                .unwrap();
+            assert_eq!(records.len(), 2);
+            assert!(records.contains_key("new"));
+            evicted
+                .into_iter()
+                .map(|record| record.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(evicted(EvictionPolicy::Oldest, &HashMap::new()), ["old"]);
+        assert_eq!(evicted(EvictionPolicy::Lru, &HashMap::new()), ["idle"]);
+        let read_idle = HashMap::from([("idle".to_string(), Utc::now())]);
+        assert_eq!(evicted(EvictionPolicy::Lru, &read_idle), ["old"]);
+    }
+
+    #[test]
+    fn test_eviction_cannot_make_room_for_oversized_writes() {
+        let quotas = Quotas {
+            max_total_bytes: Some(16),
+            policy: EvictionPolicy::Lru,
+            ..Quotas::default()
+        };
+        let mut records = stored(&[DatabaseRecord::new("a", "users", json!(1))]);
+        let usage = Usage::measure(&records);
+        let changes = insert(
+            &mut records,
+            DatabaseRecord::new("b", "users", json!("more than sixteen bytes")),
+        );
+        assert!(matches!(
+            quotas.enforce(&usage, &mut records, &changes, &HashMap::new()),
+            Err(StoreError::QuotaExceeded {
+                quota: Quota::TotalBytes,
+                ..
+            })
+        ));
+        assert!(records.contains_key("a"));
+    }
+}
@@ -0,0 +1,545 @@
+//! MCP resources exposing the database contents.
+//!
+//! `resources/list` returns the fixed resources, one entry per collection and
+//! the stored exports. Individual records are reached through the
+//! [`RECORD_TEMPLATE`] resource template, whose `id` variable supports
+//! completion, as do the `id` and `collection` arguments of the prompts;
+//! completions leave out records, collections and attachments the policy
+//! does not let the caller read.
+//! Attachments are served as blobs through [`ATTACHMENT_TEMPLATE`].
+
+use async_trait::async_trait;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use access_control::policy::PolicyEngine;
+
+use prism_mcp_rs::{
+    core::{
+        completion::CompletionHandler,
+        error::{McpError, McpResult},
+        resource::ResourceHandler,
+    },
+    protocol::types::{
+        CompletionArgument, CompletionContext, CompletionReference, Resource as ResourceInfo,
+        ResourceContents, ResourceTemplate,
+    },
+};
+
+use crate::access;
+use crate::pagination::{self, Cursor};
+use crate::prompts::Prompt;
+use crate::schema::{SchemaRegistry, Schemas};
+use crate::store::{self, Database, Records};
+use crate::subscriptions::ChangeNotifier;
+use crate::transfer::{Export, Exports};
+use crate::uri::{
+    ALL_URI, ATTACHMENT_TEMPLATE, COLLECTION_TEMPLATE, DbUri, RECORD_TEMPLATE, SCHEMA_URI,
+    STATS_URI, attachment_uri, collection_uri, record_uri,
+};
+
+/// Maximum number of values returned by `completion/complete`
+const MAX_COMPLETIONS: usize = 100;
+
+/// Number of resources returned per `resources/list` page
+const LIST_PAGE_SIZE: usize = 50;
+/// Cursor scope for `resources/list`, so `list` tool cursors are rejected
+const LIST_SCOPE: &str = "resources";
+
+/// A resource included in `resources/list`
+enum Listed {
+    All,
+    Schema,
+    Stats,
+    Collection(String),
+    Export(Export),
+}
+
+impl Listed {
+    fn info(&self) -> ResourceInfo {
+        let mut mime_type = "application/json";
+        let mut size = None;
+        let (uri, name, description) = match self {
+            Listed::All => (
+                ALL_URI.to_string(),
+                "All Records".to_string(),
+                "All records in the database".to_string(),
+            ),
+            Listed::Schema => (
+                SCHEMA_URI.to_string(),
+                "Database Schema".to_string(),
+                "JSON schema for database records and per-collection data schemas".to_string(),
+            ),
+            Listed::Stats => (
+                STATS_URI.to_string(),
+                "Database Statistics".to_string(),
+                "Record count and size against the configured quotas".to_string(),
+            ),
+            Listed::Collection(collection) => (
+                collection_uri(collection),
+                format!("Collection: {collection}"),
+                format!("All records in the '{collection}' collection"),
+            ),
+            Listed::Export(export) => {
+                mime_type = export.format.mime_type();
+                size = Some(export.content.len() as u64);
+                (
+                    export.uri(),
+                    format!("Export: {}", export.name),
+                    format!("Export of {} record(s)", export.records),
+                )
+            }
+        };
+
+        ResourceInfo {
+            uri,
+            title: Some(name.clone()),
+            name,
+            description: Some(description),
+            mime_type: Some(mime_type.to_string()),
+            annotations: None,
+            size,
+            meta: None,
+        }
+    }
+}
+
+/// `value` as the JSON contents of the resource at `uri`
+pub fn json_contents(uri: &str, value: &impl serde::Serialize) -> McpResult<Vec<ResourceContents>> {
+    Ok(vec![ResourceContents::Text {
+        uri: uri.to_string(),
+        mime_type: Some("application/json".to_string()),
+        text: serde_json::to_string_pretty(value)?,
+        meta: None,
+    }])
+}
+
+/// The contents of the schema resource: the shape of every record, and the
+/// schemas attached to collections
+pub fn schema_document(schemas: &SchemaRegistry) -> Value {
+    let record = json!({
+        "type": "object",
+        "properties": {
+            "id": {
+                "type": "string",
+                "description": "Unique identifier for the record"
+            },
+            "collection": {
+                "type": "string",
+                "description": "Collection the record belongs to"
+            },
+            "data": {
+                "description": "The stored data; must match the collection's schema in 'collections', if it has one"
+            },
+            "version": {
+                "type": "integer",
+                "minimum": 1,
+                "description": "Incremented on every update; pass as 'expected_version' for optimistic concurrency"
+            },
+            "created_at": {
+                "type": "string",
+                "format": "date-time",
+                "description": "When the record was created"
+            },
+            "updated_at": {
+                "type": "string",
+                "format": "date-time",
+                "description": "When the record was last updated"
+            },
+            "expires_at": {
+                "type": "string",
+                "format": "date-time",
+                "description": "When the record expires (only present for records with a TTL)"
+            },
+            "ttl_seconds_remaining": {
+                "type": "integer",
+                "description": "Seconds until the record expires (only present for records with a TTL)"
+            }
+        }
+    });
+
+    json!({
+        "record": record,
+        "collections": schemas.to_json()
+    })
+}
+
+/// Resource handler for accessing database contents
+pub struct DatabaseResourceHandler {
+    pub db: Database,
+    pub schemas: Schemas,
+    pub exports: Exports,
+    pub notifier: ChangeNotifier,
+}
+
+#[async_trait]
+impl ResourceHandler for DatabaseResourceHandler {
+    async fn read(
+        &self,
+        uri: &str,
+        _params: &HashMap<String, String>,
+    ) -> McpResult<Vec<ResourceContents>> {
+        let parsed = DbUri::parse(uri).map_err(McpError::InvalidUri)?;
+        match parsed {
+            DbUri::All => {
+                let db = self.db.read().await;
+                let records: Vec<_> = store::live_records(&db).map(|r| r.to_json()).collect();
+
+                json_contents(uri, &records)
+            }
+            DbUri::Stats => json_contents(uri, &self.db.stats()),
+            DbUri::Schema => json_contents(uri, &schema_document(&*self.schemas.read().await)),
+            DbUri::Collection { collection, filter } => {
+                let db = self.db.read().await;
+                let mut exists = false;
+                let records: Vec<_> = store::live_records(&db)
+                    .filter(|record| record.collection == collection)
+                    .inspect(|_| exists = true)
+                    .filter(|record| filter.matches(record))
+                    .map(|record| record.to_json())
+                    .collect();
+
+                if !exists {
+                    return Err(McpError::ResourceNotFound(uri.to_string()));
+                }
+                json_contents(uri, &records)
+            }
+            DbUri::Record { collection, id } => {
+                let db = self.db.read().await;
+
+                match store::get_live(&db, &id) {
+                    Some(record) if record.collection == collection => {
+                        self.db.touch(&id);
+                        json_contents(uri, &record.to_json())
+                    }
+                    _ => Err(McpError::ResourceNotFound(uri.to_string())),
+                }
+            }
+            DbUri::Attachment {
+                collection,
+                id,
+                name,
+            } => {
+                let db = self.db.read().await;
+
+                let attachment = store::get_live(&db, &id)
+                    .filter(|record| record.collection == collection)
+                    .and_then(|record| record.attachments.get(&name))
+                    .ok_or_else(|| McpError::ResourceNotFound(uri.to_string()))?;
+                self.db.touch(&id);
+                Ok(vec![ResourceContents::Blob {
+                    uri: uri.to_string(),
+                    mime_type: Some(attachment.mime_type.clone()),
+                    blob: BASE64.encode(&attachment.content),
+                    meta: None,
+                }])
+            }
+            DbUri::Export(name) => {
+                let exports = self.exports.read().await;
+
+                match exports.get(&name) {
+                    Some(export) => Ok(vec![ResourceContents::Text {
+                        uri: uri.to_string(),
+                        mime_type: Some(export.format.mime_type().to_string()),
+                        text: export.content.clone(),
+                        meta: None,
+                    }]),
+                    None => Err(McpError::ResourceNotFound(uri.to_string())),
+                }
+            }
+        }
+    }
+
+    async fn list(&self) -> McpResult<Vec<ResourceInfo>> {
+        let (resources, _) = self.list_paginated(None).await?;
+        Ok(resources)
+    }
+
+    async fn list_paginated(
+        &self,
+        cursor: Option<&str>,
+    ) -> McpResult<(Vec<ResourceInfo>, Option<String>)> {
+        let cursor = cursor
+            .map(|cursor| Cursor::decode(cursor, Some(LIST_SCOPE)))
+            .transpose()
+            .map_err(McpError::Validation)?;
+
+        // Every listed resource keyed by URI, which gives a stable ordering
+        let entries = {
+            let db = self.db.read().await;
+            let mut entries = BTreeMap::new();
+            entries.insert(ALL_URI.to_string(), Listed::All);
+            entries.insert(SCHEMA_URI.to_string(), Listed::Schema);
+            entries.insert(STATS_URI.to_string(), Listed::Stats);
+            for record in store::live_records(&db) {
+                entries
+                    .entry(collection_uri(&record.collection))
+                    .or_insert_with(|| Listed::Collection(record.collection.clone()));
+            }
+            for export in self.exports.read().await.iter() {
+                entries.insert(export.uri(), Listed::Export(export.clone()));
+            }
+            entries
+        };
+
+        let page = pagination::page(
+            &entries,
+            cursor.as_ref(),
+            Some(LIST_SCOPE),
+            LIST_PAGE_SIZE,
+            |_| true,
+        );
+        let resources = page.items.into_iter().map(Listed::info).collect();
+        Ok((resources, page.next_cursor))
+    }
+
+    async fn subscribe(&self, uri: &str) -> McpResult<()> {
+        if let DbUri::Export(_) = DbUri::parse(uri).map_err(McpError::InvalidUri)? {
+            return Err(McpError::Validation(
+                "Exports do not change and cannot be subscribed to".to_string(),
+            ));
+        }
+
+        self.notifier.subscribe(uri).await;
+        tracing::debug!("Subscribed to {uri}");
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, uri: &str) -> McpResult<()> {
+        self.notifier.unsubscribe(uri).await;
+        tracing::debug!("Unsubscribed from {uri}");
+        Ok(())
+    }
+}
+
+/// The resource templates published through `resources/templates/list`
+pub fn templates() -> Vec<ResourceTemplate> {
+    vec![
+        ResourceTemplate {
+            uri_template: RECORD_TEMPLATE.to_string(),
+            name: "record".to_string(),
+            title: Some("Database Record".to_string()),
+            description: Some(
+                "A single record; 'collection' and 'id' are percent-encoded".to_string(),
+            ),
+            mime_type: Some("application/json".to_string()),
+            annotations: None,
+            meta: None,
+        },
+        ResourceTemplate {
+            uri_template: COLLECTION_TEMPLATE.to_string(),
+            name: "collection".to_string(),
+            title: Some("Database Collection".to_string()),
+            description: Some(
+                "Records in a collection; 'filter' is a percent-encoded JSON object mapping JSON Pointers to the values records must hold"
+                    .to_string(),
+            ),
+            mime_type: Some("application/json".to_string()),
+            annotations: None,
+            meta: None,
+        },
+        ResourceTemplate {
+            uri_template: ATTACHMENT_TEMPLATE.to_string(),
+            name: "attachment".to_string(),
+            title: Some("Record Attachment".to_string()),
+            description: Some(
+                "A binary attachment of a record, served as a blob with its own MIME type"
+                    .to_string(),
+            ),
+            mime_type: None,
+            annotations: None,
+            meta: None,
+        },
+    ]
+}
+
+/// Values to suggest for the template variable `argument` starting with `prefix`.
+///
+/// Record ids are limited to the `collection` the client has already
+/// resolved, if any; attachment names need the record `id` resolved. Only
+/// values whose resource URI is `readable` are suggested.
+pub fn suggest(
+    records: &Records,
+    argument: &str,
+    prefix: &str,
+    resolved: &HashMap<String, String>,
+    readable: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let collection = resolved.get("collection").map(String::as_str);
+    // Each candidate with the URI of the resource it names
+    let candidates: Vec<(String, String)> = match argument {
+        "id" => store::live_records(records)
+            .filter(|record| collection.is_none_or(|collection| record.collection == collection))
+            .map(|record| {
+                let uri = record_uri(&record.collection, &record.id);
+                (record.id.clone(), uri)
+            })
+            .collect(),
+        "collection" => {
+            let mut collections: Vec<String> = store::live_records(records)
+                .map(|record| record.collection.clone())
+                .collect();
+            collections.sort();
+            collections.dedup();
+            collections
+                .into_iter()
+                .map(|collection| {
+                    let uri = collection_uri(&collection);
+                    (collection, uri)
+                })
+                .collect()
+        }
+        "name" => resolved
+            .get("id")
+            .and_then(|id| store::get_live(records, id))
+            .filter(|record| collection.is_none_or(|collection| record.collection == collection))
+            .map(|record| {
+                record
+                    .attachments
+                    .keys()
+                    .map(|name| {
+                        let uri = attachment_uri(&record.collection, &record.id, name);
+                        (name.clone(), uri)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|(candidate, uri)| candidate.starts_with(prefix) && readable(uri))
+        .map(|(candidate, _)| candidate)
+        .take(MAX_COMPLETIONS)
+        .collect()
+}
+
+/// Completes the variables of the database resource templates and prompt arguments
+pub struct DatabaseCompletionHandler {
+    pub db: Database,
+    /// Decides which suggestions the caller may see
+    pub policy: Arc<PolicyEngine>,
+}
+
+#[async_trait]
+impl CompletionHandler for DatabaseCompletionHandler {
+    async fn complete(
+        &self,
+        reference: &CompletionReference,
+        argument: &CompletionArgument,
+        context: Option<&CompletionContext>,
+    ) -> McpResult<Vec<String>> {
+        let completes = match reference {
+            CompletionReference::Resource { uri } => {
+                [RECORD_TEMPLATE, COLLECTION_TEMPLATE, ATTACHMENT_TEMPLATE].contains(&uri.as_str())
+            }
+            CompletionReference::Prompt { name } => Prompt::from_name(name).is_some(),
+        };
+        if !completes {
+            return Ok(Vec::new());
+        }
+
+        let no_arguments = HashMap::new();
+        let resolved = context
+            .and_then(|context| context.arguments.as_ref())
+            .unwrap_or(&no_arguments);
+        let db = self.db.read().await;
+        let readable = |uri: &str| access::allows_resource(&self.policy, uri);
+        Ok(suggest(
+            &db,
+            &argument.name,
+            &argument.value,
+            resolved,
+            readable,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::Attachment;
+    use crate::store::DatabaseRecord;
+    use serde_json::json;
+
+    #[test]
+    fn test_suggest_record_ids() {
+        let mut records = Records::new();
+        for (id, collection) in [("user1", "users"), ("user2", "users"), ("admin1", "admins")] {
+            records.insert(
+                id.to_string(),
+                DatabaseRecord::new(id, collection, json!({})),
+            );
+        }
+
+        let none = HashMap::new();
+        let admins = HashMap::from([("collection".to_string(), "admins".to_string())]);
+        assert_eq!(
+            suggest(&records, "id", "user", &none, |_| true),
+            ["user1", "user2"]
+        );
+        assert_eq!(suggest(&records, "id", "", &admins, |_| true), ["admin1"]);
+        assert_eq!(
+            suggest(&records, "collection", "", &none, |_| true),
+            ["admins", "users"]
+        );
+        assert!(suggest(&records, "filter", "", &none, |_| true).is_empty());
+
+        records.get_mut("user1").unwrap().attachments.insert(
+            "avatar.png".to_string(),
+            Attachment::new("image/png", vec![0u8; 4]),
+        );
+        let user1 = HashMap::from([("id".to_string(), "user1".to_string())]);
+        assert_eq!(
+            suggest(&records, "name", "av", &user1, |_| true),
+            ["avatar.png"]
+        );
+        assert!(suggest(&records, "name", "", &none, |_| true).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_suggestions_follow_the_policy() {
+        use access_control::auth::{self, AuthMethod, Principal};
+        use access_control::policy::Policy;
+
+        let mut records = Records::new();
+        for (id, collection) in [("user1", "users"), ("user2", "users"), ("admin1", "admins")] {
+            records.insert(
+                id.to_string(),
+                DatabaseRecord::new(id, collection, json!({})),
+            );
+        }
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            principals = ["ci"]
+            resources = ["db://admins*", "db://users/records/user2"]
+            "#,
+        )
+        .unwrap();
+        let policy = PolicyEngine::from(policy);
+        let ci = Principal {
+            id: "ci".to_string(),
+            roles: Vec::new(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        };
+        let none = HashMap::new();
+        let readable = |uri: &str| access::allows_resource(&policy, uri);
+
+        let (ids, collections) = auth::with_principal(Some(ci), async {
+            (
+                suggest(&records, "id", "", &none, readable),
+                suggest(&records, "collection", "", &none, readable),
+            )
+        })
+        .await;
+        assert_eq!(ids, ["user1"]);
+        assert_eq!(collections, ["users"]);
+        assert_eq!(suggest(&records, "id", "", &none, readable).len(), 3);
+    }
+}
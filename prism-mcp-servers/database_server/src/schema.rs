@@ -0,0 +1,378 @@
+//! Per-collection JSON Schemas for record data.
+//!
+//! A collection without a schema accepts any JSON value. Once a schema is
+//! attached, every write into the collection (store, patch or transaction
+//! step) must produce `data` that validates against it.
+//!
+//! Schemas come from the schema file and from the `set_schema` tool. The
+//! storage backend keeps what the tool sets (or removes), and at startup
+//! that takes precedence over the file; with the memory backend only the
+//! file's schemas outlive the server.
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::backend::BackendError;
+use crate::store::{self, Database, DatabaseRecord, StoreError};
+use crate::subscriptions::ChangeNotifier;
+use crate::uri::SCHEMA_URI;
+
+/// Maximum number of violations reported for a single value
+const MAX_VIOLATIONS: usize = 20;
+
+/// A single reason a value does not match its collection's schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the offending part of the record data
+    pub path: String,
+    /// JSON Pointer to the schema keyword that failed
+    pub schema_path: String,
+    pub message: String,
+}
+
+struct CollectionSchema {
+    schema: Value,
+    validator: jsonschema::Validator,
+}
+
+/// Schemas attached to collections
+#[derive(Default)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<String, CollectionSchema>,
+}
+
+/// Shared schema state
+///
+/// Handlers that need both locks take this one before the records lock.
+pub type Schemas = Arc<RwLock<SchemaRegistry>>;
+
+/// Compile a schema, reporting why it is not a valid JSON Schema
+fn compile(schema: &Value) -> Result<jsonschema::Validator, String> {
+    jsonschema::validator_for(schema).map_err(|e| format!("Invalid JSON Schema: {e}"))
+}
+
+impl SchemaRegistry {
+    /// Load schemas from a JSON file mapping collection names to schemas
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let schemas: BTreeMap<String, Value> = serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+
+        let mut registry = Self::default();
+        for (collection, schema) in schemas {
+            registry
+                .set(&collection, schema)
+                .map_err(|e| format!("Collection '{collection}': {e}"))?;
+        }
+        Ok(registry)
+    }
+
+    /// Apply the schemas set or removed with `set_schema`, as kept by the storage backend
+    pub fn restore(&mut self, stored: BTreeMap<String, Option<Value>>) -> Result<(), String> {
+        for (collection, schema) in stored {
+            if self.schemas.contains_key(&collection) {
+                tracing::info!(
+                    "Collection '{collection}': the schema set with set_schema replaces the schema file's"
+                );
+            }
+            match schema {
+                Some(schema) => self
+                    .set(&collection, schema)
+                    .map_err(|e| format!("Collection '{collection}': {e}"))?,
+                None => {
+                    self.remove(&collection);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Attach `schema` to `collection`, replacing any previous schema
+    pub fn set(&mut self, collection: &str, schema: Value) -> Result<(), String> {
+        let validator = compile(&schema)?;
+        self.schemas.insert(
+            collection.to_string(),
+            CollectionSchema { schema, validator },
+        );
+        Ok(())
+    }
+
+    /// Whether `collection` has a schema
+    pub fn contains(&self, collection: &str) -> bool {
+        self.schemas.contains_key(collection)
+    }
+
+    /// Detach the schema from `collection`, returning whether one was attached
+    pub fn remove(&mut self, collection: &str) -> bool {
+        self.schemas.remove(collection).is_some()
+    }
+
+    /// All attached schemas keyed by collection
+    pub fn to_json(&self) -> Value {
+        let schemas: serde_json::Map<String, Value> = self
+            .schemas
+            .iter()
+            .map(|(collection, entry)| (collection.clone(), entry.schema.clone()))
+            .collect();
+        Value::Object(schemas)
+    }
+
+    /// Validate `data` for `collection`, returning every violation found
+    pub fn validate(&self, collection: &str, data: &Value) -> Result<(), Vec<SchemaViolation>> {
+        match self.schemas.get(collection) {
+            Some(entry) => violations(&entry.validator, data),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a candidate schema against existing records before attaching it
+    pub fn check_records<'a>(
+        schema: &Value,
+        records: impl IntoIterator<Item = &'a DatabaseRecord>,
+    ) -> Result<Vec<Value>, String> {
+        let validator = compile(schema)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                violations(&validator, &record.data)
+                    .err()
+                    .map(|violations| {
+                        json!({
+                            "id": record.id,
+                            "violations": violations
+                        })
+                    })
+            })
+            .collect())
+    }
+}
+
+fn violations(validator: &jsonschema::Validator, data: &Value) -> Result<(), Vec<SchemaViolation>> {
+    let violations: Vec<_> = validator
+        .iter_errors(data)
+        .take(MAX_VIOLATIONS)
+        .map(|error| SchemaViolation {
+            path: error.instance_path.to_string(),
+            schema_path: error.schema_path.to_string(),
+            message: error.to_string(),
+        })
+        .collect();
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Tool result for a schema change the storage backend failed to save
+fn storage_failure(err: BackendError) -> ToolResult {
+    crate::store_error_result(StoreError::Storage {
+        message: err.to_string(),
+    })
+}
+
+/// Database tool handler for attaching or removing a collection's schema
+///
+/// The change is saved to the storage backend before it takes effect.
+pub struct SetSchemaHandler {
+    pub db: Database,
+    pub schemas: Schemas,
+    pub notifier: ChangeNotifier,
+}
+
+#[async_trait]
+impl ToolHandler for SetSchemaHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let collection = arguments
+            .get("collection")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| McpError::Validation("Missing 'collection' parameter".to_string()))?;
+
+        let schema = arguments.get("schema").cloned().unwrap_or(Value::Null);
+
+        let message = {
+            let mut schemas = self.schemas.write().await;
+            if schema.is_null() {
+                if !schemas.contains(collection) {
+                    return Ok(ToolResult {
+                        content: vec![Content::text(format!(
+                            "Collection '{collection}' has no schema"
+                        ))],
+                        is_error: Some(true),
+                        structured_content: None,
+                        meta: None,
+                    });
+                }
+                if let Err(err) = self.db.save_schema(collection, None) {
+                    return Ok(storage_failure(err));
+                }
+                schemas.remove(collection);
+                format!("Removed schema from collection '{collection}'")
+            } else {
+                let db = self.db.read().await;
+                let existing =
+                    store::live_records(&db).filter(|record| record.collection == collection);
+                let invalid = SchemaRegistry::check_records(&schema, existing)
+                    .map_err(McpError::Validation)?;
+                if !invalid.is_empty() {
+                    return Ok(ToolResult {
+                        content: vec![Content::text(format!(
+                            "Schema not applied: {} existing record(s) in collection '{collection}' do not match it",
+                            invalid.len()
+                        ))],
+                        is_error: Some(true),
+                        structured_content: Some(json!({
+                            "error": "schema_violation",
+                            "collection": collection,
+                            "records": invalid
+                        })),
+                        meta: None,
+                    });
+                }
+                if let Err(err) = self.db.save_schema(collection, Some(&schema)) {
+                    return Ok(storage_failure(err));
+                }
+                schemas
+                    .set(collection, schema)
+                    .map_err(McpError::Validation)?;
+                format!("Set schema for collection '{collection}'")
+            }
+        };
+        self.notifier.notify_updated(SCHEMA_URI).await;
+
+        Ok(ToolResult {
+            content: vec![Content::text(message)],
+            is_error: None,
+            structured_content: None,
+            meta: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::StorageBackend;
+    use crate::quota::Quotas;
+    use crate::store::{RecordStore, Records};
+
+    #[test]
+    fn test_only_collections_with_schemas_are_validated() {
+        let mut registry = SchemaRegistry::default();
+        registry
+            .set(
+                "users",
+                json!({
+                    "type": "object",
+                    "properties": {"age": {"type": "integer"}},
+                    "required": ["name"]
+                }),
+            )
+            .unwrap();
+
+        assert!(registry.validate("other", &json!("anything")).is_ok());
+        assert!(
+            registry
+                .validate("users", &json!({"name": "Alice"}))
+                .is_ok()
+        );
+
+        let violations = registry
+            .validate("users", &json!({"age": "thirty"}))
+            .unwrap_err();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().any(|v| v.path == "/age"));
+    }
+
+    /// Keeps the schemas it is given where the test can see them
+    #[derive(Clone, Default)]
+    struct SavedSchemas(Arc<std::sync::Mutex<BTreeMap<String, Option<Value>>>>);
+
+    impl StorageBackend for SavedSchemas {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn load(&self) -> Result<Records, BackendError> {
+            Ok(Records::new())
+        }
+
+        fn apply(
+            &self,
+            _upserts: &[&DatabaseRecord],
+            _deletes: &[&str],
+        ) -> Result<(), BackendError> {
+            Ok(())
+        }
+
+        fn load_schemas(&self) -> Result<BTreeMap<String, Option<Value>>, BackendError> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn save_schema(
+            &self,
+            collection: &str,
+            schema: Option<&Value>,
+        ) -> Result<(), BackendError> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(collection.to_string(), schema.cloned());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_schema_is_saved_and_restored() {
+        let saved = SavedSchemas::default();
+        let db = Arc::new(RecordStore::open(Box::new(saved.clone()), Quotas::default()).unwrap());
+        let (notifier, _) = ChangeNotifier::new();
+        let handler = SetSchemaHandler {
+            db,
+            schemas: Arc::new(RwLock::new(SchemaRegistry::default())),
+            notifier,
+        };
+        let call = |collection: &str, schema: Value| {
+            handler.call(HashMap::from([
+                ("collection".to_string(), json!(collection)),
+                ("schema".to_string(), schema),
+            ]))
+        };
+        let schema = json!({"type": "object"});
+        call("users", schema.clone()).await.unwrap();
+        call("notes", schema.clone()).await.unwrap();
+        call("notes", Value::Null).await.unwrap();
+
+        // On restart, what set_schema saved replaces the schema file's schemas
+        let mut restarted = SchemaRegistry::default();
+        restarted.set("notes", json!({"type": "string"})).unwrap();
+        restarted.set("tags", json!({"type": "string"})).unwrap();
+        restarted.restore(saved.load_schemas().unwrap()).unwrap();
+        assert_eq!(
+            restarted.to_json(),
+            json!({"tags": {"type": "string"}, "users": schema})
+        );
+    }
+
+    #[test]
+    fn test_invalid_schema_is_rejected() {
+        let mut registry = SchemaRegistry::default();
+        assert!(registry.set("users", json!({"type": "nonsense"})).is_err());
+        assert_eq!(registry.to_json(), json!({}));
+    }
+}
@@ -0,0 +1,553 @@
+//! Full-text search over the string values inside record data.
+//!
+//! Strings are split into alphanumeric tokens and case-folded. An inverted
+//! index maps each token to the records and positions it occurs at, which
+//! supports plain terms, `"quoted phrases"` and `prefix*` queries ranked with
+//! BM25. The index is kept up to date lazily: before each search, only
+//! records whose version changed since they were indexed are re-tokenized.
+
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tokio::sync::Mutex;
+
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::store::{self, Database, DatabaseRecord, Records};
+
+/// BM25 term-frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document-length normalization
+const B: f64 = 0.75;
+/// Tokens of context shown before the first highlighted match
+const SNIPPET_LEADING: usize = 6;
+/// Maximum tokens shown in a snippet
+const SNIPPET_TOKENS: usize = 24;
+/// Maximum snippets returned per record
+const MAX_SNIPPETS: usize = 3;
+
+/// A token and its byte range in the source text
+struct Token {
+    term: String,
+    start: usize,
+    end: usize,
+}
+
+/// Split text into case-folded alphanumeric tokens
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                tokens.push(Token {
+                    term: text[begin..index].to_lowercase(),
+                    start: begin,
+                    end: index,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Every string inside `value` with the JSON Pointer it is found at
+fn strings<'a>(pointer: String, value: &'a Value, out: &mut Vec<(String, &'a str)>) {
+    match value {
+        Value::String(text) => out.push((pointer, text)),
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                strings(format!("{pointer}/{index}"), item, out);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, item) in fields {
+                let key = key.replace('~', "~0").replace('/', "~1");
+                strings(format!("{pointer}/{key}"), item, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// One clause of a parsed query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clause {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+/// Parse a query into terms, `prefix*` and `"quoted phrases"`
+pub fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (index, part) in query.split('"').enumerate() {
+        if index % 2 == 1 {
+            // Inside quotes
+            let terms: Vec<String> = tokenize(part).into_iter().map(|t| t.term).collect();
+            match terms.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(terms.into_iter().next().unwrap())),
+                _ => clauses.push(Clause::Phrase(terms)),
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            let prefix = word.ends_with('*');
+            let mut terms: Vec<String> = tokenize(word).into_iter().map(|t| t.term).collect();
+            let last = terms.pop();
+            clauses.extend(terms.into_iter().map(Clause::Term));
+            if let Some(last) = last {
+                clauses.push(if prefix {
+                    Clause::Prefix(last)
+                } else {
+                    Clause::Term(last)
+                });
+            }
+        }
+    }
+    clauses
+}
+
+/// How multiple query clauses combine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Every clause must match
+    All,
+    /// At least one clause must match
+    Any,
+}
+
+struct IndexedDoc {
+    /// Identifies the indexed revision; a recreated record restarts at
+    /// version 1 but gets a new creation time
+    revision: (chrono::DateTime<chrono::Utc>, u64),
+    length: usize,
+    terms: BTreeSet<String>,
+}
+
+/// A scored search hit
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub id: String,
+    pub score: f64,
+    /// Indexed terms that matched, used for highlighting
+    pub matched: BTreeSet<String>,
+}
+
+/// Inverted index over record strings
+#[derive(Default)]
+pub struct SearchIndex {
+    docs: HashMap<String, IndexedDoc>,
+    /// term -> record id -> token positions
+    postings: BTreeMap<String, HashMap<String, Vec<u32>>>,
+    total_length: usize,
+}
+
+impl SearchIndex {
+    /// Bring the index up to date with the live records
+    pub fn sync(&mut self, records: &Records) {
+        let stale: Vec<String> = self
+            .docs
+            .iter()
+            .filter(|(id, doc)| {
+                store::get_live(records, id).map(|record| (record.created_at, record.version))
+                    != Some(doc.revision)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &stale {
+            self.remove(id);
+        }
+
+        for record in store::live_records(records) {
+            if !self.docs.contains_key(&record.id) {
+                self.add(record);
+            }
+        }
+    }
+
+    fn add(&mut self, record: &DatabaseRecord) {
+        let mut fields = Vec::new();
+        strings(String::new(), &record.data, &mut fields);
+
+        let mut position = 0u32;
+        let mut terms = BTreeSet::new();
+        for (_, text) in fields {
+            for token in tokenize(text) {
+                self.postings
+                    .entry(token.term.clone())
+                    .or_default()
+                    .entry(record.id.clone())
+                    .or_default()
+                    .push(position);
+                terms.insert(token.term);
+                position += 1;
+            }
+            // Leave a gap so phrases never span two fields
+            position += 1;
+        }
+
+        let length = position as usize;
+        self.total_length += length;
+        self.docs.insert(
+            record.id.clone(),
+            IndexedDoc {
+                revision: (record.created_at, record.version),
+                length,
+                terms,
+            },
+        );
+    }
+
+    fn remove(&mut self, id: &str) {
+        let Some(doc) = self.docs.remove(id) else {
+            return;
+        };
+        self.total_length -= doc.length;
+        for term in doc.terms {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    fn bm25(&self, frequency: usize, doc_frequency: usize, length: usize) -> f64 {
+        let count = self.docs.len() as f64;
+        let average = (self.total_length as f64 / count).max(1.0);
+        let idf = ((count - doc_frequency as f64 + 0.5) / (doc_frequency as f64 + 0.5) + 1.0).ln();
+        let frequency = frequency as f64;
+        idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length as f64 / average))
+    }
+
+    /// Per-record frequencies of a clause, with the terms that matched
+    fn clause_matches(&self, clause: &Clause) -> HashMap<&str, (usize, BTreeSet<String>)> {
+        let mut matches: HashMap<&str, (usize, BTreeSet<String>)> = HashMap::new();
+        match clause {
+            Clause::Term(term) => {
+                for (id, positions) in self.postings.get(term).into_iter().flatten() {
+                    let entry = matches.entry(id.as_str()).or_default();
+                    entry.0 += positions.len();
+                    entry.1.insert(term.clone());
+                }
+            }
+            Clause::Prefix(prefix) => {
+                let expansions = self
+                    .postings
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()));
+                for (term, postings) in expansions {
+                    for (id, positions) in postings {
+                        let entry = matches.entry(id.as_str()).or_default();
+                        entry.0 += positions.len();
+                        entry.1.insert(term.clone());
+                    }
+                }
+            }
+            Clause::Phrase(terms) => {
+                let Some(first) = self.postings.get(&terms[0]) else {
+                    return matches;
+                };
+                for (id, starts) in first {
+                    let occurrences = starts
+                        .iter()
+                        .filter(|&&start| {
+                            terms.iter().enumerate().skip(1).all(|(offset, term)| {
+                                self.postings
+                                    .get(term)
+                                    .and_then(|postings| postings.get(id))
+                                    .is_some_and(|positions| {
+                                        positions.binary_search(&(start + offset as u32)).is_ok()
+                                    })
+                            })
+                        })
+                        .count();
+                    if occurrences > 0 {
+                        matches.insert(id.as_str(), (occurrences, terms.iter().cloned().collect()));
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    /// Rank records matching the query clauses, best first
+    pub fn search(&self, clauses: &[Clause], mode: MatchMode) -> Vec<Hit> {
+        let mut hits: HashMap<&str, (usize, Hit)> = HashMap::new();
+        for clause in clauses {
+            let matches = self.clause_matches(clause);
+            let doc_frequency = matches.len();
+            for (id, (frequency, terms)) in matches {
+                let length = self.docs.get(id).map_or(0, |doc| doc.length);
+                let score = self.bm25(frequency, doc_frequency, length);
+                let (clauses_matched, hit) = hits.entry(id).or_insert_with(|| {
+                    (
+                        0,
+                        Hit {
+                            id: id.to_string(),
+                            score: 0.0,
+                            matched: BTreeSet::new(),
+                        },
+                    )
+                });
+                *clauses_matched += 1;
+                hit.score += score;
+                hit.matched.extend(terms);
+            }
+        }
+
+        let mut hits: Vec<Hit> = hits
+            .into_values()
+            .filter(|(clauses_matched, _)| {
+                mode == MatchMode::Any || *clauses_matched == clauses.len()
+            })
+            .map(|(_, hit)| hit)
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits
+    }
+}
+
+/// Snippets of `data` around matched terms, with matches wrapped in `**`
+pub fn snippets(data: &Value, matched: &BTreeSet<String>) -> Vec<Value> {
+    let mut fields = Vec::new();
+    strings(String::new(), data, &mut fields);
+
+    fields
+        .into_iter()
+        .filter_map(|(pointer, text)| {
+            let tokens = tokenize(text);
+            let first = tokens
+                .iter()
+                .position(|token| matched.contains(&token.term))?;
+            let from = first.saturating_sub(SNIPPET_LEADING);
+            let to = (from + SNIPPET_TOKENS).min(tokens.len());
+            let window = &tokens[from..to];
+
+            let mut snippet = String::new();
+            if from > 0 {
+                snippet.push('…');
+            }
+            let mut cursor = window[0].start;
+            for token in window {
+                snippet.push_str(&text[cursor..token.start]);
+                if matched.contains(&token.term) {
+                    snippet.push_str(&format!("**{}**", &text[token.start..token.end]));
+                } else {
+                    snippet.push_str(&text[token.start..token.end]);
+                }
+                cursor = token.end;
+            }
+            if to < tokens.len() {
+                snippet.push('…');
+            }
+
+            Some(json!({ "path": pointer, "text": snippet }))
+        })
+        .take(MAX_SNIPPETS)
+        .collect()
+}
+
+/// Database tool handler for full-text search
+pub struct SearchHandler {
+    pub db: Database,
+    pub index: Mutex<SearchIndex>,
+}
+
+#[async_trait]
+impl ToolHandler for SearchHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let query = arguments
+            .get("query")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| McpError::Validation("Missing 'query' parameter".to_string()))?;
+
+        let clauses = parse_query(query);
+        if clauses.is_empty() {
+            return Err(McpError::Validation(
+                "'query' must contain at least one word".to_string(),
+            ));
+        }
+
+        let mode = match arguments.get("match").and_then(|v| v.as_str()) {
+            None | Some("all") => MatchMode::All,
+            Some("any") => MatchMode::Any,
+            Some(other) => {
+                return Err(McpError::Validation(format!(
+                    "Invalid 'match' value '{other}': expected 'all' or 'any'"
+                )));
+            }
+        };
+
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(10)
+            .clamp(1, 100) as usize;
+
+        let collection = arguments.get("collection").and_then(|v| v.as_str());
+
+        let db = self.db.read().await;
+        let hits = {
+            let mut index = self.index.lock().await;
+            index.sync(&db);
+            index.search(&clauses, mode)
+        };
+
+        let matching: Vec<_> = hits
+            .iter()
+            .filter_map(|hit| Some((hit, db.get(&hit.id)?)))
+            .filter(|(_, record)| collection.is_none_or(|c| record.collection == c))
+            .collect();
+        let results: Vec<Value> = matching
+            .iter()
+            .take(limit)
+            .map(|(hit, record)| {
+                json!({
+                    "id": record.id,
+                    "collection": record.collection,
+                    "version": record.version,
+                    "score": (hit.score * 1000.0).round() / 1000.0,
+                    "snippets": snippets(&record.data, &hit.matched)
+                })
+            })
+            .collect();
+
+        let response = json!({
+            "query": query,
+            "total": matching.len(),
+            "returned": results.len(),
+            "results": results
+        });
+
+        Ok(ToolResult {
+            content: vec![Content::text(serde_json::to_string_pretty(&response)?)],
+            is_error: None,
+            structured_content: Some(response),
+            meta: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(notes: &[(&str, Value)]) -> SearchIndex {
+        let records: Records = notes
+            .iter()
+            .map(|(id, data)| {
+                (
+                    id.to_string(),
+                    DatabaseRecord::new(*id, "notes", data.clone()),
+                )
+            })
+            .collect();
+        let mut index = SearchIndex::default();
+        index.sync(&records);
+        index
+    }
+
+    fn ids(hits: &[Hit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query(r#"Invoice "due DATE" pay*"#),
+            vec![
+                Clause::Term("invoice".to_string()),
+                Clause::Phrase(vec!["due".to_string(), "date".to_string()]),
+                Clause::Prefix("pay".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_terms_are_ranked_with_bm25() {
+        let index = index(&[
+            ("a", json!({"text": "Invoice invoice invoice for March"})),
+            (
+                "b",
+                json!({"text": "Meeting notes; one invoice mentioned among many other words"}),
+            ),
+            ("c", json!({"text": "Nothing relevant here"})),
+        ]);
+        let hits = index.search(&parse_query("INVOICE"), MatchMode::Any);
+        assert_eq!(ids(&hits), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_phrase_and_prefix_queries() {
+        let index = index(&[
+            (
+                "a",
+                json!({"title": "payment due date", "body": "tomorrow"}),
+            ),
+            ("b", json!({"title": "date due", "tags": ["payroll"]})),
+            ("c", json!({"title": "due", "body": "date"})),
+        ]);
+        assert_eq!(
+            ids(&index.search(&parse_query("\"due date\""), MatchMode::All)),
+            ["a"]
+        );
+
+        let hits = index.search(&parse_query("pay*"), MatchMode::All);
+        let mut prefix = ids(&hits);
+        prefix.sort();
+        assert_eq!(prefix, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_index_follows_record_versions() {
+        let mut records: Records = Records::new();
+        records.insert(
+            "a".to_string(),
+            DatabaseRecord::new("a", "notes", json!("old text")),
+        );
+        let mut index = SearchIndex::default();
+        index.sync(&records);
+
+        let record = records.get_mut("a").unwrap();
+        record.data = json!("new text");
+        record.version += 1;
+        index.sync(&records);
+
+        assert!(index.search(&parse_query("old"), MatchMode::All).is_empty());
+        assert_eq!(
+            ids(&index.search(&parse_query("new"), MatchMode::All)),
+            ["a"]
+        );
+
+        records.remove("a");
+        index.sync(&records);
+        assert!(
+            index
+                .search(&parse_query("text"), MatchMode::All)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_snippets_highlight_matches() {
+        let matched = BTreeSet::from(["invoice".to_string()]);
+        let snippets = snippets(&json!({"body": "Please pay the Invoice today."}), &matched);
+        assert_eq!(
+            snippets,
+            vec![json!({"path": "/body", "text": "Please pay the **Invoice** today"})]
+        );
+    }
+}
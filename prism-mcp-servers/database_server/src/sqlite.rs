@@ -0,0 +1,474 @@
+//! Embedded SQLite storage backend.
+//!
+//! SQLite is compiled into the binary, so no external service is needed.
+//! Each record is one row; `data` and the retained history are stored as
+//! JSON text and timestamps as RFC 3339 strings. Attachment content is kept
+//! once per SHA-256 hash in `blobs`, however many attachments share it;
+//! only attachments that changed are written, and a blob is dropped once the
+//! last attachment using it is gone.
+//! A deleted record leaves its last version in `tombstones`, so record
+//! versions keep increasing across restarts. Schemas set with `set_schema`
+//! are kept in `schemas`, with a `NULL` schema for one that was removed.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, params};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::attachments::Attachment;
+use crate::backend::{BackendError, StorageBackend};
+use crate::store::{DatabaseRecord, Records};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS records (
+        id TEXT PRIMARY KEY NOT NULL,
+        collection TEXT NOT NULL,
+        data TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        expires_at TEXT,
+        history TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE TABLE IF NOT EXISTS attachments (
+        record_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        mime_type TEXT NOT NULL,
+        sha256 TEXT NOT NULL,
+        attached_at TEXT NOT NULL,
+        PRIMARY KEY (record_id, name)
+    );
+    CREATE INDEX IF NOT EXISTS attachments_by_sha256 ON attachments (sha256);
+    CREATE TABLE IF NOT EXISTS blobs (
+        sha256 TEXT PRIMARY KEY NOT NULL,
+        content BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS tombstones (
+        id TEXT PRIMARY KEY NOT NULL,
+        version INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS schemas (
+        collection TEXT PRIMARY KEY NOT NULL,
+        schema TEXT
+    );
+";
+
+/// Records persisted in a SQLite database file
+pub struct SqliteBackend {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+    /// Open (or create) the database at `path`
+    pub fn open(path: &Path) -> Result<Self, BackendError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, BackendError> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn parse_time(id: &str, value: &str) -> Result<DateTime<Utc>, BackendError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| {
+            BackendError(format!(
+                "Invalid timestamp '{value}' for record '{id}': {e}"
+            ))
+        })
+}
+
+/// Attachment rows of `record_id` by name, as `(mime_type, sha256, attached_at)`
+fn stored_attachments(
+    transaction: &rusqlite::Transaction<'_>,
+    record_id: &str,
+) -> Result<HashMap<String, (String, String, String)>, BackendError> {
+    let mut statement = transaction.prepare_cached(
+        "SELECT name, mime_type, sha256, attached_at FROM attachments WHERE record_id = ?1",
+    )?;
+    let rows = statement.query_map(params![record_id], |row| {
+        Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(id: &str, value: &str) -> Result<T, BackendError> {
+    serde_json::from_str(value)
+        .map_err(|e| BackendError(format!("Invalid stored JSON for record '{id}': {e}")))
+}
+
+impl StorageBackend for SqliteBackend {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn load(&self) -> Result<Records, BackendError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT id, collection, data, version, created_at, updated_at, expires_at, history
+             FROM records",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, String>(7)?,
+            ))
+        })?;
+
+        let mut records = Records::new();
+        for row in rows {
+            let (id, collection, data, version, created_at, updated_at, expires_at, history) = row?;
+            let record = DatabaseRecord {
+                collection,
+                data: parse_json(&id, &data)?,
+                version: version as u64,
+                created_at: parse_time(&id, &created_at)?,
+                updated_at: parse_time(&id, &updated_at)?,
+                expires_at: expires_at
+                    .map(|expires_at| parse_time(&id, &expires_at))
+                    .transpose()?,
+                history: parse_json(&id, &history)?,
+                attachments: Default::default(),
+                id: id.clone(),
+            };
+            records.insert(id, record);
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT attachments.record_id, attachments.name, attachments.mime_type,
+                    attachments.sha256, attachments.attached_at, blobs.content
+             FROM attachments JOIN blobs USING (sha256)",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, Vec<u8>>(5)?,
+            ))
+        })?;
+        let mut contents: HashMap<String, Arc<[u8]>> = HashMap::new();
+        for row in rows {
+            let (id, name, mime_type, sha256, attached_at, content) = row?;
+            let Some(record) = records.get_mut(&id) else {
+                continue;
+            };
+            let attachment = Attachment {
+                mime_type,
+                content: contents
+                    .entry(sha256.clone())
+                    .or_insert_with(|| content.into())
+                    .clone(),
+                sha256,
+                attached_at: parse_time(&id, &attached_at)?,
+            };
+            record.attachments.insert(name, attachment);
+        }
+
+        let mut statement = connection.prepare("SELECT id, version FROM tombstones")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        for row in rows {
+            let (id, version) = row?;
+            records.bury(id, version as u64);
+        }
+        Ok(records)
+    }
+
+    fn apply(&self, upserts: &[&DatabaseRecord], deletes: &[&str]) -> Result<(), BackendError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        {
+            let mut upsert = transaction.prepare_cached(
+                "INSERT INTO records
+                     (id, collection, data, version, created_at, updated_at, expires_at, history)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 ON CONFLICT (id) DO UPDATE SET
+                     collection = excluded.collection,
+                     data = excluded.data,
+                     version = excluded.version,
+                     created_at = excluded.created_at,
+                     updated_at = excluded.updated_at,
+                     expires_at = excluded.expires_at,
+                     history = excluded.history",
+            )?;
+            let mut clear_attachments =
+                transaction.prepare_cached("DELETE FROM attachments WHERE record_id = ?1")?;
+            let mut write_attachment = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO attachments (record_id, name, mime_type, sha256, attached_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            let mut remove_attachment = transaction
+                .prepare_cached("DELETE FROM attachments WHERE record_id = ?1 AND name = ?2")?;
+            let mut insert_blob = transaction
+                .prepare_cached("INSERT OR IGNORE INTO blobs (sha256, content) VALUES (?1, ?2)")?;
+            let mut unbury = transaction.prepare_cached("DELETE FROM tombstones WHERE id = ?1")?;
+            // Hashes of attachments this apply replaced or removed
+            let mut released = BTreeSet::new();
+            for record in upserts {
+                unbury.execute(params![record.id])?;
+                let mut stored = stored_attachments(&transaction, &record.id)?;
+                for (name, attachment) in &record.attachments {
+                    let row = (
+                        attachment.mime_type.clone(),
+                        attachment.sha256.clone(),
+                        attachment.attached_at.to_rfc3339(),
+                    );
+                    match stored.remove(name) {
+                        Some(previous) if previous == row => continue,
+                        Some((_, sha256, _)) => {
+                            released.insert(sha256);
+                        }
+                        None => {}
+                    }
+                    insert_blob.execute(params![attachment.sha256, &attachment.content[..]])?;
+                    write_attachment.execute(params![record.id, name, row.0, row.1, row.2])?;
+                }
+                for (name, (_, sha256, _)) in stored {
+                    remove_attachment.execute(params![record.id, name])?;
+                    released.insert(sha256);
+                }
+
+                let history = serde_json::to_string(&record.history)
+                    .map_err(|e| BackendError(e.to_string()))?;
+                upsert.execute(params![
+                    record.id,
+                    record.collection,
+                    record.data.to_string(),
+                    record.version as i64,
+                    record.created_at.to_rfc3339(),
+                    record.updated_at.to_rfc3339(),
+                    record.expires_at.map(|expires_at| expires_at.to_rfc3339()),
+                    history,
+                ])?;
+            }
+
+            let mut bury = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO tombstones (id, version)
+                 SELECT id, version FROM records WHERE id = ?1",
+            )?;
+            let mut delete = transaction.prepare_cached("DELETE FROM records WHERE id = ?1")?;
+            for id in deletes {
+                bury.execute(params![id])?;
+                delete.execute(params![id])?;
+                released.extend(
+                    stored_attachments(&transaction, id)?
+                        .into_values()
+                        .map(|(_, sha256, _)| sha256),
+                );
+                clear_attachments.execute(params![id])?;
+            }
+
+            let mut collect_blob = transaction.prepare_cached(
+                "DELETE FROM blobs WHERE sha256 = ?1
+                 AND NOT EXISTS (SELECT 1 FROM attachments WHERE sha256 = ?1)",
+            )?;
+            for sha256 in &released {
+                collect_blob.execute(params![sha256])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load_schemas(&self) -> Result<BTreeMap<String, Option<Value>>, BackendError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT collection, schema FROM schemas")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })?;
+        let mut schemas = BTreeMap::new();
+        for row in rows {
+            let (collection, schema) = row?;
+            let schema = schema
+                .map(|schema| {
+                    serde_json::from_str(&schema).map_err(|e| {
+                        BackendError(format!(
+                            "Invalid stored schema for collection '{collection}': {e}"
+                        ))
+                    })
+                })
+                .transpose()?;
+            schemas.insert(collection, schema);
+        }
+        Ok(schemas)
+    }
+
+    fn save_schema(&self, collection: &str, schema: Option<&Value>) -> Result<(), BackendError> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO schemas (collection, schema) VALUES (?1, ?2)",
+            params![collection, schema.map(Value::to_string)],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+    use serde_json::json;
+
+    fn open() -> SqliteBackend {
+        SqliteBackend::init(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        let backend = open();
+        let first = DatabaseRecord::new("a", "users", json!({"name": "Alice"}));
+        let mut second = first.clone();
+        second.data = json!({"name": "Alicia"});
+        second.version = 2;
+        second.expires_at = Some(first.created_at + chrono::Duration::hours(1));
+        second.history = history::extend(&first);
+
+        backend.apply(&[&first], &[]).unwrap();
+        backend.apply(&[&second], &[]).unwrap();
+
+        let loaded = backend.load().unwrap();
+        let record = &loaded["a"];
+        assert_eq!(record.data, second.data);
+        assert_eq!(record.version, 2);
+        assert_eq!(record.created_at, first.created_at);
+        assert_eq!(record.expires_at, second.expires_at);
+        assert_eq!(record.history, second.history);
+
+        backend.apply(&[], &["a"]).unwrap();
+        let loaded = backend.load().unwrap();
+        assert!(loaded.is_empty());
+        assert_eq!(loaded.next_version("a"), 3);
+
+        // Storing the record again clears its tombstone
+        let mut third = DatabaseRecord::new("a", "users", json!({}));
+        third.version = 3;
+        backend.apply(&[&third], &[]).unwrap();
+        assert_eq!(backend.load().unwrap().next_version("a"), 4);
+    }
+
+    #[test]
+    fn test_schemas_round_trip() {
+        let backend = open();
+        let schema = json!({"type": "object"});
+        backend.save_schema("users", Some(&schema)).unwrap();
+        backend.save_schema("notes", Some(&schema)).unwrap();
+        backend.save_schema("notes", None).unwrap();
+        assert_eq!(
+            backend.load_schemas().unwrap(),
+            BTreeMap::from([
+                ("notes".to_string(), None),
+                ("users".to_string(), Some(schema))
+            ])
+        );
+    }
+
+    #[test]
+    fn test_attachments_share_blobs() {
+        let backend = open();
+        let attachment = Attachment::new("image/png", vec![1u8, 2, 3]);
+        let mut first = DatabaseRecord::new("a", "users", json!({}));
+        first
+            .attachments
+            .insert("avatar.png".to_string(), attachment.clone());
+        let mut second = DatabaseRecord::new("b", "users", json!({}));
+        second
+            .attachments
+            .insert("copy.png".to_string(), attachment.clone());
+        backend.apply(&[&first, &second], &[]).unwrap();
+
+        let loaded = backend.load().unwrap();
+        assert_eq!(loaded["a"].attachments["avatar.png"], attachment);
+        assert_eq!(loaded["b"].attachments["copy.png"], attachment);
+        let blobs = |backend: &SqliteBackend| -> i64 {
+            backend
+                .connection()
+                .query_row("SELECT COUNT(*) FROM blobs", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(blobs(&backend), 1);
+
+        backend.apply(&[], &["a"]).unwrap();
+        assert_eq!(blobs(&backend), 1);
+        second.attachments.clear();
+        backend.apply(&[&second], &[]).unwrap();
+        assert_eq!(blobs(&backend), 0);
+        assert!(backend.load().unwrap()["b"].attachments.is_empty());
+    }
+
+    #[test]
+    fn test_only_changed_attachments_are_written() {
+        let backend = open();
+        let mut record = DatabaseRecord::new("a", "users", json!({}));
+        record.attachments.insert(
+            "avatar.png".to_string(),
+            Attachment::new("image/png", vec![1u8]),
+        );
+        backend.apply(&[&record], &[]).unwrap();
+        let row = |backend: &SqliteBackend| -> i64 {
+            backend
+                .connection()
+                .query_row(
+                    "SELECT rowid FROM attachments WHERE name = 'avatar.png'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        let first = row(&backend);
+
+        // Rewriting the record leaves its unchanged attachment row alone
+        record.data = json!({"name": "Alice"});
+        backend.apply(&[&record], &[]).unwrap();
+        assert_eq!(row(&backend), first);
+
+        // Replacing the attachment drops the old blob but not unrelated ones
+        backend
+            .connection()
+            .execute(
+                "INSERT INTO blobs (sha256, content) VALUES ('unrelated', x'00')",
+                [],
+            )
+            .unwrap();
+        let old = record.attachments["avatar.png"].sha256.clone();
+        record.attachments.insert(
+            "avatar.png".to_string(),
+            Attachment::new("image/png", vec![2u8]),
+        );
+        backend.apply(&[&record], &[]).unwrap();
+        let blob = |sha256: &str| -> i64 {
+            backend
+                .connection()
+                .query_row(
+                    "SELECT COUNT(*) FROM blobs WHERE sha256 = ?1",
+                    params![sha256],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(blob(&old), 0);
+        assert_eq!(blob(&record.attachments["avatar.png"].sha256), 1);
+        assert_eq!(blob("unrelated"), 1);
+    }
+}
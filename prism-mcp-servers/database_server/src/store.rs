@@ -0,0 +1,1258 @@
+//! Record storage and write semantics for the database server.
+//!
+//! Every write goes through [`write_record`] or [`delete_record`] (or
+//! [`attach`] and [`detach`] for attachments) so that version checks and
+//! timestamp handling stay identical across tools. Writes made in a batch
+//! are saved to an [`UndoLog`] first, so the batch can be undone as a whole.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{Notify, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::attachments::Attachment;
+use crate::backend::{BackendError, StorageBackend};
+use crate::history::{self, Revision};
+use crate::patch::{self, PatchFormat};
+use crate::quota::{Quota, Quotas, Usage};
+use crate::schema::{SchemaRegistry, SchemaViolation};
+use crate::uri;
+
+/// Collection used when a write does not name one
+pub const DEFAULT_COLLECTION: &str = "default";
+
+fn default_collection() -> String {
+    DEFAULT_COLLECTION.to_string()
+}
+
+/// In-memory database store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseRecord {
+    pub id: String,
+    /// Collection the record belongs to
+    #[serde(default = "default_collection")]
+    pub collection: String,
+    pub data: Value,
+    /// Monotonically increasing version, starting at 1 on creation; a record
+    /// deleted and created again continues from its last version
+    pub version: u64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// When the record expires; expired records are treated as missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Revisions this record replaced, oldest first
+    #[serde(skip)]
+    pub history: Vec<Revision>,
+    /// Binary attachments keyed by name
+    #[serde(skip)]
+    pub attachments: BTreeMap<String, Attachment>,
+}
+
+impl DatabaseRecord {
+    /// Create a fresh record at version 1
+    pub fn new(id: impl Into<String>, collection: impl Into<String>, data: Value) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            id: id.into(),
+            collection: collection.into(),
+            data,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            expires_at: None,
+            history: Vec::new(),
+            attachments: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the record has expired as of `now`
+    pub fn is_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// JSON representation returned by the tools
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({
+            "id": self.id,
+            "collection": self.collection,
+            "data": self.data,
+            "version": self.version,
+            "created_at": self.created_at.to_rfc3339(),
+            "updated_at": self.updated_at.to_rfc3339()
+        });
+        if let Some(expires_at) = self.expires_at {
+            let remaining = (expires_at - chrono::Utc::now()).num_seconds().max(0);
+            value["expires_at"] = json!(expires_at.to_rfc3339());
+            value["ttl_seconds_remaining"] = json!(remaining);
+        }
+        if !self.attachments.is_empty() {
+            value["attachments"] = self
+                .attachments
+                .iter()
+                .map(|(name, attachment)| {
+                    attachment.to_json(name, uri::attachment_uri(&self.collection, &self.id, name))
+                })
+                .collect();
+        }
+        value
+    }
+}
+
+/// Records keyed by ID; ordered so listings and cursors are deterministic.
+///
+/// Reads go through the map it derefs to. Removing a record leaves a
+/// tombstone with its last version, so a record created again under the same
+/// ID carries on from there and a write still expecting an old version fails.
+/// Records with an expiry are also indexed by it, so the sweeper can find
+/// the expired ones without scanning.
+#[derive(Debug, Clone, Default)]
+pub struct Records {
+    records: BTreeMap<String, DatabaseRecord>,
+    /// Last version of each removed record
+    tombstones: HashMap<String, u64>,
+    /// IDs of expiring records, soonest first
+    expiries: BTreeSet<(chrono::DateTime<chrono::Utc>, String)>,
+}
+
+impl Records {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `record` under `id`, returning the record it replaced
+    pub fn insert(&mut self, id: String, record: DatabaseRecord) -> Option<DatabaseRecord> {
+        self.tombstones.remove(&id);
+        let expires_at = record.expires_at;
+        let previous = self.records.insert(id.clone(), record);
+        if let Some(expired_at) = previous.as_ref().and_then(|previous| previous.expires_at) {
+            self.expiries.remove(&(expired_at, id.clone()));
+        }
+        if let Some(expires_at) = expires_at {
+            self.expiries.insert((expires_at, id));
+        }
+        previous
+    }
+
+    /// Remove the record `id`, leaving a tombstone with its version
+    pub fn remove(&mut self, id: &str) -> Option<DatabaseRecord> {
+        let record = self.records.remove(id)?;
+        if let Some(expires_at) = record.expires_at {
+            self.expiries.remove(&(expires_at, id.to_string()));
+        }
+        self.tombstones.insert(id.to_string(), record.version);
+        Some(record)
+    }
+
+    /// The record `id`, to change anything but its expiry; a new expiry has
+    /// to be [`Records::insert`]ed so that it is indexed
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut DatabaseRecord> {
+        self.records.get_mut(id)
+    }
+
+    /// When the next record expires, if any record has an expiry
+    pub fn next_expiry(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.expiries.first().map(|(expires_at, _)| *expires_at)
+    }
+
+    /// Record that `id` was removed at `version`, as when loading stored tombstones
+    pub fn bury(&mut self, id: String, version: u64) {
+        if !self.records.contains_key(&id) {
+            self.tombstones.insert(id, version);
+        }
+    }
+
+    /// The version a record written as `id` gets if nothing is stored under it:
+    /// 1, or one past the version it had when it was removed or expired
+    pub fn next_version(&self, id: &str) -> u64 {
+        self.records
+            .get(id)
+            .map(|record| record.version)
+            .or_else(|| self.tombstones.get(id).copied())
+            .map_or(1, |version| version + 1)
+    }
+}
+
+impl Deref for Records {
+    type Target = BTreeMap<String, DatabaseRecord>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.records
+    }
+}
+
+impl FromIterator<(String, DatabaseRecord)> for Records {
+    fn from_iter<I: IntoIterator<Item = (String, DatabaseRecord)>>(iter: I) -> Self {
+        let mut records = Self::new();
+        for (id, record) in iter {
+            records.insert(id, record);
+        }
+        records
+    }
+}
+
+/// Shared database state
+pub type Database = Arc<RecordStore>;
+
+/// The in-memory records and the backend that persists them
+pub struct RecordStore {
+    records: RwLock<Records>,
+    backend: Box<dyn StorageBackend>,
+    quotas: Quotas,
+    /// Updated only while the records are locked for writing
+    usage: Mutex<Usage>,
+    /// When each record was last read or written, for LRU eviction
+    last_used: Mutex<HashMap<String, chrono::DateTime<chrono::Utc>>>,
+    /// Signalled when a commit stores a record with an expiry
+    expiry_scheduled: Notify,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl RecordStore {
+    /// Load the records held by `backend`
+    pub fn open(backend: Box<dyn StorageBackend>, quotas: Quotas) -> Result<Self, BackendError> {
+        let records = backend.load()?;
+        let usage = Usage::measure(&records);
+        if quotas.max_records.is_some_and(|max| usage.records() > max)
+            || quotas
+                .max_total_bytes
+                .is_some_and(|max| usage.bytes() > max)
+        {
+            tracing::warn!(
+                "Stored records exceed the configured quotas ({} records, {} bytes)",
+                usage.records(),
+                usage.bytes()
+            );
+        }
+        Ok(Self {
+            records: RwLock::new(records),
+            backend,
+            quotas,
+            usage: Mutex::new(usage),
+            last_used: Mutex::new(HashMap::new()),
+            expiry_scheduled: Notify::new(),
+        })
+    }
+
+    /// Name of the configured backend
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Schemas set with `set_schema`, as kept by the backend
+    pub fn load_schemas(&self) -> Result<BTreeMap<String, Option<Value>>, BackendError> {
+        self.backend.load_schemas()
+    }
+
+    /// Have the backend keep the schema set for `collection`, or its removal
+    pub fn save_schema(
+        &self,
+        collection: &str,
+        schema: Option<&Value>,
+    ) -> Result<(), BackendError> {
+        self.backend.save_schema(collection, schema)
+    }
+
+    /// Lock the records for reading
+    pub async fn read(&self) -> RwLockReadGuard<'_, Records> {
+        self.records.read().await
+    }
+
+    /// Lock the records for writing; changes must be [`RecordsWriteGuard::commit`]ted
+    pub async fn write(&self) -> RecordsWriteGuard<'_> {
+        RecordsWriteGuard {
+            records: self.records.write().await,
+            store: self,
+        }
+    }
+
+    /// Wait until a commit stores a record with an expiry, which may be
+    /// sooner than [`Records::next_expiry`] was when the wait began
+    pub async fn expiry_scheduled(&self) {
+        self.expiry_scheduled.notified().await
+    }
+
+    /// Note that the record `id` was read, for LRU eviction
+    pub fn touch(&self, id: &str) {
+        lock(&self.last_used).insert(id.to_string(), chrono::Utc::now());
+    }
+
+    /// Usage statistics against the configured quotas
+    pub fn stats(&self) -> Value {
+        let mut stats = self.quotas.report(&lock(&self.usage));
+        stats["backend"] = json!(self.backend.name());
+        stats
+    }
+}
+
+/// Exclusive access to the records, able to persist what was changed
+pub struct RecordsWriteGuard<'a> {
+    records: RwLockWriteGuard<'a, Records>,
+    store: &'a RecordStore,
+}
+
+impl RecordsWriteGuard<'_> {
+    /// Check `changes` against the quotas and persist them to the backend.
+    ///
+    /// Returns the committed changes, followed by the deletion of any records
+    /// evicted to make room; callers publish these. If the write is over a
+    /// quota or the backend fails, `changes` are undone so the in-memory
+    /// records never run ahead of what is stored.
+    pub fn commit(&mut self, mut changes: Vec<Change>) -> Result<Vec<Change>, StoreError> {
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+        let store = self.store;
+
+        let evicted = store.quotas.enforce(
+            &lock(&store.usage),
+            &mut self.records,
+            &changes,
+            &lock(&store.last_used),
+        );
+        let evicted = match evicted {
+            Ok(evicted) => evicted,
+            Err(err) => {
+                self.undo(&changes);
+                lock(&store.usage).rejected += 1;
+                return Err(err);
+            }
+        };
+        let evictions = evicted.len();
+        changes.extend(
+            evicted
+                .iter()
+                .filter_map(|record| Change::between(Some(record), None)),
+        );
+
+        let mut upserts = Vec::new();
+        let mut deletes = Vec::new();
+        for change in &changes {
+            match self.records.get(&change.id) {
+                Some(record) => upserts.push(record),
+                None => deletes.push(change.id.as_str()),
+            }
+        }
+        if let Err(err) = store.backend.apply(&upserts, &deletes) {
+            tracing::error!(
+                "Failed to persist changes to {}: {err}",
+                store.backend.name()
+            );
+            self.undo(&changes);
+            return Err(StoreError::Storage {
+                message: err.to_string(),
+            });
+        }
+
+        if upserts.iter().any(|record| record.expires_at.is_some()) {
+            store.expiry_scheduled.notify_one();
+        }
+
+        let ids = || changes.iter().map(|change| change.id.as_str());
+        let mut usage = lock(&store.usage);
+        usage.update(&self.records, ids());
+        usage.evicted += evictions as u64;
+        let now = chrono::Utc::now();
+        let mut last_used = lock(&store.last_used);
+        for id in ids() {
+            if self.records.contains_key(id) {
+                last_used.insert(id.to_string(), now);
+            } else {
+                last_used.remove(id);
+            }
+        }
+        if evictions > 0 {
+            tracing::info!(
+                "Evicted {evictions} record(s) to stay within quotas ({:?} policy)",
+                store.quotas.policy
+            );
+        }
+        Ok(changes)
+    }
+
+    /// Restore the records `changes` touched to their previous state
+    fn undo(&mut self, changes: &[Change]) {
+        for change in changes.iter().rev() {
+            match &change.before {
+                Some(before) => {
+                    self.records.insert(change.id.clone(), before.clone());
+                }
+                None => {
+                    self.records.remove(&change.id);
+                }
+            }
+        }
+    }
+}
+
+impl Deref for RecordsWriteGuard<'_> {
+    type Target = Records;
+
+    fn deref(&self) -> &Records {
+        &self.records
+    }
+}
+
+impl DerefMut for RecordsWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Records {
+        &mut self.records
+    }
+}
+
+/// Look up a record, treating expired records as missing
+pub fn get_live<'a>(records: &'a Records, id: &str) -> Option<&'a DatabaseRecord> {
+    let now = chrono::Utc::now();
+    records.get(id).filter(|record| !record.is_expired(now))
+}
+
+/// Iterate over records that have not expired
+pub fn live_records(records: &Records) -> impl Iterator<Item = &DatabaseRecord> {
+    let now = chrono::Utc::now();
+    records
+        .values()
+        .filter(move |record| !record.is_expired(now))
+}
+
+/// Remove every record that has expired as of `now`
+pub fn sweep_expired(
+    records: &mut Records,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<DatabaseRecord> {
+    let expired: Vec<String> = records
+        .expiries
+        .iter()
+        .take_while(|(expires_at, _)| *expires_at <= now)
+        .map(|(_, id)| id.clone())
+        .collect();
+    expired.iter().filter_map(|id| records.remove(id)).collect()
+}
+
+/// How a write treats an existing (or missing) record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteMode {
+    /// Create the record or replace it if it already exists
+    #[default]
+    Upsert,
+    /// Fail if the record already exists
+    Insert,
+    /// Fail if the record does not exist
+    Update,
+}
+
+/// Reasons a write can be refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// An insert targeted an existing record
+    AlreadyExists { id: String },
+    /// An update or delete targeted a missing record
+    NotFound { id: String },
+    /// A detach named an attachment the record does not have
+    AttachmentNotFound { id: String, name: String },
+    /// The caller's `expected_version` does not match the stored version
+    VersionConflict {
+        id: String,
+        expected: u64,
+        actual: Option<u64>,
+    },
+    /// A patch could not be applied; the record is left unchanged
+    PatchFailed { id: String, message: String },
+    /// The requested expiry is invalid
+    InvalidExpiry { id: String, message: String },
+    /// The resulting data does not match the collection's schema
+    SchemaViolation {
+        id: String,
+        collection: String,
+        violations: Vec<SchemaViolation>,
+    },
+    /// The write would exceed a configured quota; nothing was changed
+    QuotaExceeded {
+        quota: Quota,
+        limit: usize,
+        requested: usize,
+    },
+    /// The storage backend failed to persist the write; nothing was changed
+    Storage { message: String },
+}
+
+impl StoreError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            StoreError::AlreadyExists { .. } => "already_exists",
+            StoreError::NotFound { .. } => "not_found",
+            StoreError::AttachmentNotFound { .. } => "attachment_not_found",
+            StoreError::VersionConflict { .. } => "version_conflict",
+            StoreError::PatchFailed { .. } => "patch_failed",
+            StoreError::InvalidExpiry { .. } => "invalid_expiry",
+            StoreError::SchemaViolation { .. } => "schema_violation",
+            StoreError::QuotaExceeded { .. } => "quota_exceeded",
+            StoreError::Storage { .. } => "storage_error",
+        }
+    }
+
+    /// Structured description of the error for `structured_content`
+    pub fn to_json(&self) -> Value {
+        match self {
+            StoreError::AlreadyExists { id } | StoreError::NotFound { id } => json!({
+                "error": self.code(),
+                "id": id,
+                "message": self.to_string()
+            }),
+            StoreError::AttachmentNotFound { id, name } => json!({
+                "error": self.code(),
+                "id": id,
+                "name": name,
+                "message": self.to_string()
+            }),
+            StoreError::VersionConflict {
+                id,
+                expected,
+                actual,
+            } => json!({
+                "error": self.code(),
+                "id": id,
+                "expected_version": expected,
+                "current_version": actual,
+                "message": self.to_string()
+            }),
+            StoreError::PatchFailed { id, message } | StoreError::InvalidExpiry { id, message } => {
+                json!({
+                    "error": self.code(),
+                    "id": id,
+                    "reason": message,
+                    "message": self.to_string()
+                })
+            }
+            StoreError::SchemaViolation {
+                id,
+                collection,
+                violations,
+            } => json!({
+                "error": self.code(),
+                "id": id,
+                "collection": collection,
+                "violations": violations,
+                "message": self.to_string()
+            }),
+            StoreError::QuotaExceeded {
+                quota,
+                limit,
+                requested,
+            } => json!({
+                "error": self.code(),
+                "quota": quota.name(),
+                "limit": limit,
+                "requested": requested,
+                "message": self.to_string()
+            }),
+            StoreError::Storage { message } => json!({
+                "error": self.code(),
+                "reason": message,
+                "message": self.to_string()
+            }),
+        }
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::AlreadyExists { id } => {
+                write!(f, "Record with ID '{id}' already exists")
+            }
+            StoreError::NotFound { id } => write!(f, "No record found with ID: {id}"),
+            StoreError::AttachmentNotFound { id, name } => {
+                write!(f, "Record '{id}' has no attachment named '{name}'")
+            }
+            StoreError::VersionConflict {
+                id,
+                expected,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "Version conflict for record '{id}': expected version {expected}, current version is {actual}"
+            ),
+            StoreError::VersionConflict {
+                id,
+                expected,
+                actual: None,
+            } => write!(
+                f,
+                "Version conflict for record '{id}': expected version {expected}, but the record does not exist"
+            ),
+            StoreError::PatchFailed { id, message } => {
+                write!(f, "Failed to patch record '{id}': {message}")
+            }
+            StoreError::InvalidExpiry { id, message } => {
+                write!(f, "Invalid expiry for record '{id}': {message}")
+            }
+            StoreError::SchemaViolation {
+                id,
+                collection,
+                violations,
+            } => {
+                write!(
+                    f,
+                    "Record '{id}' does not match the schema of collection '{collection}'"
+                )?;
+                for violation in violations {
+                    let path = if violation.path.is_empty() {
+                        "/"
+                    } else {
+                        &violation.path
+                    };
+                    write!(f, "\n  {path}: {}", violation.message)?;
+                }
+                Ok(())
+            }
+            StoreError::QuotaExceeded {
+                quota: Quota::RecordBytes,
+                limit,
+                requested,
+            } => write!(
+                f,
+                "Record data is {requested} bytes, over the {limit} byte limit ({quota})",
+                quota = Quota::RecordBytes
+            ),
+            StoreError::QuotaExceeded {
+                quota,
+                limit,
+                requested,
+            } => write!(
+                f,
+                "The write would bring the database to {requested}, over its limit of {limit} ({quota})"
+            ),
+            StoreError::Storage { message } => write!(f, "Failed to persist the write: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// What happened to a record in a committed write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A change to a single record, used to commit it and drive notifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub id: String,
+    /// Collections the record belonged to before and after the change
+    pub collections: Vec<String>,
+    /// The record before the change, restored if the commit fails
+    pub before: Option<DatabaseRecord>,
+}
+
+impl Change {
+    /// Describe the transition from `before` to `after`, if anything changed
+    pub fn between(
+        before: Option<&DatabaseRecord>,
+        after: Option<&DatabaseRecord>,
+    ) -> Option<Self> {
+        let kind = match (before, after) {
+            (None, Some(_)) => ChangeKind::Created,
+            (Some(_), None) => ChangeKind::Deleted,
+            (Some(b), Some(a)) if b.version != a.version || b.created_at != a.created_at => {
+                ChangeKind::Updated
+            }
+            _ => return None,
+        };
+        let mut collections: Vec<String> = before
+            .into_iter()
+            .chain(after)
+            .map(|record| record.collection.clone())
+            .collect();
+        collections.dedup();
+
+        Some(Change {
+            kind,
+            id: before.or(after).map(|r| r.id.clone()).unwrap_or_default(),
+            collections,
+            before: before.cloned(),
+        })
+    }
+}
+
+/// Result of a successful write
+#[derive(Debug, Clone)]
+pub struct WriteOutcome {
+    pub record: DatabaseRecord,
+    /// The record as it was before the write, if it existed
+    pub previous: Option<DatabaseRecord>,
+}
+
+impl WriteOutcome {
+    /// Whether the write created a new record
+    pub fn created(&self) -> bool {
+        self.previous.is_none()
+    }
+
+    /// The change this write made
+    pub fn change(&self) -> Change {
+        Change::between(self.previous.as_ref(), Some(&self.record))
+            .expect("a committed write always changes the record")
+    }
+}
+
+/// Optional parameters of a write
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions<'a> {
+    /// Collection to write into; `None` keeps the current collection
+    pub collection: Option<&'a str>,
+    pub mode: WriteMode,
+    pub expected_version: Option<u64>,
+    /// New expiry time; `None` keeps the current expiry
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Check `expected_version` against the currently stored record
+fn check_version(
+    id: &str,
+    current: Option<&DatabaseRecord>,
+    expected_version: Option<u64>,
+) -> Result<(), StoreError> {
+    match expected_version {
+        Some(expected) if current.map(|r| r.version) != Some(expected) => {
+            Err(StoreError::VersionConflict {
+                id: id.to_string(),
+                expected,
+                actual: current.map(|r| r.version),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Create or replace a record, honouring `mode` and `expected_version`.
+///
+/// Updates keep the original `created_at` and bump `version` by one; a new
+/// record continues from the version a deleted record of that ID had. When
+/// `collection` is `None` an existing record stays in its collection and a
+/// new record goes to [`DEFAULT_COLLECTION`]; likewise an update keeps its
+/// expiry unless a new one is given. The data must match the target
+/// collection's schema, if it has one. An expired record is replaced as if
+/// it did not exist.
+pub fn write_record(
+    records: &mut Records,
+    schemas: &SchemaRegistry,
+    id: &str,
+    data: Value,
+    options: WriteOptions<'_>,
+) -> Result<WriteOutcome, StoreError> {
+    let WriteOptions {
+        collection,
+        mode,
+        expected_version,
+        expires_at,
+    } = options;
+    let current = get_live(records, id);
+
+    match (mode, current) {
+        (WriteMode::Insert, Some(_)) => {
+            return Err(StoreError::AlreadyExists { id: id.to_string() });
+        }
+        (WriteMode::Update, None) if expected_version.is_none() => {
+            return Err(StoreError::NotFound { id: id.to_string() });
+        }
+        _ => {}
+    }
+    check_version(id, current, expected_version)?;
+
+    let collection = collection
+        .or(current.map(|existing| existing.collection.as_str()))
+        .unwrap_or(DEFAULT_COLLECTION);
+    schemas
+        .validate(collection, &data)
+        .map_err(|violations| StoreError::SchemaViolation {
+            id: id.to_string(),
+            collection: collection.to_string(),
+            violations,
+        })?;
+
+    let record = match current {
+        Some(existing) => DatabaseRecord {
+            id: id.to_string(),
+            collection: collection.to_string(),
+            data,
+            version: existing.version + 1,
+            created_at: existing.created_at,
+            updated_at: chrono::Utc::now(),
+            expires_at: expires_at.or(existing.expires_at),
+            history: history::extend(existing),
+            attachments: existing.attachments.clone(),
+        },
+        None => DatabaseRecord {
+            version: records.next_version(id),
+            expires_at,
+            ..DatabaseRecord::new(id, collection, data)
+        },
+    };
+
+    let previous = current.cloned();
+    records.insert(id.to_string(), record.clone());
+    Ok(WriteOutcome { record, previous })
+}
+
+/// Apply a JSON Patch or merge patch to an existing record's `data`.
+///
+/// The patch is applied to a copy, so the stored record is untouched unless
+/// every operation succeeds.
+pub fn patch_record(
+    records: &mut Records,
+    schemas: &SchemaRegistry,
+    id: &str,
+    patch: &Value,
+    format: PatchFormat,
+    expected_version: Option<u64>,
+) -> Result<WriteOutcome, StoreError> {
+    let current = get_live(records, id);
+    if current.is_none() && expected_version.is_none() {
+        return Err(StoreError::NotFound { id: id.to_string() });
+    }
+    check_version(id, current, expected_version)?;
+    let current = current.ok_or_else(|| StoreError::NotFound { id: id.to_string() })?;
+
+    let data =
+        patch::apply(&current.data, patch, format).map_err(|message| StoreError::PatchFailed {
+            id: id.to_string(),
+            message,
+        })?;
+    let version = current.version;
+
+    write_record(
+        records,
+        schemas,
+        id,
+        data,
+        WriteOptions {
+            mode: WriteMode::Update,
+            expected_version: Some(version),
+            ..Default::default()
+        },
+    )
+}
+
+/// Replace a live record with a new version changed by `change`
+fn revise(
+    records: &mut Records,
+    id: &str,
+    expected_version: Option<u64>,
+    change: impl FnOnce(&mut DatabaseRecord) -> Result<(), StoreError>,
+) -> Result<WriteOutcome, StoreError> {
+    let current = get_live(records, id);
+    if current.is_none() && expected_version.is_none() {
+        return Err(StoreError::NotFound { id: id.to_string() });
+    }
+    check_version(id, current, expected_version)?;
+    let current = current.ok_or_else(|| StoreError::NotFound { id: id.to_string() })?;
+
+    let mut record = DatabaseRecord {
+        version: current.version + 1,
+        updated_at: chrono::Utc::now(),
+        history: history::extend(current),
+        ..current.clone()
+    };
+    change(&mut record)?;
+
+    let previous = Some(current.clone());
+    records.insert(id.to_string(), record.clone());
+    Ok(WriteOutcome { record, previous })
+}
+
+/// Attach `attachment` to a record as `name`, replacing any attachment of that name
+pub fn attach(
+    records: &mut Records,
+    id: &str,
+    name: &str,
+    attachment: Attachment,
+    expected_version: Option<u64>,
+) -> Result<WriteOutcome, StoreError> {
+    revise(records, id, expected_version, |record| {
+        record.attachments.insert(name.to_string(), attachment);
+        Ok(())
+    })
+}
+
+/// Remove the attachment `name` from a record
+pub fn detach(
+    records: &mut Records,
+    id: &str,
+    name: &str,
+    expected_version: Option<u64>,
+) -> Result<WriteOutcome, StoreError> {
+    revise(records, id, expected_version, |record| {
+        record
+            .attachments
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| StoreError::AttachmentNotFound {
+                id: id.to_string(),
+                name: name.to_string(),
+            })
+    })
+}
+
+/// Remove a record, optionally requiring it to be at `expected_version`
+pub fn delete_record(
+    records: &mut Records,
+    id: &str,
+    expected_version: Option<u64>,
+) -> Result<DatabaseRecord, StoreError> {
+    let current = get_live(records, id);
+    if current.is_none() && expected_version.is_none() {
+        return Err(StoreError::NotFound { id: id.to_string() });
+    }
+    check_version(id, current, expected_version)?;
+
+    records
+        .remove(id)
+        .ok_or_else(|| StoreError::NotFound { id: id.to_string() })
+}
+
+/// The records a batch of writes touched, as they were before the batch
+///
+/// Only touched records are copied, so a batch can be applied to the records
+/// in place and still be undone.
+#[derive(Debug, Default)]
+pub struct UndoLog {
+    before: BTreeMap<String, Option<DatabaseRecord>>,
+}
+
+impl UndoLog {
+    /// Remember the record `id` as it is now, unless it already was
+    pub fn save(&mut self, records: &Records, id: &str) {
+        if !self.before.contains_key(id) {
+            self.before.insert(id.to_string(), records.get(id).cloned());
+        }
+    }
+
+    /// Put every saved record back as it was
+    pub fn rollback(self, records: &mut Records) {
+        for (id, before) in self.before {
+            match before {
+                Some(record) => {
+                    records.insert(id, record);
+                }
+                None => {
+                    records.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// The net change to each saved record, in ID order
+    pub fn changes(&self, records: &Records) -> Vec<Change> {
+        let now = chrono::Utc::now();
+        self.before
+            .iter()
+            .filter_map(|(id, before)| {
+                let before = before.as_ref().filter(|record| !record.is_expired(now));
+                Change::between(before, get_live(records, id))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_bumps_version_and_keeps_created_at() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        let first = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(1),
+            WriteOptions::default(),
+        )
+        .unwrap();
+        assert!(first.created());
+        assert_eq!(first.record.version, 1);
+
+        let second = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(2),
+            WriteOptions::default(),
+        )
+        .unwrap();
+        assert!(!second.created());
+        assert_eq!(second.record.version, 2);
+        assert_eq!(second.record.created_at, first.record.created_at);
+    }
+
+    #[test]
+    fn test_expected_version_mismatch_is_conflict() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(1),
+            WriteOptions::default(),
+        )
+        .unwrap();
+
+        let err = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(2),
+            WriteOptions {
+                expected_version: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            StoreError::VersionConflict {
+                id: "a".to_string(),
+                expected: 3,
+                actual: Some(1)
+            }
+        );
+        assert_eq!(records["a"].data, json!(1));
+
+        let err = delete_record(&mut records, "a", Some(2)).unwrap_err();
+        assert_eq!(err.code(), "version_conflict");
+        assert!(delete_record(&mut records, "a", Some(1)).is_ok());
+    }
+
+    #[test]
+    fn test_recreated_record_continues_its_version() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        let write = |records: &mut Records, expected_version| {
+            write_record(
+                records,
+                &schemas,
+                "a",
+                json!(1),
+                WriteOptions {
+                    expected_version,
+                    ..Default::default()
+                },
+            )
+        };
+        write(&mut records, None).unwrap();
+        write(&mut records, None).unwrap();
+        delete_record(&mut records, "a", Some(2)).unwrap();
+
+        // A writer that read version 1 before the delete must not clobber the new record
+        let recreated = write(&mut records, None).unwrap();
+        assert!(recreated.created());
+        assert_eq!(recreated.record.version, 3);
+        assert_eq!(
+            write(&mut records, Some(1)).unwrap_err(),
+            StoreError::VersionConflict {
+                id: "a".to_string(),
+                expected: 1,
+                actual: Some(3)
+            }
+        );
+
+        // Expired records are replaced, and swept, without losing their version
+        let expire = |records: &mut Records| {
+            let mut record = records["a"].clone();
+            record.expires_at = Some(chrono::Utc::now() - chrono::Duration::seconds(1));
+            records.insert("a".to_string(), record);
+        };
+        expire(&mut records);
+        assert_eq!(write(&mut records, None).unwrap().record.version, 4);
+        expire(&mut records);
+        assert_eq!(sweep_expired(&mut records, chrono::Utc::now()).len(), 1);
+        assert_eq!(write(&mut records, None).unwrap().record.version, 5);
+    }
+
+    #[test]
+    fn test_insert_and_update_modes() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        let err = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(1),
+            WriteOptions {
+                mode: WriteMode::Update,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "not_found");
+
+        write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(1),
+            WriteOptions {
+                mode: WriteMode::Insert,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let err = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(2),
+            WriteOptions {
+                mode: WriteMode::Insert,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "already_exists");
+
+        let updated = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(2),
+            WriteOptions {
+                mode: WriteMode::Update,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(updated.record.version, 2);
+    }
+
+    #[test]
+    fn test_schema_violation_leaves_record_unchanged() {
+        let mut records = Records::new();
+        let mut schemas = SchemaRegistry::default();
+        schemas
+            .set("users", json!({"type": "object", "required": ["name"]}))
+            .unwrap();
+
+        let err = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!({}),
+            WriteOptions {
+                collection: Some("users"),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "schema_violation");
+        assert!(records.is_empty());
+
+        write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!({"name": "Alice"}),
+            WriteOptions {
+                collection: Some("users"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let err = patch_record(
+            &mut records,
+            &schemas,
+            "a",
+            &json!([{"op": "remove", "path": "/name"}]),
+            PatchFormat::JsonPatch,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "schema_violation");
+        assert_eq!(records["a"].data, json!({"name": "Alice"}));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_commit_is_undone() {
+        let quotas = Quotas {
+            max_records: Some(1),
+            ..Quotas::default()
+        };
+        let store = RecordStore::open(Box::new(crate::backend::MemoryBackend), quotas).unwrap();
+        let schemas = SchemaRegistry::default();
+        let mut db = store.write().await;
+
+        let outcome =
+            write_record(&mut db, &schemas, "a", json!(1), WriteOptions::default()).unwrap();
+        db.commit(vec![outcome.change()]).unwrap();
+        let outcome =
+            write_record(&mut db, &schemas, "a", json!(2), WriteOptions::default()).unwrap();
+        db.commit(vec![outcome.change()]).unwrap();
+
+        let outcome =
+            write_record(&mut db, &schemas, "b", json!(3), WriteOptions::default()).unwrap();
+        let err = db.commit(vec![outcome.change()]).unwrap_err();
+        assert_eq!(err.code(), "quota_exceeded");
+        assert!(!db.contains_key("b"));
+        assert_eq!(db["a"].data, json!(2));
+        drop(db);
+
+        let stats = store.stats();
+        assert_eq!(stats["records"], 1);
+        assert_eq!(stats["utilization"]["records"], 1.0);
+        assert_eq!(stats["rejected"], 1);
+    }
+
+    #[test]
+    fn test_attachments_are_versioned_writes() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(1),
+            WriteOptions::default(),
+        )
+        .unwrap();
+
+        let attachment = Attachment::new("text/plain", b"notes".to_vec());
+        let attached = attach(&mut records, "a", "notes.txt", attachment.clone(), Some(1)).unwrap();
+        assert_eq!(attached.record.version, 2);
+        assert_eq!(attached.change().kind, ChangeKind::Updated);
+        assert_eq!(
+            attached.record.to_json()["attachments"][0]["uri"],
+            "db://default/records/a/attachments/notes.txt"
+        );
+
+        let updated = write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!(2),
+            WriteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(updated.record.attachments["notes.txt"], attachment);
+
+        let err = detach(&mut records, "a", "missing.txt", None).unwrap_err();
+        assert_eq!(err.code(), "attachment_not_found");
+        assert_eq!(records["a"].version, 3);
+        let detached = detach(&mut records, "a", "notes.txt", Some(3)).unwrap();
+        assert!(detached.record.attachments.is_empty());
+        assert_eq!(detached.record.version, 4);
+    }
+}
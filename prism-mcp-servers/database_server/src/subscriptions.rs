@@ -0,0 +1,205 @@
+//! Resource subscriptions and change notifications.
+//!
+//! Write handlers publish the [`Change`]s they commit; the notifier turns them
+//! into `notifications/resources/updated` for subscribed URIs and
+//! `notifications/resources/list_changed` when records appear or disappear.
+//! Notifications are queued on a channel that `main` drains into the server.
+//!
+//! Over stdio the one client's subscriptions are kept here. The http and
+//! websocket transports keep them per session or connection instead, drop
+//! them when it ends, and deliver each update only to its subscribers; the
+//! notifier then asks the transport which URIs anyone is subscribed to.
+
+use serde_json::json;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{RwLock, mpsc};
+
+use prism_mcp_rs::protocol::{
+    messages::JsonRpcNotification,
+    methods::{RESOURCES_LIST_CHANGED, RESOURCES_UPDATED},
+};
+
+use crate::store::{Change, ChangeKind};
+use crate::uri::DbUri;
+
+/// The URIs subscribed to through a network transport
+type TransportSubscriptions = Arc<dyn Fn() -> HashSet<String> + Send + Sync>;
+
+/// Tracks subscribed URIs and emits change notifications for them
+#[derive(Clone)]
+pub struct ChangeNotifier {
+    /// Subscriptions of the stdio client
+    subscriptions: Arc<RwLock<HashSet<String>>>,
+    /// Set when a network transport keeps the subscriptions
+    transport: Arc<OnceLock<TransportSubscriptions>>,
+    sender: mpsc::UnboundedSender<JsonRpcNotification>,
+}
+
+impl ChangeNotifier {
+    /// Create a notifier and the receiving end of its notification queue
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<JsonRpcNotification>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let notifier = Self {
+            subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            transport: Arc::new(OnceLock::new()),
+            sender,
+        };
+        (notifier, receiver)
+    }
+
+    /// Take subscriptions from a network transport rather than keeping them here
+    pub fn follow_transport(
+        &self,
+        subscriptions: impl Fn() -> HashSet<String> + Send + Sync + 'static,
+    ) {
+        if self.transport.set(Arc::new(subscriptions)).is_err() {
+            tracing::warn!("Change notifications already follow a transport");
+        }
+    }
+
+    /// Subscribe to `uri`, which must parse as a [`DbUri`]
+    ///
+    /// Does nothing once a network transport keeps the subscriptions.
+    pub async fn subscribe(&self, uri: &str) {
+        if self.transport.get().is_none() {
+            self.subscriptions.write().await.insert(uri.to_string());
+        }
+    }
+
+    pub async fn unsubscribe(&self, uri: &str) {
+        if self.transport.get().is_none() {
+            self.subscriptions.write().await.remove(uri);
+        }
+    }
+
+    /// URIs someone is subscribed to
+    async fn subscribed(&self) -> HashSet<String> {
+        match self.transport.get() {
+            Some(subscriptions) => subscriptions(),
+            None => self.subscriptions.read().await.clone(),
+        }
+    }
+
+    /// Notify subscribers about committed changes
+    pub async fn publish(&self, changes: &[Change]) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let touched: BTreeSet<String> = self
+            .subscribed()
+            .await
+            .into_iter()
+            .filter(|subscribed| {
+                DbUri::parse(subscribed)
+                    .is_ok_and(|parsed| changes.iter().any(|change| parsed.touched_by(change)))
+            })
+            .collect();
+        for uri in touched {
+            self.send(RESOURCES_UPDATED, json!({ "uri": uri }));
+        }
+
+        let membership_changed = changes
+            .iter()
+            .any(|change| change.kind != ChangeKind::Updated || change.collections.len() > 1);
+        if membership_changed {
+            self.notify_list_changed();
+        }
+    }
+
+    /// Notify clients that the set of listed resources has changed
+    pub fn notify_list_changed(&self) {
+        self.send(RESOURCES_LIST_CHANGED, json!({}));
+    }
+
+    /// Notify subscribers that a resource other than a record has changed
+    pub async fn notify_updated(&self, uri: &str) {
+        if self.subscribed().await.contains(uri) {
+            self.send(RESOURCES_UPDATED, json!({ "uri": uri }));
+        }
+    }
+
+    fn send(&self, method: &str, params: serde_json::Value) {
+        let notification = match JsonRpcNotification::new(method.to_string(), Some(params)) {
+            Ok(notification) => notification,
+            Err(e) => {
+                tracing::warn!("Failed to build {method} notification: {e}");
+                return;
+            }
+        };
+        if self.sender.send(notification).is_err() {
+            tracing::debug!("Dropping {method} notification: server is shutting down");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uri;
+
+    fn change(kind: ChangeKind, id: &str, collections: &[&str]) -> Change {
+        Change {
+            kind,
+            id: id.to_string(),
+            collections: collections.iter().map(|c| c.to_string()).collect(),
+            before: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_subscribed_uris_are_notified() {
+        let (notifier, mut receiver) = ChangeNotifier::new();
+        notifier.subscribe(&uri::record_uri("users", "a")).await;
+
+        notifier
+            .publish(&[change(ChangeKind::Updated, "b", &["users"])])
+            .await;
+        assert!(receiver.try_recv().is_err());
+
+        notifier
+            .publish(&[change(ChangeKind::Updated, "a", &["users"])])
+            .await;
+        let notification = receiver.try_recv().unwrap();
+        assert_eq!(notification.method, RESOURCES_UPDATED);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transport_subscriptions_replace_the_local_ones() {
+        let (notifier, mut receiver) = ChangeNotifier::new();
+        let record = uri::record_uri("users", "a");
+        let subscribed = Arc::new(std::sync::Mutex::new(HashSet::from([record.clone()])));
+        let transport = subscribed.clone();
+        notifier.follow_transport(move || transport.lock().unwrap().clone());
+        notifier.subscribe(&uri::record_uri("users", "b")).await;
+
+        notifier
+            .publish(&[change(ChangeKind::Updated, "b", &["users"])])
+            .await;
+        assert!(receiver.try_recv().is_err());
+        notifier
+            .publish(&[change(ChangeKind::Updated, "a", &["users"])])
+            .await;
+        assert_eq!(receiver.try_recv().unwrap().method, RESOURCES_UPDATED);
+
+        // The session or connection that subscribed has ended
+        subscribed.lock().unwrap().clear();
+        notifier
+            .publish(&[change(ChangeKind::Updated, "a", &["users"])])
+            .await;
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_created_records_change_the_list() {
+        let (notifier, mut receiver) = ChangeNotifier::new();
+
+        notifier
+            .publish(&[change(ChangeKind::Created, "a", &["users"])])
+            .await;
+        let notification = receiver.try_recv().unwrap();
+        assert_eq!(notification.method, RESOURCES_LIST_CHANGED);
+    }
+}
@@ -0,0 +1,466 @@
+//! Atomic multi-operation transactions.
+//!
+//! Operations are applied in order to the records, each saving the record it
+//! changes to an undo log first; if one fails, the log puts back every record
+//! the transaction touched, so it applies completely or not at all. Each
+//! operation is held to the policy on the tool it stands for, so denying
+//! `delete` also denies deleting through a transaction.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use access_control::policy::PolicyEngine;
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::access;
+use crate::expiry::ExpiryArgs;
+use crate::patch::PatchFormat;
+use crate::schema::{SchemaRegistry, Schemas};
+use crate::store::{self, Change, Database, Records, StoreError, UndoLog, WriteMode, WriteOptions};
+use crate::subscriptions::ChangeNotifier;
+
+/// Default maximum number of operations accepted in a single transaction
+pub const MAX_OPERATIONS: usize = 100;
+
+/// A single step of a transaction
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TxOperation {
+    /// Write a whole record (same semantics as the `store`, `insert` and `update` tools)
+    Store {
+        id: String,
+        data: Value,
+        collection: Option<String>,
+        #[serde(default)]
+        mode: WriteMode,
+        expected_version: Option<u64>,
+        #[serde(flatten)]
+        expiry: ExpiryArgs,
+    },
+    /// Patch a record (same semantics as the `patch` tool)
+    Patch {
+        id: String,
+        patch: Value,
+        format: Option<String>,
+        expected_version: Option<u64>,
+    },
+    /// Delete a record
+    Delete {
+        id: String,
+        expected_version: Option<u64>,
+    },
+    /// Abort the transaction unless the record is in the expected state
+    Assert {
+        id: String,
+        #[serde(default = "default_exists")]
+        exists: bool,
+        expected_version: Option<u64>,
+    },
+}
+
+fn default_exists() -> bool {
+    true
+}
+
+impl TxOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            TxOperation::Store { .. } => "store",
+            TxOperation::Patch { .. } => "patch",
+            TxOperation::Delete { .. } => "delete",
+            TxOperation::Assert { .. } => "assert",
+        }
+    }
+
+    /// The tool the operation stands for; asserts change nothing and have none
+    fn tool(&self) -> Option<&'static str> {
+        match self {
+            TxOperation::Store { mode, .. } => Some(match mode {
+                WriteMode::Upsert => "store",
+                WriteMode::Insert => "insert",
+                WriteMode::Update => "update",
+            }),
+            TxOperation::Patch { .. } => Some("patch"),
+            TxOperation::Delete { .. } => Some("delete"),
+            TxOperation::Assert { .. } => None,
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            TxOperation::Store { id, .. }
+            | TxOperation::Patch { id, .. }
+            | TxOperation::Delete { id, .. }
+            | TxOperation::Assert { id, .. } => id,
+        }
+    }
+
+    /// Apply the operation to `records`, returning the resulting version (if any)
+    fn apply(
+        &self,
+        records: &mut Records,
+        schemas: &SchemaRegistry,
+    ) -> Result<Option<u64>, StoreError> {
+        match self {
+            TxOperation::Store {
+                id,
+                data,
+                collection,
+                mode,
+                expected_version,
+                expiry,
+            } => {
+                let expires_at = expiry.resolve(chrono::Utc::now()).map_err(|message| {
+                    StoreError::InvalidExpiry {
+                        id: id.clone(),
+                        message,
+                    }
+                })?;
+                let options = WriteOptions {
+                    collection: collection.as_deref(),
+                    mode: *mode,
+                    expected_version: *expected_version,
+                    expires_at,
+                };
+                store::write_record(records, schemas, id, data.clone(), options)
+                    .map(|outcome| Some(outcome.record.version))
+            }
+            TxOperation::Patch {
+                id,
+                patch,
+                format,
+                expected_version,
+            } => {
+                let format = PatchFormat::resolve(format.as_deref(), patch).map_err(|message| {
+                    StoreError::PatchFailed {
+                        id: id.clone(),
+                        message,
+                    }
+                })?;
+                store::patch_record(records, schemas, id, patch, format, *expected_version)
+                    .map(|outcome| Some(outcome.record.version))
+            }
+            TxOperation::Delete {
+                id,
+                expected_version,
+            } => store::delete_record(records, id, *expected_version).map(|_| None),
+            TxOperation::Assert {
+                id,
+                exists,
+                expected_version,
+            } => {
+                let current = store::get_live(records, id);
+                match (current, *exists) {
+                    (Some(_), false) => Err(StoreError::AlreadyExists { id: id.clone() }),
+                    (None, true) => Err(StoreError::NotFound { id: id.clone() }),
+                    _ => Ok(()),
+                }?;
+                match (current, *expected_version) {
+                    (Some(record), Some(expected)) if record.version != expected => {
+                        Err(StoreError::VersionConflict {
+                            id: id.clone(),
+                            expected,
+                            actual: Some(record.version),
+                        })
+                    }
+                    _ => Ok(current.map(|record| record.version)),
+                }
+            }
+        }
+    }
+}
+
+/// Why a transaction was rolled back
+#[derive(Debug)]
+pub struct TransactionFailure {
+    /// Index of the operation that failed
+    pub index: usize,
+    pub error: StoreError,
+    /// Per-operation results, with every operation marked as not applied
+    pub results: Vec<Value>,
+}
+
+/// A committed transaction
+#[derive(Debug)]
+pub struct TransactionCommit {
+    /// Per-operation results, in order
+    pub results: Vec<Value>,
+    /// Net change to each record touched by the transaction
+    pub changes: Vec<Change>,
+}
+
+/// Apply all operations atomically.
+///
+/// On success per-operation results are returned; on failure every record is
+/// restored to what it was before the call.
+pub fn apply_all(
+    records: &mut Records,
+    schemas: &SchemaRegistry,
+    operations: &[TxOperation],
+) -> Result<TransactionCommit, TransactionFailure> {
+    let mut undo = UndoLog::default();
+    let mut results = Vec::with_capacity(operations.len());
+
+    for (index, operation) in operations.iter().enumerate() {
+        if operation.tool().is_some() {
+            undo.save(records, operation.id());
+        }
+        match operation.apply(records, schemas) {
+            Ok(version) => results.push(json!({
+                "index": index,
+                "op": operation.name(),
+                "id": operation.id(),
+                "status": "ok",
+                "version": version
+            })),
+            Err(error) => {
+                undo.rollback(records);
+                let mut results: Vec<Value> = results
+                    .into_iter()
+                    .map(|mut result| {
+                        result["status"] = json!("rolled_back");
+                        result
+                    })
+                    .collect();
+                results.push(json!({
+                    "index": index,
+                    "op": operation.name(),
+                    "id": operation.id(),
+                    "status": "failed",
+                    "error": error.to_json()
+                }));
+                results.extend(operations.iter().enumerate().skip(index + 1).map(
+                    |(index, operation)| {
+                        json!({
+                            "index": index,
+                            "op": operation.name(),
+                            "id": operation.id(),
+                            "status": "skipped"
+                        })
+                    },
+                ));
+                return Err(TransactionFailure {
+                    index,
+                    error,
+                    results,
+                });
+            }
+        }
+    }
+
+    let changes = undo.changes(records);
+    Ok(TransactionCommit { results, changes })
+}
+
+/// Database tool handler for atomic multi-operation transactions
+pub struct TransactionHandler {
+    pub db: Database,
+    pub schemas: Schemas,
+    pub notifier: ChangeNotifier,
+    /// Largest number of operations accepted in one call
+    pub max_operations: usize,
+    /// Checked for each operation as for a call of the tool it stands for
+    pub policy: Arc<PolicyEngine>,
+}
+
+#[async_trait]
+impl ToolHandler for TransactionHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let requested = arguments
+            .get("operations")
+            .ok_or_else(|| McpError::Validation("Missing 'operations' parameter".to_string()))?;
+
+        let operations: Vec<TxOperation> = serde_json::from_value(requested.clone())
+            .map_err(|e| McpError::Validation(format!("Invalid 'operations' parameter: {e}")))?;
+
+        if operations.is_empty() || operations.len() > self.max_operations {
+            return Err(McpError::Validation(format!(
+                "'operations' must contain between 1 and {} operations",
+                self.max_operations
+            )));
+        }
+        let requested = requested.as_array().into_iter().flatten();
+        for (operation, arguments) in operations.iter().zip(requested) {
+            if let Some(tool) = operation.tool() {
+                access::check_tool(&self.policy, tool, arguments)?;
+            }
+        }
+
+        let result = {
+            let schemas = self.schemas.read().await;
+            let mut db = self.db.write().await;
+            let mut result = apply_all(&mut db, &schemas, &operations);
+            if let Ok(commit) = &mut result {
+                match db.commit(std::mem::take(&mut commit.changes)) {
+                    Ok(changes) => commit.changes = changes,
+                    Err(err) => return Ok(crate::store_error_result(err)),
+                }
+            }
+            result
+        };
+        match result {
+            Ok(commit) => {
+                self.notifier.publish(&commit.changes).await;
+                let response = json!({
+                    "committed": true,
+                    "results": commit.results
+                });
+                Ok(ToolResult {
+                    content: vec![Content::text(serde_json::to_string_pretty(&response)?)],
+                    is_error: None,
+                    structured_content: Some(response),
+                    meta: None,
+                })
+            }
+            Err(failure) => {
+                let response = json!({
+                    "committed": false,
+                    "failed_index": failure.index,
+                    "error": failure.error.to_json(),
+                    "results": failure.results
+                });
+                Ok(ToolResult {
+                    content: vec![Content::text(format!(
+                        "Transaction rolled back: operation {} failed: {}",
+                        failure.index, failure.error
+                    ))],
+                    is_error: Some(true),
+                    structured_content: Some(response),
+                    meta: None,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operations(value: Value) -> Vec<TxOperation> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_failed_operation_rolls_back_everything() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        store::write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!({"items": [1]}),
+            WriteOptions::default(),
+        )
+        .unwrap();
+
+        let ops = operations(json!([
+            {"op": "patch", "id": "a", "patch": [{"op": "remove", "path": "/items/0"}]},
+            {"op": "store", "id": "b", "data": {"items": [1]}, "mode": "update"}
+        ]));
+        let failure = apply_all(&mut records, &schemas, &ops).unwrap_err();
+        assert_eq!(failure.index, 1);
+        assert_eq!(failure.results[0]["status"], "rolled_back");
+        assert_eq!(records["a"].data, json!({"items": [1]}));
+        assert_eq!(records["a"].version, 1);
+        assert!(!records.contains_key("b"));
+
+        // Records deleted or created before the failing operation are put back too
+        let ops = operations(json!([
+            {"op": "delete", "id": "a"},
+            {"op": "store", "id": "c", "data": {}},
+            {"op": "assert", "id": "a"}
+        ]));
+        let failure = apply_all(&mut records, &schemas, &ops).unwrap_err();
+        assert_eq!(failure.index, 2);
+        assert_eq!(records["a"].version, 1);
+        assert!(!records.contains_key("c"));
+    }
+
+    #[test]
+    fn test_successful_transaction_commits_in_order() {
+        let mut records = Records::new();
+        let schemas = SchemaRegistry::default();
+        store::write_record(
+            &mut records,
+            &schemas,
+            "a",
+            json!({"items": [1]}),
+            WriteOptions::default(),
+        )
+        .unwrap();
+
+        let ops = operations(json!([
+            {"op": "assert", "id": "a", "expected_version": 1},
+            {"op": "assert", "id": "b", "exists": false},
+            {"op": "patch", "id": "a", "patch": [{"op": "remove", "path": "/items/0"}]},
+            {"op": "store", "id": "b", "data": {"items": [1]}, "mode": "insert", "ttl_seconds": 60},
+            {"op": "delete", "id": "a", "expected_version": 2}
+        ]));
+        let commit = apply_all(&mut records, &schemas, &ops).unwrap();
+        assert_eq!(commit.results.len(), 5);
+        assert_eq!(commit.changes.len(), 2);
+        assert!(!records.contains_key("a"));
+        assert_eq!(records["b"].data, json!({"items": [1]}));
+        assert!(records["b"].expires_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_operations_are_checked_as_their_tools() {
+        use crate::backend::MemoryBackend;
+        use crate::quota::Quotas;
+        use crate::store::{DatabaseRecord, RecordStore};
+        use access_control::policy::Policy;
+        use tokio::sync::RwLock;
+
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            tools = ["delete", "insert"]
+            "#,
+        )
+        .unwrap();
+        let db = Arc::new(RecordStore::open(Box::new(MemoryBackend), Quotas::default()).unwrap());
+        db.write().await.insert(
+            "a".to_string(),
+            DatabaseRecord::new("a", "users", json!({})),
+        );
+        let (notifier, _) = ChangeNotifier::new();
+        let handler = TransactionHandler {
+            db: db.clone(),
+            schemas: Arc::new(RwLock::new(SchemaRegistry::default())),
+            notifier,
+            max_operations: MAX_OPERATIONS,
+            policy: Arc::new(PolicyEngine::from(policy)),
+        };
+        let call = |operations: Value| {
+            handler.call(HashMap::from([("operations".to_string(), operations)]))
+        };
+
+        for denied in [
+            json!([{"op": "assert", "id": "a"}, {"op": "delete", "id": "a"}]),
+            json!([{"op": "store", "id": "b", "data": {}, "mode": "insert"}]),
+        ] {
+            let error = call(denied).await.unwrap_err();
+            assert!(matches!(error, McpError::Authentication(_)), "{error}");
+        }
+        assert!(db.read().await.contains_key("a"));
+        assert!(!db.read().await.contains_key("b"));
+
+        let result = call(json!([{"op": "store", "id": "b", "data": {}}]))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, None);
+        assert!(db.read().await.contains_key("b"));
+    }
+}
@@ -0,0 +1,755 @@
+//! Bulk import and export of records as NDJSON, JSON or CSV.
+//!
+//! Exports either become a resource under [`uri::EXPORT_PREFIX`] or are written to
+//! a file inside the configured data directory. Imports read the same formats
+//! and are applied atomically: if a row fails, the rows before it are undone.
+//!
+//! CSV flattens record data into one column per leaf value (`data.name`,
+//! `data.address.city`, `data.tags.0`, ...). On import, cells that parse as
+//! JSON numbers, booleans, `null`, `{}` or `[]` keep that type; everything
+//! else is read as a string.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use prism_mcp_rs::{
+    core::{
+        error::{McpError, McpResult},
+        tool::ToolHandler,
+    },
+    protocol::types::{Content, ToolResult},
+};
+
+use crate::expiry::ExpiryArgs;
+use crate::filter::RecordFilter;
+use crate::schema::{SchemaRegistry, Schemas};
+use crate::store::{
+    self, Change, Database, DatabaseRecord, StoreError, UndoLog, WriteOptions, WriteOutcome,
+};
+use crate::subscriptions::ChangeNotifier;
+use crate::uri;
+
+/// Number of export resources kept; older exports are discarded
+pub const MAX_EXPORTS: usize = 16;
+
+/// Columns written before the flattened data columns in CSV exports
+const CSV_RECORD_COLUMNS: [&str; 6] = [
+    "id",
+    "collection",
+    "version",
+    "created_at",
+    "updated_at",
+    "expires_at",
+];
+
+/// Serialization format for import and export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON record per line
+    Ndjson,
+    /// A JSON array of records
+    Json,
+    /// Comma-separated values with flattened data columns
+    Csv,
+}
+
+impl Format {
+    /// Use the explicit `format` if given, otherwise infer it from the file
+    /// extension of `path`, defaulting to NDJSON
+    pub fn resolve(format: Option<&str>, path: Option<&str>) -> Result<Self, String> {
+        match format {
+            Some("ndjson") => Ok(Format::Ndjson),
+            Some("json") => Ok(Format::Json),
+            Some("csv") => Ok(Format::Csv),
+            Some(other) => Err(format!(
+                "Unknown format '{other}': expected 'ndjson', 'json' or 'csv'"
+            )),
+            None => {
+                let extension = path
+                    .and_then(|path| Path::new(path).extension())
+                    .and_then(|extension| extension.to_str());
+                match extension {
+                    Some("json") => Ok(Format::Json),
+                    Some("csv") => Ok(Format::Csv),
+                    _ => Ok(Format::Ndjson),
+                }
+            }
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Ndjson => "application/x-ndjson",
+            Format::Json => "application/json",
+            Format::Csv => "text/csv",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Ndjson => "ndjson",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+/// A record read by an import; bookkeeping fields such as `version` are ignored
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportRow {
+    pub id: String,
+    #[serde(default)]
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub data: Value,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Serialize records in the given format
+pub fn encode(records: &[&DatabaseRecord], format: Format) -> Result<String, String> {
+    match format {
+        Format::Ndjson => {
+            let mut output = String::new();
+            for record in records {
+                output.push_str(&serde_json::to_string(record).map_err(|e| e.to_string())?);
+                output.push('\n');
+            }
+            Ok(output)
+        }
+        Format::Json => serde_json::to_string_pretty(records).map_err(|e| e.to_string()),
+        Format::Csv => encode_csv(records),
+    }
+}
+
+fn encode_csv(records: &[&DatabaseRecord]) -> Result<String, String> {
+    let rows: Vec<BTreeMap<String, String>> = records
+        .iter()
+        .map(|record| {
+            let mut row = BTreeMap::new();
+            flatten("data", &record.data, &mut row);
+            row
+        })
+        .collect();
+    let data_columns: BTreeSet<&String> = rows.iter().flat_map(|row| row.keys()).collect();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(
+            CSV_RECORD_COLUMNS
+                .iter()
+                .copied()
+                .chain(data_columns.iter().map(|column| column.as_str())),
+        )
+        .map_err(|e| e.to_string())?;
+
+    for (record, row) in records.iter().zip(&rows) {
+        let fixed = [
+            record.id.clone(),
+            record.collection.clone(),
+            record.version.to_string(),
+            record.created_at.to_rfc3339(),
+            record.updated_at.to_rfc3339(),
+            record
+                .expires_at
+                .map(|expires_at| expires_at.to_rfc3339())
+                .unwrap_or_default(),
+        ];
+        let data = data_columns
+            .iter()
+            .map(|column| row.get(*column).cloned().unwrap_or_default());
+        writer
+            .write_record(fixed.into_iter().chain(data))
+            .map_err(|e| e.to_string())?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+/// Flatten `value` into dotted column names below `prefix`
+fn flatten(prefix: &str, value: &Value, row: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(fields) if !fields.is_empty() => {
+            for (key, value) in fields {
+                flatten(&format!("{prefix}.{key}"), value, row);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, value) in items.iter().enumerate() {
+                flatten(&format!("{prefix}.{index}"), value, row);
+            }
+        }
+        Value::String(text) => {
+            row.insert(prefix.to_string(), text.clone());
+        }
+        Value::Null => {
+            row.insert(prefix.to_string(), String::new());
+        }
+        other => {
+            row.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// Parse records in the given format; errors name the offending row
+pub fn decode(input: &str, format: Format) -> Result<Vec<ImportRow>, String> {
+    match format {
+        Format::Ndjson => input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| format!("Line {}: {e}", index + 1))
+            })
+            .collect(),
+        Format::Json => serde_json::from_str(input).map_err(|e| format!("Invalid JSON: {e}")),
+        Format::Csv => decode_csv(input),
+    }
+}
+
+fn decode_csv(input: &str) -> Result<Vec<ImportRow>, String> {
+    let mut reader = csv::Reader::from_reader(input.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    if !headers.iter().any(|header| header == "id") {
+        return Err("CSV input must have an 'id' column".to_string());
+    }
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let row_number = index + 1;
+        let record = record.map_err(|e| format!("Row {row_number}: {e}"))?;
+
+        let mut id = None;
+        let mut collection = None;
+        let mut expires_at = None;
+        let mut data = Value::Null;
+        for (header, cell) in headers.iter().zip(record.iter()) {
+            if cell.is_empty() {
+                continue;
+            }
+            match header {
+                "id" => id = Some(cell.to_string()),
+                "collection" => collection = Some(cell.to_string()),
+                "expires_at" => {
+                    let parsed = DateTime::parse_from_rfc3339(cell)
+                        .map_err(|e| format!("Row {row_number}: invalid 'expires_at': {e}"))?;
+                    expires_at = Some(parsed.with_timezone(&Utc));
+                }
+                "data" => data = parse_cell(cell),
+                _ => {
+                    if let Some(path) = header.strip_prefix("data.") {
+                        insert_path(&mut data, path, parse_cell(cell));
+                    }
+                }
+            }
+        }
+
+        rows.push(ImportRow {
+            id: id.ok_or_else(|| format!("Row {row_number}: missing 'id'"))?,
+            collection,
+            data: restore_arrays(data),
+            expires_at,
+        });
+    }
+    Ok(rows)
+}
+
+fn parse_cell(cell: &str) -> Value {
+    match serde_json::from_str::<Value>(cell) {
+        Ok(value @ (Value::Number(_) | Value::Bool(_) | Value::Null)) => value,
+        Ok(Value::Object(fields)) if fields.is_empty() => Value::Object(fields),
+        Ok(Value::Array(items)) if items.is_empty() => Value::Array(items),
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+/// Set `value` at a dotted `path` inside `target`, creating objects as needed
+fn insert_path(target: &mut Value, path: &str, value: Value) {
+    let mut current = target;
+    for segment in path.split('.') {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .expect("just made an object")
+            .entry(segment)
+            .or_insert(Value::Null);
+    }
+    *current = value;
+}
+
+/// Turn objects keyed `0..n` (from flattened arrays) back into arrays
+fn restore_arrays(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let is_array = !fields.is_empty()
+                && (0..fields.len()).all(|index| fields.contains_key(&index.to_string()));
+            if is_array {
+                let mut fields = fields;
+                Value::Array(
+                    (0..fields.len())
+                        .map(|index| restore_arrays(fields.remove(&index.to_string()).unwrap()))
+                        .collect(),
+                )
+            } else {
+                Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(key, value)| (key, restore_arrays(value)))
+                        .collect(),
+                )
+            }
+        }
+        other => other,
+    }
+}
+
+/// Directory that `path` arguments of `import` and `export` are confined to
+#[derive(Debug, Clone, Default)]
+pub struct DataDir(pub Option<PathBuf>);
+
+impl DataDir {
+    /// Resolve a relative path inside the data directory
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let root = self.0.as_ref().ok_or(
+            "File import/export is disabled: no data directory is configured (set DATABASE_DATA_DIR)",
+        )?;
+        let relative = Path::new(path);
+        let confined = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !confined || path.is_empty() {
+            return Err(format!(
+                "Invalid path '{path}': must be relative to the data directory and must not contain '..'"
+            ));
+        }
+        Ok(root.join(relative))
+    }
+}
+
+/// An export kept in memory and served as a resource
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub name: String,
+    pub format: Format,
+    pub content: String,
+    pub records: usize,
+}
+
+impl Export {
+    pub fn uri(&self) -> String {
+        uri::export_uri(&self.name)
+    }
+}
+
+/// Most recent exports, oldest first
+#[derive(Debug, Default)]
+pub struct ExportStore {
+    exports: VecDeque<Export>,
+    next_id: u64,
+}
+
+/// Shared export state
+pub type Exports = Arc<RwLock<ExportStore>>;
+
+impl ExportStore {
+    /// Keep a new export, discarding the oldest beyond [`MAX_EXPORTS`]
+    pub fn add(&mut self, format: Format, content: String, records: usize) -> Export {
+        self.next_id += 1;
+        let export = Export {
+            name: format!("export-{}.{}", self.next_id, format.extension()),
+            format,
+            content,
+            records,
+        };
+        self.exports.push_back(export.clone());
+        while self.exports.len() > MAX_EXPORTS {
+            self.exports.pop_front();
+        }
+        export
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Export> {
+        self.exports.iter().find(|export| export.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Export> {
+        self.exports.iter()
+    }
+}
+
+/// Database tool handler for exporting records
+pub struct ExportHandler {
+    pub db: Database,
+    pub exports: Exports,
+    pub data_dir: DataDir,
+    pub notifier: ChangeNotifier,
+}
+
+#[async_trait]
+impl ToolHandler for ExportHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let filter = RecordFilter::from_arguments(&arguments).map_err(McpError::Validation)?;
+        let path = arguments.get("path").and_then(|v| v.as_str());
+        let format = Format::resolve(arguments.get("format").and_then(|v| v.as_str()), path)
+            .map_err(McpError::Validation)?;
+        let target = path
+            .map(|path| self.data_dir.resolve(path))
+            .transpose()
+            .map_err(McpError::Validation)?;
+
+        let (content, count) = {
+            let db = self.db.read().await;
+            let records: Vec<_> = store::live_records(&db)
+                .filter(|record| filter.matches(record))
+                .collect();
+            let content = encode(&records, format).map_err(McpError::Serialization)?;
+            (content, records.len())
+        };
+
+        let mut response = json!({
+            "format": format.extension(),
+            "exported": count
+        });
+        let message = match target {
+            Some(target) => {
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&target, &content).await?;
+                response["path"] = json!(target.display().to_string());
+                format!("Exported {count} record(s) to {}", target.display())
+            }
+            None => {
+                let export = self.exports.write().await.add(format, content, count);
+                self.notifier.notify_list_changed();
+                response["uri"] = json!(export.uri());
+                format!("Exported {count} record(s) to {}", export.uri())
+            }
+        };
+
+        Ok(ToolResult {
+            content: vec![Content::text(message)],
+            is_error: None,
+            structured_content: Some(response),
+            meta: None,
+        })
+    }
+}
+
+/// How an import treats records that already exist
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Replace existing records
+    #[default]
+    Upsert,
+    /// Leave existing records untouched
+    Skip,
+    /// Abort the whole import if any record already exists
+    Fail,
+}
+
+/// Counts reported by a committed import
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportCounts {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Why an import was aborted
+#[derive(Debug)]
+pub struct ImportFailure {
+    /// 1-based index of the offending row
+    pub row: usize,
+    pub error: StoreError,
+}
+
+/// Apply imported rows atomically, returning counts and committed changes
+pub fn import_rows(
+    records: &mut store::Records,
+    schemas: &SchemaRegistry,
+    rows: &[ImportRow],
+    default_collection: Option<&str>,
+    mode: ImportMode,
+) -> Result<(ImportCounts, Vec<Change>), ImportFailure> {
+    let mut undo = UndoLog::default();
+    let mut counts = ImportCounts::default();
+    let now = Utc::now();
+
+    for (index, row) in rows.iter().enumerate() {
+        undo.save(records, &row.id);
+        match import_row(records, schemas, row, default_collection, mode, now) {
+            Ok(Some(outcome)) if outcome.created() => counts.created += 1,
+            Ok(Some(_)) => counts.updated += 1,
+            Ok(None) => counts.skipped += 1,
+            Err(error) => {
+                undo.rollback(records);
+                return Err(ImportFailure {
+                    row: index + 1,
+                    error,
+                });
+            }
+        }
+    }
+
+    let changes = undo.changes(records);
+    Ok((counts, changes))
+}
+
+/// Write one imported row; `None` if `mode` skipped it
+fn import_row(
+    records: &mut store::Records,
+    schemas: &SchemaRegistry,
+    row: &ImportRow,
+    default_collection: Option<&str>,
+    mode: ImportMode,
+    now: DateTime<Utc>,
+) -> Result<Option<WriteOutcome>, StoreError> {
+    if store::get_live(records, &row.id).is_some() {
+        match mode {
+            ImportMode::Skip => return Ok(None),
+            ImportMode::Fail => return Err(StoreError::AlreadyExists { id: row.id.clone() }),
+            ImportMode::Upsert => {}
+        }
+    }
+
+    let expiry = ExpiryArgs {
+        ttl_seconds: None,
+        expires_at: row.expires_at,
+    };
+    let expires_at = expiry
+        .resolve(now)
+        .map_err(|message| StoreError::InvalidExpiry {
+            id: row.id.clone(),
+            message,
+        })?;
+    let options = WriteOptions {
+        collection: row.collection.as_deref().or(default_collection),
+        expires_at,
+        ..Default::default()
+    };
+    store::write_record(records, schemas, &row.id, row.data.clone(), options).map(Some)
+}
+
+/// Database tool handler for importing records
+pub struct ImportHandler {
+    pub db: Database,
+    pub schemas: Schemas,
+    pub data_dir: DataDir,
+    pub notifier: ChangeNotifier,
+    /// Largest input accepted, in bytes
+    pub max_bytes: usize,
+}
+
+#[async_trait]
+impl ToolHandler for ImportHandler {
+    async fn call(&self, arguments: HashMap<String, Value>) -> McpResult<ToolResult> {
+        let content = arguments.get("content").and_then(|v| v.as_str());
+        let path = arguments.get("path").and_then(|v| v.as_str());
+        let format = Format::resolve(arguments.get("format").and_then(|v| v.as_str()), path)
+            .map_err(McpError::Validation)?;
+
+        let mode: ImportMode = match arguments.get("mode") {
+            None | Some(Value::Null) => ImportMode::default(),
+            Some(mode) => serde_json::from_value(mode.clone()).map_err(|_| {
+                McpError::Validation("'mode' must be 'upsert', 'skip' or 'fail'".to_string())
+            })?,
+        };
+        let collection = arguments.get("collection").and_then(|v| v.as_str());
+
+        let too_large = |size: u64| {
+            McpError::Validation(format!(
+                "Import of {size} bytes exceeds the limit of {} bytes",
+                self.max_bytes
+            ))
+        };
+        let input = match (content, path) {
+            (Some(content), None) if content.len() > self.max_bytes => {
+                return Err(too_large(content.len() as u64));
+            }
+            (Some(content), None) => content.to_string(),
+            (None, Some(path)) => {
+                let source = self.data_dir.resolve(path).map_err(McpError::Validation)?;
+                let size = tokio::fs::metadata(&source).await?.len();
+                if size > self.max_bytes as u64 {
+                    return Err(too_large(size));
+                }
+                tokio::fs::read_to_string(&source).await?
+            }
+            _ => {
+                return Err(McpError::Validation(
+                    "Provide exactly one of 'content' or 'path'".to_string(),
+                ));
+            }
+        };
+
+        let rows = match decode(&input, format) {
+            Ok(rows) => rows,
+            Err(message) => {
+                return Ok(ToolResult {
+                    content: vec![Content::text(format!("Import failed: {message}"))],
+                    is_error: Some(true),
+                    structured_content: Some(json!({
+                        "error": "invalid_input",
+                        "message": message
+                    })),
+                    meta: None,
+                });
+            }
+        };
+
+        let result = {
+            let schemas = self.schemas.read().await;
+            let mut db = self.db.write().await;
+            let mut result = import_rows(&mut db, &schemas, &rows, collection, mode);
+            if let Ok((_, changes)) = &mut result {
+                match db.commit(std::mem::take(changes)) {
+                    Ok(committed) => *changes = committed,
+                    Err(err) => return Ok(crate::store_error_result(err)),
+                }
+            }
+            result
+        };
+
+        match result {
+            Ok((counts, changes)) => {
+                self.notifier.publish(&changes).await;
+                let response = json!({
+                    "imported": counts.created + counts.updated,
+                    "created": counts.created,
+                    "updated": counts.updated,
+                    "skipped": counts.skipped
+                });
+                Ok(ToolResult {
+                    content: vec![Content::text(format!(
+                        "Imported {} record(s): {} created, {} updated, {} skipped",
+                        counts.created + counts.updated,
+                        counts.created,
+                        counts.updated,
+                        counts.skipped
+                    ))],
+                    is_error: None,
+                    structured_content: Some(response),
+                    meta: None,
+                })
+            }
+            Err(failure) => Ok(ToolResult {
+                content: vec![Content::text(format!(
+                    "Import rolled back: row {} failed: {}",
+                    failure.row, failure.error
+                ))],
+                is_error: Some(true),
+                structured_content: Some(json!({
+                    "row": failure.row,
+                    "error": failure.error.to_json()
+                })),
+                meta: None,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, data: Value) -> DatabaseRecord {
+        DatabaseRecord::new(id, "users", data)
+    }
+
+    #[test]
+    fn test_csv_round_trip_flattens_data() {
+        let alice = record(
+            "a",
+            json!({"name": "Alice", "age": 30, "address": {"city": "Paris"}, "tags": ["x", "y"]}),
+        );
+        let bob = record("b", json!({"name": "Bob", "active": true}));
+
+        let csv = encode(&[&alice, &bob], Format::Csv).unwrap();
+        let header = csv.lines().next().unwrap();
+        assert!(header.starts_with("id,collection,version"));
+        assert!(header.contains("data.address.city"));
+        assert!(header.contains("data.tags.1"));
+
+        let rows = decode(&csv, Format::Csv).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].data, alice.data);
+        assert_eq!(rows[1].data, bob.data);
+        assert_eq!(rows[1].collection.as_deref(), Some("users"));
+    }
+
+    #[test]
+    fn test_ndjson_and_json_round_trip() {
+        let alice = record("a", json!({"name": "Alice"}));
+        for format in [Format::Ndjson, Format::Json] {
+            let encoded = encode(&[&alice], format).unwrap();
+            let rows = decode(&encoded, format).unwrap();
+            assert_eq!(rows[0].id, "a");
+            assert_eq!(rows[0].data, alice.data);
+        }
+    }
+
+    #[test]
+    fn test_import_modes() {
+        let schemas = SchemaRegistry::default();
+        let mut records = store::Records::new();
+        records.insert("a".to_string(), record("a", json!(1)));
+        let rows = decode(
+            "{\"id\": \"a\", \"data\": 2}\n{\"id\": \"b\", \"data\": 3}\n",
+            Format::Ndjson,
+        )
+        .unwrap();
+
+        let failure =
+            import_rows(&mut records, &schemas, &rows, None, ImportMode::Fail).unwrap_err();
+        assert_eq!(failure.row, 1);
+        assert!(!records.contains_key("b"));
+
+        // Rows before the failing one are undone
+        let reversed: Vec<ImportRow> = rows.iter().rev().cloned().collect();
+        let failure =
+            import_rows(&mut records, &schemas, &reversed, None, ImportMode::Fail).unwrap_err();
+        assert_eq!(failure.row, 2);
+        assert!(!records.contains_key("b"));
+
+        let (counts, changes) =
+            import_rows(&mut records, &schemas, &rows, None, ImportMode::Skip).unwrap();
+        assert_eq!(
+            counts,
+            ImportCounts {
+                created: 1,
+                updated: 0,
+                skipped: 1
+            }
+        );
+        assert_eq!(changes.len(), 1);
+        assert_eq!(records["a"].data, json!(1));
+
+        let (counts, _) =
+            import_rows(&mut records, &schemas, &rows, None, ImportMode::Upsert).unwrap();
+        assert_eq!(counts.updated, 2);
+        assert_eq!(records["a"].data, json!(2));
+    }
+
+    #[test]
+    fn test_data_dir_confines_paths() {
+        let data_dir = DataDir(Some(PathBuf::from("/srv/data")));
+        assert_eq!(
+            data_dir.resolve("exports/users.csv").unwrap(),
+            PathBuf::from("/srv/data/exports/users.csv")
+        );
+        assert!(data_dir.resolve("../etc/passwd").is_err());
+        assert!(data_dir.resolve("/etc/passwd").is_err());
+        assert!(DataDir::default().resolve("users.csv").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! The transport the database is served on.
+//!
+//! Stdio goes through the SDK transport and trusts its one client. The http
+//! and websocket transports are the ones the HTTP and WebSocket servers use:
+//! callers are authenticated with `[auth]`, held to the `[policy]` before the
+//! `[rate_limits]`, and both listeners answer `GET /metrics` and check `Host`
+//! and `Origin` against `[security]`. Those two keep resource subscriptions
+//! per session or connection and send each update only to subscribers the
+//! policy lets read the resource.
+
+use std::future::{self, Future};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use access_control::auth::Authenticator;
+use access_control::limits::RateLimiter;
+use access_control::policy::PolicyEngine;
+use network_transport::http_transport::HttpTransport;
+use network_transport::metrics::Metrics;
+use network_transport::origin::OriginGuard;
+use network_transport::replay::ReplayLimits;
+use network_transport::websocket::{Notifier, WebSocketTransport};
+use prism_mcp_rs::{
+    core::error::{McpError, McpResult},
+    protocol::{messages::JsonRpcNotification, methods::RESOURCES_UPDATED},
+    server::McpServer,
+    transport::stdio::StdioServerTransport,
+};
+
+use crate::config::{Config, TransportKind};
+use crate::subscriptions::ChangeNotifier;
+
+/// Runs until the listener fails; never for stdio
+pub type Listening = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
+/// Where change notifications are delivered
+pub enum Clients {
+    Stdio(McpServer),
+    Http(Arc<HttpTransport>),
+    WebSocket(Notifier),
+}
+
+impl Clients {
+    pub async fn notify(&self, notification: JsonRpcNotification) {
+        match self {
+            Self::Stdio(server) => {
+                if let Err(e) = server.send_notification(notification).await {
+                    tracing::warn!("Failed to send notification: {e}");
+                }
+            }
+            Self::Http(transport) => {
+                let uri = notification
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("uri"))
+                    .and_then(|uri| uri.as_str());
+                match uri {
+                    Some(uri) if notification.method == RESOURCES_UPDATED => {
+                        transport.notify_subscribers(uri, &notification)
+                    }
+                    _ => transport.notify(&notification, None),
+                }
+            }
+            Self::WebSocket(notifier) => notifier.notify(&notification),
+        }
+    }
+
+    pub async fn stop(self) -> McpResult<()> {
+        match self {
+            Self::Stdio(server) => server.stop().await,
+            Self::Http(_) | Self::WebSocket(_) => Ok(()),
+        }
+    }
+}
+
+/// Start serving `server` on the configured transport
+pub async fn start(
+    mut server: McpServer,
+    config: &Config,
+    policy: Arc<PolicyEngine>,
+    notifier: &ChangeNotifier,
+) -> McpResult<(Clients, Listening)> {
+    let bind = config.transport.bind_address();
+    if config.transport.kind == TransportKind::Stdio {
+        tracing::info!("Starting database server on stdio...");
+        server.start(StdioServerTransport::new()).await?;
+        return Ok((Clients::Stdio(server), Box::pin(future::pending())));
+    }
+
+    let listener = TcpListener::bind(bind).await?;
+    serve(server, config, policy, notifier, listener)
+}
+
+/// Serve `server` on `listener` with the configured http or websocket transport
+fn serve(
+    server: McpServer,
+    config: &Config,
+    policy: Arc<PolicyEngine>,
+    notifier: &ChangeNotifier,
+    listener: TcpListener,
+) -> McpResult<(Clients, Listening)> {
+    let bind = config.transport.bind_address();
+    let authenticator = Authenticator::new(&config.auth).map_err(McpError::Validation)?;
+    if !authenticator.is_enabled() {
+        tracing::warn!(
+            "Authentication is disabled: anyone who can reach {bind} can call tools; configure [auth]"
+        );
+    }
+    let metrics = Arc::new(Metrics::new());
+    let limiter = RateLimiter::new(config.rate_limits.clone());
+    let origins = OriginGuard::new(&config.security, listener.local_addr()?);
+    if origins.accepts_any_host() {
+        tracing::warn!(
+            "Host headers are not checked on {bind}: set [security] allowed_hosts to guard against DNS rebinding"
+        );
+    }
+    if config.transport.kind == TransportKind::Websocket {
+        tracing::info!("Starting database server on ws://{bind}...");
+        let connections = Notifier::new();
+        let transport = WebSocketTransport::new(
+            server,
+            connections.clone(),
+            metrics,
+            authenticator,
+            policy,
+            limiter,
+            origins,
+        );
+        let subscriptions = transport.clone();
+        notifier.follow_transport(move || subscriptions.subscriptions());
+        return Ok((
+            Clients::WebSocket(connections),
+            Box::pin(transport.serve(listener)),
+        ));
+    }
+
+    tracing::info!("Starting database server on http://{bind}...");
+    let transport = HttpTransport::new(
+        server,
+        metrics,
+        authenticator,
+        policy,
+        limiter,
+        origins,
+        ReplayLimits::default(),
+    );
+    let subscriptions = transport.clone();
+    notifier.follow_transport(move || subscriptions.subscriptions());
+    Ok((
+        Clients::Http(transport.clone()),
+        Box::pin(transport.serve(listener)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use access_control::auth;
+    use access_control::policy::Policy;
+    use serde_json::Value;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn call(address: SocketAddr, key: &str, body: &str) -> Value {
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "POST /mcp HTTP/1.1\r\nHost: 127.0.0.1\r\nX-API-Key: {key}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_transport_applies_the_policy() {
+        let config = Config::from_toml(
+            r#"
+            [transport]
+            type = "http"
+
+            [[auth.api_keys]]
+            principal = "ops"
+            key = "ops-key"
+            roles = ["admin"]
+
+            [[auth.api_keys]]
+            principal = "ci"
+            key = "ci-key"
+            "#,
+        )
+        .unwrap();
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "allow"
+            roles = ["admin"]
+            tools = ["delete"]
+
+            [[rules]]
+            effect = "deny"
+            tools = ["delete"]
+            "#,
+        )
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = McpServer::new("test".to_string(), "1.0.0".to_string());
+        let (notifier, _) = ChangeNotifier::new();
+        let (_clients, listening) = serve(
+            server,
+            &config,
+            Arc::new(PolicyEngine::from(policy)),
+            &notifier,
+            listener,
+        )
+        .unwrap();
+        tokio::spawn(listening);
+
+        let delete = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"delete","arguments":{"id":"user1"}}}"#;
+        let reply = call(address, "ci-key", delete).await;
+        assert_eq!(reply["error"]["code"], auth::FORBIDDEN);
+        let reply = call(address, "ops-key", delete).await;
+        assert_ne!(reply["error"]["code"], auth::FORBIDDEN);
+        let reply = call(address, "wrong", delete).await;
+        assert_eq!(reply["error"]["code"], auth::UNAUTHENTICATED);
+    }
+}
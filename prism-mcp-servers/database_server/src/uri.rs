@@ -0,0 +1,284 @@
+//! `db://` resource URIs.
+//!
+//! | URI                               | Resource                                   |
+//! |-----------------------------------|--------------------------------------------|
+//! | `db:///records`                   | every record                               |
+//! | `db:///schema`                    | record and per-collection schemas          |
+//! | `db:///stats`                     | usage against the configured quotas        |
+//! | `db:///exports/{name}`            | a stored export                            |
+//! | `db://{collection}{?filter}`      | records in a collection, optionally filtered |
+//! | `db://{collection}/records/{id}`  | a single record                            |
+//! | `db://{collection}/records/{id}/attachments/{name}` | a record's attachment, as a blob |
+//!
+//! Collection names, record ids and attachment names are percent-encoded, so they may contain
+//! any character. `filter` is a percent-encoded JSON object of JSON Pointer
+//! to value, as taken by the `filter` tool argument.
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::filter::RecordFilter;
+use crate::store::Change;
+
+/// URI of the resource listing every record
+pub const ALL_URI: &str = "db:///records";
+/// URI of the record schema resource
+pub const SCHEMA_URI: &str = "db:///schema";
+/// URI of the usage statistics resource
+pub const STATS_URI: &str = "db:///stats";
+/// Prefix of stored export URIs
+pub const EXPORT_PREFIX: &str = "db:///exports/";
+
+/// RFC 6570 template of record URIs
+pub const RECORD_TEMPLATE: &str = "db://{collection}/records/{id}";
+/// RFC 6570 template of attachment URIs
+pub const ATTACHMENT_TEMPLATE: &str = "db://{collection}/records/{id}/attachments/{name}";
+/// RFC 6570 template of collection URIs
+pub const COLLECTION_TEMPLATE: &str = "db://{collection}{?filter}";
+
+const SCHEME: &str = "db://";
+
+/// Characters escaped in URI components: everything except RFC 3986 unreserved characters
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Percent-encode a collection name, record id or query value
+pub fn encode(component: &str) -> String {
+    utf8_percent_encode(component, COMPONENT).to_string()
+}
+
+fn decode(component: &str) -> Result<String, String> {
+    percent_decode_str(component)
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| format!("'{component}' is not valid percent-encoded UTF-8"))
+}
+
+/// URI of an individual record
+pub fn record_uri(collection: &str, id: &str) -> String {
+    format!("{SCHEME}{}/records/{}", encode(collection), encode(id))
+}
+
+/// URI of an attachment of a record
+pub fn attachment_uri(collection: &str, id: &str, name: &str) -> String {
+    format!(
+        "{}/attachments/{}",
+        record_uri(collection, id),
+        encode(name)
+    )
+}
+
+/// URI of all records in a collection
+pub fn collection_uri(collection: &str) -> String {
+    format!("{SCHEME}{}", encode(collection))
+}
+
+/// URI of a stored export
+pub fn export_uri(name: &str) -> String {
+    format!("{EXPORT_PREFIX}{}", encode(name))
+}
+
+/// A parsed `db://` URI
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbUri {
+    All,
+    Schema,
+    Stats,
+    Export(String),
+    Collection {
+        collection: String,
+        filter: RecordFilter,
+    },
+    Record {
+        collection: String,
+        id: String,
+    },
+    Attachment {
+        collection: String,
+        id: String,
+        name: String,
+    },
+}
+
+impl DbUri {
+    /// Parse and percent-decode `uri`
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let rest = uri
+            .strip_prefix(SCHEME)
+            .ok_or_else(|| format!("'{uri}' is not a {SCHEME} URI"))?;
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+        if authority.is_empty() {
+            if query.is_some() {
+                return Err(format!("'{uri}' does not take query parameters"));
+            }
+            return match path {
+                "/records" => Ok(DbUri::All),
+                "/schema" => Ok(DbUri::Schema),
+                "/stats" => Ok(DbUri::Stats),
+                _ => match path.strip_prefix("/exports/") {
+                    Some(name) if !name.is_empty() && !name.contains('/') => {
+                        Ok(DbUri::Export(decode(name)?))
+                    }
+                    _ => Err(format!("Unknown database resource '{uri}'")),
+                },
+            };
+        }
+
+        let collection = decode(authority)?;
+        match path {
+            "" | "/" => Ok(DbUri::Collection {
+                filter: parse_filter(&collection, query)?,
+                collection,
+            }),
+            _ => {
+                if query.is_some() {
+                    return Err(format!("'{uri}' does not take query parameters"));
+                }
+                let segments: Vec<&str> = path
+                    .strip_prefix("/records/")
+                    .map(|rest| rest.split('/').collect())
+                    .unwrap_or_default();
+                match segments[..] {
+                    [id] if !id.is_empty() => Ok(DbUri::Record {
+                        collection,
+                        id: decode(id)?,
+                    }),
+                    [id, "attachments", name] if !id.is_empty() && !name.is_empty() => {
+                        Ok(DbUri::Attachment {
+                            collection,
+                            id: decode(id)?,
+                            name: decode(name)?,
+                        })
+                    }
+                    _ => Err(format!(
+                        "Unknown database resource '{uri}' (expected {RECORD_TEMPLATE} or {ATTACHMENT_TEMPLATE})"
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Whether `change` affects the contents of this resource
+    pub fn touched_by(&self, change: &Change) -> bool {
+        match self {
+            DbUri::All | DbUri::Stats => true,
+            DbUri::Schema | DbUri::Export(_) => false,
+            DbUri::Collection { collection, .. } => change.collections.contains(collection),
+            DbUri::Record { collection, id } | DbUri::Attachment { collection, id, .. } => {
+                *id == change.id && change.collections.contains(collection)
+            }
+        }
+    }
+}
+
+/// Read the `filter` query parameter of a collection URI
+fn parse_filter(collection: &str, query: Option<&str>) -> Result<RecordFilter, String> {
+    let mut arguments = HashMap::from([("collection".to_string(), json!(collection))]);
+    for pair in query.into_iter().flat_map(|query| query.split('&')) {
+        match pair.split_once('=') {
+            Some(("filter", value)) => {
+                let filter: Value = serde_json::from_str(&decode(value)?)
+                    .map_err(|e| format!("'filter' must be a JSON object: {e}"))?;
+                arguments.insert("filter".to_string(), filter);
+            }
+            _ if pair.is_empty() => {}
+            _ => {
+                return Err(format!(
+                    "Unknown query parameter '{pair}' (expected 'filter')"
+                ));
+            }
+        }
+    }
+    RecordFilter::from_arguments(&arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{ChangeKind, DatabaseRecord};
+
+    #[test]
+    fn test_uris_round_trip_with_percent_encoding() {
+        let uri = record_uri("team a", "user/1?");
+        assert_eq!(uri, "db://team%20a/records/user%2F1%3F");
+        assert_eq!(
+            DbUri::parse(&uri).unwrap(),
+            DbUri::Record {
+                collection: "team a".to_string(),
+                id: "user/1?".to_string()
+            }
+        );
+
+        assert_eq!(DbUri::parse(ALL_URI).unwrap(), DbUri::All);
+        assert_eq!(DbUri::parse(SCHEMA_URI).unwrap(), DbUri::Schema);
+        assert_eq!(DbUri::parse(STATS_URI).unwrap(), DbUri::Stats);
+        assert_eq!(
+            DbUri::parse(&export_uri("export-1.csv")).unwrap(),
+            DbUri::Export("export-1.csv".to_string())
+        );
+        assert!(DbUri::parse("db:/// record/a").is_err());
+        assert_eq!(
+            DbUri::parse(&attachment_uri("users", "a", "scan 1.pdf")).unwrap(),
+            DbUri::Attachment {
+                collection: "users".to_string(),
+                id: "a".to_string(),
+                name: "scan 1.pdf".to_string()
+            }
+        );
+        assert!(DbUri::parse("db://users/records/").is_err());
+        assert!(DbUri::parse("db://users/records/a/attachments/").is_err());
+        assert!(DbUri::parse("db://users/records/a/other/b").is_err());
+        assert!(DbUri::parse("db://users/other/a").is_err());
+        assert!(DbUri::parse("file:///etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_collection_filter() {
+        let uri = format!(
+            "{}?filter={}",
+            collection_uri("users"),
+            encode(r#"{"/address/city":"Paris"}"#)
+        );
+        let DbUri::Collection { collection, filter } = DbUri::parse(&uri).unwrap() else {
+            panic!("expected a collection URI");
+        };
+        assert_eq!(collection, "users");
+
+        let paris = json!({"address": {"city": "Paris"}});
+        assert!(filter.matches(&DatabaseRecord::new("a", "users", paris.clone())));
+        assert!(!filter.matches(&DatabaseRecord::new("b", "admins", paris)));
+        assert!(!filter.matches(&DatabaseRecord::new("c", "users", json!({}))));
+
+        assert!(DbUri::parse("db://users?filter=%5B%5D").is_err());
+        assert!(DbUri::parse("db://users?sort=name").is_err());
+    }
+
+    #[test]
+    fn test_changes_touch_matching_resources() {
+        let change = Change {
+            kind: ChangeKind::Updated,
+            id: "a".to_string(),
+            collections: vec!["users".to_string()],
+            before: None,
+        };
+        let touched = |uri: &str| DbUri::parse(uri).unwrap().touched_by(&change);
+
+        assert!(touched(ALL_URI));
+        assert!(touched(STATS_URI));
+        assert!(touched("db://users"));
+        assert!(touched("db://users?filter=%7B%7D"));
+        assert!(touched(&record_uri("users", "a")));
+        assert!(!touched(&record_uri("admins", "a")));
+        assert!(!touched(&record_uri("users", "b")));
+        assert!(!touched(SCHEMA_URI));
+    }
+}
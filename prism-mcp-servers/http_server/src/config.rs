@@ -0,0 +1,270 @@
+//! Command-line options and the TOML configuration file.
+//!
+//! Settings are layered: built-in defaults, then the configuration file
+//! given with `--config`, then command-line flags (several of which can
+//! also be set through the `HTTP_SERVER_*` environment variables).
+
+use clap::Parser;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use access_control::auth::AuthConfig;
+use access_control::limits::LimitsConfig;
+use access_control::policy::PolicyConfig;
+use network_transport::origin::SecurityConfig;
+use network_transport::replay::{self, ReplayLimits};
+
+/// Command-line options
+#[derive(Debug, Default, Parser)]
+#[command(name = "http-server", version, about = "MCP HTTP server")]
+pub struct Cli {
+    /// TOML configuration file
+    #[arg(short, long, env = "HTTP_SERVER_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on
+    #[arg(long, env = "HTTP_SERVER_BIND")]
+    pub bind: Option<String>,
+
+    /// Origin allowed to call the server (repeatable; `*` allows any)
+    #[arg(long = "allowed-origin")]
+    pub allowed_origins: Vec<String>,
+
+    /// Host name the server may be reached by (repeatable; `*` allows any)
+    #[arg(long = "allowed-host")]
+    pub allowed_hosts: Vec<String>,
+
+    /// Events kept per SSE stream for Last-Event-ID replay (0 disables replay)
+    #[arg(long)]
+    pub sse_replay_events: Option<usize>,
+
+    /// Seconds events stay available for Last-Event-ID replay
+    #[arg(long)]
+    pub sse_replay_retention_secs: Option<u64>,
+
+    /// TOML or JSON authorization policy file
+    #[arg(long, env = "HTTP_SERVER_POLICY_FILE")]
+    pub policy_file: Option<PathBuf>,
+}
+
+/// `[sse]` section
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SseConfig {
+    /// Events kept per session (and for the sessionless stream) for replay
+    pub replay_events: usize,
+    /// Seconds events stay available for replay
+    pub replay_retention_secs: u64,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        Self {
+            replay_events: replay::DEFAULT_REPLAY_EVENTS,
+            replay_retention_secs: replay::DEFAULT_REPLAY_RETENTION.as_secs(),
+        }
+    }
+}
+
+impl SseConfig {
+    pub fn replay_limits(&self) -> ReplayLimits {
+        ReplayLimits {
+            events: self.replay_events,
+            retention: Duration::from_secs(self.replay_retention_secs),
+        }
+    }
+}
+
+/// Complete server configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address to listen on; loopback unless set otherwise
+    pub bind: String,
+    /// Accepted `Host` and `Origin` headers
+    pub security: SecurityConfig,
+    pub sse: SseConfig,
+    /// API keys and JWT verification; off unless configured
+    pub auth: AuthConfig,
+    /// Which principals may use which tools and resources
+    pub policy: PolicyConfig,
+    /// Request rate and in-flight limits; off unless configured
+    pub limits: LimitsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:3000".to_string(),
+            security: SecurityConfig::default(),
+            sse: SseConfig::default(),
+            auth: AuthConfig::default(),
+            policy: PolicyConfig::default(),
+            limits: LimitsConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Parse a TOML configuration
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Read the TOML configuration file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        Self::from_toml(&source).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Build the configuration from the command line and the file it names
+    pub fn load(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Override settings with those given on the command line
+    fn apply(&mut self, cli: Cli) {
+        if let Some(bind) = cli.bind {
+            self.bind = bind;
+        }
+        if !cli.allowed_origins.is_empty() {
+            self.security.allowed_origins = cli.allowed_origins;
+        }
+        if !cli.allowed_hosts.is_empty() {
+            self.security.allowed_hosts = cli.allowed_hosts;
+        }
+        if let Some(events) = cli.sse_replay_events {
+            self.sse.replay_events = events;
+        }
+        if let Some(secs) = cli.sse_replay_retention_secs {
+            self.sse.replay_retention_secs = secs;
+        }
+        if cli.policy_file.is_some() {
+            self.policy.file = cli.policy_file;
+        }
+    }
+
+    /// Reject settings that cannot work
+    pub fn validate(&self) -> Result<(), String> {
+        self.bind
+            .parse::<SocketAddr>()
+            .map_err(|e| format!("Invalid bind address '{}': {e}", self.bind))?;
+        self.limits.validate()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_with_cli_overrides() {
+        let mut config = Config::from_toml(
+            r#"
+            bind = "127.0.0.1:4000"
+
+            [sse]
+            replay_events = 1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sse.replay_retention_secs, 300);
+
+        let cli = Cli::try_parse_from([
+            "http-server",
+            "--sse-replay-retention-secs",
+            "30",
+            "--policy-file",
+            "policy.toml",
+        ])
+        .unwrap();
+        config.apply(cli);
+        config.validate().unwrap();
+
+        assert_eq!(config.bind, "127.0.0.1:4000");
+        assert_eq!(config.policy.file, Some(PathBuf::from("policy.toml")));
+        assert!(!config.policy.hot_reload);
+        assert_eq!(
+            config.sse.replay_limits(),
+            ReplayLimits {
+                events: 1000,
+                retention: Duration::from_secs(30),
+            }
+        );
+    }
+
+    #[test]
+    fn test_auth_section() {
+        let config = Config::from_toml(
+            r#"
+            [auth]
+            required_roles = ["mcp"]
+
+            [[auth.api_keys]]
+            principal = "ci"
+            key_sha256 = "2c69bc9111c27110a9b9a7974ba3f8ac0c053c16b23a0738115ee829fbc4d57b"
+            roles = ["mcp"]
+
+            [auth.jwt]
+            jwks_file = "/etc/mcp/jwks.json"
+            issuer = "https://login.example.com"
+            audience = "mcp"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.auth.api_keys[0].principal, "ci");
+        let jwt = config.auth.jwt.unwrap();
+        assert_eq!(jwt.audience.as_deref(), Some("mcp"));
+        assert_eq!(jwt.roles_claim, "roles");
+        assert!(Config::from_toml("[auth]\napi_key = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_binds_to_loopback_unless_configured() {
+        let config = Config::load(Cli::default()).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:3000");
+        assert_eq!(config.security, SecurityConfig::default());
+
+        let mut config = Config::from_toml(
+            r#"
+            bind = "0.0.0.0:3000"
+
+            [security]
+            allowed_hosts = ["mcp.example.com"]
+            "#,
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from([
+            "http-server",
+            "--allowed-origin",
+            "https://app.example.com",
+            "--allowed-origin",
+            "https://admin.example.com",
+        ])
+        .unwrap();
+        config.apply(cli);
+        assert_eq!(config.bind, "0.0.0.0:3000");
+        assert_eq!(config.security.allowed_hosts, vec!["mcp.example.com"]);
+        assert_eq!(config.security.allowed_origins.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(Config::from_toml("unknown = true").is_err());
+        assert!(Config::from_toml("[sse]\nreplay_events = -1").is_err());
+        let config = Config::from_toml("bind = \"localhost\"").unwrap();
+        assert!(config.validate().is_err());
+        let config = Config::from_toml("[limits.global]\nmax_in_flight = 0").unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -15,21 +15,47 @@
 // ! cargo run --example http_server --features "http-server"
 // ! ```
 
+mod config;
+
 use async_trait::async_trait;
 use serde_json::{Value, json};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
+use access_control::auth::Authenticator;
+use access_control::limits::RateLimiter;
+use access_control::policy::PolicyEngine;
+use access_control::whoami::WhoAmIHandler;
 use prism_mcp_rs::{
     core::{
         error::{McpError, McpResult},
         resource::ResourceHandler,
         tool::ToolHandler,
     },
-    protocol::types::{Content, ResourceContents, ResourceInfo, ToolResult},
-    server::HttpMcpServer,
-    transport::http::HttpServerTransport,
+    protocol::{
+        messages::JsonRpcNotification,
+        methods::RESOURCES_UPDATED,
+        types::{
+            Content, ResourceContents, ResourceInfo, ResourcesCapability, ServerCapabilities,
+            ToolResult, ToolsCapability,
+        },
+    },
+    server::McpServer,
 };
 
+use clap::Parser;
+use config::{Cli, Config};
+use network_transport::http_transport::{self as transport, HttpTransport};
+use network_transport::metrics::Metrics;
+use network_transport::origin::OriginGuard;
+
+pub const STATUS_URI: &str = "http://server/status";
+pub const METRICS_URI: &str = "http://server/metrics";
+
+/// How often subscribers of the status resources are told about changed values
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(1);
+
 /// HTTP-aware calculator tool
 struct HttpCalculatorHandler;
 
@@ -90,7 +116,12 @@ impl ToolHandler for HttpCalculatorHandler {
 }
 
 /// HTTP status resource handler
-struct HttpStatusHandler;
+///
+/// Subscriptions are kept per session by the transport.
+#[derive(Clone)]
+struct HttpStatusHandler {
+    metrics: Arc<Metrics>,
+}
 
 #[async_trait]
 impl ResourceHandler for HttpStatusHandler {
@@ -100,20 +131,24 @@ impl ResourceHandler for HttpStatusHandler {
         _params: &HashMap<String, String>,
     ) -> McpResult<Vec<ResourceContents>> {
         match uri {
-            "http://server/status" => {
-                let status = json!({
+            STATUS_URI => {
+                let mut status = json!({
                     "transport": "HTTP",
                     "protocol": "MCP over HTTP",
                     "features": ["requests", "notifications", "sse"],
                     "endpoints": {
-                        "requests": "/mcp",
-                        "notifications": "/mcp/notify",
-                        "events": "/mcp/events",
-                        "health": "/health"
-                    },
-                    "uptime": "running",
-                    "clients": "connected via HTTP"
+                        "requests": transport::RPC_PATH,
+                        "notifications": transport::NOTIFY_PATH,
+                        "events": transport::EVENTS_PATH,
+                        "health": transport::HEALTH_PATH,
+                        "metrics": transport::PROMETHEUS_PATH
+                    }
                 });
+                if let (Some(status), Value::Object(summary)) =
+                    (status.as_object_mut(), self.metrics.summary())
+                {
+                    status.extend(summary);
+                }
 
                 Ok(vec![ResourceContents::Text {
                     uri: uri.to_string(),
@@ -122,15 +157,9 @@ impl ResourceHandler for HttpStatusHandler {
                     meta: None,
                 }])
             }
-            "http://server/metrics" => {
-                let metrics = json!({
-                    "requests_processed": 42,
-                    "notifications_sent": 15,
-                    "sse_connections": 3,
-                    "average_response_time_ms": 12.5,
-                    "transport_type": "http"
-                });
-
+            METRICS_URI => {
+                let mut metrics = self.metrics.to_json();
+                metrics["transport_type"] = json!("http");
                 Ok(vec![ResourceContents::Text {
                     uri: uri.to_string(),
                     mime_type: Some("application/json".to_string()),
@@ -143,31 +172,13 @@ impl ResourceHandler for HttpStatusHandler {
     }
 
     async fn list(&self) -> McpResult<Vec<ResourceInfo>> {
-        Ok(vec![
-            ResourceInfo {
-                uri: "http://server/status".to_string(),
-                name: "HTTP Server Status".to_string(),
-                description: Some("Current status of the HTTP MCP server".to_string()),
-                mime_type: Some("application/json".to_string()),
-                annotations: None,
-                size: None,
-                title: None,
-                meta: None,
-            },
-            ResourceInfo {
-                uri: "http://server/metrics".to_string(),
-                name: "HTTP Server Metrics".to_string(),
-                description: Some("Performance metrics for the HTTP transport".to_string()),
-                mime_type: Some("application/json".to_string()),
-                annotations: None,
-                size: None,
-                title: None,
-                meta: None,
-            },
-        ])
+        Ok(vec![status_info(), metrics_info()])
     }
 
-    async fn subscribe(&self, _uri: &str) -> McpResult<()> {
+    async fn subscribe(&self, uri: &str) -> McpResult<()> {
+        if uri != STATUS_URI && uri != METRICS_URI {
+            return Err(McpError::ResourceNotFound(uri.to_string()));
+        }
         Ok(())
     }
 
@@ -176,103 +187,207 @@ impl ResourceHandler for HttpStatusHandler {
     }
 }
 
+fn status_info() -> ResourceInfo {
+    ResourceInfo {
+        uri: STATUS_URI.to_string(),
+        name: "HTTP Server Status".to_string(),
+        description: Some("Current status of the HTTP MCP server".to_string()),
+        mime_type: Some("application/json".to_string()),
+        annotations: None,
+        size: None,
+        title: None,
+        meta: None,
+    }
+}
+
+fn metrics_info() -> ResourceInfo {
+    ResourceInfo {
+        uri: METRICS_URI.to_string(),
+        name: "HTTP Server Metrics".to_string(),
+        description: Some(
+            "Requests by method and tool, errors by code, SSE connections and latency histograms"
+                .to_string(),
+        ),
+        mime_type: Some("application/json".to_string()),
+        annotations: None,
+        size: None,
+        title: None,
+        meta: None,
+    }
+}
+
+/// Send `notifications/resources/updated` to the sessions subscribed to a status resource whenever the metrics move
+///
+/// Changes are coalesced to at most one notification per resource and
+/// [`NOTIFY_INTERVAL`]; uptime alone does not count as a change.
+fn spawn_status_notifier(
+    transport: Arc<HttpTransport>,
+    metrics: Arc<Metrics>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(NOTIFY_INTERVAL);
+        let mut announced = metrics.generation();
+        loop {
+            interval.tick().await;
+            let generation = metrics.generation();
+            if generation == announced {
+                continue;
+            }
+            announced = generation;
+            for uri in [STATUS_URI, METRICS_URI] {
+                if transport.subscribers(uri).is_empty() {
+                    continue;
+                }
+                match JsonRpcNotification::new(
+                    RESOURCES_UPDATED.to_string(),
+                    Some(json!({ "uri": uri })),
+                ) {
+                    Ok(notification) => transport.notify_subscribers(uri, &notification),
+                    Err(e) => {
+                        tracing::warn!("Failed to build {RESOURCES_UPDATED} notification: {e}")
+                    }
+                }
+            }
+        }
+    })
+}
+
 #[tokio::main]
 async fn main() -> McpResult<()> {
     // Initialize logging
     #[cfg(feature = "tracing-subscriber")]
     tracing_subscriber::fmt::init();
 
-    let mut http_server = HttpMcpServer::new("http-mcp-server".to_string(), "1.0.0".to_string());
+    let config = Config::load(Cli::parse()).map_err(McpError::Validation)?;
+    let authenticator = Authenticator::new(&config.auth).map_err(McpError::Validation)?;
+    if !authenticator.is_enabled() {
+        tracing::warn!(
+            "Authentication is disabled: anyone who can reach {} can call tools; configure [auth]",
+            config.bind
+        );
+    }
+
+    let policy = Arc::new(PolicyEngine::new(&config.policy).map_err(McpError::Validation)?);
+    let reloader = config
+        .policy
+        .hot_reload
+        .then(|| policy.spawn_reloader(Duration::from_secs(config.policy.reload_interval_secs)));
 
-    // Get a reference to the underlying server for adding tools and resources
-    let server = http_server.server().await;
+    let mut server = McpServer::new("http-mcp-server".to_string(), "1.0.0".to_string());
+    server.set_capabilities(ServerCapabilities {
+        tools: Some(ToolsCapability {
+            list_changed: Some(false),
+        }),
+        resources: Some(ResourcesCapability {
+            subscribe: Some(true),
+            list_changed: Some(false),
+        }),
+        prompts: None,
+        completions: None,
+        sampling: None,
+        logging: None,
+        experimental: None,
+    });
+
+    let metrics = Arc::new(Metrics::new());
+    let status = HttpStatusHandler {
+        metrics: metrics.clone(),
+    };
 
     // Add HTTP-aware calculator tool
-    {
-        let server_guard = server.lock().await;
-        server_guard
-            .add_tool(
-                "http_calculator".to_string(),
-                Some("complete calculator with HTTP transport support".to_string()),
-                json!({
-                    "type": "object",
-                    "properties": {
-                        "operation": {
-                            "type": "string",
-                            "enum": ["add", "subtract", "multiply", "divide", "power", "modulo"],
-                            "description": "Mathematical operation to perform",
-                            "default": "add"
-                        },
-                        "a": {
-                            "type": "number",
-                            "description": "First operand"
-                        },
-                        "b": {
-                            "type": "number",
-                            "description": "Second operand"
-                        }
+    server
+        .add_tool(
+            "http_calculator".to_string(),
+            Some("complete calculator with HTTP transport support".to_string()),
+            json!({
+                "type": "object",
+                "properties": {
+                    "operation": {
+                        "type": "string",
+                        "enum": ["add", "subtract", "multiply", "divide", "power", "modulo"],
+                        "description": "Mathematical operation to perform",
+                        "default": "add"
                     },
-                    "required": ["a", "b"]
-                }),
-                HttpCalculatorHandler,
-            )
-            .await?;
-
-        // Add HTTP status resources individually
-        server_guard
-            .add_resource_detailed(
-                ResourceInfo {
-                    uri: "http://server/status".to_string(),
-                    name: "HTTP Server Status".to_string(),
-                    description: Some("Current status of the HTTP MCP server".to_string()),
-                    mime_type: Some("application/json".to_string()),
-                    annotations: None,
-                    size: None,
-                    title: None,
-                    meta: None,
-                },
-                HttpStatusHandler,
-            )
-            .await?;
-
-        server_guard
-            .add_resource_detailed(
-                ResourceInfo {
-                    uri: "http://server/metrics".to_string(),
-                    name: "HTTP Server Metrics".to_string(),
-                    description: Some("Performance metrics for the HTTP transport".to_string()),
-                    mime_type: Some("application/json".to_string()),
-                    annotations: None,
-                    size: None,
-                    title: None,
-                    meta: None,
+                    "a": {
+                        "type": "number",
+                        "description": "First operand"
+                    },
+                    "b": {
+                        "type": "number",
+                        "description": "Second operand"
+                    }
                 },
-                HttpStatusHandler,
-            )
-            .await?;
-    }
+                "required": ["a", "b"]
+            }),
+            HttpCalculatorHandler,
+        )
+        .await?;
+
+    server
+        .add_tool(
+            "whoami".to_string(),
+            Some("Show the authenticated principal of this request".to_string()),
+            json!({
+                "type": "object",
+                "properties": {}
+            }),
+            WhoAmIHandler,
+        )
+        .await?;
+
+    // Add HTTP status resources individually
+    server
+        .add_resource_detailed(status_info(), status.clone())
+        .await?;
+
+    server
+        .add_resource_detailed(metrics_info(), status.clone())
+        .await?;
 
     // Start HTTP server
-    tracing::info!("Starting HTTP MCP server on http://localhost:3000");
+    tracing::info!("Starting HTTP MCP server on http://{}", config.bind);
     tracing::info!("Endpoints:");
-    tracing::info!("  - POST /mcp - JSON-RPC requests");
+    tracing::info!("  - POST /mcp - JSON-RPC requests (Streamable HTTP, JSON or SSE responses)");
+    tracing::info!("  - GET /mcp - Server-Sent Events for an Mcp-Session-Id");
+    tracing::info!("  - DELETE /mcp - End an Mcp-Session-Id session");
     tracing::info!("  - POST /mcp/notify - Notifications");
     tracing::info!("  - GET /mcp/events - Server-Sent Events");
     tracing::info!("  - GET /health - Health check");
+    tracing::info!("  - GET /metrics - Prometheus metrics");
 
-    let transport = HttpServerTransport::new("0.0.0.0:3000");
-    http_server.start(transport).await?;
+    let listener = tokio::net::TcpListener::bind(&config.bind).await?;
+    let origins = OriginGuard::new(&config.security, listener.local_addr()?);
+    if origins.accepts_any_host() {
+        tracing::warn!(
+            "Host headers are not checked on {}: set [security] allowed_hosts to guard against DNS rebinding",
+            config.bind
+        );
+    }
+    let transport = HttpTransport::new(
+        server,
+        metrics.clone(),
+        authenticator,
+        policy,
+        RateLimiter::new(config.limits.clone()),
+        origins,
+        config.sse.replay_limits(),
+    );
+    let notifier = spawn_status_notifier(transport.clone(), metrics);
 
     tracing::info!("HTTP MCP server is running!");
     tracing::info!(
         "Test with: curl -X POST http://localhost:3000/mcp -H 'Content-Type: application/json' -d '{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/list\"}}'"
     );
 
-    // Keep running until interrupted
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for ctrl+c");
-    http_server.stop().await?;
+    // Serve until interrupted
+    tokio::select! {
+        result = transport.serve(listener) => result?,
+        _ = tokio::signal::ctrl_c() => tracing::info!("Shutting down HTTP MCP server"),
+    }
+    notifier.abort();
+    if let Some(reloader) = reloader {
+        reloader.abort();
+    }
 
     Ok(())
 }
@@ -0,0 +1,357 @@
+//! Minimal HTTP/1.1 message handling for the MCP endpoints.
+//!
+//! The transport only needs what JSON-RPC over HTTP and Server-Sent Events
+//! use: a request line, headers, a `Content-Length` body and responses that
+//! are either complete or streamed until the client goes away. Chunked
+//! request bodies are refused rather than half-supported.
+
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Longest request line or header line accepted
+const MAX_LINE_BYTES: usize = 8 * 1024;
+/// Most headers accepted on one request
+const MAX_HEADERS: usize = 100;
+/// Largest request body accepted
+pub const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    pub query: Option<String>,
+    /// Header names are lowercased
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// First value of the header `name` (lowercase)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the client asked to close the connection after this request
+    pub fn wants_close(&self) -> bool {
+        self.header("connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// Why a request could not be read
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    /// The request is malformed or unsupported; answer with this status and close
+    Rejected(u16, &'static str),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Read one request; `Ok(None)` means the client closed the connection between requests
+pub async fn read_request<R>(reader: &mut R) -> Result<Option<Request>, ReadError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let Some(request_line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ReadError::Rejected(400, "Malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(ReadError::Rejected(505, "Only HTTP/1.x is supported"));
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)
+            .await?
+            .ok_or(ReadError::Rejected(400, "Truncated headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(ReadError::Rejected(431, "Too many headers"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ReadError::Rejected(400, "Malformed header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path,
+        query,
+        headers,
+        body: Vec::new(),
+    };
+    if request.header("transfer-encoding").is_some() {
+        return Err(ReadError::Rejected(411, "Chunked bodies are not supported"));
+    }
+    let length = match request.header("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| ReadError::Rejected(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(ReadError::Rejected(413, "Request body too large"));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).await?;
+    Ok(Some(request))
+}
+
+async fn read_line<R>(reader: &mut R) -> Result<Option<String>, ReadError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    let read = (&mut *reader)
+        .take(MAX_LINE_BYTES as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(ReadError::Rejected(431, "Header line too long"));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ReadError::Rejected(400, "Request is not valid UTF-8"))
+}
+
+/// A complete HTTP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Self::new(status)
+            .header("Content-Type", "application/json")
+            .body(value.to_string().into_bytes())
+    }
+
+    pub fn text(status: u16, text: impl Into<String>) -> Self {
+        Self::new(status)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(text.into().into_bytes())
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn headers(mut self, headers: &[(String, String)]) -> Self {
+        self.headers.extend_from_slice(headers);
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub async fn write_to<W>(&self, writer: &mut W, close: bool) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut head = status_line(self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        if close {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes()).await?;
+        writer.write_all(&self.body).await?;
+        writer.flush().await
+    }
+}
+
+/// Start a Server-Sent Events response; events follow with [`write_event`]
+pub async fn write_event_stream_head<W>(
+    writer: &mut W,
+    headers: &[(String, String)],
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut head = status_line(200);
+    head.push_str("Content-Type: text/event-stream\r\n");
+    head.push_str("Cache-Control: no-cache\r\n");
+    head.push_str("Connection: close\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Write one SSE event; multi-line data is split across `data:` fields
+pub async fn write_event<W>(
+    writer: &mut W,
+    id: Option<&str>,
+    event: Option<&str>,
+    data: &str,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer
+        .write_all(format_event(id, event, data).as_bytes())
+        .await?;
+    writer.flush().await
+}
+
+/// SSE frame for one event
+pub fn format_event(id: Option<&str>, event: Option<&str>, data: &str) -> String {
+    let mut frame = String::new();
+    if let Some(id) = id {
+        frame.push_str(&format!("id: {id}\n"));
+    }
+    if let Some(event) = event {
+        frame.push_str(&format!("event: {event}\n"));
+    }
+    for line in data.lines() {
+        frame.push_str(&format!("data: {line}\n"));
+    }
+    frame.push('\n');
+    frame
+}
+
+/// SSE comment that keeps idle connections (and proxies) from timing out
+pub const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+fn status_line(status: u16) -> String {
+    format!("HTTP/1.1 {status} {}\r\n", reason(status))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    async fn parse(raw: &str) -> Result<Option<Request>, ReadError> {
+        read_request(&mut BufReader::new(raw.as_bytes())).await
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_parsed() {
+        let request = parse(
+            "POST /mcp?debug=1 HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/mcp");
+        assert_eq!(request.query.as_deref(), Some("debug=1"));
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(request.body, b"{}");
+
+        assert!(parse("").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_requests_are_rejected() {
+        let status = |result: Result<Option<Request>, ReadError>| match result {
+            Err(ReadError::Rejected(status, _)) => status,
+            other => panic!("expected a rejection, got {other:?}"),
+        };
+        assert_eq!(status(parse("GET /\r\n\r\n").await), 400);
+        assert_eq!(status(parse("GET / HTTP/2\r\n\r\n").await), 505);
+        assert_eq!(
+            status(parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n").await),
+            411
+        );
+        assert_eq!(
+            status(parse("POST / HTTP/1.1\r\nContent-Length: 999999999\r\n\r\n").await),
+            413
+        );
+    }
+
+    #[tokio::test]
+    async fn test_responses_and_events_are_framed() {
+        let mut written = Vec::new();
+        Response::json(200, &serde_json::json!({"ok": true}))
+            .write_to(&mut written, true)
+            .await
+            .unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(written.contains("Content-Length: 11\r\nConnection: close\r\n\r\n{\"ok\":true}"));
+
+        assert_eq!(
+            format_event(None, Some("message"), "a\nb"),
+            "event: message\ndata: a\ndata: b\n\n"
+        );
+        assert_eq!(
+            format_event(Some("1-7"), None, "{}"),
+            "id: 1-7\ndata: {}\n\n"
+        );
+    }
+}
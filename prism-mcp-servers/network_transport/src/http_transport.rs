@@ -0,0 +1,1255 @@
+//! MCP over HTTP with Server-Sent Events for server notifications.
+//!
+//! JSON-RPC requests are dispatched to the [`McpServer`] from here rather than
+//! through the SDK transport so every request can be timed and counted in
+//! [`Metrics`], and so the SSE streams are ours to count.
+//!
+//! `/mcp` is the Streamable HTTP endpoint: POST a message and get the response
+//! as JSON or as a one-event SSE stream depending on `Accept`, GET it for a
+//! server-to-client stream, DELETE it to end the session. Initializing issues
+//! an `Mcp-Session-Id`; requests carrying one must name a live session, while
+//! requests without one are served sessionless so clients of the older split
+//! endpoints keep working. Resource subscriptions need a session: the
+//! transport records them per session and forgets them when the session
+//! ends, and [`HttpTransport::notify_subscribers`] sends a resource update to
+//! the subscribed sessions whose principal the policy lets read it.
+//!
+//! Events on both SSE endpoints carry ids; reconnecting with `Last-Event-ID`
+//! replays the events buffered since, per session on `GET /mcp` and from a
+//! shared buffer on the sessionless `/mcp/events`.
+//!
+//! Before anything else, the [`OriginGuard`] refuses requests whose `Host` or
+//! `Origin` the server does not expect, which is what a DNS-rebinding page
+//! sends; `/health` is exempt so probes can reach it by any address. Pages
+//! from the origins it allows get CORS headers: `OPTIONS` preflights are
+//! answered without credentials, and responses let the page read them and
+//! their `Mcp-Session-Id`.
+//!
+//! When the [`Authenticator`] is enabled every endpoint but `/health` needs
+//! an API key or bearer token; failures get 401 or 403 with a JSON-RPC error
+//! body, and each dispatch runs with the caller's principal in scope for the
+//! handlers. The [`PolicyEngine`] then decides which tools and resources the
+//! caller may use: denied calls get a JSON-RPC error and denied tools are left
+//! out of `tools/list`. Requests over a [`RateLimiter`] limit are answered
+//! with 429 and `Retry-After`. Endpoints:
+//!
+//! - `POST /mcp` - JSON-RPC requests
+//! - `GET /mcp` - Server-Sent Events for a session
+//! - `DELETE /mcp` - session termination
+//! - `POST /mcp/notify` - client notifications
+//! - `GET /mcp/events` - Server-Sent Events
+//! - `GET /health` - health check
+//! - `GET /metrics` - Prometheus scrape endpoint
+
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+
+use access_control::auth::{self, API_KEY_HEADER, AuthError, Authenticator, Principal};
+use access_control::gate::{self, Gate, Refusal};
+use access_control::jsonrpc::{INVALID_REQUEST, PARSE_ERROR, error_response};
+use access_control::limits::{RATE_LIMITED, RateLimiter};
+use access_control::policy::PolicyEngine;
+use prism_mcp_rs::{
+    protocol::messages::{JsonRpcNotification, JsonRpcRequest},
+    server::McpServer,
+};
+
+use crate::http::{self, ReadError, Request, Response};
+use crate::metrics::{Metrics, Outcome};
+use crate::origin::{OriginError, OriginGuard};
+use crate::process::ProcessStats;
+use crate::prometheus;
+use crate::replay::{Event, EventIds, Replay, ReplayBuffer, ReplayLimits};
+use crate::sessions::{SESSION_HEADER, SessionError, Sessions};
+
+pub const RPC_PATH: &str = "/mcp";
+pub const NOTIFY_PATH: &str = "/mcp/notify";
+pub const EVENTS_PATH: &str = "/mcp/events";
+pub const HEALTH_PATH: &str = "/health";
+pub const PROMETHEUS_PATH: &str = prometheus::PATH;
+
+/// Notifications buffered per SSE stream before a slow client starts missing them
+const EVENT_BUFFER: usize = 256;
+/// Interval between keep-alive comments on idle SSE streams
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Request headers a browser page may send, answered in CORS preflights
+const CORS_ALLOWED_HEADERS: &str = "Accept, Authorization, Content-Type, Last-Event-ID, Mcp-Protocol-Version, Mcp-Session-Id, X-API-Key";
+/// Seconds a browser may cache a preflight answer
+const CORS_MAX_AGE: &str = "600";
+
+const SUBSCRIBE: &str = "resources/subscribe";
+const UNSUBSCRIBE: &str = "resources/unsubscribe";
+
+const JSON: &str = "application/json";
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// How a POSTed request is answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    Json,
+    /// A single `message` event carrying the response
+    EventStream,
+}
+
+impl ResponseFormat {
+    /// Pick a format from `Accept`; JSON unless the client only takes SSE
+    fn negotiate(request: &Request) -> Option<Self> {
+        let Some(accept) = request.header("accept") else {
+            return Some(Self::Json);
+        };
+        let accepted: Vec<&str> = accept
+            .split(',')
+            .map(|media| media.split(';').next().unwrap_or_default().trim())
+            .collect();
+        if accepted
+            .iter()
+            .any(|media| matches!(*media, JSON | "application/*" | "*/*"))
+        {
+            Some(Self::Json)
+        } else if accepted.contains(&EVENT_STREAM) {
+            Some(Self::EventStream)
+        } else {
+            None
+        }
+    }
+}
+
+/// A server-to-client stream, bound to a session when one was named
+struct EventStream {
+    session: Option<String>,
+    terminated: Option<watch::Receiver<bool>>,
+    /// Sequence of the client's `Last-Event-ID`, when it is one of ours
+    last_event: Option<u64>,
+}
+
+/// Sequence numbering and replay buffer of the sessionless stream
+struct Outbox {
+    sequence: u64,
+    replay: ReplayBuffer,
+}
+
+/// Serves an [`McpServer`] over HTTP
+pub struct HttpTransport {
+    server: McpServer,
+    metrics: Arc<Metrics>,
+    authenticator: Authenticator,
+    gate: Gate,
+    origins: OriginGuard,
+    sessions: Sessions,
+    ids: EventIds,
+    outbox: Mutex<Outbox>,
+    events: broadcast::Sender<Arc<Event>>,
+}
+
+impl HttpTransport {
+    pub fn new(
+        server: McpServer,
+        metrics: Arc<Metrics>,
+        authenticator: Authenticator,
+        policy: Arc<PolicyEngine>,
+        limiter: Arc<RateLimiter>,
+        origins: OriginGuard,
+        replay: ReplayLimits,
+    ) -> Arc<Self> {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Arc::new(Self {
+            server,
+            metrics,
+            authenticator,
+            gate: Gate::new(policy, limiter),
+            origins,
+            sessions: Sessions::new(crate::sessions::SESSION_IDLE_TIMEOUT, replay),
+            ids: EventIds::new(epoch),
+            outbox: Mutex::new(Outbox {
+                sequence: 0,
+                replay: ReplayBuffer::new(replay),
+            }),
+            events,
+        })
+    }
+
+    /// Send a notification to the streams of `session`, or to every connected
+    /// SSE stream, and buffer it for replay
+    pub fn notify(&self, notification: &JsonRpcNotification, session: Option<&str>) {
+        let message = match serde_json::to_string(notification) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!("Failed to serialize {}: {e}", notification.method);
+                return;
+            }
+        };
+        // Numbering, buffering and sending under one lock keeps every stream in sequence order
+        let mut outbox = self.outbox.lock().unwrap();
+        outbox.sequence += 1;
+        let event = Arc::new(Event::new(outbox.sequence, message.into()).for_session(session));
+        if session.is_none() {
+            outbox.replay.push(event.clone());
+        }
+        self.sessions.record(&event);
+        // Sending only fails when no stream is connected
+        let _ = self.events.send(event);
+    }
+
+    /// Ids of the sessions subscribed to the resource `uri`
+    pub fn subscribers(&self, uri: &str) -> Vec<String> {
+        self.sessions.subscribers(uri)
+    }
+
+    /// Send an update of the resource `uri` to the sessions subscribed to it,
+    /// leaving out those whose principal may no longer read it
+    pub fn notify_subscribers(&self, uri: &str, notification: &JsonRpcNotification) {
+        for (session, principal) in self.sessions.subscribed(uri) {
+            if self.gate.allows_resource(principal.as_ref(), uri) {
+                self.notify(notification, Some(&session));
+            }
+        }
+    }
+
+    /// URIs at least one live session is subscribed to
+    pub fn subscriptions(&self) -> HashSet<String> {
+        self.sessions.subscriptions()
+    }
+
+    /// Buffered events after `last_sequence` for a session's stream or the sessionless one
+    fn replay(&self, session: Option<&str>, last_sequence: u64) -> Replay {
+        match session {
+            Some(session) => self
+                .sessions
+                .replay(session, last_sequence)
+                .unwrap_or(Replay {
+                    events: Vec::new(),
+                    incomplete: false,
+                }),
+            None => self.outbox.lock().unwrap().replay.since(last_sequence),
+        }
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            tracing::debug!("HTTP connection from {peer}");
+            tokio::spawn(self.clone().connection(stream, peer));
+        }
+    }
+
+    async fn connection(self: Arc<Self>, stream: TcpStream, peer: SocketAddr) {
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read);
+        loop {
+            let request = match http::read_request(&mut reader).await {
+                Ok(Some(request)) => request,
+                Ok(None) => return,
+                Err(ReadError::Rejected(status, reason)) => {
+                    let _ = Response::text(status, reason)
+                        .write_to(&mut write, true)
+                        .await;
+                    return;
+                }
+                Err(ReadError::Io(e)) => {
+                    tracing::debug!("Dropping HTTP connection: {e}");
+                    return;
+                }
+            };
+
+            if let Err(error) = self.check_origin(&request) {
+                let _ = refused(&error).write_to(&mut write, true).await;
+                return;
+            }
+            let cors = self.cors_headers(&request);
+            // Browsers send preflights without credentials
+            if request.method == "OPTIONS" {
+                let close = request.wants_close();
+                let response = preflight(&request).headers(&cors);
+                if response.write_to(&mut write, close).await.is_err() || close {
+                    return;
+                }
+                continue;
+            }
+            let principal = match self.authenticate(&request) {
+                Ok(principal) => principal,
+                Err(error) => {
+                    let close = request.wants_close();
+                    if unauthorized(&error)
+                        .headers(&cors)
+                        .write_to(&mut write, close)
+                        .await
+                        .is_err()
+                        || close
+                    {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let owner = principal.as_ref().map(|principal| principal.id.as_str());
+            // Anonymous callers are told apart by address for rate limiting
+            let client = owner.map_or_else(|| peer.ip().to_string(), str::to_string);
+
+            if request.method == "GET" && matches!(request.path.as_str(), RPC_PATH | EVENTS_PATH) {
+                match self.open_stream(&request, owner) {
+                    Ok(stream) => {
+                        let session = stream.session.clone();
+                        self.stream_events(reader, write, stream, &cors).await;
+                        if let Some(session) = session {
+                            self.sessions.close_stream(&session);
+                        }
+                        return;
+                    }
+                    Err(response) => {
+                        let _ = response.headers(&cors).write_to(&mut write, true).await;
+                        return;
+                    }
+                }
+            }
+            let response = self
+                .route(&request, principal.as_ref(), &client)
+                .await
+                .headers(&cors);
+            let close = request.wants_close();
+            if response.write_to(&mut write, close).await.is_err() || close {
+                return;
+            }
+        }
+    }
+
+    /// Refuse requests for a host or from an origin the server does not serve
+    fn check_origin(&self, request: &Request) -> Result<(), OriginError> {
+        if request.path == HEALTH_PATH {
+            return Ok(());
+        }
+        let result = self.origins.check(request);
+        if let Err(error) = &result {
+            tracing::warn!("Refused {} {}: {error}", request.method, request.path);
+            self.metrics.record_error(auth::FORBIDDEN);
+        }
+        result
+    }
+
+    /// CORS headers for a request from a page whose origin is allowed
+    fn cors_headers(&self, request: &Request) -> Vec<(String, String)> {
+        let Some(origin) = request
+            .header("origin")
+            .filter(|origin| self.origins.allows_origin(origin))
+        else {
+            return Vec::new();
+        };
+        vec![
+            (
+                "Access-Control-Allow-Origin".to_string(),
+                origin.to_string(),
+            ),
+            (
+                "Access-Control-Expose-Headers".to_string(),
+                "Mcp-Session-Id".to_string(),
+            ),
+            ("Vary".to_string(), "Origin".to_string()),
+        ]
+    }
+
+    /// The caller of `request`; `/health` and disabled authentication need no credentials
+    fn authenticate(&self, request: &Request) -> Result<Option<Principal>, AuthError> {
+        if request.path == HEALTH_PATH {
+            return Ok(None);
+        }
+        let result = self.authenticator.authenticate(
+            request.header("authorization"),
+            request.header(API_KEY_HEADER),
+        );
+        if let Err(error) = &result {
+            tracing::debug!("Rejected {} {}: {error}", request.method, request.path);
+            self.metrics.record_error(error.code());
+        }
+        result
+    }
+
+    async fn route(
+        &self,
+        request: &Request,
+        principal: Option<&Principal>,
+        client: &str,
+    ) -> Response {
+        let owner = principal.map(|principal| principal.id.as_str());
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", RPC_PATH) => self.handle_rpc(request, principal, client).await,
+            ("DELETE", RPC_PATH) => match request.header(SESSION_HEADER) {
+                Some(session) => match self.sessions.terminate(session, owner) {
+                    Ok(()) => Response::new(204),
+                    Err(_) => session_not_found(),
+                },
+                None => Response::text(400, "Missing Mcp-Session-Id header"),
+            },
+            ("POST", NOTIFY_PATH) => match self.parse_message(request) {
+                Ok(message) if message.get("id").is_some() => {
+                    self.metrics.record_error(INVALID_REQUEST);
+                    Response::json(
+                        400,
+                        &error_response(
+                            Value::Null,
+                            INVALID_REQUEST,
+                            &format!(
+                                "Only notifications are accepted here; send requests to {RPC_PATH}"
+                            ),
+                        ),
+                    )
+                }
+                Ok(message) => self.handle_notification(message, principal).await,
+                Err(response) => response,
+            },
+            ("GET", HEALTH_PATH) => Response::json(
+                200,
+                &json!({
+                    "status": "ok",
+                    "uptime_seconds": self.metrics.uptime().as_secs(),
+                    "sessions": self.sessions.count()
+                }),
+            ),
+            ("GET", PROMETHEUS_PATH) => {
+                let process = ProcessStats::read();
+                Response::new(200)
+                    .header("Content-Type", prometheus::CONTENT_TYPE)
+                    .body(
+                        prometheus::render(&self.metrics, &process, Some(self.sessions.count()))
+                            .into_bytes(),
+                    )
+            }
+            (_, path) => match allowed_methods(path) {
+                Some(allowed) => Response::new(405).header("Allow", format!("{allowed}, OPTIONS")),
+                None => Response::text(404, "Not found"),
+            },
+        }
+    }
+
+    /// Check the session and `Accept` of a GET stream request
+    fn open_stream(&self, request: &Request, owner: Option<&str>) -> Result<EventStream, Response> {
+        let accepts_events = request
+            .header("accept")
+            .is_none_or(|accept| accept.contains(EVENT_STREAM) || accept.contains("*/*"));
+        if !accepts_events {
+            return Err(Response::text(
+                406,
+                "GET streams require Accept: text/event-stream",
+            ));
+        }
+        let last_event = request
+            .header("last-event-id")
+            .and_then(|id| self.ids.parse(id));
+        let session = match request.header(SESSION_HEADER) {
+            Some(session) if request.path == RPC_PATH => session,
+            _ => {
+                return Ok(EventStream {
+                    session: None,
+                    terminated: None,
+                    last_event,
+                });
+            }
+        };
+        match self.sessions.open_stream(session, owner) {
+            Ok(terminated) => Ok(EventStream {
+                session: Some(session.to_string()),
+                terminated: Some(terminated),
+                last_event,
+            }),
+            Err(SessionError::NotFound) => Err(session_not_found()),
+            Err(SessionError::StreamOpen) => Err(Response::text(
+                409,
+                "A stream is already open for this session",
+            )),
+        }
+    }
+
+    /// Dispatch one JSON-RPC message and record how it went
+    async fn handle_rpc(
+        &self,
+        http_request: &Request,
+        principal: Option<&Principal>,
+        client: &str,
+    ) -> Response {
+        let Some(format) = ResponseFormat::negotiate(http_request) else {
+            return Response::text(406, "Accept application/json or text/event-stream");
+        };
+        let owner = principal.map(|principal| principal.id.as_str());
+        if let Some(session) = http_request.header(SESSION_HEADER) {
+            if self.sessions.touch(session, owner).is_err() {
+                return session_not_found();
+            }
+        }
+
+        let message = match self.parse_message(http_request) {
+            Ok(message) => message,
+            Err(response) => return response,
+        };
+        // Notifications have no id and get no response
+        let Some(id) = message.get("id").cloned() else {
+            return self.handle_notification(message, principal).await;
+        };
+        let request: JsonRpcRequest = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => {
+                self.metrics.record_error(INVALID_REQUEST);
+                return Response::json(
+                    200,
+                    &error_response(id, INVALID_REQUEST, &format!("Invalid request: {e}")),
+                );
+            }
+        };
+
+        let method = request.method.clone();
+        let params = request.params.clone().unwrap_or(Value::Null);
+        let tool = gate::tool_name(&method, &params);
+        // Reading a resource such as the metrics would otherwise change them and
+        // notify their subscribers again
+        let announce = method != "resources/read";
+
+        // Subscriptions belong to a session, whose stream gets the updates
+        let session = http_request.header(SESSION_HEADER);
+        let subscription = matches!(method.as_str(), SUBSCRIBE | UNSUBSCRIBE).then(|| {
+            params
+                .get("uri")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+        });
+        if subscription.is_some() && session.is_none() {
+            self.metrics.record_error(INVALID_REQUEST);
+            return Response::json(
+                200,
+                &error_response(
+                    id,
+                    INVALID_REQUEST,
+                    "Subscriptions need the Mcp-Session-Id issued by initialize",
+                ),
+            );
+        }
+
+        let timer = self.metrics.begin();
+        let dispatch = self.gate.dispatch(&self.server, request, principal, client);
+        let (response, outcome) = match dispatch.await {
+            Ok(response) => {
+                let outcome = Outcome::of(&response);
+                (response, outcome)
+            }
+            Err(Refusal::Limited(limited)) => {
+                self.metrics.record_error(RATE_LIMITED);
+                return Response::json(429, &limited.to_response(id))
+                    .header("Retry-After", limited.retry_after_secs().to_string());
+            }
+            Err(denied) => (denied.to_response(id), Outcome::Error(denied.code())),
+        };
+        timer.finish(&method, tool, outcome, announce);
+        if let (Some(uri), Some(session), Outcome::Success) = (subscription, session, outcome) {
+            // The session was checked above and can only have ended since
+            let _ = match method.as_str() {
+                SUBSCRIBE => self.sessions.subscribe(session, owner, uri),
+                _ => self.sessions.unsubscribe(session, owner, uri),
+            };
+        }
+
+        let mut response = match format {
+            ResponseFormat::Json => Response::json(200, &response),
+            ResponseFormat::EventStream => Response::new(200)
+                .header("Content-Type", EVENT_STREAM)
+                .header("Cache-Control", "no-cache")
+                .body(
+                    http::format_event(None, Some("message"), &response.to_string()).into_bytes(),
+                ),
+        };
+        if method == "initialize" && outcome == Outcome::Success {
+            response = response.header("Mcp-Session-Id", self.sessions.create(principal));
+        }
+        response
+    }
+
+    /// The JSON-RPC message in a request body; batches are not supported
+    fn parse_message(&self, request: &Request) -> Result<Value, Response> {
+        let (code, message) = match serde_json::from_slice::<Value>(&request.body) {
+            Ok(Value::Array(_)) => (INVALID_REQUEST, "Batches are not supported".to_string()),
+            Ok(message) => return Ok(message),
+            Err(e) => (PARSE_ERROR, format!("Parse error: {e}")),
+        };
+        self.metrics.record_error(code);
+        Err(Response::json(
+            400,
+            &error_response(Value::Null, code, &message),
+        ))
+    }
+
+    /// Pass a client notification to the server
+    ///
+    /// Messages with neither id nor method are responses, and the server sends
+    /// no requests that wait for one, so they are accepted and dropped.
+    async fn handle_notification(&self, message: Value, principal: Option<&Principal>) -> Response {
+        if message.get("method").is_none() {
+            return Response::new(202);
+        }
+        match serde_json::from_value::<JsonRpcNotification>(message) {
+            Ok(notification) => {
+                self.gate
+                    .deliver(&self.server, notification, principal)
+                    .await;
+                Response::new(202)
+            }
+            Err(e) => {
+                self.metrics.record_error(INVALID_REQUEST);
+                Response::json(
+                    400,
+                    &error_response(
+                        Value::Null,
+                        INVALID_REQUEST,
+                        &format!("Invalid notification: {e}"),
+                    ),
+                )
+            }
+        }
+    }
+
+    async fn stream_events(
+        &self,
+        mut reader: BufReader<OwnedReadHalf>,
+        mut write: OwnedWriteHalf,
+        stream: EventStream,
+        cors: &[(String, String)],
+    ) {
+        let mut terminated = stream.terminated;
+        let session = stream.session.as_deref();
+        // Subscribe before replaying so nothing falls between the two
+        let mut events = self.events.subscribe();
+        let _connected = self.metrics.sse_connected();
+        if http::write_event_stream_head(&mut write, cors)
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let mut last_sent = 0;
+        if let Some(last_event) = stream.last_event {
+            last_sent = last_event;
+            if self
+                .send_replay(&mut write, session, &mut last_sent)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+        keep_alive.tick().await;
+        let mut discard = [0u8; 512];
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) if event.sequence <= last_sent || !event.is_for(session) => {}
+                    Ok(event) => {
+                        if self.send_event(&mut write, &event).await.is_err() {
+                            return;
+                        }
+                        last_sent = event.sequence;
+                    }
+                    // Catch up from the replay buffer instead of dropping what was skipped
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        if self.send_replay(&mut write, session, &mut last_sent).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = keep_alive.tick() => {
+                    if tokio::io::AsyncWriteExt::write_all(&mut write, http::KEEP_ALIVE).await.is_err() {
+                        return;
+                    }
+                }
+                // Clients send nothing on an event stream; a read returning means they left
+                read = reader.read(&mut discard) => {
+                    if !matches!(read, Ok(n) if n > 0) {
+                        return;
+                    }
+                }
+                _ = session_terminated(&mut terminated) => return,
+            }
+        }
+    }
+
+    async fn send_event(&self, write: &mut OwnedWriteHalf, event: &Event) -> std::io::Result<()> {
+        let id = self.ids.format(event.sequence);
+        http::write_event(write, Some(&id), Some("message"), &event.data).await?;
+        self.metrics.notification_sent();
+        Ok(())
+    }
+
+    /// Send the buffered events after `last_sent`, advancing it
+    async fn send_replay(
+        &self,
+        write: &mut OwnedWriteHalf,
+        session: Option<&str>,
+        last_sent: &mut u64,
+    ) -> std::io::Result<()> {
+        let replay = self.replay(session, *last_sent);
+        if replay.incomplete {
+            tracing::warn!(
+                "SSE client resumed after event {last_sent}, but some later events are no longer buffered"
+            );
+        }
+        for event in replay.events {
+            self.send_event(write, &event).await?;
+            *last_sent = event.sequence;
+        }
+        Ok(())
+    }
+}
+
+/// Resolves once the session behind a stream is terminated; never for sessionless streams
+async fn session_terminated(terminated: &mut Option<watch::Receiver<bool>>) {
+    match terminated {
+        // The sender going away also means the session is gone
+        Some(terminated) => {
+            let _ = terminated.wait_for(|terminated| *terminated).await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Methods served on `path`, or `None` for unknown paths
+fn allowed_methods(path: &str) -> Option<&'static str> {
+    match path {
+        RPC_PATH => Some("GET, POST, DELETE"),
+        NOTIFY_PATH => Some("POST"),
+        EVENTS_PATH | HEALTH_PATH | PROMETHEUS_PATH => Some("GET"),
+        _ => None,
+    }
+}
+
+/// Answer to a CORS preflight; the origin headers are added by the caller
+fn preflight(request: &Request) -> Response {
+    match allowed_methods(&request.path) {
+        Some(allowed) => Response::new(204)
+            .header("Access-Control-Allow-Methods", allowed)
+            .header("Access-Control-Allow-Headers", CORS_ALLOWED_HEADERS)
+            .header("Access-Control-Max-Age", CORS_MAX_AGE),
+        None => Response::text(404, "Not found"),
+    }
+}
+
+/// Answer to a request refused by the [`OriginGuard`]
+fn refused(error: &OriginError) -> Response {
+    Response::json(
+        error.status(),
+        &error_response(Value::Null, auth::FORBIDDEN, &error.to_string()),
+    )
+}
+
+/// 401 or 403 with a challenge and a JSON-RPC error body
+fn unauthorized(error: &AuthError) -> Response {
+    Response::json(error.status(), &error.to_response(Value::Null))
+        .header("WWW-Authenticate", error.challenge())
+}
+
+fn session_not_found() -> Response {
+    Response::json(
+        404,
+        &error_response(
+            Value::Null,
+            INVALID_REQUEST,
+            "Unknown or terminated session; initialize a new one",
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use access_control::jsonrpc::{INVALID_PARAMS, METHOD_NOT_FOUND, error_code};
+    use access_control::limits::LimitsConfig;
+    use access_control::policy::Policy;
+    use prism_mcp_rs::core::error::McpError;
+    use std::collections::HashMap;
+
+    fn loopback_guard() -> OriginGuard {
+        OriginGuard::new(&Default::default(), "127.0.0.1:3000".parse().unwrap())
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: RPC_PATH.to_string(),
+            query: None,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_response_format_follows_accept() {
+        let negotiate = |accept: &str| ResponseFormat::negotiate(&request(&[("accept", accept)]));
+        assert_eq!(
+            ResponseFormat::negotiate(&request(&[])),
+            Some(ResponseFormat::Json)
+        );
+        assert_eq!(
+            negotiate("application/json, text/event-stream"),
+            Some(ResponseFormat::Json)
+        );
+        assert_eq!(
+            negotiate("text/event-stream"),
+            Some(ResponseFormat::EventStream)
+        );
+        assert_eq!(negotiate("*/*;q=0.8"), Some(ResponseFormat::Json));
+        assert_eq!(negotiate("text/html"), None);
+    }
+
+    #[test]
+    fn test_notifications_are_numbered_and_replayable() {
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(LimitsConfig::default()),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+        let notification = |uri: &str| {
+            JsonRpcNotification::new(
+                "notifications/resources/updated".to_string(),
+                Some(json!({ "uri": uri })),
+            )
+            .unwrap()
+        };
+        transport.notify(&notification("http://server/status"), None);
+        let session = transport.sessions.create(None);
+        transport.notify(&notification("http://server/metrics"), None);
+
+        let sessionless = transport.replay(None, 1);
+        assert_eq!(sessionless.events.len(), 1);
+        assert!(sessionless.events[0].data.contains("http://server/metrics"));
+        assert_eq!(transport.replay(None, 0).events.len(), 2);
+
+        // Sessions only buffer what was sent after they were created
+        let replay = transport.replay(Some(&session), 0);
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.events[0].sequence, 2);
+
+        let id = transport.ids.format(2);
+        assert_eq!(transport.ids.parse(&id), Some(2));
+
+        // Notifications for one session reach neither the others nor sessionless streams
+        let other = transport.sessions.create(None);
+        transport.notify(&notification("http://server/status"), Some(&other));
+        assert_eq!(transport.replay(Some(&other), 2).events.len(), 1);
+        assert!(transport.replay(Some(&session), 2).events.is_empty());
+        assert!(transport.replay(None, 2).events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_belong_to_sessions() {
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(LimitsConfig::default()),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+        let subscribe = |session: Option<&str>| {
+            let mut request = match session {
+                Some(session) => request(&[(SESSION_HEADER, session)]),
+                None => request(&[]),
+            };
+            request.body = br#"{"jsonrpc":"2.0","id":1,"method":"resources/subscribe","params":{"uri":"http://server/status"}}"#.to_vec();
+            request
+        };
+
+        let response = transport.route(&subscribe(None), None, "c").await;
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["error"]["code"], INVALID_REQUEST);
+        assert!(transport.subscribers("http://server/status").is_empty());
+
+        let session = transport.sessions.create(None);
+        let _other = transport.sessions.create(None);
+        let response = transport.route(&subscribe(Some(&session)), None, "c").await;
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert!(body.get("error").is_none(), "{body}");
+        assert_eq!(transport.subscribers("http://server/status"), vec![session]);
+        assert!(transport.subscribers("http://server/metrics").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_updates_reach_only_subscribers_the_policy_allows() {
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            principals = ["mallory"]
+            resources = ["db://records/*"]
+            "#,
+        )
+        .unwrap();
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(policy)),
+            RateLimiter::new(LimitsConfig::default()),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+        let principal = |id: &str| Principal {
+            id: id.to_string(),
+            roles: Vec::new(),
+            method: auth::AuthMethod::ApiKey,
+            expires_at: None,
+        };
+        let mut sessions = HashMap::new();
+        for id in ["alice", "mallory", "bob"] {
+            let session = transport.sessions.create(Some(&principal(id)));
+            sessions.insert(id, session);
+        }
+        for id in ["alice", "mallory"] {
+            transport
+                .sessions
+                .subscribe(&sessions[id], Some(id), "db://records/a")
+                .unwrap();
+        }
+        let notification = JsonRpcNotification::new(
+            "notifications/resources/updated".to_string(),
+            Some(json!({ "uri": "db://records/a" })),
+        )
+        .unwrap();
+        transport.notify_subscribers("db://records/a", &notification);
+
+        let delivered = |id: &str| transport.replay(Some(&sessions[id]), 0).events.len();
+        assert_eq!(delivered("alice"), 1);
+        assert_eq!(delivered("mallory"), 0);
+        assert_eq!(delivered("bob"), 0);
+        assert!(transport.replay(None, 0).events.is_empty());
+
+        transport
+            .sessions
+            .terminate(&sessions["alice"], Some("alice"))
+            .unwrap();
+        assert_eq!(
+            transport.subscriptions(),
+            HashSet::from(["db://records/a".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_accepted_and_batches_refused() {
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(LimitsConfig::default()),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+        let post = |path: &str, body: &str| {
+            let mut request = request(&[]);
+            request.path = path.to_string();
+            request.body = body.as_bytes().to_vec();
+            request
+        };
+        let initialized = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+        let ping = r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+
+        for path in [RPC_PATH, NOTIFY_PATH] {
+            let response = transport.route(&post(path, initialized), None, "c").await;
+            assert_eq!(response.status, 202, "{path}");
+        }
+        let response = transport.route(&post(NOTIFY_PATH, ping), None, "c").await;
+        assert_eq!(response.status, 400);
+
+        let response = transport
+            .route(&post(RPC_PATH, &format!("[{ping}]")), None, "c")
+            .await;
+        assert_eq!(response.status, 400);
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["error"]["code"], INVALID_REQUEST);
+        let response = transport
+            .route(
+                &post(RPC_PATH, r#"{"jsonrpc":"2.0","method":7}"#),
+                None,
+                "c",
+            )
+            .await;
+        assert_eq!(response.status, 400);
+        assert_eq!(transport.metrics.errors(), vec![(INVALID_REQUEST, 3)]);
+    }
+
+    #[test]
+    fn test_requests_are_authenticated_except_health() {
+        let authenticator = Authenticator::new(&auth::AuthConfig {
+            api_keys: vec![auth::ApiKeyConfig {
+                principal: "ci".to_string(),
+                key: Some("secret".to_string()),
+                key_sha256: None,
+                roles: Vec::new(),
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            authenticator,
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(LimitsConfig::default()),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+
+        let error = transport.authenticate(&request(&[])).unwrap_err();
+        let response = unauthorized(&error);
+        assert_eq!(response.status, 401);
+        assert!(response.headers.contains(&(
+            "WWW-Authenticate".to_string(),
+            error.challenge().to_string()
+        )));
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["error"]["code"], auth::UNAUTHENTICATED);
+        assert_eq!(transport.metrics.errors(), vec![(auth::UNAUTHENTICATED, 1)]);
+
+        let principal = transport
+            .authenticate(&request(&[("authorization", "Bearer secret")]))
+            .unwrap();
+        assert_eq!(principal.unwrap().id, "ci");
+
+        let mut health = request(&[]);
+        health.path = HEALTH_PATH.to_string();
+        assert_eq!(transport.authenticate(&health), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_policy_denies_tool_calls() {
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            tools = ["delete"]
+            "#,
+        )
+        .unwrap();
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(policy)),
+            RateLimiter::new(LimitsConfig::default()),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+        let mut call = request(&[("accept", "application/json")]);
+        call.body = br#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"delete","arguments":{}}}"#.to_vec();
+
+        let response = transport.handle_rpc(&call, None, "127.0.0.1").await;
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["id"], 7);
+        assert_eq!(body["error"]["code"], auth::FORBIDDEN);
+        assert_eq!(transport.metrics.errors(), vec![(auth::FORBIDDEN, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_requests_get_429() {
+        let limits: LimitsConfig =
+            toml::from_str("[client]\nrequests_per_second = 0.1\nburst = 1").unwrap();
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(limits),
+            loopback_guard(),
+            ReplayLimits::default(),
+        );
+        let mut ping = request(&[("accept", "application/json")]);
+        ping.body = br#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#.to_vec();
+
+        assert_eq!(
+            transport.handle_rpc(&ping, None, "10.0.0.1").await.status,
+            200
+        );
+        let response = transport.handle_rpc(&ping, None, "10.0.0.1").await;
+        assert_eq!(response.status, 429);
+        assert!(
+            response
+                .headers
+                .contains(&("Retry-After".to_string(), "10".to_string()))
+        );
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["error"]["code"], RATE_LIMITED);
+        assert_eq!(transport.metrics.errors(), vec![(RATE_LIMITED, 1)]);
+        // Another client is not held back
+        assert_eq!(
+            transport.handle_rpc(&ping, None, "10.0.0.2").await.status,
+            200
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebound_hosts_and_foreign_origins_are_refused() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            Authenticator::new(&Default::default()).unwrap(),
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(LimitsConfig::default()),
+            OriginGuard::new(&Default::default(), address),
+            ReplayLimits::default(),
+        );
+        tokio::spawn(transport.serve(listener));
+
+        let status = |headers: &'static str, target: &'static str| async move {
+            let body = r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let request = format!(
+                "{target} HTTP/1.1\r\n{headers}Content-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response[9..12].to_string()
+        };
+
+        assert_eq!(status("Host: 127.0.0.1\r\n", "POST /mcp").await, "200");
+        assert_eq!(
+            status(
+                "Host: localhost\r\nOrigin: http://localhost:5173\r\n",
+                "POST /mcp"
+            )
+            .await,
+            "200"
+        );
+        assert_eq!(
+            status("Host: attacker.example\r\n", "POST /mcp").await,
+            "403"
+        );
+        assert_eq!(
+            status(
+                "Host: localhost\r\nOrigin: https://attacker.example\r\n",
+                "POST /mcp"
+            )
+            .await,
+            "403"
+        );
+        assert_eq!(status("", "POST /mcp").await, "400");
+        assert_eq!(
+            status("Host: attacker.example\r\n", "GET /health").await,
+            "200"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origins_get_cors_headers() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let authenticator = Authenticator::new(&auth::AuthConfig {
+            api_keys: vec![auth::ApiKeyConfig {
+                principal: "ci".to_string(),
+                key: Some("secret".to_string()),
+                key_sha256: None,
+                roles: Vec::new(),
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let transport = HttpTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Arc::new(Metrics::new()),
+            authenticator,
+            Arc::new(PolicyEngine::from(Policy::default())),
+            RateLimiter::new(LimitsConfig::default()),
+            OriginGuard::new(&Default::default(), address),
+            ReplayLimits::default(),
+        );
+        tokio::spawn(transport.serve(listener));
+
+        let send = |head: &'static str| async move {
+            let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize"}"#;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let request = format!(
+                "{head}\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let allowed = "Access-Control-Allow-Origin: http://localhost:5173\r\n";
+
+        // Preflights carry no credentials
+        let response = send(
+            "OPTIONS /mcp HTTP/1.1\r\nOrigin: http://localhost:5173\r\n\
+             Access-Control-Request-Method: POST",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 204"), "{response}");
+        assert!(response.contains(allowed));
+        assert!(response.contains("Access-Control-Allow-Methods: GET, POST, DELETE\r\n"));
+        assert!(response.contains("Mcp-Session-Id, X-API-Key\r\n"));
+
+        let response =
+            send("POST /mcp HTTP/1.1\r\nOrigin: http://localhost:5173\r\nX-API-Key: secret").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(allowed));
+        assert!(response.contains("Access-Control-Expose-Headers: Mcp-Session-Id\r\n"));
+        assert!(response.contains("Mcp-Session-Id: "));
+
+        // Pages can read refusals too
+        let response = send("POST /mcp HTTP/1.1\r\nOrigin: http://localhost:5173").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+        assert!(response.contains(allowed));
+
+        // Other origins get nothing, even where the origin is not checked
+        let response = send("OPTIONS /health HTTP/1.1\r\nOrigin: https://attacker.example").await;
+        assert!(response.starts_with("HTTP/1.1 204"), "{response}");
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+        let response = send("POST /mcp HTTP/1.1\r\nX-API-Key: secret").await;
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+    }
+
+    #[test]
+    fn test_outcomes_follow_the_response() {
+        assert_eq!(
+            Outcome::of(&json!({"jsonrpc": "2.0", "id": 1, "result": {}})),
+            Outcome::Success
+        );
+        assert_eq!(
+            Outcome::of(&json!({"id": 1, "result": {"content": [], "isError": true}})),
+            Outcome::ToolError
+        );
+        assert_eq!(
+            Outcome::of(&error_response(json!(1), METHOD_NOT_FOUND, "nope")),
+            Outcome::Error(METHOD_NOT_FOUND)
+        );
+        assert_eq!(
+            error_code(&McpError::ToolNotFound("x".into())),
+            INVALID_PARAMS
+        );
+    }
+}
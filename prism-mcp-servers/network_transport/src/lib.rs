@@ -0,0 +1,22 @@
+//! The HTTP and WebSocket transports shared by the network MCP servers.
+//!
+//! - [`http_transport`]: MCP over HTTP, with Server-Sent Events for
+//!   notifications
+//! - [`websocket`]: MCP over WebSocket
+//! - [`http`]: minimal HTTP/1.1 requests, responses and Server-Sent Events
+//! - [`origin`]: `Host` and `Origin` checks against DNS rebinding, and CORS
+//! - [`sessions`]: Streamable HTTP sessions and their subscriptions
+//! - [`replay`]: event numbering and `Last-Event-ID` replay buffers
+//! - [`metrics`]: request, error, connection and latency metrics
+//! - [`prometheus`]: the `GET /metrics` text exposition of those metrics
+//! - [`process`]: resource usage of the server process
+
+pub mod http;
+pub mod http_transport;
+pub mod metrics;
+pub mod origin;
+pub mod process;
+pub mod prometheus;
+pub mod replay;
+pub mod sessions;
+pub mod websocket;
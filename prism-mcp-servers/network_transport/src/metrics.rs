@@ -0,0 +1,541 @@
+//! Request, error, connection and latency metrics of a network transport.
+//!
+//! Every counter is an atomic so request tasks can record without contending
+//! on a lock; the labeled series (per method, per tool, per error code) are
+//! created on first use and then updated through shared `Arc`s. A change
+//! counter lets the resource notifier tell whether anything moved since the
+//! last time it looked.
+
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use access_control::jsonrpc::INTERNAL_ERROR;
+
+/// Upper bounds of the latency histogram buckets, in milliseconds
+pub const LATENCY_BUCKETS_MS: [f64; 12] = [
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 10000.0,
+];
+
+/// Latency histogram with fixed buckets; the final slot counts observations above every bound
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        let slot = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| millis <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[slot].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Sum of all observations, in seconds
+    pub fn sum_seconds(&self) -> f64 {
+        self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+    }
+
+    /// Cumulative counts per bucket bound, ending with the `+Inf` bucket
+    pub fn cumulative(&self) -> Vec<u64> {
+        self.buckets
+            .iter()
+            .scan(0, |total, bucket| {
+                *total += bucket.load(Ordering::Relaxed);
+                Some(*total)
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let count = self.count();
+        let cumulative = self.cumulative();
+        let buckets: Map<String, Value> = LATENCY_BUCKETS_MS
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(std::iter::once("+Inf".to_string()))
+            .zip(cumulative)
+            .map(|(bound, total)| (bound, json!(total)))
+            .collect();
+        json!({
+            "count": count,
+            "average_ms": (count > 0).then(|| self.sum_seconds() * 1000.0 / count as f64),
+            "buckets_ms": buckets
+        })
+    }
+}
+
+/// Count, failures and latency of one method or tool
+#[derive(Debug, Default)]
+pub struct Series {
+    pub count: AtomicU64,
+    pub errors: AtomicU64,
+    pub latency: Histogram,
+}
+
+impl Series {
+    fn to_json(&self) -> Value {
+        json!({
+            "count": self.count.load(Ordering::Relaxed),
+            "errors": self.errors.load(Ordering::Relaxed),
+            "latency": self.latency.to_json()
+        })
+    }
+}
+
+/// Series keyed by a label, created the first time a label is seen
+#[derive(Debug, Default)]
+pub struct Labeled<K: Ord> {
+    series: RwLock<BTreeMap<K, Arc<Series>>>,
+}
+
+impl<K: Ord + Clone> Labeled<K> {
+    pub fn get(&self, label: &K) -> Arc<Series> {
+        if let Some(series) = self.series.read().unwrap().get(label) {
+            return series.clone();
+        }
+        self.series
+            .write()
+            .unwrap()
+            .entry(label.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Current series in label order
+    pub fn snapshot(&self) -> Vec<(K, Arc<Series>)> {
+        self.series
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(label, series)| (label.clone(), series.clone()))
+            .collect()
+    }
+}
+
+/// How a JSON-RPC request ended, as far as the metrics are concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    /// A JSON-RPC error response with this code
+    Error(i64),
+    /// A tool call that returned a result marked `is_error`
+    ToolError,
+}
+
+impl Outcome {
+    /// Outcome of a JSON-RPC response
+    pub fn of(response: &Value) -> Self {
+        if let Some(error) = response.get("error") {
+            return Self::Error(
+                error
+                    .get("code")
+                    .and_then(Value::as_i64)
+                    .unwrap_or(INTERNAL_ERROR),
+            );
+        }
+        if response
+            .pointer("/result/isError")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+        {
+            return Self::ToolError;
+        }
+        Self::Success
+    }
+}
+
+/// Kinds of long-lived client connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connection {
+    Sse,
+    WebSocket,
+}
+
+/// Live metrics of a transport
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    started_at: DateTime<Utc>,
+    requests: AtomicU64,
+    in_flight: AtomicU64,
+    methods: Labeled<String>,
+    tools: Labeled<String>,
+    errors: RwLock<BTreeMap<i64, Arc<AtomicU64>>>,
+    sse_active: AtomicU64,
+    sse_total: AtomicU64,
+    websocket_active: AtomicU64,
+    websocket_total: AtomicU64,
+    notifications: AtomicU64,
+    changes: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: Utc::now(),
+            requests: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            methods: Labeled::default(),
+            tools: Labeled::default(),
+            errors: RwLock::new(BTreeMap::new()),
+            sse_active: AtomicU64::new(0),
+            sse_total: AtomicU64::new(0),
+            websocket_active: AtomicU64::new(0),
+            websocket_total: AtomicU64::new(0),
+            notifications: AtomicU64::new(0),
+            changes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Start timing a request; dropping the guard without finishing it records nothing
+    pub fn begin(&self) -> RequestTimer<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestTimer {
+            metrics: self,
+            started: Instant::now(),
+        }
+    }
+
+    /// Record an error that was answered before a method could be dispatched
+    pub fn record_error(&self, code: i64) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.count_error(code);
+        self.changed();
+    }
+
+    fn count_error(&self, code: i64) {
+        if let Some(counter) = self.errors.read().unwrap().get(&code) {
+            counter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.errors
+            .write()
+            .unwrap()
+            .entry(code)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Track an SSE stream until the returned guard is dropped
+    pub fn sse_connected(self: &Arc<Self>) -> ConnectionGuard {
+        self.connected(Connection::Sse)
+    }
+
+    pub fn sse_connections(&self) -> u64 {
+        self.sse_active.load(Ordering::Relaxed)
+    }
+
+    /// SSE streams opened since startup
+    pub fn sse_connections_total(&self) -> u64 {
+        self.sse_total.load(Ordering::Relaxed)
+    }
+
+    /// Track a WebSocket connection until the returned guard is dropped
+    pub fn websocket_connected(self: &Arc<Self>) -> ConnectionGuard {
+        self.connected(Connection::WebSocket)
+    }
+
+    pub fn websocket_connections(&self) -> u64 {
+        self.websocket_active.load(Ordering::Relaxed)
+    }
+
+    /// WebSocket connections accepted since startup
+    pub fn websocket_connections_total(&self) -> u64 {
+        self.websocket_total.load(Ordering::Relaxed)
+    }
+
+    fn connected(self: &Arc<Self>, connection: Connection) -> ConnectionGuard {
+        let (active, total) = self.connection_counters(connection);
+        active.fetch_add(1, Ordering::Relaxed);
+        total.fetch_add(1, Ordering::Relaxed);
+        self.changed();
+        ConnectionGuard {
+            metrics: self.clone(),
+            connection,
+        }
+    }
+
+    /// Open and total counters of a kind of connection
+    fn connection_counters(&self, connection: Connection) -> (&AtomicU64, &AtomicU64) {
+        match connection {
+            Connection::Sse => (&self.sse_active, &self.sse_total),
+            Connection::WebSocket => (&self.websocket_active, &self.websocket_total),
+        }
+    }
+
+    /// Notifications delivered to SSE streams and WebSocket connections since startup
+    pub fn notifications(&self) -> u64 {
+        self.notifications.load(Ordering::Relaxed)
+    }
+
+    /// Count a notification delivered to a client
+    ///
+    /// Deliveries do not count as a change; otherwise announcing a change
+    /// would itself be a change to announce.
+    pub fn notification_sent(&self) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Monotonic counter bumped whenever a reported value changes
+    pub fn generation(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
+    }
+
+    fn changed(&self) {
+        self.changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn methods(&self) -> Vec<(String, Arc<Series>)> {
+        self.methods.snapshot()
+    }
+
+    pub fn tools(&self) -> Vec<(String, Arc<Series>)> {
+        self.tools.snapshot()
+    }
+
+    pub fn errors(&self) -> Vec<(i64, u64)> {
+        self.errors
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(code, counter)| (*code, counter.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Everything served at `http://server/metrics`
+    pub fn to_json(&self) -> Value {
+        let series = |labeled: Vec<(String, Arc<Series>)>| -> Map<String, Value> {
+            labeled
+                .into_iter()
+                .map(|(label, series)| (label, series.to_json()))
+                .collect()
+        };
+        let errors: Map<String, Value> = self
+            .errors()
+            .into_iter()
+            .map(|(code, count)| (code.to_string(), json!(count)))
+            .collect();
+        json!({
+            "uptime_seconds": self.uptime().as_secs(),
+            "requests": {
+                "total": self.requests(),
+                "in_flight": self.in_flight(),
+                "by_method": series(self.methods()),
+                "by_tool": series(self.tools())
+            },
+            "errors_by_code": errors,
+            "sse": {
+                "active_connections": self.sse_connections(),
+                "total_connections": self.sse_connections_total()
+            },
+            "websocket": {
+                "active_connections": self.websocket_connections(),
+                "total_connections": self.websocket_connections_total()
+            },
+            "notifications_sent": self.notifications()
+        })
+    }
+
+    /// Uptime and load summary included in `http://server/status`
+    pub fn summary(&self) -> Value {
+        json!({
+            "started_at": self.started_at.to_rfc3339(),
+            "uptime_seconds": self.uptime().as_secs(),
+            "requests_processed": self.requests(),
+            "in_flight": self.in_flight(),
+            "sse_connections": self.sse_connections()
+        })
+    }
+}
+
+/// Times one request from [`Metrics::begin`] to [`RequestTimer::finish`]
+pub struct RequestTimer<'a> {
+    metrics: &'a Metrics,
+    started: Instant,
+}
+
+impl RequestTimer<'_> {
+    /// Record the request under `method` and, for tool calls, `tool`
+    ///
+    /// Unless `announce` is set the request is counted without bumping the
+    /// change counter, for requests that merely read the metrics.
+    pub fn finish(self, method: &str, tool: Option<&str>, outcome: Outcome, announce: bool) {
+        let elapsed = self.started.elapsed();
+        let metrics = self.metrics;
+        metrics.requests.fetch_add(1, Ordering::Relaxed);
+
+        let mut labeled = vec![metrics.methods.get(&method.to_string())];
+        if let Some(tool) = tool {
+            labeled.push(metrics.tools.get(&tool.to_string()));
+        }
+        for series in labeled {
+            series.count.fetch_add(1, Ordering::Relaxed);
+            if outcome != Outcome::Success {
+                series.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            series.latency.observe(elapsed);
+        }
+        if let Outcome::Error(code) = outcome {
+            metrics.count_error(code);
+        }
+        if announce {
+            metrics.changed();
+        }
+    }
+}
+
+impl Drop for RequestTimer<'_> {
+    fn drop(&mut self) {
+        self.metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Keeps an SSE stream or WebSocket connection counted as active while it lives
+pub struct ConnectionGuard {
+    metrics: Arc<Metrics>,
+    connection: Connection,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let (active, _) = self.metrics.connection_counters(self.connection);
+        active.fetch_sub(1, Ordering::Relaxed);
+        self.metrics.changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(7));
+        histogram.observe(Duration::from_secs(60));
+
+        let cumulative = histogram.cumulative();
+        assert_eq!(cumulative[0], 1);
+        assert_eq!(cumulative[3], 2);
+        assert_eq!(cumulative[LATENCY_BUCKETS_MS.len() - 1], 2);
+        assert_eq!(cumulative[LATENCY_BUCKETS_MS.len()], 3);
+        assert_eq!(histogram.count(), 3);
+        assert!(histogram.sum_seconds() > 60.0);
+    }
+
+    #[test]
+    fn test_requests_are_counted_by_method_tool_and_code() {
+        let metrics = Metrics::new();
+        let before = metrics.generation();
+
+        metrics.begin().finish(
+            "tools/call",
+            Some("http_calculator"),
+            Outcome::ToolError,
+            true,
+        );
+        metrics
+            .begin()
+            .finish("tools/list", None, Outcome::Success, true);
+        metrics
+            .begin()
+            .finish("nope", None, Outcome::Error(-32601), true);
+        metrics.record_error(-32700);
+
+        let snapshot = metrics.to_json();
+        assert_eq!(snapshot["requests"]["total"], 4);
+        assert_eq!(snapshot["requests"]["in_flight"], 0);
+        assert_eq!(snapshot["requests"]["by_method"]["tools/call"]["errors"], 1);
+        assert_eq!(
+            snapshot["requests"]["by_tool"]["http_calculator"]["latency"]["count"],
+            1
+        );
+        assert_eq!(snapshot["errors_by_code"]["-32601"], 1);
+        assert_eq!(snapshot["errors_by_code"]["-32700"], 1);
+        assert!(metrics.generation() > before);
+
+        let generation = metrics.generation();
+        metrics
+            .begin()
+            .finish("resources/read", None, Outcome::Success, false);
+        assert_eq!(metrics.generation(), generation);
+        assert_eq!(metrics.requests(), 5);
+    }
+
+    #[test]
+    fn test_sse_gauge_follows_guards() {
+        let metrics = Arc::new(Metrics::new());
+        let first = metrics.sse_connected();
+        let second = metrics.sse_connected();
+        assert_eq!(metrics.sse_connections(), 2);
+
+        drop(first);
+        assert_eq!(metrics.sse_connections(), 1);
+        drop(second);
+        assert_eq!(metrics.to_json()["sse"]["total_connections"], 2);
+
+        let generation = metrics.generation();
+        metrics.notification_sent();
+        assert_eq!(metrics.generation(), generation);
+
+        let websocket = metrics.websocket_connected();
+        assert_eq!(metrics.websocket_connections(), 1);
+        assert_eq!(metrics.sse_connections(), 0);
+        drop(websocket);
+        let snapshot = metrics.to_json();
+        assert_eq!(snapshot["websocket"]["active_connections"], 0);
+        assert_eq!(snapshot["websocket"]["total_connections"], 1);
+        assert_eq!(snapshot["notifications_sent"], 1);
+    }
+
+    #[test]
+    fn test_outcomes_follow_the_response() {
+        assert_eq!(
+            Outcome::of(&json!({"jsonrpc": "2.0", "id": 1, "result": {}})),
+            Outcome::Success
+        );
+        assert_eq!(
+            Outcome::of(&json!({"id": 1, "result": {"content": [], "isError": true}})),
+            Outcome::ToolError
+        );
+        assert_eq!(
+            Outcome::of(&json!({"id": 1, "error": {"code": -32601, "message": "nope"}})),
+            Outcome::Error(-32601)
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! `Origin` and `Host` validation against DNS rebinding.
+//!
+//! A web page can make a browser send requests to a server on the user's
+//! machine, and by rebinding its own domain name to 127.0.0.1 it can read the
+//! answers too. Such requests carry the page's `Origin` and the attacker's
+//! domain in `Host`, so both are checked before anything else:
+//!
+//! - `Host` must name one of `allowed_hosts`. Left empty, a server bound to a
+//!   loopback address accepts only `localhost`, `127.0.0.1` and `[::1]`; a
+//!   server bound elsewhere cannot know its public names and accepts any host.
+//! - `Origin`, when sent, must be one of `allowed_origins`. Left empty, only
+//!   pages served from a loopback host are accepted. Clients other than
+//!   browsers send no `Origin` and are not affected.
+//!
+//! Either list may hold `*` to accept everything.
+
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+use crate::http::Request;
+
+/// Host names of the loopback interface
+const LOOPBACK_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+/// `[security]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Origins of the web pages allowed to call the server, e.g. `https://app.example.com`
+    pub allowed_origins: Vec<String>,
+    /// Names clients may reach the server by, with or without a port
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginError {
+    MissingHost,
+    Host(String),
+    Origin(String),
+}
+
+impl OriginError {
+    /// HTTP status of the refusal
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::MissingHost => 400,
+            Self::Host(_) | Self::Origin(_) => 403,
+        }
+    }
+}
+
+impl std::fmt::Display for OriginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingHost => write!(f, "Missing Host header"),
+            Self::Host(host) => write!(f, "Host '{host}' is not allowed"),
+            Self::Origin(origin) => write!(f, "Origin '{origin}' is not allowed"),
+        }
+    }
+}
+
+impl std::error::Error for OriginError {}
+
+/// Checks the `Host` and `Origin` of incoming requests
+#[derive(Debug, Clone)]
+pub struct OriginGuard {
+    /// `None` accepts any host
+    hosts: Option<Vec<String>>,
+    /// Empty accepts loopback origins only
+    origins: Vec<String>,
+}
+
+impl OriginGuard {
+    pub fn new(config: &SecurityConfig, bind: SocketAddr) -> Self {
+        let lowercase = |values: &[String]| -> Vec<String> {
+            values
+                .iter()
+                .map(|value| value.trim_end_matches('/').to_ascii_lowercase())
+                .collect()
+        };
+        let hosts = if !config.allowed_hosts.is_empty() {
+            Some(lowercase(&config.allowed_hosts))
+        } else if bind.ip().is_loopback() {
+            Some(LOOPBACK_HOSTS.iter().map(|host| host.to_string()).collect())
+        } else {
+            None
+        };
+        Self {
+            hosts,
+            origins: lowercase(&config.allowed_origins),
+        }
+    }
+
+    /// Whether any `Host` is accepted
+    pub fn accepts_any_host(&self) -> bool {
+        self.hosts
+            .as_ref()
+            .is_none_or(|hosts| hosts.iter().any(|host| host == "*"))
+    }
+
+    pub fn check(&self, request: &Request) -> Result<(), OriginError> {
+        self.check_headers(request.header("host"), request.header("origin"))
+    }
+
+    /// [`check`](Self::check) for requests parsed elsewhere, such as a WebSocket handshake
+    pub fn check_headers(
+        &self,
+        host: Option<&str>,
+        origin: Option<&str>,
+    ) -> Result<(), OriginError> {
+        if let Some(hosts) = &self.hosts {
+            let host = host.ok_or(OriginError::MissingHost)?;
+            if !host_allowed(hosts, host) {
+                return Err(OriginError::Host(host.to_string()));
+            }
+        }
+        if let Some(origin) = origin {
+            if !self.allows_origin(origin) {
+                return Err(OriginError::Origin(origin.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether pages from `origin` may call the server
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+        if self.origins.is_empty() {
+            // Opaque origins such as "null" have no host
+            return origin
+                .split_once("://")
+                .is_some_and(|(_, authority)| LOOPBACK_HOSTS.contains(&hostname(authority)));
+        }
+        self.origins
+            .iter()
+            .any(|allowed| allowed == "*" || *allowed == origin)
+    }
+}
+
+/// Whether `host` (a `Host` header value) matches an entry of `allowed`
+///
+/// Entries without a port match any port.
+fn host_allowed(allowed: &[String], host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let (name, port) = split_port(&host);
+    allowed.iter().any(|allowed| {
+        let (allowed_name, allowed_port) = split_port(allowed);
+        allowed == "*" || (allowed_name == name && allowed_port.is_none_or(|p| Some(p) == port))
+    })
+}
+
+/// Host name of an authority, without port or IPv6 brackets
+fn hostname(authority: &str) -> &str {
+    split_port(authority).0
+}
+
+/// Split an authority into host name and port
+fn split_port(authority: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = authority.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((address, rest)) => (address, rest.strip_prefix(':')),
+            None => (rest, None),
+        };
+    }
+    match authority.split_once(':') {
+        // More than one colon is a bare IPv6 address
+        Some((name, port)) if !port.contains(':') => (name, Some(port)),
+        _ => (authority, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/mcp".to_string(),
+            query: None,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    fn guard(config: &SecurityConfig, bind: &str) -> OriginGuard {
+        OriginGuard::new(config, bind.parse().unwrap())
+    }
+
+    #[test]
+    fn test_loopback_server_rejects_rebound_hosts() {
+        let guard = guard(&SecurityConfig::default(), "127.0.0.1:3000");
+        assert!(!guard.accepts_any_host());
+        for host in [
+            "localhost:3000",
+            "127.0.0.1:3000",
+            "[::1]:3000",
+            "LOCALHOST",
+        ] {
+            assert_eq!(guard.check(&request(&[("host", host)])), Ok(()), "{host}");
+        }
+        assert_eq!(
+            guard.check(&request(&[("host", "attacker.example:3000")])),
+            Err(OriginError::Host("attacker.example:3000".to_string()))
+        );
+        assert_eq!(
+            guard.check(&request(&[("host", "localhost.attacker.example")])),
+            Err(OriginError::Host("localhost.attacker.example".to_string()))
+        );
+        assert_eq!(guard.check(&request(&[])), Err(OriginError::MissingHost));
+    }
+
+    #[test]
+    fn test_default_origins_are_loopback_only() {
+        let guard = guard(&SecurityConfig::default(), "127.0.0.1:3000");
+        let with_origin = |origin| request(&[("host", "localhost:3000"), ("origin", origin)]);
+        assert!(guard.check(&with_origin("http://localhost:5173")).is_ok());
+        assert!(guard.check(&with_origin("http://[::1]:8080")).is_ok());
+        assert_eq!(
+            guard.check(&with_origin("https://attacker.example")),
+            Err(OriginError::Origin("https://attacker.example".to_string()))
+        );
+        assert!(guard.check(&with_origin("null")).is_err());
+        assert!(
+            guard
+                .check(&with_origin("http://localhost.attacker.example"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_configured_hosts_and_origins() {
+        let config = SecurityConfig {
+            allowed_origins: vec!["https://App.example.com/".to_string()],
+            allowed_hosts: vec!["mcp.example.com".to_string(), "10.0.0.5:3000".to_string()],
+        };
+        let guard = guard(&config, "0.0.0.0:3000");
+        assert!(
+            guard
+                .check(&request(&[("host", "mcp.example.com:443")]))
+                .is_ok()
+        );
+        assert!(guard.check(&request(&[("host", "10.0.0.5:3000")])).is_ok());
+        assert!(guard.check(&request(&[("host", "10.0.0.5:4000")])).is_err());
+        assert!(guard.check(&request(&[("host", "localhost")])).is_err());
+        assert!(
+            guard
+                .check(&request(&[
+                    ("host", "mcp.example.com"),
+                    ("origin", "https://app.example.com")
+                ]))
+                .is_ok()
+        );
+        assert!(
+            guard
+                .check(&request(&[
+                    ("host", "mcp.example.com"),
+                    ("origin", "http://localhost:3000")
+                ]))
+                .is_err()
+        );
+
+        // Bound to all interfaces without a host list, any host is accepted
+        let open = OriginGuard::new(&SecurityConfig::default(), "0.0.0.0:3000".parse().unwrap());
+        assert!(open.accepts_any_host());
+        assert!(open.check(&request(&[])).is_ok());
+        let any = SecurityConfig {
+            allowed_origins: vec!["*".to_string()],
+            allowed_hosts: vec!["*".to_string()],
+        };
+        let any = OriginGuard::new(&any, "127.0.0.1:3000".parse().unwrap());
+        assert!(
+            any.check(&request(&[
+                ("host", "x.example"),
+                ("origin", "https://y.example")
+            ]))
+            .is_ok()
+        );
+    }
+}
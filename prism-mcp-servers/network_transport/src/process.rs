@@ -0,0 +1,105 @@
+//! Resource usage of this process, read from `/proc`.
+//!
+//! Only Linux exposes these files; elsewhere every reading is `None` and the
+//! corresponding metrics are left out of the scrape.
+
+use std::fs;
+
+/// Kernel clock ticks per second (`USER_HZ`), which is 100 on every mainstream Linux build
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// A point-in-time reading of the process's resource usage
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessStats {
+    pub resident_memory_bytes: Option<u64>,
+    pub virtual_memory_bytes: Option<u64>,
+    pub cpu_seconds: Option<f64>,
+    pub threads: Option<u64>,
+    pub open_fds: Option<u64>,
+    /// Unix time the process started, in seconds
+    pub start_time_seconds: Option<f64>,
+}
+
+impl ProcessStats {
+    pub fn read() -> Self {
+        let mut stats = fs::read_to_string("/proc/self/stat")
+            .ok()
+            .and_then(|stat| parse_stat(&stat))
+            .unwrap_or_default();
+        stats.resident_memory_bytes = fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| parse_rss(&status));
+        stats.open_fds = fs::read_dir("/proc/self/fd")
+            .ok()
+            .map(|entries| entries.count() as u64);
+        if let (Some(started), Some(boot)) = (
+            stats.start_time_seconds,
+            fs::read_to_string("/proc/stat")
+                .ok()
+                .and_then(|stat| parse_boot_time(&stat)),
+        ) {
+            stats.start_time_seconds = Some(boot + started);
+        } else {
+            stats.start_time_seconds = None;
+        }
+        stats
+    }
+}
+
+/// Parse `/proc/self/stat`; the start time is left relative to boot
+fn parse_stat(stat: &str) -> Option<ProcessStats> {
+    // The command name is parenthesized and may itself contain spaces or parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    // `fields[0]` is field 3 (state) in proc(5)
+    let field = |number: usize| fields.get(number - 3)?.parse::<u64>().ok();
+    let ticks = |ticks: u64| ticks as f64 / CLOCK_TICKS_PER_SECOND;
+    Some(ProcessStats {
+        cpu_seconds: Some(ticks(field(14)? + field(15)?)),
+        threads: field(20),
+        start_time_seconds: field(22).map(ticks),
+        virtual_memory_bytes: field(23),
+        ..ProcessStats::default()
+    })
+}
+
+/// `VmRSS` from `/proc/self/status`, which the kernel reports in kB
+fn parse_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// System boot time (`btime`) from `/proc/stat`, in Unix seconds
+fn parse_boot_time(stat: &str) -> Option<f64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proc_files_are_parsed() {
+        let stat = "4242 (http (server)) S 1 4242 4242 0 -1 4194560 1234 0 0 0 250 50 0 0 20 0 7 0 12345 104857600 2560 18446744073709551615";
+        let stats = parse_stat(stat).unwrap();
+        assert_eq!(stats.cpu_seconds, Some(3.0));
+        assert_eq!(stats.threads, Some(7));
+        assert_eq!(stats.start_time_seconds, Some(123.45));
+        assert_eq!(stats.virtual_memory_bytes, Some(104_857_600));
+
+        assert_eq!(
+            parse_rss("Name:\thttp-server\nVmRSS:\t   2048 kB\nThreads:\t7\n"),
+            Some(2 * 1024 * 1024)
+        );
+        assert_eq!(
+            parse_boot_time("cpu  1 2 3\nbtime 1700000000\nprocesses 9\n"),
+            Some(1_700_000_000.0)
+        );
+        assert!(parse_stat("garbage").is_none());
+    }
+}
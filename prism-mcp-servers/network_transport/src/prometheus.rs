@@ -0,0 +1,328 @@
+//! Prometheus text exposition of the transport and process metrics.
+//!
+//! Served at `GET /metrics` in the text format (version 0.0.4) that every
+//! Prometheus-compatible scraper reads, so no exporter sidecar is needed.
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use crate::metrics::{Histogram, LATENCY_BUCKETS_MS, Metrics, Series};
+use crate::process::ProcessStats;
+
+/// Path the exposition is served at
+pub const PATH: &str = "/metrics";
+
+/// `Content-Type` of the exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Render every metric family; `sessions` is left out for transports without sessions
+pub fn render(metrics: &Metrics, process: &ProcessStats, sessions: Option<usize>) -> String {
+    let mut out = Exposition::default();
+
+    out.family(
+        "mcp_requests_total",
+        "counter",
+        "JSON-RPC requests handled, by method",
+    );
+    for (method, series) in metrics.methods() {
+        out.sample("mcp_requests_total", &[("method", &method)], count(&series));
+    }
+    out.family(
+        "mcp_request_errors_total",
+        "counter",
+        "JSON-RPC requests that failed, by method",
+    );
+    for (method, series) in metrics.methods() {
+        out.sample(
+            "mcp_request_errors_total",
+            &[("method", &method)],
+            errors(&series),
+        );
+    }
+    out.histogram_family(
+        "mcp_request_duration_seconds",
+        "JSON-RPC request latency, by method",
+        "method",
+        &metrics.methods(),
+    );
+
+    out.family(
+        "mcp_tool_calls_total",
+        "counter",
+        "Tool calls handled, by tool",
+    );
+    for (tool, series) in metrics.tools() {
+        out.sample("mcp_tool_calls_total", &[("tool", &tool)], count(&series));
+    }
+    out.family(
+        "mcp_tool_call_errors_total",
+        "counter",
+        "Tool calls that failed or returned an error result, by tool",
+    );
+    for (tool, series) in metrics.tools() {
+        out.sample(
+            "mcp_tool_call_errors_total",
+            &[("tool", &tool)],
+            errors(&series),
+        );
+    }
+    out.histogram_family(
+        "mcp_tool_call_duration_seconds",
+        "Tool call latency, by tool",
+        "tool",
+        &metrics.tools(),
+    );
+
+    out.family(
+        "mcp_errors_total",
+        "counter",
+        "JSON-RPC error responses, by error code",
+    );
+    for (code, total) in metrics.errors() {
+        out.sample(
+            "mcp_errors_total",
+            &[("code", &code.to_string())],
+            total as f64,
+        );
+    }
+
+    out.gauge(
+        "mcp_requests_in_flight",
+        "JSON-RPC requests currently being handled",
+        metrics.in_flight() as f64,
+    );
+    out.gauge(
+        "mcp_sse_connections",
+        "Open Server-Sent Events streams",
+        metrics.sse_connections() as f64,
+    );
+    out.counter(
+        "mcp_sse_connections_total",
+        "Server-Sent Events streams opened",
+        metrics.sse_connections_total() as f64,
+    );
+    out.gauge(
+        "mcp_websocket_connections",
+        "Open WebSocket connections",
+        metrics.websocket_connections() as f64,
+    );
+    out.counter(
+        "mcp_websocket_connections_total",
+        "WebSocket connections accepted",
+        metrics.websocket_connections_total() as f64,
+    );
+    out.counter(
+        "mcp_notifications_sent_total",
+        "Notifications delivered to Server-Sent Events streams and WebSocket connections",
+        metrics.notifications() as f64,
+    );
+    if let Some(sessions) = sessions {
+        out.gauge(
+            "mcp_sessions",
+            "Live Streamable HTTP sessions",
+            sessions as f64,
+        );
+    }
+    out.gauge(
+        "mcp_uptime_seconds",
+        "Seconds since the server started",
+        metrics.uptime().as_secs_f64(),
+    );
+
+    let process_gauges = [
+        (
+            "process_resident_memory_bytes",
+            "gauge",
+            "Resident memory size in bytes",
+            process.resident_memory_bytes.map(|bytes| bytes as f64),
+        ),
+        (
+            "process_virtual_memory_bytes",
+            "gauge",
+            "Virtual memory size in bytes",
+            process.virtual_memory_bytes.map(|bytes| bytes as f64),
+        ),
+        (
+            "process_cpu_seconds_total",
+            "counter",
+            "Total user and system CPU time spent in seconds",
+            process.cpu_seconds,
+        ),
+        (
+            "process_threads",
+            "gauge",
+            "Number of OS threads in the process",
+            process.threads.map(|threads| threads as f64),
+        ),
+        (
+            "process_open_fds",
+            "gauge",
+            "Number of open file descriptors",
+            process.open_fds.map(|fds| fds as f64),
+        ),
+        (
+            "process_start_time_seconds",
+            "gauge",
+            "Start time of the process since the Unix epoch in seconds",
+            process.start_time_seconds,
+        ),
+    ];
+    for (name, kind, help, value) in process_gauges {
+        if let Some(value) = value {
+            out.family(name, kind, help);
+            out.sample(name, &[], value);
+        }
+    }
+
+    out.text
+}
+
+fn count(series: &Series) -> f64 {
+    series.count.load(Ordering::Relaxed) as f64
+}
+
+fn errors(series: &Series) -> f64 {
+    series.errors.load(Ordering::Relaxed) as f64
+}
+
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {name} {help}");
+        let _ = writeln!(self.text, "# TYPE {name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{}\"", escape(value)))
+                .collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", format_value(value));
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "counter", help);
+        self.sample(name, &[], value);
+    }
+
+    fn histogram_family(
+        &mut self,
+        name: &str,
+        help: &str,
+        label: &str,
+        series: &[(String, Arc<Series>)],
+    ) {
+        self.family(name, "histogram", help);
+        for (value, series) in series {
+            self.histogram(name, label, value, &series.latency);
+        }
+    }
+
+    fn histogram(&mut self, name: &str, label: &str, value: &str, histogram: &Histogram) {
+        let bucket = format!("{name}_bucket");
+        let bounds = LATENCY_BUCKETS_MS
+            .iter()
+            .map(|millis| format_value(millis / 1000.0))
+            .chain(std::iter::once("+Inf".to_string()));
+        for (bound, total) in bounds.zip(histogram.cumulative()) {
+            self.sample(&bucket, &[(label, value), ("le", &bound)], total as f64);
+        }
+        self.sample(
+            &format!("{name}_sum"),
+            &[(label, value)],
+            histogram.sum_seconds(),
+        );
+        self.sample(
+            &format!("{name}_count"),
+            &[(label, value)],
+            histogram.count() as f64,
+        );
+    }
+}
+
+/// Escape a label value: backslash, double quote and newline
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_finite() && value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Outcome;
+
+    #[test]
+    fn test_exposition_covers_requests_tools_and_process() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.begin().finish(
+            "tools/call",
+            Some("http_calculator"),
+            Outcome::ToolError,
+            true,
+        );
+        metrics.record_error(-32700);
+        let process = ProcessStats {
+            resident_memory_bytes: Some(4096),
+            cpu_seconds: Some(1.5),
+            ..ProcessStats::default()
+        };
+
+        let websocket = metrics.websocket_connected();
+        let text = render(&metrics, &process, Some(2));
+        assert!(text.contains("# TYPE mcp_requests_total counter\n"));
+        assert!(text.contains("mcp_requests_total{method=\"tools/call\"} 1\n"));
+        assert!(text.contains("mcp_tool_call_errors_total{tool=\"http_calculator\"} 1\n"));
+        assert!(text.contains(
+            "mcp_request_duration_seconds_bucket{method=\"tools/call\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(
+            text.contains(
+                "mcp_request_duration_seconds_bucket{method=\"tools/call\",le=\"0.0025\"}"
+            )
+        );
+        assert!(text.contains("mcp_errors_total{code=\"-32700\"} 1\n"));
+        assert!(text.contains("mcp_requests_in_flight 0\n"));
+        assert!(text.contains("mcp_sessions 2\n"));
+        assert!(text.contains("# TYPE mcp_websocket_connections gauge\n"));
+        assert!(text.contains("mcp_websocket_connections 1\n"));
+        drop(websocket);
+        let text = render(&metrics, &process, None);
+        assert!(text.contains("mcp_websocket_connections 0\n"));
+        assert!(text.contains("mcp_websocket_connections_total 1\n"));
+        assert!(!text.contains("mcp_sessions"));
+        assert!(text.contains("process_resident_memory_bytes 4096\n"));
+        assert!(text.contains("process_cpu_seconds_total 1.5\n"));
+        assert!(!text.contains("process_threads"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        assert_eq!(format_value(10.0), "10");
+        assert_eq!(format_value(0.005), "0.005");
+    }
+}
@@ -0,0 +1,211 @@
+//! Replay of missed Server-Sent Events.
+//!
+//! Every event sent to an SSE stream carries an id of the form
+//! `<epoch>-<sequence>`: the epoch is fixed per server process and the
+//! sequence grows by one per event, so a reconnecting client's
+//! `Last-Event-ID` tells exactly which events it missed, and ids from before
+//! a restart are recognized as stale. Each session keeps the most recent
+//! events in a bounded [`ReplayBuffer`] for that purpose.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default number of events kept per buffer
+pub const DEFAULT_REPLAY_EVENTS: usize = 256;
+/// Default time events stay replayable
+pub const DEFAULT_REPLAY_RETENTION: Duration = Duration::from_secs(5 * 60);
+
+/// A notification as delivered on SSE streams
+#[derive(Debug)]
+pub struct Event {
+    pub sequence: u64,
+    pub data: Arc<str>,
+    /// The only session the event is for; `None` sends it to every stream
+    pub session: Option<String>,
+    at: Instant,
+}
+
+impl Event {
+    pub fn new(sequence: u64, data: Arc<str>) -> Self {
+        Self {
+            sequence,
+            data,
+            session: None,
+            at: Instant::now(),
+        }
+    }
+
+    /// Address the event to `session` alone
+    pub fn for_session(mut self, session: Option<&str>) -> Self {
+        self.session = session.map(str::to_string);
+        self
+    }
+
+    /// Whether a stream of `session` (or a sessionless one) should get the event
+    pub fn is_for(&self, session: Option<&str>) -> bool {
+        self.session.is_none() || self.session.as_deref() == session
+    }
+}
+
+/// Issues and parses event ids for one server process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventIds {
+    epoch: u64,
+}
+
+impl EventIds {
+    pub fn new(epoch: u64) -> Self {
+        Self { epoch }
+    }
+
+    pub fn format(&self, sequence: u64) -> String {
+        format!("{}-{sequence}", self.epoch)
+    }
+
+    /// Sequence of a `Last-Event-ID` issued by this process; `None` for foreign or stale ids
+    pub fn parse(&self, id: &str) -> Option<u64> {
+        let (epoch, sequence) = id.trim().split_once('-')?;
+        if epoch.parse::<u64>().ok()? != self.epoch {
+            return None;
+        }
+        sequence.parse().ok()
+    }
+}
+
+/// Size and age limits of a replay buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayLimits {
+    pub events: usize,
+    pub retention: Duration,
+}
+
+impl Default for ReplayLimits {
+    fn default() -> Self {
+        Self {
+            events: DEFAULT_REPLAY_EVENTS,
+            retention: DEFAULT_REPLAY_RETENTION,
+        }
+    }
+}
+
+/// Events to send a reconnecting client
+#[derive(Debug)]
+pub struct Replay {
+    pub events: Vec<Arc<Event>>,
+    /// Some events after the client's last one were already dropped from the buffer
+    pub incomplete: bool,
+}
+
+/// The most recent events, bounded by count and age
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    events: VecDeque<Arc<Event>>,
+    limits: ReplayLimits,
+    /// Sequence of the newest event ever pushed, even if since dropped
+    latest: u64,
+}
+
+impl ReplayBuffer {
+    pub fn new(limits: ReplayLimits) -> Self {
+        Self {
+            events: VecDeque::new(),
+            limits,
+            latest: 0,
+        }
+    }
+
+    pub fn push(&mut self, event: Arc<Event>) {
+        self.latest = self.latest.max(event.sequence);
+        self.events.push_back(event);
+        while self.events.len() > self.limits.events {
+            self.events.pop_front();
+        }
+        self.expire();
+    }
+
+    /// Events newer than `last_sequence`, oldest first
+    pub fn since(&mut self, last_sequence: u64) -> Replay {
+        self.expire();
+        let events: Vec<Arc<Event>> = self
+            .events
+            .iter()
+            .filter(|event| event.sequence > last_sequence)
+            .cloned()
+            .collect();
+        let first_kept = events
+            .first()
+            .map_or(self.latest + 1, |event| event.sequence);
+        Replay {
+            incomplete: first_kept > last_sequence + 1 && last_sequence < self.latest,
+            events,
+        }
+    }
+
+    fn expire(&mut self) {
+        let retention = self.limits.retention;
+        while self
+            .events
+            .front()
+            .is_some_and(|event| event.at.elapsed() > retention)
+        {
+            self.events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(sequence: u64) -> Arc<Event> {
+        Arc::new(Event::new(sequence, format!("event {sequence}").into()))
+    }
+
+    #[test]
+    fn test_ids_are_scoped_to_the_process() {
+        let ids = EventIds::new(1700000000000);
+        assert_eq!(ids.format(42), "1700000000000-42");
+        assert_eq!(ids.parse("1700000000000-42"), Some(42));
+        assert_eq!(ids.parse("1600000000000-42"), None);
+        assert_eq!(ids.parse("42"), None);
+    }
+
+    #[test]
+    fn test_missed_events_are_replayed_within_limits() {
+        let mut buffer = ReplayBuffer::new(ReplayLimits {
+            events: 3,
+            retention: DEFAULT_REPLAY_RETENTION,
+        });
+        for sequence in 1..=5 {
+            buffer.push(event(sequence));
+        }
+
+        let replay = buffer.since(3);
+        let sequences: Vec<u64> = replay.events.iter().map(|event| event.sequence).collect();
+        assert_eq!(sequences, vec![4, 5]);
+        assert!(!replay.incomplete);
+
+        // Event 2 was dropped to make room
+        let replay = buffer.since(1);
+        assert_eq!(replay.events.len(), 3);
+        assert!(replay.incomplete);
+
+        let replay = buffer.since(5);
+        assert!(replay.events.is_empty());
+        assert!(!replay.incomplete);
+    }
+
+    #[test]
+    fn test_old_events_expire() {
+        let mut buffer = ReplayBuffer::new(ReplayLimits {
+            events: 10,
+            retention: Duration::ZERO,
+        });
+        buffer.push(event(1));
+        std::thread::sleep(Duration::from_millis(2));
+        let replay = buffer.since(0);
+        assert!(replay.events.is_empty());
+        assert!(replay.incomplete);
+    }
+}
@@ -0,0 +1,343 @@
+//! MCP sessions of the Streamable HTTP transport.
+//!
+//! A session is issued when a client initializes and is identified by the
+//! `Mcp-Session-Id` header on every later request. Each session buffers the
+//! events sent to it so a reconnecting stream can replay what it missed.
+//! Deleting a session ends its server-to-client stream; sessions left idle
+//! are forgotten after [`SESSION_IDLE_TIMEOUT`], and with them the resources
+//! they subscribed to. With authentication on, a
+//! session belongs to the principal that initialized it and is unknown to
+//! everyone else.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use access_control::auth::Principal;
+
+use crate::replay::{Event, Replay, ReplayBuffer, ReplayLimits};
+
+/// Header carrying the session id
+pub const SESSION_HEADER: &str = "mcp-session-id";
+/// Sessions without any request or open stream for this long are dropped
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+struct Session {
+    /// Principal that initialized the session
+    principal: Option<Principal>,
+    last_seen: Instant,
+    /// Whether a GET stream is open for the session
+    streaming: bool,
+    /// Flipped to `true` when the session is terminated
+    terminated: watch::Sender<bool>,
+    replay: ReplayBuffer,
+    /// URIs of the resources the session subscribed to
+    subscriptions: HashSet<String>,
+}
+
+/// Why a session could not be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionError {
+    /// Never issued, terminated or expired; clients must initialize again
+    NotFound,
+    /// The session already has a server-to-client stream open
+    StreamOpen,
+}
+
+/// Live sessions by id
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+    idle_timeout: Duration,
+    replay_limits: ReplayLimits,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new(SESSION_IDLE_TIMEOUT, ReplayLimits::default())
+    }
+}
+
+impl Sessions {
+    pub fn new(idle_timeout: Duration, replay_limits: ReplayLimits) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+            replay_limits,
+        }
+    }
+
+    /// Issue a new session id owned by `principal`
+    pub fn create(&self, principal: Option<&Principal>) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (terminated, _) = watch::channel(false);
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+        sessions.insert(
+            id.clone(),
+            Session {
+                principal: principal.cloned(),
+                last_seen: Instant::now(),
+                streaming: false,
+                terminated,
+                replay: ReplayBuffer::new(self.replay_limits),
+                subscriptions: HashSet::new(),
+            },
+        );
+        id
+    }
+
+    /// Check that `id` is a live session of `owner` and mark it as used
+    pub fn touch(&self, id: &str, owner: Option<&str>) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+        let session = owned(&mut sessions, id, owner)?;
+        session.last_seen = Instant::now();
+        Ok(())
+    }
+
+    /// Claim the session's server-to-client stream
+    ///
+    /// The receiver changes when the session is terminated; hand the stream
+    /// back with [`Sessions::close_stream`].
+    pub fn open_stream(
+        &self,
+        id: &str,
+        owner: Option<&str>,
+    ) -> Result<watch::Receiver<bool>, SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+        let session = owned(&mut sessions, id, owner)?;
+        if session.streaming {
+            return Err(SessionError::StreamOpen);
+        }
+        session.streaming = true;
+        session.last_seen = Instant::now();
+        Ok(session.terminated.subscribe())
+    }
+
+    pub fn close_stream(&self, id: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(id) {
+            session.streaming = false;
+            session.last_seen = Instant::now();
+        }
+    }
+
+    /// Buffer an event for the sessions it is for, whether or not their stream is connected
+    pub fn record(&self, event: &Arc<Event>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        match &event.session {
+            Some(id) => {
+                if let Some(session) = sessions.get_mut(id) {
+                    session.replay.push(event.clone());
+                }
+            }
+            None => {
+                for session in sessions.values_mut() {
+                    session.replay.push(event.clone());
+                }
+            }
+        }
+    }
+
+    /// Subscribe session `id` of `owner` to updates of the resource `uri`
+    pub fn subscribe(&self, id: &str, owner: Option<&str>, uri: &str) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        owned(&mut sessions, id, owner)?
+            .subscriptions
+            .insert(uri.to_string());
+        Ok(())
+    }
+
+    pub fn unsubscribe(
+        &self,
+        id: &str,
+        owner: Option<&str>,
+        uri: &str,
+    ) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        owned(&mut sessions, id, owner)?.subscriptions.remove(uri);
+        Ok(())
+    }
+
+    /// Ids of the sessions subscribed to `uri`
+    pub fn subscribers(&self, uri: &str) -> Vec<String> {
+        self.subscribed(uri).into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Ids and principals of the sessions subscribed to `uri`
+    pub fn subscribed(&self, uri: &str) -> Vec<(String, Option<Principal>)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+        sessions
+            .iter()
+            .filter(|(_, session)| session.subscriptions.contains(uri))
+            .map(|(id, session)| (id.clone(), session.principal.clone()))
+            .collect()
+    }
+
+    /// URIs at least one live session is subscribed to
+    pub fn subscriptions(&self) -> HashSet<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+        sessions
+            .values()
+            .flat_map(|session| session.subscriptions.iter().cloned())
+            .collect()
+    }
+
+    /// Buffered events of session `id` sent after `last_sequence`
+    pub fn replay(&self, id: &str, last_sequence: u64) -> Result<Replay, SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id).ok_or(SessionError::NotFound)?;
+        Ok(session.replay.since(last_sequence))
+    }
+
+    /// End a session of `owner`, closing its stream if one is open
+    pub fn terminate(&self, id: &str, owner: Option<&str>) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        owned(&mut sessions, id, owner)?;
+        let session = sessions.remove(id).ok_or(SessionError::NotFound)?;
+        let _ = session.terminated.send(true);
+        Ok(())
+    }
+
+    /// Number of live sessions
+    pub fn count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    fn expire(&self, sessions: &mut HashMap<String, Session>) {
+        let idle_timeout = self.idle_timeout;
+        sessions
+            .retain(|_, session| session.streaming || session.last_seen.elapsed() < idle_timeout);
+    }
+}
+
+/// Session `id` if it belongs to `owner`; someone else's session is reported as not found
+fn owned<'a>(
+    sessions: &'a mut HashMap<String, Session>,
+    id: &str,
+    owner: Option<&str>,
+) -> Result<&'a mut Session, SessionError> {
+    sessions
+        .get_mut(id)
+        .filter(|session| session.principal.as_ref().map(|p| p.id.as_str()) == owner)
+        .ok_or(SessionError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use access_control::auth::AuthMethod;
+
+    fn principal(id: &str) -> Principal {
+        Principal {
+            id: id.to_string(),
+            roles: Vec::new(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_sessions_are_checked_and_terminated() {
+        let sessions = Sessions::default();
+        let id = sessions.create(None);
+        assert_eq!(sessions.touch(&id, None), Ok(()));
+        assert_eq!(sessions.touch("unknown", None), Err(SessionError::NotFound));
+
+        let stream = sessions.open_stream(&id, None).unwrap();
+        assert_eq!(
+            sessions.open_stream(&id, None).unwrap_err(),
+            SessionError::StreamOpen
+        );
+
+        sessions.terminate(&id, None).unwrap();
+        assert!(*stream.borrow());
+        assert_eq!(sessions.touch(&id, None), Err(SessionError::NotFound));
+        assert_eq!(sessions.terminate(&id, None), Err(SessionError::NotFound));
+    }
+
+    #[test]
+    fn test_sessions_belong_to_their_principal() {
+        let sessions = Sessions::default();
+        let id = sessions.create(Some(&principal("alice")));
+        assert_eq!(sessions.touch(&id, Some("alice")), Ok(()));
+        assert_eq!(
+            sessions.touch(&id, Some("mallory")),
+            Err(SessionError::NotFound)
+        );
+        assert_eq!(sessions.touch(&id, None), Err(SessionError::NotFound));
+        assert!(sessions.open_stream(&id, Some("mallory")).is_err());
+        assert_eq!(
+            sessions.terminate(&id, Some("mallory")),
+            Err(SessionError::NotFound)
+        );
+        assert_eq!(sessions.terminate(&id, Some("alice")), Ok(()));
+    }
+
+    #[test]
+    fn test_idle_sessions_expire_unless_streaming() {
+        let sessions = Sessions::new(Duration::from_millis(20), ReplayLimits::default());
+        let idle = sessions.create(None);
+        let streaming = sessions.create(None);
+        let _stream = sessions.open_stream(&streaming, None).unwrap();
+        sessions.subscribe(&idle, None, "a://x").unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(sessions.subscriptions().is_empty());
+        assert_eq!(sessions.touch(&idle, None), Err(SessionError::NotFound));
+        assert_eq!(sessions.touch(&streaming, None), Ok(()));
+        assert_eq!(sessions.count(), 1);
+    }
+
+    #[test]
+    fn test_events_are_buffered_per_session() {
+        let sessions = Sessions::default();
+        let early = sessions.create(None);
+        sessions.record(&Arc::new(Event::new(1, "first".into())));
+        let late = sessions.create(None);
+        sessions.record(&Arc::new(Event::new(2, "second".into())));
+
+        assert_eq!(sessions.replay(&early, 0).unwrap().events.len(), 2);
+        let replay = sessions.replay(&late, 1).unwrap();
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(&*replay.events[0].data, "second");
+        assert!(sessions.replay("unknown", 0).is_err());
+
+        // Events addressed to a session are buffered for that session only
+        sessions.record(&Arc::new(
+            Event::new(3, "third".into()).for_session(Some(&late)),
+        ));
+        assert_eq!(sessions.replay(&early, 2).unwrap().events.len(), 0);
+        assert_eq!(sessions.replay(&late, 2).unwrap().events.len(), 1);
+    }
+
+    #[test]
+    fn test_subscriptions_are_kept_per_session() {
+        let sessions = Sessions::default();
+        let alice = sessions.create(Some(&principal("alice")));
+        let bob = sessions.create(Some(&principal("bob")));
+        assert_eq!(sessions.subscribe(&alice, Some("alice"), "a://x"), Ok(()));
+        assert_eq!(sessions.subscribe(&bob, Some("bob"), "a://y"), Ok(()));
+        assert_eq!(
+            sessions.subscribe(&alice, Some("bob"), "a://y"),
+            Err(SessionError::NotFound)
+        );
+        assert_eq!(sessions.subscribers("a://x"), vec![alice.clone()]);
+        assert_eq!(sessions.subscribers("a://y"), vec![bob.clone()]);
+        assert_eq!(
+            sessions.subscribed("a://x"),
+            vec![(alice.clone(), Some(principal("alice")))]
+        );
+        assert_eq!(sessions.subscriptions().len(), 2);
+
+        sessions
+            .unsubscribe(&alice, Some("alice"), "a://x")
+            .unwrap();
+        assert!(sessions.subscribers("a://x").is_empty());
+        sessions.terminate(&bob, Some("bob")).unwrap();
+        assert!(sessions.subscribers("a://y").is_empty());
+        assert!(sessions.subscriptions().is_empty());
+    }
+}
@@ -0,0 +1,914 @@
+//! MCP over WebSocket, one JSON-RPC message per text frame.
+//!
+//! The opening handshake is refused with 403 when its `Host` or `Origin` is
+//! not allowed by the [`OriginGuard`], as on the HTTP transport, so a page
+//! that rebinds its domain to the server cannot open a connection.
+//! Credentials are checked once, during the handshake too: a request
+//! without a valid API key or bearer token is answered with 401 (or 403 for
+//! a principal lacking the required roles) and never upgraded. Messages on
+//! an accepted connection are dispatched to the [`McpServer`] with the
+//! connection's principal in scope for the handlers, once the [`PolicyEngine`]
+//! has allowed them; tools the principal may not call are also left out of
+//! `tools/list`. A JWT that expires while the connection is open ends it with
+//! a JSON-RPC error and a policy close. Messages over a [`RateLimiter`] limit
+//! get a JSON-RPC error carrying the seconds to wait in `data.retryAfter`.
+//!
+//! Notifications sent through the [`Notifier`] go to every open connection,
+//! interleaved with the replies on the same socket, except resource updates:
+//! subscriptions are kept per connection and dropped when it closes, and an
+//! update is only sent to the connections subscribed to its resource whose
+//! principal the policy lets read it.
+//!
+//! A plain `GET /metrics` on the same port, with the same credentials, is
+//! answered with the Prometheus exposition of the connection and request
+//! [`Metrics`] instead of being upgraded.
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::{
+    Message,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{HeaderValue, StatusCode, header},
+    protocol::{CloseFrame, frame::coding::CloseCode},
+};
+
+use access_control::auth::{self, API_KEY_HEADER, AuthError, Authenticator, Principal};
+use access_control::gate::{self, Gate, Refusal};
+use access_control::jsonrpc::{INVALID_REQUEST, PARSE_ERROR, error_response};
+use access_control::limits::{RATE_LIMITED, RateLimiter};
+use access_control::policy::PolicyEngine;
+use prism_mcp_rs::{
+    protocol::{
+        messages::{JsonRpcNotification, JsonRpcRequest},
+        methods::RESOURCES_UPDATED,
+    },
+    server::McpServer,
+};
+
+use crate::http;
+use crate::metrics::{Metrics, Outcome};
+use crate::origin::{OriginError, OriginGuard};
+use crate::process::ProcessStats;
+use crate::prometheus;
+
+/// Notifications buffered per connection before a slow client starts missing them
+const NOTIFICATION_BUFFER: usize = 256;
+
+/// Times the start of a request is peeked at while it is still arriving
+const PEEK_ATTEMPTS: usize = 5;
+
+const SUBSCRIBE: &str = "resources/subscribe";
+const UNSUBSCRIBE: &str = "resources/unsubscribe";
+
+/// A notification on its way to the open connections
+struct Broadcast {
+    /// Resource of an update, which only its subscribers get
+    resource: Option<String>,
+    message: Arc<str>,
+}
+
+/// Sends server-to-client notifications to every open connection
+#[derive(Clone)]
+pub struct Notifier {
+    notifications: broadcast::Sender<Arc<Broadcast>>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_BUFFER);
+        Self { notifications }
+    }
+
+    pub fn notify(&self, notification: &JsonRpcNotification) {
+        let resource = notification
+            .params
+            .as_ref()
+            .and_then(|params| params.get("uri"))
+            .and_then(Value::as_str)
+            .filter(|_| notification.method == RESOURCES_UPDATED)
+            .map(str::to_string);
+        match serde_json::to_string(notification) {
+            // Sending only fails when no connection is open
+            Ok(message) => {
+                let _ = self.notifications.send(Arc::new(Broadcast {
+                    resource,
+                    message: message.into(),
+                }));
+            }
+            Err(e) => tracing::warn!("Failed to serialize {}: {e}", notification.method),
+        }
+    }
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serves an [`McpServer`] over WebSocket
+pub struct WebSocketTransport {
+    server: McpServer,
+    notifier: Notifier,
+    metrics: Arc<Metrics>,
+    authenticator: Authenticator,
+    gate: Gate,
+    origins: OriginGuard,
+    /// URIs each open connection is subscribed to, by connection number
+    subscriptions: Mutex<HashMap<u64, HashSet<String>>>,
+    connections: AtomicU64,
+}
+
+impl WebSocketTransport {
+    pub fn new(
+        server: McpServer,
+        notifier: Notifier,
+        metrics: Arc<Metrics>,
+        authenticator: Authenticator,
+        policy: Arc<PolicyEngine>,
+        limiter: Arc<RateLimiter>,
+        origins: OriginGuard,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            server,
+            notifier,
+            metrics,
+            authenticator,
+            gate: Gate::new(policy, limiter),
+            origins,
+            subscriptions: Mutex::new(HashMap::new()),
+            connections: AtomicU64::new(0),
+        })
+    }
+
+    /// URIs at least one open connection is subscribed to
+    pub fn subscriptions(&self) -> HashSet<String> {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.values().flatten().cloned().collect()
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            tokio::spawn(self.clone().connection(stream, peer));
+        }
+    }
+
+    async fn connection(self: Arc<Self>, stream: TcpStream, peer: SocketAddr) {
+        if requests_metrics(&stream).await {
+            self.serve_metrics(stream, peer).await;
+            return;
+        }
+        let mut principal = None;
+        // The error type is tungstenite's handshake response
+        #[allow(clippy::result_large_err)]
+        let check_credentials = |request: &Request, response: Response| {
+            let header = |name: &str| {
+                request
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+            };
+            if let Err(error) = self.check_origin(header("host"), header("origin")) {
+                return Err(refused(&error));
+            }
+            match self
+                .authenticator
+                .authenticate(header("authorization"), header(API_KEY_HEADER))
+            {
+                Ok(authenticated) => {
+                    principal = authenticated;
+                    Ok(response)
+                }
+                Err(error) => {
+                    tracing::debug!("Rejected WebSocket handshake from {peer}: {error}");
+                    self.metrics.record_error(error.code());
+                    Err(rejection(&error))
+                }
+            }
+        };
+        let mut socket = match tokio_tungstenite::accept_hdr_async(stream, check_credentials).await
+        {
+            Ok(socket) => socket,
+            Err(e) => {
+                tracing::debug!("WebSocket handshake with {peer} failed: {e}");
+                return;
+            }
+        };
+        if let Some(principal) = &principal {
+            tracing::debug!("WebSocket connection from {peer} as '{}'", principal.id);
+        }
+        // Anonymous callers are told apart by address for rate limiting
+        let client = principal
+            .as_ref()
+            .map_or_else(|| peer.ip().to_string(), |principal| principal.id.clone());
+        let _connected = self.metrics.websocket_connected();
+        let connection = self.connections.fetch_add(1, Ordering::Relaxed);
+
+        let mut notifications = self.notifier.notifications.subscribe();
+        let expired = || principal.as_ref().is_some_and(Principal::is_expired);
+        loop {
+            tokio::select! {
+                message = socket.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Binary(data))) => {
+                            String::from_utf8_lossy(&data).into_owned()
+                        }
+                        Some(Ok(Message::Close(_))) | None => break,
+                        // Pings are answered by tungstenite itself
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => {
+                            tracing::debug!("WebSocket connection from {peer} failed: {e}");
+                            break;
+                        }
+                    };
+
+                    if expired() {
+                        self.metrics.record_error(AuthError::Expired.code());
+                        let id = serde_json::from_str::<Value>(&text)
+                            .ok()
+                            .and_then(|message| message.get("id").cloned())
+                            .unwrap_or(Value::Null);
+                        let reply = AuthError::Expired.to_response(id).to_string();
+                        let _ = socket.send(Message::Text(reply)).await;
+                        let _ = socket.send(Message::Close(Some(expired_close()))).await;
+                        break;
+                    }
+
+                    let dispatch = self.dispatch(&text, principal.clone(), &client, connection);
+                    if let Some(reply) = dispatch.await {
+                        if socket.send(Message::Text(reply)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                notification = notifications.recv() => match notification {
+                    Ok(_) if expired() => {
+                        let _ = socket.send(Message::Close(Some(expired_close()))).await;
+                        break;
+                    }
+                    Ok(notification) if !self.delivers(connection, principal.as_ref(), &notification) => {}
+                    Ok(notification) => {
+                        if socket.send(Message::Text(notification.message.to_string())).await.is_err() {
+                            break;
+                        }
+                        self.metrics.notification_sent();
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("WebSocket client {peer} missed {missed} notifications");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+        self.subscriptions.lock().unwrap().remove(&connection);
+    }
+
+    /// Whether `notification` is for the connection: updates go only to
+    /// subscribers of the resource that may still read it
+    fn delivers(
+        &self,
+        connection: u64,
+        principal: Option<&Principal>,
+        notification: &Broadcast,
+    ) -> bool {
+        let Some(uri) = &notification.resource else {
+            return true;
+        };
+        let subscribed = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .get(&connection)
+            .is_some_and(|uris| uris.contains(uri));
+        subscribed && self.gate.allows_resource(principal, uri)
+    }
+
+    /// Answer a plain HTTP `GET /metrics`, authenticated like a handshake
+    async fn serve_metrics(&self, mut stream: TcpStream, peer: SocketAddr) {
+        let (read, mut write) = stream.split();
+        let request = match http::read_request(&mut BufReader::new(read)).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::debug!("Bad metrics request from {peer}: {e:?}");
+                return;
+            }
+        };
+        if let Err(error) = self.check_origin(request.header("host"), request.header("origin")) {
+            let body = error_response(Value::Null, auth::FORBIDDEN, &error.to_string());
+            let _ = http::Response::json(error.status(), &body)
+                .write_to(&mut write, true)
+                .await;
+            return;
+        }
+        let response = match self.authenticator.authenticate(
+            request.header("authorization"),
+            request.header(API_KEY_HEADER),
+        ) {
+            Ok(_) => {
+                let process = ProcessStats::read();
+                http::Response::new(200)
+                    .header("Content-Type", prometheus::CONTENT_TYPE)
+                    .body(prometheus::render(&self.metrics, &process, None).into_bytes())
+            }
+            Err(error) => {
+                tracing::debug!("Rejected metrics request from {peer}: {error}");
+                self.metrics.record_error(error.code());
+                http::Response::json(error.status(), &error.to_response(Value::Null))
+                    .header("WWW-Authenticate", error.challenge())
+            }
+        };
+        let _ = response.write_to(&mut write, true).await;
+    }
+
+    /// Refuse connections for a host or from an origin the server does not serve
+    fn check_origin(&self, host: Option<&str>, origin: Option<&str>) -> Result<(), OriginError> {
+        let result = self.origins.check_headers(host, origin);
+        if let Err(error) = &result {
+            tracing::warn!("Refused WebSocket handshake: {error}");
+            self.metrics.record_error(auth::FORBIDDEN);
+        }
+        result
+    }
+
+    /// Handle one JSON-RPC message; notifications get no reply and batches are refused
+    async fn dispatch(
+        &self,
+        text: &str,
+        principal: Option<Principal>,
+        client: &str,
+        connection: u64,
+    ) -> Option<String> {
+        let message: Value = match serde_json::from_str(text) {
+            Ok(Value::Array(_)) => {
+                self.metrics.record_error(INVALID_REQUEST);
+                return Some(
+                    error_response(Value::Null, INVALID_REQUEST, "Batches are not supported")
+                        .to_string(),
+                );
+            }
+            Ok(message) => message,
+            Err(e) => {
+                self.metrics.record_error(PARSE_ERROR);
+                return Some(
+                    error_response(Value::Null, PARSE_ERROR, &format!("Parse error: {e}"))
+                        .to_string(),
+                );
+            }
+        };
+        let Some(id) = message.get("id").cloned() else {
+            // Without a method it is a response, and the server sends no requests
+            message.get("method")?;
+            return match serde_json::from_value::<JsonRpcNotification>(message) {
+                Ok(notification) => {
+                    self.gate
+                        .deliver(&self.server, notification, principal.as_ref())
+                        .await;
+                    None
+                }
+                Err(e) => {
+                    self.metrics.record_error(INVALID_REQUEST);
+                    Some(
+                        error_response(
+                            Value::Null,
+                            INVALID_REQUEST,
+                            &format!("Invalid notification: {e}"),
+                        )
+                        .to_string(),
+                    )
+                }
+            };
+        };
+        let request: JsonRpcRequest = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => {
+                self.metrics.record_error(INVALID_REQUEST);
+                return Some(
+                    error_response(id, INVALID_REQUEST, &format!("Invalid request: {e}"))
+                        .to_string(),
+                );
+            }
+        };
+
+        let method = request.method.clone();
+        let params = request.params.clone().unwrap_or(Value::Null);
+        let tool = gate::tool_name(&method, &params);
+        let timer = self.metrics.begin();
+        let dispatch = self
+            .gate
+            .dispatch(&self.server, request, principal.as_ref(), client);
+        let (response, outcome) = match dispatch.await {
+            Ok(response) => {
+                let outcome = Outcome::of(&response);
+                (response, outcome)
+            }
+            Err(Refusal::Limited(limited)) => {
+                self.metrics.record_error(RATE_LIMITED);
+                return Some(limited.to_response(id).to_string());
+            }
+            Err(denied) => (denied.to_response(id), Outcome::Error(denied.code())),
+        };
+        timer.finish(&method, tool, outcome, true);
+        let uri = params.get("uri").and_then(Value::as_str);
+        if let (SUBSCRIBE | UNSUBSCRIBE, Some(uri), Outcome::Success) =
+            (method.as_str(), uri, outcome)
+        {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            let uris = subscriptions.entry(connection).or_default();
+            if method == SUBSCRIBE {
+                uris.insert(uri.to_string());
+            } else {
+                uris.remove(uri);
+            }
+        }
+        Some(response.to_string())
+    }
+}
+
+/// Whether the connection opens with a plain `GET /metrics` rather than a handshake
+///
+/// Only peeks, so a handshake is left untouched for tungstenite to read.
+async fn requests_metrics(stream: &TcpStream) -> bool {
+    let expected = format!("GET {}", prometheus::PATH);
+    let mut start = [0; 16];
+    for _ in 0..PEEK_ATTEMPTS {
+        let read = match stream.peek(&mut start).await {
+            Ok(0) | Err(_) => return false,
+            Ok(read) => read,
+        };
+        if read > expected.len() {
+            return start.starts_with(expected.as_bytes())
+                && matches!(start[expected.len()], b' ' | b'?');
+        }
+        if !expected.as_bytes().starts_with(&start[..read]) {
+            return false;
+        }
+        // The request line is still arriving
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+/// Close frame for a connection whose credentials expired
+fn expired_close() -> CloseFrame<'static> {
+    CloseFrame {
+        code: CloseCode::Policy,
+        reason: "credentials expired".into(),
+    }
+}
+
+/// Handshake answer for a request whose credentials were refused
+/// 400 or 403 with a JSON-RPC error body
+fn refused(error: &OriginError) -> ErrorResponse {
+    let body = error_response(Value::Null, auth::FORBIDDEN, &error.to_string());
+    let mut response = ErrorResponse::new(Some(body.to_string()));
+    *response.status_mut() = match error.status() {
+        400 => StatusCode::BAD_REQUEST,
+        _ => StatusCode::FORBIDDEN,
+    };
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+fn rejection(error: &AuthError) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(error.to_response(Value::Null).to_string()));
+    *response.status_mut() = match error.status() {
+        403 => StatusCode::FORBIDDEN,
+        _ => StatusCode::UNAUTHORIZED,
+    };
+    let headers = response.headers_mut();
+    headers.insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static(error.challenge()),
+    );
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::origin::SecurityConfig;
+    use access_control::auth::{self, ApiKeyConfig, AuthConfig, AuthMethod};
+    use access_control::limits::{LimitsConfig, RATE_LIMITED};
+    use access_control::policy::Policy;
+    use serde_json::json;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+
+    async fn listen() -> (String, TcpListener) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        (url, listener)
+    }
+
+    fn transport_with(policy: Policy, limits: LimitsConfig) -> Arc<WebSocketTransport> {
+        let authenticator = Authenticator::new(&AuthConfig {
+            api_keys: vec![ApiKeyConfig {
+                principal: "ci".to_string(),
+                key: Some("secret".to_string()),
+                key_sha256: None,
+                roles: Vec::new(),
+            }],
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        WebSocketTransport::new(
+            McpServer::new("test".to_string(), "1.0.0".to_string()),
+            Notifier::new(),
+            Arc::new(Metrics::new()),
+            authenticator,
+            Arc::new(PolicyEngine::from(policy)),
+            RateLimiter::new(limits),
+            OriginGuard::new(&SecurityConfig::default(), "127.0.0.1:0".parse().unwrap()),
+        )
+    }
+
+    fn transport() -> Arc<WebSocketTransport> {
+        transport_with(Policy::default(), LimitsConfig::default())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_requires_credentials() {
+        let (url, listener) = listen().await;
+        tokio::spawn(transport().serve(listener));
+
+        match tokio_tungstenite::connect_async(url.as_str()).await {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+                assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+                let body: Value =
+                    serde_json::from_slice(response.body().as_ref().unwrap()).unwrap();
+                assert_eq!(body["error"]["code"], auth::UNAUTHENTICATED);
+            }
+            other => panic!("handshake was not refused: {other:?}"),
+        }
+
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("x-api-key", HeaderValue::from_static("secret"));
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        socket
+            .send(Message::Text("{not json".to_string()))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(reply))) = socket.next().await else {
+            panic!("no reply");
+        };
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_from_rebound_origins_is_refused() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (url, listener) = listen().await;
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(transport().serve(listener));
+
+        for (name, value) in [
+            ("origin", "http://attacker.example"),
+            ("host", "attacker.example"),
+        ] {
+            let mut request = url.as_str().into_client_request().unwrap();
+            let headers = request.headers_mut();
+            headers.insert("x-api-key", HeaderValue::from_static("secret"));
+            headers.insert(name, HeaderValue::from_static(value));
+            match tokio_tungstenite::connect_async(request).await {
+                Err(tungstenite::Error::Http(response)) => {
+                    assert_eq!(response.status(), StatusCode::FORBIDDEN, "{name}");
+                    let body: Value =
+                        serde_json::from_slice(response.body().as_ref().unwrap()).unwrap();
+                    assert_eq!(body["error"]["code"], auth::FORBIDDEN);
+                }
+                other => panic!("handshake with {name} {value} was not refused: {other:?}"),
+            }
+        }
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(
+                b"GET /metrics HTTP/1.1\r\nHost: attacker.example\r\nX-API-Key: secret\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+
+        let mut request = url.into_client_request().unwrap();
+        let headers = request.headers_mut();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        headers.insert("origin", HeaderValue::from_static("http://localhost:3000"));
+        assert!(tokio_tungstenite::connect_async(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_notifications_reach_open_connections() {
+        let (url, listener) = listen().await;
+        let transport = transport();
+        tokio::spawn(transport.clone().serve(listener));
+
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("x-api-key", HeaderValue::from_static("secret"));
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        // A reply means the connection is listening for notifications too
+        socket
+            .send(Message::Text(
+                r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(_))) = socket.next().await else {
+            panic!("no reply");
+        };
+
+        let notification = JsonRpcNotification::new(
+            "notifications/message".to_string(),
+            Some(json!({ "level": "info", "data": "hello" })),
+        )
+        .unwrap();
+        transport.notifier.notify(&notification);
+        let Some(Ok(Message::Text(message))) = socket.next().await else {
+            panic!("no notification");
+        };
+        let message: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(message["method"], "notifications/message");
+        assert_eq!(message["params"]["data"], "hello");
+        assert!(message.get("id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_updates_reach_only_subscribed_connections() {
+        let (url, listener) = listen().await;
+        let transport = transport();
+        tokio::spawn(transport.clone().serve(listener));
+        let connect = || async {
+            let mut request = url.as_str().into_client_request().unwrap();
+            request
+                .headers_mut()
+                .insert("x-api-key", HeaderValue::from_static("secret"));
+            tokio_tungstenite::connect_async(request).await.unwrap().0
+        };
+        let mut subscriber = connect().await;
+        let mut other = connect().await;
+        subscriber
+            .send(Message::Text(
+                r#"{"jsonrpc":"2.0","id":1,"method":"resources/subscribe","params":{"uri":"ws://server/status"}}"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(reply))) = subscriber.next().await else {
+            panic!("no reply");
+        };
+        assert!(!reply.contains("error"), "{reply}");
+        assert_eq!(
+            transport.subscriptions(),
+            HashSet::from(["ws://server/status".to_string()])
+        );
+
+        let notify = |method: &str, params: Value| {
+            let notification = JsonRpcNotification::new(method.to_string(), Some(params));
+            transport.notifier.notify(&notification.unwrap());
+        };
+        notify(RESOURCES_UPDATED, json!({ "uri": "ws://server/status" }));
+        notify("notifications/message", json!({ "data": "after" }));
+        let Some(Ok(Message::Text(message))) = subscriber.next().await else {
+            panic!("no update");
+        };
+        assert!(message.contains(RESOURCES_UPDATED), "{message}");
+        // The update was skipped, so the next message is the one sent after it
+        let Some(Ok(Message::Text(message))) = other.next().await else {
+            panic!("no notification");
+        };
+        assert!(message.contains("after"), "{message}");
+
+        subscriber.close(None).await.unwrap();
+        while subscriber.next().await.is_some() {}
+        for _ in 0..50 {
+            if transport.subscriptions().is_empty() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("subscriptions outlived the connection");
+    }
+
+    #[test]
+    fn test_updates_need_the_policy_to_allow_the_resource() {
+        let policy = Policy::from_toml(
+            r#"
+            [[rules]]
+            effect = "deny"
+            principals = ["mallory"]
+            resources = ["db://records/*"]
+            "#,
+        )
+        .unwrap();
+        let transport = transport_with(policy, LimitsConfig::default());
+        transport
+            .subscriptions
+            .lock()
+            .unwrap()
+            .insert(7, HashSet::from(["db://records/a".to_string()]));
+        let update = |uri: &str| Broadcast {
+            resource: Some(uri.to_string()),
+            message: "{}".into(),
+        };
+        let principal = |id: &str| Principal {
+            id: id.to_string(),
+            roles: Vec::new(),
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        };
+
+        let alice = principal("alice");
+        let mallory = principal("mallory");
+        assert!(transport.delivers(7, Some(&alice), &update("db://records/a")));
+        assert!(!transport.delivers(7, Some(&mallory), &update("db://records/a")));
+        assert!(!transport.delivers(7, Some(&alice), &update("db://records/b")));
+        assert!(!transport.delivers(8, Some(&alice), &update("db://records/a")));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_served_beside_the_websocket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (url, listener) = listen().await;
+        let address = listener.local_addr().unwrap();
+        let transport = transport();
+        tokio::spawn(transport.clone().serve(listener));
+        let scrape = |headers: &'static str| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let request = format!("GET /metrics HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = scrape("").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("x-api-key", HeaderValue::from_static("secret"));
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        socket
+            .send(Message::Text(
+                r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(_))) = socket.next().await else {
+            panic!("no reply");
+        };
+
+        let response = scrape("X-API-Key: secret\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(prometheus::CONTENT_TYPE));
+        assert!(response.contains("mcp_websocket_connections 1\n"));
+        assert!(response.contains("mcp_requests_total{method=\"ping\"} 1\n"));
+        assert!(response.contains("mcp_errors_total{code=\"-32001\"} 1\n"));
+        assert!(!response.contains("mcp_sessions"));
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_dispatched() {
+        let transport = transport();
+        assert_eq!(
+            transport
+                .dispatch(
+                    r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+                    None,
+                    "127.0.0.1",
+                    0
+                )
+                .await,
+            None
+        );
+        let reply = transport
+            .dispatch(
+                r#"{"jsonrpc":"2.0","id":3,"method":42}"#,
+                None,
+                "127.0.0.1",
+                0,
+            )
+            .await
+            .unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["id"], 3);
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        let reply = transport
+            .dispatch(
+                r#"[{"jsonrpc":"2.0","id":4,"method":"ping"}]"#,
+                None,
+                "127.0.0.1",
+                0,
+            )
+            .await
+            .unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        assert_eq!(
+            rejection(&AuthError::Forbidden("no".into())).status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policy_is_applied_to_messages() {
+        let transport = transport_with(
+            Policy::from_toml(
+                r#"
+                [[rules]]
+                effect = "allow"
+                roles = ["admin"]
+                tools = ["delete"]
+
+                [[rules]]
+                effect = "deny"
+                tools = ["delete"]
+                "#,
+            )
+            .unwrap(),
+            LimitsConfig::default(),
+        );
+        let call = r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"delete"}}"#;
+
+        let reply = transport
+            .dispatch(call, None, "127.0.0.1", 0)
+            .await
+            .unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["id"], 5);
+        assert_eq!(reply["error"]["code"], auth::FORBIDDEN);
+
+        // Admins get through to the server, which has no such tool
+        let admin = Principal {
+            id: "ops".to_string(),
+            roles: vec!["admin".to_string()],
+            method: AuthMethod::ApiKey,
+            expires_at: None,
+        };
+        let reply = transport
+            .dispatch(call, Some(admin), "ops", 0)
+            .await
+            .unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_ne!(reply["error"]["code"], auth::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_rate_limited() {
+        let limits: LimitsConfig =
+            toml::from_str("[tools.ws_chat]\nrequests_per_second = 1").unwrap();
+        let transport = transport_with(Policy::default(), limits);
+        let call = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"ws_chat"}}"#;
+
+        let reply = transport.dispatch(call, None, "agent", 0).await.unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_ne!(reply["error"]["code"], RATE_LIMITED);
+        let reply = transport.dispatch(call, None, "agent", 0).await.unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["error"]["code"], RATE_LIMITED);
+        assert_eq!(reply["error"]["data"]["retryAfter"], 1);
+        // Other tools and other clients are not held back
+        assert!(
+            !transport
+                .dispatch(
+                    r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#,
+                    None,
+                    "agent",
+                    0
+                )
+                .await
+                .unwrap()
+                .contains("retryAfter")
+        );
+        let reply = transport.dispatch(call, None, "alice", 0).await.unwrap();
+        assert!(!reply.contains("retryAfter"));
+    }
+}
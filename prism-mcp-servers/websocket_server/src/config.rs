@@ -0,0 +1,165 @@
+//! Command-line options and the TOML configuration file.
+//!
+//! Settings are layered: built-in defaults, then the configuration file
+//! given with `--config`, then command-line flags (which can also be set
+//! through the `WEBSOCKET_SERVER_*` environment variables).
+
+use clap::Parser;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use access_control::auth::AuthConfig;
+use access_control::limits::LimitsConfig;
+use access_control::policy::PolicyConfig;
+use network_transport::origin::SecurityConfig;
+
+/// Command-line options
+#[derive(Debug, Default, Parser)]
+#[command(name = "websocket-server", version, about = "MCP WebSocket server")]
+pub struct Cli {
+    /// TOML configuration file
+    #[arg(short, long, env = "WEBSOCKET_SERVER_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on
+    #[arg(long, env = "WEBSOCKET_SERVER_BIND")]
+    pub bind: Option<String>,
+
+    /// TOML or JSON authorization policy file
+    #[arg(long, env = "WEBSOCKET_SERVER_POLICY_FILE")]
+    pub policy_file: Option<PathBuf>,
+}
+
+/// Complete server configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address to listen on; loopback unless set otherwise
+    pub bind: String,
+    /// Accepted `Host` and `Origin` headers
+    pub security: SecurityConfig,
+    /// API keys and JWT verification; off unless configured
+    pub auth: AuthConfig,
+    /// Which principals may use which tools and resources
+    pub policy: PolicyConfig,
+    /// Message rate and in-flight limits; off unless configured
+    pub limits: LimitsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8081".to_string(),
+            security: SecurityConfig::default(),
+            auth: AuthConfig::default(),
+            policy: PolicyConfig::default(),
+            limits: LimitsConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Parse a TOML configuration
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Read the TOML configuration file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        Self::from_toml(&source).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Build the configuration from the command line and the file it names
+    pub fn load(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Override settings with those given on the command line
+    fn apply(&mut self, cli: Cli) {
+        if let Some(bind) = cli.bind {
+            self.bind = bind;
+        }
+        if cli.policy_file.is_some() {
+            self.policy.file = cli.policy_file;
+        }
+    }
+
+    /// Reject settings that cannot work
+    pub fn validate(&self) -> Result<(), String> {
+        self.bind
+            .parse::<SocketAddr>()
+            .map_err(|e| format!("Invalid bind address '{}': {e}", self.bind))?;
+        self.limits.validate()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_with_cli_overrides() {
+        let mut config = Config::from_toml(
+            r#"
+            bind = "127.0.0.1:9000"
+
+            [[auth.api_keys]]
+            principal = "ci"
+            key = "secret"
+            roles = ["admin"]
+
+            [policy]
+            hot_reload = true
+
+            [security]
+            allowed_hosts = ["mcp.example.com"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.auth.api_keys[0].roles, vec!["admin"]);
+        assert_eq!(config.security.allowed_hosts, vec!["mcp.example.com"]);
+
+        let cli = Cli::try_parse_from([
+            "websocket-server",
+            "--bind",
+            "127.0.0.1:9001",
+            "--policy-file",
+            "policy.json",
+        ])
+        .unwrap();
+        config.apply(cli);
+        config.validate().unwrap();
+        assert_eq!(config.bind, "127.0.0.1:9001");
+        assert_eq!(config.policy.file, Some(PathBuf::from("policy.json")));
+        assert!(config.policy.hot_reload);
+        assert_eq!(config.policy.reload_interval_secs, 5);
+    }
+
+    #[test]
+    fn test_binds_to_loopback_unless_configured() {
+        let config = Config::load(Cli::default()).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:8081");
+        let config = Config::from_toml("bind = \"0.0.0.0:8081\"").unwrap();
+        assert_eq!(config.bind, "0.0.0.0:8081");
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(Config::from_toml("unknown = true").is_err());
+        assert!(Config::from_toml("[auth]\nrequired_roles = \"admin\"").is_err());
+        let config = Config::from_toml("bind = \"localhost\"").unwrap();
+        assert!(config.validate().is_err());
+        let config = Config::from_toml("[limits.client]\nburst = 10").unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -15,21 +15,37 @@
 // ! cargo run --example websocket_server --features "websocket-server"
 // ! ```
 
+mod config;
+
 use async_trait::async_trait;
 use serde_json::{Value, json};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
+use access_control::auth::Authenticator;
+use access_control::limits::RateLimiter;
+use access_control::policy::PolicyEngine;
+use access_control::whoami::WhoAmIHandler;
 use prism_mcp_rs::{
     core::{
         error::{McpError, McpResult},
         resource::ResourceHandler,
         tool::ToolHandler,
     },
-    protocol::types::{Content, ResourceContents, ResourceInfo, ToolResult},
+    protocol::{
+        messages::JsonRpcNotification,
+        types::{Content, ResourceContents, ResourceInfo, ToolResult},
+    },
     server::McpServer,
-    transport::websocket::WebSocketServerTransport,
 };
 
+use clap::Parser;
+use config::{Cli, Config};
+use network_transport::metrics::Metrics;
+use network_transport::origin::OriginGuard;
+use network_transport::websocket::{Notifier, WebSocketTransport};
+
 /// WebSocket echo tool with connection info
 struct WebSocketEchoHandler;
 
@@ -91,7 +107,11 @@ impl ToolHandler for WebSocketEchoHandler {
 }
 
 /// Real-time chat tool for WebSocket connections
-struct WebSocketChatHandler;
+///
+/// Messages are also sent to every connected client as a log notification.
+struct WebSocketChatHandler {
+    notifier: Notifier,
+}
 
 #[async_trait]
 impl ToolHandler for WebSocketChatHandler {
@@ -111,6 +131,18 @@ impl ToolHandler for WebSocketChatHandler {
             .and_then(|v| v.as_str())
             .unwrap_or("general");
 
+        match JsonRpcNotification::new(
+            "notifications/message".to_string(),
+            Some(json!({
+                "level": "info",
+                "logger": "ws_chat",
+                "data": { "room": room, "username": username, "message": message }
+            })),
+        ) {
+            Ok(notification) => self.notifier.notify(&notification),
+            Err(e) => tracing::warn!("Failed to build chat notification: {e}"),
+        }
+
         Ok(ToolResult {
             content: vec![Content::text(format!(
                 "Chat: [{room}] {username}: {message}"
@@ -123,7 +155,9 @@ impl ToolHandler for WebSocketChatHandler {
 }
 
 /// WebSocket connection status resource
-struct WebSocketStatusHandler;
+struct WebSocketStatusHandler {
+    metrics: Arc<Metrics>,
+}
 
 #[async_trait]
 impl ResourceHandler for WebSocketStatusHandler {
@@ -139,9 +173,9 @@ impl ResourceHandler for WebSocketStatusHandler {
                     "protocol": "MCP over WebSocket",
                     "features": ["bidirectional", "real-time", "low-latency"],
                     "connection_info": {
-                        "active_connections": 2,
-                        "total_connections": 15,
-                        "uptime": "5 minutes"
+                        "active_connections": self.metrics.websocket_connections(),
+                        "total_connections": self.metrics.websocket_connections_total(),
+                        "uptime_seconds": self.metrics.uptime().as_secs()
                     },
                     "capabilities": [
                         "instant messaging",
@@ -236,7 +270,24 @@ async fn main() -> McpResult<()> {
     #[cfg(feature = "tracing-subscriber")]
     tracing_subscriber::fmt::init();
 
-    let mut server = McpServer::new("websocket-mcp-server".to_string(), "1.0.0".to_string());
+    let config = Config::load(Cli::parse()).map_err(McpError::Validation)?;
+    let authenticator = Authenticator::new(&config.auth).map_err(McpError::Validation)?;
+    if !authenticator.is_enabled() {
+        tracing::warn!(
+            "Authentication is disabled: anyone who can reach {} can call tools; configure [auth]",
+            config.bind
+        );
+    }
+
+    let policy = Arc::new(PolicyEngine::new(&config.policy).map_err(McpError::Validation)?);
+    let reloader = config
+        .policy
+        .hot_reload
+        .then(|| policy.spawn_reloader(Duration::from_secs(config.policy.reload_interval_secs)));
+
+    let server = McpServer::new("websocket-mcp-server".to_string(), "1.0.0".to_string());
+    let notifier = Notifier::new();
+    let metrics = Arc::new(Metrics::new());
 
     // Add WebSocket echo tool
     server
@@ -297,7 +348,21 @@ async fn main() -> McpResult<()> {
                 },
                 "required": ["message"]
             }),
-            WebSocketChatHandler,
+            WebSocketChatHandler {
+                notifier: notifier.clone(),
+            },
+        )
+        .await?;
+
+    server
+        .add_tool(
+            "whoami".to_string(),
+            Some("Show the authenticated principal of this connection".to_string()),
+            json!({
+                "type": "object",
+                "properties": {}
+            }),
+            WhoAmIHandler,
         )
         .await?;
 
@@ -314,31 +379,52 @@ async fn main() -> McpResult<()> {
                 title: None,
                 meta: None,
             },
-            WebSocketStatusHandler,
+            WebSocketStatusHandler {
+                metrics: metrics.clone(),
+            },
         )
         .await?;
 
     // Start WebSocket server
-    tracing::info!("Starting WebSocket MCP server on ws://localhost:8081");
+    tracing::info!("Starting WebSocket MCP server on ws://{}", config.bind);
     tracing::info!("Features:");
     tracing::info!("  - Bidirectional real-time communication");
     tracing::info!("  - Multiple concurrent connections");
     tracing::info!("  - Automatic message routing");
     tracing::info!("  - Low-latency responses");
 
-    let transport = WebSocketServerTransport::new("0.0.0.0:8081");
-    server.start(transport).await?;
+    let listener = tokio::net::TcpListener::bind(&config.bind).await?;
+    let origins = OriginGuard::new(&config.security, listener.local_addr()?);
+    if origins.accepts_any_host() {
+        tracing::warn!(
+            "Host headers are not checked on {}: set [security] allowed_hosts to guard against DNS rebinding",
+            config.bind
+        );
+    }
+    let transport = WebSocketTransport::new(
+        server,
+        notifier,
+        metrics,
+        authenticator,
+        policy,
+        RateLimiter::new(config.limits.clone()),
+        origins,
+    );
 
     tracing::info!("WebSocket MCP server is running!");
-    tracing::info!("Connect with a WebSocket client to: ws://localhost:8081");
-    tracing::info!("Test tools: ws_echo, ws_chat");
+    tracing::info!("Connect with a WebSocket client to: ws://{}", config.bind);
+    tracing::info!("Test tools: ws_echo, ws_chat, whoami");
     tracing::info!("Test resources: ws://server/status, ws://server/connections");
+    tracing::info!("Prometheus metrics: http://{}/metrics", config.bind);
 
-    // Keep running until interrupted
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for ctrl+c");
-    server.stop().await?;
+    // Serve until interrupted
+    tokio::select! {
+        result = transport.serve(listener) => result?,
+        _ = tokio::signal::ctrl_c() => tracing::info!("Shutting down WebSocket MCP server"),
+    }
+    if let Some(reloader) = reloader {
+        reloader.abort();
+    }
 
     Ok(())
 }